@@ -35,6 +35,26 @@ struct Args {
     /// Use multilevel paging
     #[arg(long, default_value_t = false)]
     use_multilevel_paging: bool,
+
+    /// Guest XLEN (32 or 64); defaults to the ELF class
+    #[arg(long)]
+    xlen: Option<u32>,
+
+    /// Machine: flat (RAM everywhere) or virt (QEMU's board layout)
+    #[arg(long, default_value = "flat")]
+    machine: String,
+
+    /// RAM size in MiB for the virt machine
+    #[arg(long, default_value_t = 128)]
+    ram: u64,
+
+    /// Kernel command line placed in the virt machine's device tree
+    #[arg(long, default_value = "")]
+    bootargs: String,
+
+    /// Start in supervisor mode with the built-in SBI instead of firmware
+    #[arg(long, default_value_t = false)]
+    sbi: bool,
 }
 
 fn main() -> Result<(), String> {
@@ -46,10 +66,23 @@ fn main() -> Result<(), String> {
     struct ElfLoader { data: Vec<u8> }
     impl ElfLoader {
         fn new(data: Vec<u8>) -> Self { Self { data } }
-        fn load_into_memory(&self, mem: &mut vane::Mem) -> Result<u64, String> {
-            let elf_file = elf::ElfBytes::<elf::endian::AnyEndian>::minimal_parse(&self.data)
-                .map_err(|e| format!("Failed to parse ELF: {}", e))?;
+        /// Load the ELF's segments, or with `raw_base` a non-ELF image there as a 64-bit guest
+        fn load_into_memory(&self, mem: &mut vane::Mem, raw_base: Option<u64>) -> Result<(u64, u32), String> {
+            let elf_file = match (elf::ElfBytes::<elf::endian::AnyEndian>::minimal_parse(&self.data), raw_base) {
+                (Ok(elf_file), _) => elf_file,
+                (Err(_), Some(base)) => {
+                    for (i, &byte) in self.data.iter().enumerate() {
+                        mem.write_byte(base + i as u64, byte);
+                    }
+                    return Ok((base, 64));
+                }
+                (Err(e), None) => return Err(format!("Failed to parse ELF: {}", e)),
+            };
             let entry_point = elf_file.ehdr.e_entry;
+            let xlen = match elf_file.ehdr.class {
+                elf::file::Class::ELF32 => 32,
+                elf::file::Class::ELF64 => 64,
+            };
             if let Some(segments) = elf_file.segments() {
                 for segment in segments.iter() {
                     if segment.p_type == elf::abi::PT_LOAD {
@@ -71,19 +104,26 @@ fn main() -> Result<(), String> {
                     }
                 }
             }
-            Ok(entry_point)
+            Ok((entry_point, xlen))
         }
     }
 
     // Prepare memory and reactor
+    let virt = match args.machine.as_str() {
+        "flat" => false,
+        "virt" => true,
+        m => return Err(format!("Unknown machine: {}", m)),
+    };
     let loader = ElfLoader::new(data);
     let mut mem = vane::Mem::default();
-    let entry = loader.load_into_memory(&mut mem)?;
+    let raw_base = virt.then_some(vane::virt::RAM_BASE);
+    let (entry, elf_xlen) = loader.load_into_memory(&mut mem, raw_base)?;
 
     let reactor = vane::Reactor::new_with_mem(mem);
 
     // Configure flags
     reactor.set_test_mode(args.test_mode);
+    reactor.set_xlen(args.xlen.unwrap_or(elf_xlen));
     match args.paging.as_str() {
         "legacy" => reactor.set_paging_mode("legacy"),
         "shared" => reactor.set_paging_mode("shared"),
@@ -94,6 +134,21 @@ fn main() -> Result<(), String> {
     reactor.set_use_multilevel_paging(args.use_multilevel_paging);
     reactor.set_shared_page_table_vaddr(args.shared_page_table_vaddr);
     reactor.set_shared_security_directory_vaddr(args.shared_security_directory_vaddr);
+    if virt {
+        reactor.use_virt(args.ram << 20);
+        reactor.load_device_tree(&args.bootargs);
+    }
+    if args.sbi {
+        reactor.enable_sbi();
+    }
+    if virt || args.sbi {
+        reactor.set_console(|bytes| {
+            use std::io::Write;
+            let mut out = std::io::stdout();
+            let _ = out.write_all(bytes);
+            let _ = out.flush();
+        });
+    }
 
     // Run
     let result = if args.jit {
@@ -0,0 +1,54 @@
+//! LR/SC reservation tracking in [`crate::Mem`] and decoding of the RV64 AMOs.
+use alloc::string::ToString;
+
+use crate::ext::ExtInst;
+use crate::Mem;
+use rv_asm::Xlen;
+
+#[test]
+fn reservation_survives_until_sc() {
+    let mut mem = Mem::default();
+    mem.get_page(0x1000);
+    mem.reserve(0x1000);
+    assert!(mem.check_reservation(0x1004));
+    // `sc` consumes the reservation even when it succeeds
+    assert!(!mem.check_reservation(0x1000));
+}
+
+#[test]
+fn stores_break_reservation() {
+    let mut mem = Mem::default();
+    mem.reserve(0x1000);
+    mem.write_byte(0x1007, 1);
+    assert!(!mem.check_reservation(0x1000));
+
+    // An unaligned doubleword access ending inside the granule
+    mem.reserve(0x1000);
+    mem.get_page(0xff9);
+    assert!(!mem.check_reservation(0x1000));
+
+    // Neighbouring granules leave it alone
+    mem.reserve(0x1000);
+    mem.write_byte(0x1008, 1);
+    mem.get_page(0xff8);
+    assert!(mem.check_reservation(0x1000));
+
+    mem.reserve(0x1000);
+    assert!(!mem.check_reservation(0x2000));
+}
+
+#[test]
+fn decode_doubleword_amos() {
+    let cases: &[(u32, &str)] = &[
+        (0x1005_35af, "lr.d a1, (a0)"),
+        (0x1ec5_35af, "sc.d.aqrl a1, a2, (a0)"),
+        (0x00c5_35af, "amoadd.d a1, a2, (a0)"),
+        (0x0cc5_35af, "amoswap.d.aq a1, a2, (a0)"),
+        (0xe0c5_35af, "amomaxu.d a1, a2, (a0)"),
+    ];
+    for &(code, text) in cases {
+        let (i, _) = ExtInst::decode(code, Xlen::Rv64).unwrap();
+        assert_eq!(i.to_string(), text, "{code:#x}");
+        assert!(ExtInst::decode(code, Xlen::Rv32).is_none());
+    }
+}
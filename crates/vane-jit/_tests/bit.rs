@@ -0,0 +1,166 @@
+use alloc::string::ToString;
+use rv_asm::Xlen;
+
+use crate::bit::{exec, BitOp::*};
+use crate::ext::ExtInst;
+
+#[test]
+fn decode_rv64() {
+    let cases: &[(u32, &str)] = &[
+        (0x08c5853b, "add.uw a0, a1, a2"),
+        (0x20c5a533, "sh1add a0, a1, a2"),
+        (0x20c5c533, "sh2add a0, a1, a2"),
+        (0x20c5e533, "sh3add a0, a1, a2"),
+        (0x20c5a53b, "sh1add.uw a0, a1, a2"),
+        (0x20c5c53b, "sh2add.uw a0, a1, a2"),
+        (0x20c5e53b, "sh3add.uw a0, a1, a2"),
+        (0x0855951b, "slli.uw a0, a1, 5"),
+        (0x40c5f533, "andn a0, a1, a2"),
+        (0x40c5e533, "orn a0, a1, a2"),
+        (0x40c5c533, "xnor a0, a1, a2"),
+        (0x60059513, "clz a0, a1"),
+        (0x6005951b, "clzw a0, a1"),
+        (0x60159513, "ctz a0, a1"),
+        (0x6015951b, "ctzw a0, a1"),
+        (0x60259513, "cpop a0, a1"),
+        (0x6025951b, "cpopw a0, a1"),
+        (0x0ac5e533, "max a0, a1, a2"),
+        (0x0ac5f533, "maxu a0, a1, a2"),
+        (0x0ac5c533, "min a0, a1, a2"),
+        (0x0ac5d533, "minu a0, a1, a2"),
+        (0x60459513, "sext.b a0, a1"),
+        (0x60559513, "sext.h a0, a1"),
+        (0x0805c53b, "zext.h a0, a1"),
+        (0x60c59533, "rol a0, a1, a2"),
+        (0x60c5953b, "rolw a0, a1, a2"),
+        (0x60c5d533, "ror a0, a1, a2"),
+        (0x6285d513, "rori a0, a1, 40"),
+        (0x6075d51b, "roriw a0, a1, 7"),
+        (0x60c5d53b, "rorw a0, a1, a2"),
+        (0x2875d513, "orc.b a0, a1"),
+        (0x6b85d513, "rev8 a0, a1"),
+        (0x0ac59533, "clmul a0, a1, a2"),
+        (0x0ac5b533, "clmulh a0, a1, a2"),
+        (0x0ac5a533, "clmulr a0, a1, a2"),
+        (0x48c59533, "bclr a0, a1, a2"),
+        (0x4a859513, "bclri a0, a1, 40"),
+        (0x48c5d533, "bext a0, a1, a2"),
+        (0x4a85d513, "bexti a0, a1, 40"),
+        (0x68c59533, "binv a0, a1, a2"),
+        (0x6a859513, "binvi a0, a1, 40"),
+        (0x28c59533, "bset a0, a1, a2"),
+        (0x2a859513, "bseti a0, a1, 40"),
+    ];
+    for &(code, text) in cases {
+        assert!(rv_asm::Inst::decode(code, Xlen::Rv64).is_err(), "{text}");
+        let (i, _) = ExtInst::decode(code, Xlen::Rv64).unwrap();
+        assert_eq!(i.to_string(), text, "{code:#x}");
+    }
+}
+
+#[test]
+fn decode_rv32() {
+    let dec = |code| ExtInst::decode(code, Xlen::Rv32).map(|(i, _)| i.to_string());
+    assert_eq!(dec(0x69855513).as_deref(), Some("rev8 a0, a0"));
+    assert_eq!(dec(0x0805c533).as_deref(), Some("zext.h a0, a1"));
+    assert_eq!(dec(0x61f5d513).as_deref(), Some("rori a0, a1, 31"));
+    // The RV64 encodings of these do not exist on RV32
+    for code in [0x6b85d513, 0x0805c53b, 0x6285d513, 0x08c5853b, 0x6005951b, 0x4a859513] {
+        assert_eq!(dec(code), None, "{code:#x}");
+    }
+}
+
+#[test]
+fn binary_rv64() {
+    let (a, b) = (0x8000_0012_3456_00f0, 0xffff_ffff_0000_0003);
+    let cases = [
+        (AddUw, 0xffffffff345600f3),
+        (Sh1add, 0x2368ac01e3),
+        (Sh2add, 0x47d15803c3),
+        (Sh3add, 0x90a2b00783),
+        (Sh1addUw, 0xffffffff68ac01e3),
+        (Sh2addUw, 0xffffffffd15803c3),
+        (Sh3addUw, 0xa2b00783),
+        (Andn, 0x345600f0),
+        (Orn, 0x80000012fffffffc),
+        (Xnor, 0x80000012cba9ff0c),
+        (Max, 0xffffffff00000003),
+        (Maxu, 0xffffffff00000003),
+        (Min, 0x80000012345600f0),
+        (Minu, 0x80000012345600f0),
+        (Rol, 0x91a2b00784),
+        (Ror, 0x10000002468ac01e),
+        (Rolw, 0xffffffffa2b00781),
+        (Rorw, 0x68ac01e),
+        (Clmul, 0x6c3200665cfa0110),
+        (Clmulh, 0x7ffffff193cdffa0),
+        (Clmulr, 0xffffffe3279bff40),
+        (Bclr, 0x80000012345600f0),
+        (Bext, 0),
+        (Binv, 0x80000012345600f8),
+        (Bset, 0x80000012345600f8),
+    ];
+    for (op, want) in cases {
+        assert_eq!(exec(op, a, b, Xlen::Rv64), want, "{op:?}");
+    }
+}
+
+#[test]
+fn immediate_rv64() {
+    let a = 0x8000_0012_3456_00f0;
+    assert_eq!(exec(Ror, a, 40, Xlen::Rv64), 0x12345600f0800000);
+    assert_eq!(exec(Rorw, a, 7, Xlen::Rv64), 0xffffffffe068ac01);
+    assert_eq!(exec(SlliUw, a, 5, Xlen::Rv64), 0x68ac01e00);
+    assert_eq!(exec(Bclr, a, 63, Xlen::Rv64), 0x12345600f0);
+    assert_eq!(exec(Bext, a, 40, Xlen::Rv64), 0);
+    assert_eq!(exec(Binv, a, 63, Xlen::Rv64), 0x12345600f0);
+    assert_eq!(exec(Bset, a, 0, Xlen::Rv64), 0x80000012345600f1);
+}
+
+#[test]
+fn unary_rv64() {
+    let cases = [
+        (0x8000_0012_3456_00f0, [0, 2, 4, 4, 14, 11]),
+        (0x0000_0000_0010_0080, [43, 11, 7, 7, 2, 2]),
+        (0, [64, 32, 64, 32, 0, 0]),
+    ];
+    for (a, want) in cases {
+        let ops = [Clz, Clzw, Ctz, Ctzw, Cpop, Cpopw];
+        for (op, want) in ops.into_iter().zip(want) {
+            assert_eq!(exec(op, a, 0, Xlen::Rv64), want, "{op:?} {a:#x}");
+        }
+    }
+    let a = 0x8000_0012_3456_00f0;
+    assert_eq!(exec(SextB, a, 0, Xlen::Rv64), 0xfffffffffffffff0);
+    assert_eq!(exec(SextH, a, 0, Xlen::Rv64), 0xf0);
+    assert_eq!(exec(SextH, 0x8000, 0, Xlen::Rv64), 0xffffffffffff8000);
+    assert_eq!(exec(ZextH, a, 0, Xlen::Rv64), 0xf0);
+    assert_eq!(exec(OrcB, a, 0, Xlen::Rv64), 0xff0000ffffff00ff);
+    assert_eq!(exec(Rev8, a, 0, Xlen::Rv64), 0xf000563412000080);
+}
+
+#[test]
+fn rv32() {
+    let (a, b) = (0x8001_00f0, 0x24);
+    let cases = [
+        (Sh3add, 0x807a4),
+        (Orn, 0xfffffffb),
+        (Max, 0x24),
+        (Rol, 0x100f08),
+        (Ror, 0x800100f),
+        (Clmul, 0x241dc0),
+        (Clmulh, 0x12),
+        (Clmulr, 0x24),
+        (Bset, 0x800100f0),
+        (Clz, 0),
+        (Ctz, 4),
+        (Cpop, 6),
+        (Rev8, 0xf0000180),
+        (SextB, 0xfffffff0),
+    ];
+    for (op, want) in cases {
+        assert_eq!(exec(op, a, b, Xlen::Rv32), want, "{op:?}");
+    }
+    assert_eq!(exec(Clz, 0, 0, Xlen::Rv32), 32);
+    assert_eq!(exec(Ctz, 0, 0, Xlen::Rv32), 32);
+}
@@ -0,0 +1,119 @@
+use alloc::string::ToString;
+use rv_asm::Xlen;
+
+use crate::arch::Riscv;
+use crate::clint::*;
+use crate::csr::*;
+use crate::flate::DebugFlate;
+use crate::template::{Flags, Labels, Params, RegCache, Region, TemplateJit};
+use crate::trap::*;
+use crate::{Heat, Mem};
+
+#[test]
+fn registers() {
+    let mut c = Clint::default();
+    assert!(Clint::contains(BASE + MTIME) && !Clint::contains(BASE + SIZE));
+    assert_eq!(c.read(MTIME, 8, 100), 100);
+    assert_eq!(c.read(MTIMECMP, 8, 0), u64::MAX);
+    assert_eq!(c.pending(100), 0);
+    assert_eq!(c.due(100), u64::MAX);
+
+    // RV32 guests set `mtimecmp` a word at a time
+    c.write(MTIMECMP, 4, 0x1_0000_0150, 100);
+    c.write(MTIMECMP + 4, 4, 0, 100);
+    assert_eq!(c.mtimecmp, 0x150);
+    assert_eq!(c.read(MTIMECMP + 1, 1, 0), 1);
+    assert_eq!(c.due(100), 0x150);
+    assert_eq!(c.pending(0x14f), 0);
+    assert_eq!(c.pending(0x150), Interrupt::MachineTimer.bit());
+
+    // Writing `mtime` moves it against `instret`
+    c.write(MTIME, 8, 0x1000, 100);
+    assert_eq!(c.mtime(150), 0x1000 + 50);
+    assert_eq!(c.due(150), u64::MAX);
+    c.write(MTIME + 4, 4, 0, 100);
+    assert_eq!(c.read(MTIME, 4, 100), 0x1000);
+
+    c.write(MSIP, 4, 3, 0);
+    assert!(c.msip);
+    assert_eq!(c.read(MSIP, 4, 0), 1);
+    assert_eq!(c.pending(0) & Interrupt::MachineSoftware.bit(), Interrupt::MachineSoftware.bit());
+    // Holes read as zero and ignore writes
+    c.write(0x8, 4, 1, 0);
+    assert_eq!(c.read(0x8, 4, 0), 0);
+}
+
+#[test]
+fn pending_interrupts() {
+    let mut file = CsrFile::default();
+    file.lines = Interrupt::MachineTimer.bit() | Interrupt::MachineExternal.bit();
+    // `mip` shows the lines, whose bits software cannot clear
+    assert_eq!(file.read(MIP, 0, Xlen::Rv64), Ok(0x880));
+    file.write(MIP, 0, Xlen::Rv64).unwrap();
+    assert_eq!(file.read(MIP, 0, Xlen::Rv64), Ok(0x880));
+
+    // Machine mode needs `mstatus.MIE` as well as `mie`
+    file.write(MIE, 0xaaa, Xlen::Rv64).unwrap();
+    assert_eq!(file.pending_interrupt(), None);
+    file.write(MSTATUS, mstatus::MIE, Xlen::Rv64).unwrap();
+    assert_eq!(
+        file.pending_interrupt(),
+        Some(Trap::interrupt(Interrupt::MachineExternal))
+    );
+    file.lines = Interrupt::MachineTimer.bit();
+    file.write(MIP, Interrupt::SupervisorSoftware.bit(), Xlen::Rv64).unwrap();
+    assert_eq!(file.pending_interrupt(), Some(Trap::interrupt(Interrupt::MachineTimer)));
+
+    // Delegated interrupts never preempt machine mode
+    file.lines = 0;
+    file.write(MIDELEG, 0x222, Xlen::Rv64).unwrap();
+    assert_eq!(file.read(SIP, 0, Xlen::Rv64), Ok(2));
+    assert_eq!(file.pending_interrupt(), None);
+    file.mode = Priv::Supervisor;
+    file.mstatus = 0;
+    assert_eq!(file.pending_interrupt(), None);
+    file.mstatus = mstatus::SIE;
+    assert_eq!(
+        file.pending_interrupt(),
+        Some(Trap::interrupt(Interrupt::SupervisorSoftware))
+    );
+    // Machine interrupts are always enabled below machine mode
+    file.mstatus = 0;
+    file.lines = Interrupt::MachineTimer.bit();
+    assert_eq!(file.pending_interrupt(), Some(Trap::interrupt(Interrupt::MachineTimer)));
+    file.mode = Priv::User;
+    file.lines = 0;
+    assert_eq!(
+        file.pending_interrupt(),
+        Some(Trap::interrupt(Interrupt::SupervisorSoftware))
+    );
+    assert_eq!(
+        Trap::interrupt(Interrupt::SupervisorSoftware).to_string(),
+        "SupervisorSoftware (tval 0x0)"
+    );
+}
+
+#[test]
+fn blocks_check_the_deadline() {
+    let mut mem = Mem::default();
+    // addi a0, a0, 1
+    for (i, b) in 0x0015_0513u32.to_le_bytes().into_iter().enumerate() {
+        mem.write_byte(0x1000 + i as u64, b);
+    }
+    let jit = TemplateJit {
+        params: Params {
+            react: &mem,
+            trial: &|_| Heat::New,
+            flate: &DebugFlate {},
+            regs: &RegCache::default(),
+            region: &Region::default(),
+            root: 0x1000,
+            flags: Flags::default(),
+        },
+        pc: 0x1000,
+        labels: &Labels::default(),
+        depth: 0,
+    };
+    let js = Riscv(&jit).to_string();
+    assert!(js.contains("if($._r.i>=$._r.t){sync();return $.interrupt(4096n)}p=4096n;"), "{js}");
+}
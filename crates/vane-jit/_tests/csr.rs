@@ -0,0 +1,90 @@
+use alloc::string::ToString;
+use rv_asm::{Reg, Xlen};
+
+use crate::csr::*;
+use crate::ext::ExtInst;
+
+#[test]
+fn decode_and_display() {
+    let cases: &[(u32, &str)] = &[
+        // csrrs a0, cycle, zero (rdcycle a0)
+        (0xc0002573, "csrrs a0, 0xc00, zero"),
+        // csrrw a1, fcsr, a2
+        (0x003615f3, "csrrw a1, 0x3, a2"),
+        // csrrc zero, fflags, a3
+        (0x0016b073, "csrrc zero, 0x1, a3"),
+        // csrrwi t0, frm, 3
+        (0x0021d2f3, "csrrwi t0, 0x2, 3"),
+        // csrrsi zero, 0x7c0, 31
+        (0x7c0fe073, "csrrsi zero, 0x7c0, 31"),
+        // csrrci a0, 0x7c0, 0
+        (0x7c007573, "csrrci a0, 0x7c0, 0"),
+    ];
+    for &(code, text) in cases {
+        let (i, _) = ExtInst::decode(code, Xlen::Rv64).unwrap();
+        assert_eq!(i.to_string(), text, "{code:#x}");
+    }
+    // funct3 000 and 100 are not Zicsr
+    assert_eq!(ExtInst::decode(0x00000073, Xlen::Rv64), None);
+    assert_eq!(ExtInst::decode(0x00004073, Xlen::Rv64), None);
+}
+
+#[test]
+fn side_effects() {
+    let csr = |op, dest, src| ExtInst::Csr {
+        op,
+        csr: FCSR,
+        dest: Reg(dest),
+        src: Reg(src),
+    };
+    // csrw does not read
+    assert_eq!(csr(CsrOp::Rw, 0, 5).csr_effects(), Some((false, true)));
+    assert_eq!(csr(CsrOp::Rw, 1, 0).csr_effects(), Some((true, true)));
+    // csrr does not write
+    assert_eq!(csr(CsrOp::Rs, 1, 0).csr_effects(), Some((true, false)));
+    assert_eq!(csr(CsrOp::Rc, 1, 2).csr_effects(), Some((true, true)));
+    let imm = ExtInst::CsrI {
+        op: CsrOp::Rs,
+        csr: FCSR,
+        dest: Reg(1),
+        uimm: 0,
+    };
+    assert_eq!(imm.csr_effects(), Some((true, false)));
+    assert_eq!(CsrOp::Rs.apply(0b1010, 0b0101), 0b1111);
+    assert_eq!(CsrOp::Rc.apply(0b1111, 0b0101), 0b1010);
+    assert_eq!(CsrOp::Rw.apply(0b1111, 0b0101), 0b0101);
+}
+
+#[test]
+fn builtin_csrs() {
+    let mut file = CsrFile::default();
+    file.write(FCSR, 0x1ff, Xlen::Rv64).unwrap();
+    assert_eq!(file.fcsr, 0xff);
+    file.write(FFLAGS, 0, Xlen::Rv64).unwrap();
+    assert_eq!(file.read(FRM, 0, Xlen::Rv64), Ok(7));
+    file.write(FRM, 1, Xlen::Rv64).unwrap();
+    file.write(FFLAGS, 0x3, Xlen::Rv64).unwrap();
+    assert_eq!(file.read(FCSR, 0, Xlen::Rv64), Ok(0x23));
+
+    let n = 0x1_2345_6789;
+    for c in [CYCLE, TIME, INSTRET] {
+        assert_eq!(file.read(c, n, Xlen::Rv64), Ok(n));
+        assert_eq!(file.read(c, n, Xlen::Rv32), Ok(0x2345_6789));
+        assert_eq!(file.write(c, 0, Xlen::Rv64), Err(CsrError::ReadOnly(c)));
+    }
+    for c in [CYCLEH, TIMEH, INSTRETH] {
+        assert_eq!(file.read(c, n, Xlen::Rv32), Ok(1));
+        assert_eq!(file.read(c, n, Xlen::Rv64), Err(CsrError::Missing(c)));
+    }
+    assert_eq!(file.read(0x7c0, 0, Xlen::Rv64), Err(CsrError::Missing(0x7c0)));
+    assert!(file.hook(0x7c0).is_none());
+    file.register(
+        0x7c0,
+        CsrHook {
+            read: alloc::rc::Rc::new(|| 42),
+            write: None,
+        },
+    );
+    assert_eq!(file.hook(0x7c0).map(|h| (h.read)()), Some(42));
+    assert!(file.unregister(0x7c0).is_some());
+}
@@ -0,0 +1,37 @@
+//! Zifencei: tracking which compiled blocks a `fence.i` must drop.
+use alloc::string::ToString;
+use alloc::vec::Vec;
+
+use crate::ext::ExtInst;
+use crate::Mem;
+use rv_asm::Xlen;
+
+#[test]
+fn decode_fence_i() {
+    assert!(rv_asm::Inst::decode(0x0000100f, Xlen::Rv64).is_err());
+    let (i, _) = ExtInst::decode(0x0000100f, Xlen::Rv32).unwrap();
+    assert_eq!(i, ExtInst::FenceI);
+    assert_eq!(i.to_string(), "fence.i");
+}
+
+#[test]
+fn stores_stale_compiled_code() {
+    let mut mem = Mem::default();
+    // One block spans two pages; another sits on the second
+    mem.note_code(0x1_fffc, 0x1_fffc);
+    mem.note_code(0x2_0000, 0x1_fffc);
+    mem.note_code(0x2_0100, 0x2_0100);
+    // Nothing was stored to, so nothing is dropped
+    assert!(mem.fence_i().is_empty());
+
+    // Data pages without code are not tracked
+    mem.write_byte(0x5_0000, 1);
+    assert!(mem.stale_code.is_empty());
+    mem.note_store(0x2_0400);
+    let roots: Vec<u64> = mem.fence_i().into_iter().collect();
+    assert_eq!(roots, [0x1_fffc, 0x2_0100]);
+    // The page is forgotten until code is compiled from it again
+    mem.write_byte(0x2_0000, 0x13);
+    assert!(mem.fence_i().is_empty());
+    assert!(mem.code_pages.contains_key(&1));
+}
@@ -0,0 +1,146 @@
+//! Known-answer tests for the shared F/D semantics in [`crate::fp`].
+use alloc::string::ToString;
+
+use crate::ext::ExtInst;
+use crate::fp::*;
+use rv_asm::Xlen;
+
+fn run(op: FpOp, fmt: FpFmt, rm: u8, a: u64, b: u64) -> (u64, u32) {
+    let mut fcsr = 0;
+    let r = exec(op, fmt, rm, a, b, 0, &mut fcsr).unwrap();
+    (r, fcsr)
+}
+
+fn d(x: f64) -> u64 {
+    x.to_bits()
+}
+
+fn s(x: f32) -> u64 {
+    box_s(x.to_bits())
+}
+
+#[test]
+fn add_rounding_modes() {
+    // 1 + 2^-53 lies exactly between 1 and its successor
+    let (a, b) = (d(1.0), d(f64::EPSILON / 2.0));
+    assert_eq!(run(FpOp::Add, FpFmt::D, 0, a, b), (d(1.0), FFLAG_NX));
+    assert_eq!(run(FpOp::Add, FpFmt::D, 1, a, b), (d(1.0), FFLAG_NX));
+    assert_eq!(run(FpOp::Add, FpFmt::D, 3, a, b), (d(1.0 + f64::EPSILON), FFLAG_NX));
+    assert_eq!(run(FpOp::Add, FpFmt::D, 4, a, b), (d(1.0 + f64::EPSILON), FFLAG_NX));
+    assert_eq!(run(FpOp::Add, FpFmt::S, 0, s(1.5), s(2.25)), (s(3.75), 0));
+}
+
+#[test]
+fn dynamic_rounding_mode() {
+    let mut fcsr = 3 << 5;
+    let r = exec(FpOp::Div, FpFmt::S, RM_DYN, s(1.0), s(3.0), 0, &mut fcsr);
+    assert_eq!(r, Some(box_s(0x3eaa_aaab)));
+    assert_eq!(fcsr, (3 << 5) | FFLAG_NX);
+    let mut fcsr = 5 << 5;
+    assert_eq!(exec(FpOp::Add, FpFmt::S, RM_DYN, s(1.0), s(1.0), 0, &mut fcsr), None);
+}
+
+#[test]
+fn nan_boxing() {
+    // An improperly boxed single reads as the canonical NaN
+    let (r, fl) = run(FpOp::Add, FpFmt::S, 0, 0x3f80_0000, s(1.0));
+    assert_eq!((r, fl), (box_s(0x7fc0_0000), 0));
+    assert_eq!(unbox_s(s(2.0)), 2.0f32.to_bits());
+    let (r, fl) = run(FpOp::Class, FpFmt::S, 0, 0x3f80_0000, 0);
+    assert_eq!((r, fl), (1 << 9, 0));
+}
+
+#[test]
+fn exceptional_cases() {
+    assert_eq!(
+        run(FpOp::Div, FpFmt::D, 0, d(1.0), d(0.0)),
+        (d(f64::INFINITY), FFLAG_DZ)
+    );
+    assert_eq!(
+        run(FpOp::Sqrt, FpFmt::D, 0, d(-1.0), 0),
+        (0x7ff8_0000_0000_0000, FFLAG_NV)
+    );
+    assert_eq!(
+        run(FpOp::Mul, FpFmt::S, 0, s(f32::MAX), s(2.0)),
+        (s(f32::INFINITY), FFLAG_OF | FFLAG_NX)
+    );
+    assert_eq!(
+        run(FpOp::Mul, FpFmt::S, 1, s(f32::MAX), s(2.0)),
+        (s(f32::MAX), FFLAG_OF | FFLAG_NX)
+    );
+    assert_eq!(
+        run(FpOp::Mul, FpFmt::D, 0, d(f64::MIN_POSITIVE), d(0.5)),
+        (d(f64::MIN_POSITIVE / 2.0), 0)
+    );
+    assert_eq!(
+        run(FpOp::Mul, FpFmt::D, 0, d(f64::MIN_POSITIVE), d(1.0 / 3.0)).1,
+        FFLAG_UF | FFLAG_NX
+    );
+    // x - x is -0 only when rounding down
+    assert_eq!(run(FpOp::Sub, FpFmt::D, 0, d(1.0), d(1.0)), (d(0.0), 0));
+    assert_eq!(run(FpOp::Sub, FpFmt::D, 2, d(1.0), d(1.0)), (d(-0.0), 0));
+}
+
+#[test]
+fn min_max_and_compare() {
+    let snan = 0x7ff0_0000_0000_0001;
+    assert_eq!(run(FpOp::Min, FpFmt::D, 0, d(-0.0), d(0.0)), (d(-0.0), 0));
+    assert_eq!(run(FpOp::Max, FpFmt::D, 0, d(-0.0), d(0.0)), (d(0.0), 0));
+    assert_eq!(run(FpOp::Min, FpFmt::D, 0, snan, d(2.0)), (d(2.0), FFLAG_NV));
+    assert_eq!(run(FpOp::Eq, FpFmt::D, 0, d(f64::NAN), d(1.0)), (0, 0));
+    assert_eq!(run(FpOp::Lt, FpFmt::D, 0, d(f64::NAN), d(1.0)), (0, FFLAG_NV));
+    assert_eq!(run(FpOp::Le, FpFmt::S, 0, s(1.0), s(1.0)), (1, 0));
+}
+
+#[test]
+fn conversions() {
+    assert_eq!(run(FpOp::ToW, FpFmt::D, 1, d(-2.5), 0), (-2i64 as u64, FFLAG_NX));
+    assert_eq!(run(FpOp::ToW, FpFmt::D, 2, d(-2.5), 0), (-3i64 as u64, FFLAG_NX));
+    assert_eq!(run(FpOp::ToW, FpFmt::D, 0, d(-2.5), 0), (-2i64 as u64, FFLAG_NX));
+    assert_eq!(run(FpOp::ToW, FpFmt::D, 4, d(-2.5), 0), (-3i64 as u64, FFLAG_NX));
+    assert_eq!(run(FpOp::ToWu, FpFmt::S, 0, s(-1.0), 0), (0, FFLAG_NV));
+    assert_eq!(run(FpOp::ToWu, FpFmt::S, 0, s(4e9), 0), (4_000_000_000u32 as i32 as i64 as u64, 0));
+    assert_eq!(run(FpOp::ToL, FpFmt::D, 0, d(f64::NAN), 0), (i64::MAX as u64, FFLAG_NV));
+    assert_eq!(run(FpOp::ToLu, FpFmt::D, 0, d(1e20), 0), (u64::MAX, FFLAG_NV));
+    assert_eq!(run(FpOp::FromL, FpFmt::D, 0, u64::MAX, 0), (d(-1.0), 0));
+    assert_eq!(run(FpOp::FromLu, FpFmt::D, 0, u64::MAX, 0), (d(18446744073709551616.0), FFLAG_NX));
+    assert_eq!(run(FpOp::FromW, FpFmt::S, 0, 16_777_217, 0), (s(16_777_216.0), FFLAG_NX));
+    assert_eq!(run(FpOp::Cvt, FpFmt::S, 0, d(0.1), 0), (s(0.1), FFLAG_NX));
+    assert_eq!(run(FpOp::Cvt, FpFmt::D, 0, s(0.1), 0), (d(0.1f32 as f64), 0));
+}
+
+#[test]
+fn fused_multiply_add() {
+    let mut fcsr = 0;
+    // 0.1 * 10 - 1 is exact only without intermediate rounding
+    let r = exec(FpOp::Msub, FpFmt::D, 0, d(0.1), d(10.0), d(1.0), &mut fcsr);
+    assert_eq!(r, Some(d(5.551115123125783e-17)));
+    assert_eq!(fcsr, 0);
+}
+
+#[test]
+fn decode_and_display() {
+    let cases: &[(u32, Xlen, &str)] = &[
+        (0x0025_3007, Xlen::Rv64, "fld f0, 2(a0)"),
+        (0x00a5_b427, Xlen::Rv64, "fsd f10, 8(a1)"),
+        (0x02b5_7553, Xlen::Rv64, "fadd.d f10, f10, f11"),
+        (0x5805_7053, Xlen::Rv64, "fsqrt.s f0, f10"),
+        (0xc225_1553, Xlen::Rv64, "fcvt.l.d a0, f10"),
+        (0xd005_0553, Xlen::Rv64, "fcvt.s.w f10, a0"),
+        (0x4015_7553, Xlen::Rv64, "fcvt.s.d f10, f10"),
+        (0xe205_0553, Xlen::Rv64, "fmv.x.d a0, f10"),
+        (0xa0b5_2553, Xlen::Rv64, "feq.s a0, f10, f11"),
+        (0x62b5_7543, Xlen::Rv64, "fmadd.d f10, f10, f11, f12"),
+        (0x2008, Xlen::Rv64, "fld f10, 0(s0)"),
+        (0xa02a, Xlen::Rv64, "fsd f10, 0(sp)"),
+        (0x6008, Xlen::Rv32, "flw f10, 0(s0)"),
+    ];
+    for &(code, xlen, text) in cases {
+        let (i, _) = ExtInst::decode(code, xlen).unwrap();
+        assert_eq!(i.to_string(), text, "{code:#x}");
+    }
+    // `fcvt.l.d` is RV64-only, and rounding modes 5 and 6 are reserved
+    assert!(ExtInst::decode(0xc225_1553, Xlen::Rv32).is_none());
+    assert!(ExtInst::decode(0x02b5_5553, Xlen::Rv64).is_none());
+}
+
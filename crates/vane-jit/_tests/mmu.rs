@@ -0,0 +1,260 @@
+//! Sv32/Sv39 page-table walks in [`crate::Mem`] and supervisor-mode traps.
+use alloc::string::ToString;
+use rv_asm::Xlen;
+
+use crate::csr::*;
+use crate::ext::ExtInst;
+use crate::flate::DebugFlate;
+use crate::mmu::{pte, transfer, Access, SatpMode};
+use crate::template::{CoreJS, Flags, RegCache, Region};
+use crate::trap::{Exception, Priv, Trap};
+use crate::Mem;
+
+fn put(mem: &mut Mem, addr: u64, v: u64, size: u64) {
+    for i in 0..size {
+        mem.write_byte(addr + i, (v >> (8 * i)) as u8);
+    }
+}
+
+fn get(mem: &Mem, addr: u64) -> u64 {
+    (0..8).fold(0, |v, i| v | (mem.read_byte(addr + i) as u64) << (8 * i))
+}
+
+fn leaf(pa: u64, flags: u64) -> u64 {
+    (pa >> 12) << 10 | flags | pte::V
+}
+
+/// Sv39 tables rooted at 0x1000; 0x5000 and up are 4 KiB pages through
+/// the level-0 table at 0x3000, 0x20_0000 is a 2 MiB megapage
+fn sv39() -> (Mem, CsrFile) {
+    let mut mem = Mem::default();
+    put(&mut mem, 0x1000, leaf(0x2000, 0), 8);
+    put(&mut mem, 0x2000, leaf(0x3000, 0), 8);
+    let rwx = pte::R | pte::W | pte::X;
+    put(&mut mem, 0x3000 + 5 * 8, leaf(0x8000, rwx), 8);
+    put(&mut mem, 0x3000 + 6 * 8, leaf(0x9000, pte::R | pte::U), 8);
+    put(&mut mem, 0x3000 + 7 * 8, leaf(0xa000, pte::X), 8);
+    put(&mut mem, 0x3000 + 8 * 8, leaf(0xb000, pte::W), 8);
+    put(&mut mem, 0x2000 + 8, leaf(0x40_0000, rwx | pte::A | pte::D), 8);
+    put(&mut mem, 0x2000 + 16, leaf(0x40_1000, rwx), 8);
+    let mut csrs = CsrFile::default();
+    csrs.write(SATP, 8 << 60 | 1, Xlen::Rv64).unwrap();
+    csrs.mode = Priv::Supervisor;
+    (mem, csrs)
+}
+
+fn fault(e: Exception, vaddr: u64) -> Result<u64, Trap> {
+    Err(Trap::exception(e, vaddr))
+}
+
+#[test]
+fn sv39_pages() {
+    let (mut mem, csrs) = sv39();
+    let rv64 = Xlen::Rv64;
+    // Peeking leaves the accessed bit alone
+    assert_eq!(mem.translate(0x5123, Access::Execute, &csrs, rv64, true), Ok(0x8123));
+    assert_eq!(get(&mem, 0x3028) & (pte::A | pte::D), 0);
+    assert_eq!(mem.translate(0x5123, Access::Read, &csrs, rv64, false), Ok(0x8123));
+    assert_eq!(get(&mem, 0x3028) & (pte::A | pte::D), pte::A);
+    assert_eq!(mem.translate(0x5ff8, Access::Write, &csrs, rv64, false), Ok(0x8ff8));
+    assert_eq!(get(&mem, 0x3028) & (pte::A | pte::D), pte::A | pte::D);
+
+    assert_eq!(mem.translate(0x2f_fff0, Access::Write, &csrs, rv64, false), Ok(0x4f_fff0));
+    // Megapages must be 2 MiB aligned
+    assert_eq!(
+        mem.translate(0x40_0000, Access::Read, &csrs, rv64, false),
+        fault(Exception::LoadPageFault, 0x40_0000)
+    );
+    // Nothing is mapped at level 2 entry 1
+    assert_eq!(
+        mem.translate(0x4000_0000, Access::Execute, &csrs, rv64, false),
+        fault(Exception::InstructionPageFault, 0x4000_0000)
+    );
+    // Bits 63:39 must all equal bit 38
+    assert_eq!(
+        mem.translate(1 << 39 | 0x5000, Access::Read, &csrs, rv64, false),
+        fault(Exception::LoadPageFault, 1 << 39 | 0x5000)
+    );
+    // Write-only is a reserved encoding
+    assert_eq!(
+        mem.translate(0x8000, Access::Write, &csrs, rv64, false),
+        fault(Exception::StorePageFault, 0x8000)
+    );
+}
+
+#[test]
+fn accesses_split_at_pages() {
+    let (mut mem, csrs) = sv39();
+    let rwx = pte::R | pte::W | pte::X;
+    // Adjacent virtual pages backed out of order
+    put(&mut mem, 0x3000 + 9 * 8, leaf(0xd000, rwx), 8);
+    put(&mut mem, 0x3000 + 10 * 8, leaf(0xc000, rwx), 8);
+    let mut page = |a: u64, access: Access| {
+        let a = mem.translate(a, access, &csrs, Xlen::Rv64, false)?;
+        Ok(mem.get_page(a))
+    };
+    let mut buf = 0x1122_3344_5566_7788u64.to_le_bytes();
+    transfer(&mut page, 0x9ffc, &mut buf, true, u64::MAX).unwrap();
+    let mut back = [0; 8];
+    transfer(&mut page, 0x9ffc, &mut back, false, u64::MAX).unwrap();
+    assert_eq!(u64::from_le_bytes(back), 0x1122_3344_5566_7788);
+    // The page after 0x5000 is user-only, so the store faults before writing
+    assert_eq!(
+        transfer(&mut page, 0x5ffc, &mut buf, true, u64::MAX),
+        Err(Trap::exception(Exception::StorePageFault, 0x6003))
+    );
+    assert_eq!(get(&mem, 0xdff8) >> 32, 0x5566_7788);
+    assert_eq!(get(&mem, 0xc000) as u32, 0x1122_3344);
+    assert_eq!(get(&mem, 0x8ff8), 0);
+
+    // The generated accessors split the same way, or raise when asked to
+    let js = |flags: Flags| {
+        CoreJS {
+            content: &"",
+            flate: &DebugFlate {},
+            regs: &RegCache::default(),
+            region: &Region::default(),
+            root: 0,
+            flags,
+        }
+        .to_string()
+    };
+    let split = js(Flags::default());
+    assert!(split.contains("if((p&0xfffn)>4096n-n)"), "{split}");
+    assert!(!split.contains("$.misaligned"));
+    let strict = js(Flags::default().with_trap_misaligned(true));
+    assert!(strict.contains("if(p&n-1n)$.misaligned(p,false);"), "{strict}");
+    assert!(strict.contains("if(p&n-1n)$.misaligned(p,true);"));
+}
+
+#[test]
+fn permissions() {
+    let (mut mem, mut csrs) = sv39();
+    let rv64 = Xlen::Rv64;
+    let mut check = |csrs: &CsrFile, a: u64, access: Access| {
+        mem.translate(a, access, csrs, rv64, false).is_ok()
+    };
+    // Supervisor code reaches user pages only for data, and only with SUM
+    assert!(!check(&csrs, 0x6000, Access::Read));
+    csrs.mstatus |= mstatus::SUM;
+    assert!(check(&csrs, 0x6000, Access::Read));
+    assert!(!check(&csrs, 0x6000, Access::Write));
+    assert!(!check(&csrs, 0x6000, Access::Execute));
+
+    // MXR makes execute-only pages readable
+    assert!(!check(&csrs, 0x7000, Access::Read));
+    csrs.mstatus |= mstatus::MXR;
+    assert!(check(&csrs, 0x7000, Access::Read));
+
+    csrs.mode = Priv::User;
+    assert!(check(&csrs, 0x6000, Access::Read));
+    assert!(!check(&csrs, 0x5000, Access::Read));
+
+    // Machine mode is untranslated, except for data under MPRV
+    csrs.mode = Priv::Machine;
+    assert!(check(&csrs, 0x4000_0000, Access::Read));
+    csrs.mstatus |= mstatus::MPRV | 1 << 11;
+    assert!(!check(&csrs, 0x4000_0000, Access::Read));
+    assert!(check(&csrs, 0x4000_0000, Access::Execute));
+}
+
+#[test]
+fn sv32() {
+    let mut mem = Mem::default();
+    let rv32 = Xlen::Rv32;
+    // 0x0040_3000 is vpn1 1, vpn0 3; 0x8000_0000 is a 4 MiB megapage
+    put(&mut mem, 0x1000 + 4, leaf(0x2000, 0), 4);
+    put(&mut mem, 0x2000 + 12, leaf(0x7000, pte::R), 4);
+    put(&mut mem, 0x1000 + 0x200 * 4, leaf(0x40_0000, pte::R | pte::X), 4);
+    let mut csrs = CsrFile::default();
+    csrs.write(SATP, 1 << 31 | 1, rv32).unwrap();
+    csrs.mode = Priv::Supervisor;
+    assert_eq!(mem.translate(0x0040_3abc, Access::Read, &csrs, rv32, false), Ok(0x7abc));
+    assert_eq!(get(&mem, 0x200c) & pte::A, pte::A);
+    assert_eq!(
+        mem.translate(0x8012_3456, Access::Execute, &csrs, rv32, false),
+        Ok(0x52_3456)
+    );
+    assert_eq!(
+        mem.translate(0x0040_3000, Access::Write, &csrs, rv32, false),
+        fault(Exception::StorePageFault, 0x0040_3000)
+    );
+}
+
+#[test]
+fn satp_modes() {
+    let mut file = CsrFile::default();
+    for (satp, mode) in [(8 << 60, SatpMode::Sv39), (9 << 60, SatpMode::Sv48), (10 << 60, SatpMode::Sv57)] {
+        assert_eq!(SatpMode::from_satp(satp, Xlen::Rv64), Some(mode));
+        file.write(SATP, satp | 5, Xlen::Rv64).unwrap();
+        assert_eq!(file.read(SATP, 0, Xlen::Rv64), Ok(satp | 5));
+    }
+    // Unsupported modes leave satp as it was
+    file.write(SATP, 1 << 60, Xlen::Rv64).unwrap();
+    assert_eq!(file.satp, 10 << 60 | 5);
+    assert_eq!(SatpMode::from_satp(1 << 31, Xlen::Rv32), Some(SatpMode::Sv32));
+
+    // TVM keeps satp and sfence.vma from supervisor mode
+    file.mode = Priv::Supervisor;
+    assert!(file.sfence_allowed());
+    file.mstatus |= mstatus::TVM;
+    assert_eq!(file.read(SATP, 0, Xlen::Rv64), Err(CsrError::Privileged(SATP)));
+    assert!(!file.sfence_allowed());
+    file.mode = Priv::User;
+    file.mstatus = 0;
+    assert!(!file.sfence_allowed());
+}
+
+#[test]
+fn delegation_and_sret() {
+    let mut file = CsrFile::default();
+    let rv64 = Xlen::Rv64;
+    file.write(MTVEC, 0x100, rv64).unwrap();
+    file.write(STVEC, 0x200, rv64).unwrap();
+    file.write(MEDELEG, u64::MAX, rv64).unwrap();
+    // Machine-mode ecalls can never be delegated
+    assert_eq!(file.medeleg, 0xb3ff);
+    file.write(SSTATUS, mstatus::SIE | mstatus::MIE, rv64).unwrap();
+    assert_eq!(file.mstatus, mstatus::SIE);
+
+    // Machine mode ignores delegation
+    let ecall = Trap::exception(Exception::EcallU, 0);
+    assert_eq!(file.trap(&ecall, 0x10, rv64), 0x100);
+    file.mode = Priv::User;
+    assert_eq!(file.trap(&ecall, 0x20, rv64), 0x200);
+    assert_eq!(file.mode, Priv::Supervisor);
+    assert_eq!((file.sepc, file.scause, file.stval), (0x20, 8, 0));
+    assert_eq!(file.mstatus & (mstatus::SIE | mstatus::SPIE | mstatus::SPP), mstatus::SPIE);
+    assert_eq!(file.read(SSTATUS, 0, rv64), Ok(2 << 32 | mstatus::SPIE));
+    assert_eq!(file.read(MSTATUS, 0, rv64), Err(CsrError::Privileged(MSTATUS)));
+
+    let fault = Trap::exception(Exception::LoadPageFault, 0xdead);
+    assert_eq!(file.trap(&fault, 0x200, rv64), 0x200);
+    assert_eq!(file.mstatus & mstatus::SPP, mstatus::SPP);
+    assert_eq!((file.sepc, file.stval), (0x200, 0xdead));
+
+    assert_eq!(file.sret(), Some(0x200));
+    assert_eq!(file.mode, Priv::Supervisor);
+    file.write(SEPC, 0x24, rv64).unwrap();
+    assert_eq!(file.sret(), Some(0x24));
+    assert_eq!(file.mode, Priv::User);
+    assert_eq!(file.sret(), None);
+
+    file.mode = Priv::Supervisor;
+    file.mstatus |= mstatus::TSR;
+    assert_eq!(file.sret(), None);
+}
+
+#[test]
+fn decode_sret_sfence() {
+    for (code, text) in [
+        (0x10200073, "sret"),
+        // sfence.vma a0, a1
+        (0x12b50073, "sfence.vma a0, a1"),
+        (0x12000073, "sfence.vma zero, zero"),
+    ] {
+        assert!(rv_asm::Inst::decode(code, Xlen::Rv64).is_err(), "{text}");
+        let (i, _) = ExtInst::decode(code, Xlen::Rv64).unwrap();
+        assert_eq!(i.to_string(), text);
+    }
+}
@@ -0,0 +1,164 @@
+//! The C extension: every compressed form against its 32-bit expansion.
+use alloc::string::{String, ToString};
+
+use rv_asm::{Inst, IsCompressed, Xlen};
+
+use crate::arch::Riscv;
+use crate::ext;
+use crate::flate::DebugFlate;
+use crate::rvc;
+use crate::template::{Flags, Labels, Params, RegCache, Region, TemplateJit};
+use crate::{Heat, Mem};
+
+/// `(compressed, expansion, llvm-mc syntax)`
+const RV64: &[(u32, u32, &str)] = &[
+    (0x1fe0, 0x3fc10413, "c.addi4spn s0, sp, 1020"),
+    (0x5fe8, 0x07c7a503, "c.lw a0, 124(a5)"),
+    (0x7fe8, 0x0f87b503, "c.ld a0, 248(a5)"),
+    (0xdfe8, 0x06a7ae23, "c.sw a0, 124(a5)"),
+    (0xffe8, 0x0ea7bc23, "c.sd a0, 248(a5)"),
+    (0x3fe8, 0x0f87b507, "c.fld fa0, 248(a5)"),
+    (0xa788, 0x00a7b427, "c.fsd fa0, 8(a5)"),
+    (0x0001, 0x00000013, "c.nop"),
+    (0x1501, 0xfe050513, "c.addi a0, -32"),
+    (0x357d, 0xfff5051b, "c.addiw a0, -1"),
+    (0x457d, 0x01f00513, "c.li a0, 31"),
+    (0x7101, 0xe0010113, "c.addi16sp sp, -512"),
+    (0x7505, 0xfffe1537, "c.lui a0, 0xfffe1"),
+    (0x917d, 0x03f55513, "c.srli a0, 63"),
+    (0x9505, 0x42155513, "c.srai a0, 33"),
+    (0x9931, 0xfec57513, "c.andi a0, -20"),
+    (0x8d1d, 0x40f50533, "c.sub a0, a5"),
+    (0x8d3d, 0x00f54533, "c.xor a0, a5"),
+    (0x8d5d, 0x00f56533, "c.or a0, a5"),
+    (0x8d7d, 0x00f57533, "c.and a0, a5"),
+    (0x9d1d, 0x40f5053b, "c.subw a0, a5"),
+    (0x9d3d, 0x00f5053b, "c.addw a0, a5"),
+    (0xb001, 0x801ff06f, "c.j -2048"),
+    (0xd101, 0xf00500e3, "c.beqz a0, -256"),
+    (0xed7d, 0x0e051f63, "c.bnez a0, 254"),
+    (0x157e, 0x03f51513, "c.slli a0, 63"),
+    (0x557e, 0x0fc12503, "c.lwsp a0, 252(sp)"),
+    (0x757e, 0x1f813503, "c.ldsp a0, 504(sp)"),
+    (0x357e, 0x1f813507, "c.fldsp fa0, 504(sp)"),
+    (0x8502, 0x00050067, "c.jr a0"),
+    (0x853e, 0x00f00533, "c.mv a0, a5"),
+    (0x9002, 0x00100073, "c.ebreak"),
+    (0x9502, 0x000500e7, "c.jalr a0"),
+    (0x953e, 0x00f50533, "c.add a0, a5"),
+    (0xdfaa, 0x0ea12e23, "c.swsp a0, 252(sp)"),
+    (0xffaa, 0x1ea13c23, "c.sdsp a0, 504(sp)"),
+    (0xa42a, 0x00a13427, "c.fsdsp fa0, 8(sp)"),
+];
+
+const RV32: &[(u32, u32, &str)] = &[
+    (0x2ffd, 0x7fe000ef, "c.jal 2046"),
+    (0x7fe8, 0x07c7a507, "c.flw fa0, 124(a5)"),
+    (0xe3c8, 0x00a7a227, "c.fsw fa0, 4(a5)"),
+    (0x757e, 0x0fc12507, "c.flwsp fa0, 252(sp)"),
+    (0xe22a, 0x00a12227, "c.fswsp fa0, 4(sp)"),
+    (0x817d, 0x01f55513, "c.srli a0, 31"),
+    (0x0506, 0x00151513, "c.slli a0, 1"),
+];
+
+fn expands(xlen: Xlen, table: &[(u32, u32, &str)]) {
+    for &(c, full, text) in table {
+        let (short, compressed) = ext::decode(c, xlen).unwrap_or_else(|_| panic!("{text}"));
+        let (long, _) = ext::decode(full, xlen).unwrap();
+        assert!(compressed == IsCompressed::Yes, "{text}");
+        assert!(short == long, "{text}");
+    }
+}
+
+#[test]
+fn every_instruction_rv64() {
+    expands(Xlen::Rv64, RV64);
+}
+
+#[test]
+fn every_instruction_rv32() {
+    expands(Xlen::Rv32, RV32);
+    // The forms the two widths share
+    let shared = RV64.iter().filter(|(_, _, t)| {
+        !["c.ld", "c.sd", "c.addiw", "c.subw", "c.addw", "c.ldsp", "c.sdsp", "c.srli a0, 63", "c.srai", "c.slli a0, 63"]
+            .iter()
+            .any(|p| t.starts_with(p))
+    });
+    expands(Xlen::Rv32, &shared.copied().collect::<alloc::vec::Vec<_>>());
+}
+
+#[test]
+fn reserved_encodings() {
+    for (c, xlen, what) in [
+        (0x0000, Xlen::Rv64, "all zeroes"),
+        (0x0004, Xlen::Rv64, "c.addi4spn with a zero immediate"),
+        (0x8000, Xlen::Rv64, "C0 funct3 100"),
+        (0x2001, Xlen::Rv64, "c.addiw x0"),
+        (0x6101, Xlen::Rv64, "c.addi16sp with a zero immediate"),
+        (0x6501, Xlen::Rv64, "c.lui with a zero immediate"),
+        (0x9d5d, Xlen::Rv64, "C1 arithmetic with bit 12 and funct2 10"),
+        (0x9d1d, Xlen::Rv32, "c.subw on RV32"),
+        (0x9001, Xlen::Rv32, "c.srli with shamt[5] on RV32"),
+        (0x1002, Xlen::Rv32, "c.slli with shamt[5] on RV32"),
+        (0x8002, Xlen::Rv64, "c.jr x0"),
+        (0x4002, Xlen::Rv64, "c.lwsp x0"),
+        (0x6002, Xlen::Rv64, "c.ldsp x0"),
+    ] {
+        assert!(ext::decode(c, xlen).is_err(), "{what}");
+    }
+    // ...which the other width accepts
+    assert!(rvc::decode(0x9001, Xlen::Rv64).is_ok());
+    assert!(matches!(rvc::decode(0x2001, Xlen::Rv32), Ok((Inst::Jal { .. }, _))));
+}
+
+/// Render the block at 0x1000 holding `code`, stopping at its successors
+fn render(code: u32, xlen: Xlen) -> String {
+    let mut mem = Mem::default();
+    for (i, b) in code.to_le_bytes().into_iter().enumerate() {
+        mem.write_byte(0x1000 + i as u64, b);
+    }
+    let trial = |pc| match pc {
+        0x1000 => Heat::New,
+        _ => Heat::Cached,
+    };
+    let jit = TemplateJit {
+        params: Params {
+            react: &mem,
+            trial: &trial,
+            flate: &DebugFlate {},
+            regs: &RegCache::default(),
+            region: &Region::default(),
+            root: 0x1000,
+            flags: Flags::default().with_xlen(xlen),
+        },
+        pc: 0x1000,
+        labels: &Labels::default(),
+        depth: 0,
+    };
+    Riscv(&jit).to_string()
+}
+
+#[test]
+fn jump_targets_are_byte_offsets() {
+    for (code, xlen, target) in [
+        // c.j -2048
+        (0xb001, Xlen::Rv64, 0x800),
+        // c.jal 2046
+        (0x2ffd, Xlen::Rv32, 0x17fe),
+        // c.beqz a0, -256 and c.bnez a0, 254
+        (0xd101, Xlen::Rv64, 0xf00),
+        (0xed7d, Xlen::Rv64, 0x10fe),
+        // jal zero, -2048 and beq a0, zero, -256
+        (0x801ff06f, Xlen::Rv64, 0x800),
+        (0xf00500e3, Xlen::Rv64, 0xf00),
+    ] {
+        let js = render(code, xlen);
+        let exit = alloc::format!("return links[{target}]??link({target}n)");
+        assert!(js.contains(&exit), "{code:#x}: {js}");
+    }
+    // The fall-through of a compressed branch is two bytes on
+    assert!(render(0xd101, Xlen::Rv64).contains("return links[4098]??link(4098n)"));
+    // Register jumps clear bit 0 within XLEN
+    assert!(render(0x9502, Xlen::Rv32).contains(&alloc::format!("&{}n", 0xffff_fffe_u32)));
+    assert!(render(0x8502, Xlen::Rv64).contains(&alloc::format!("&{}n", u64::MAX - 1)));
+}
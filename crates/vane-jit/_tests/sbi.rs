@@ -0,0 +1,89 @@
+use rv_asm::Xlen;
+
+use crate::clint::Clint;
+use crate::sbi::*;
+use crate::trap::Interrupt;
+
+fn call(eid: u64, fid: u64, args: &[u64], xlen: Xlen) -> Result<Call, i64> {
+    let mut a = [0; 6];
+    a[..args.len()].copy_from_slice(args);
+    Call::decode(eid, fid, a, xlen)
+}
+
+#[test]
+fn base_extension() {
+    let base = |fid, a0| call(eid::BASE, fid, &[a0], Xlen::Rv64);
+    assert_eq!(base(0, 0), Ok(Call::Base(SbiRet::ok(2 << 24))));
+    assert_eq!(base(1, 0), Ok(Call::Base(SbiRet::ok(IMPL_ID))));
+    for e in eid::ALL {
+        assert_eq!(base(3, e), Ok(Call::Base(SbiRet::ok(1))));
+    }
+    // The legacy console is not there
+    assert_eq!(base(3, 1), Ok(Call::Base(SbiRet::ok(0))));
+    assert_eq!(base(4, 0), Ok(Call::Base(SbiRet::ok(0))));
+    assert_eq!(base(7, 0), Ok(Call::Base(SbiRet::err(error::NOT_SUPPORTED))));
+    assert_eq!(call(1, 0, &[b'x' as u64], Xlen::Rv64), Err(error::NOT_SUPPORTED));
+    assert_eq!(call(eid::TIME, 1, &[], Xlen::Rv64), Err(error::NOT_SUPPORTED));
+}
+
+#[test]
+fn decode_calls() {
+    // 64-bit arguments take a register pair on RV32
+    assert_eq!(call(eid::TIME, 0, &[0x1_2345, 7], Xlen::Rv64), Ok(Call::SetTimer(0x1_2345)));
+    assert_eq!(call(eid::TIME, 0, &[0x2345, 1], Xlen::Rv32), Ok(Call::SetTimer(0x1_0000_2345)));
+    assert_eq!(
+        call(eid::DBCN, 0, &[3, 0x8000_0000, 1], Xlen::Rv32),
+        Ok(Call::ConsoleWrite {
+            len: 3,
+            addr: 0x1_8000_0000
+        })
+    );
+    assert_eq!(call(eid::DBCN, 2, &[0x141], Xlen::Rv64), Ok(Call::ConsoleWriteByte(b'A')));
+
+    // Hart masks: a base of -1 means all harts, and only hart 0 exists
+    assert_eq!(call(eid::IPI, 0, &[0, u64::MAX], Xlen::Rv64), Ok(Call::SendIpi(true)));
+    assert_eq!(call(eid::IPI, 0, &[0, 0xffff_ffff], Xlen::Rv32), Ok(Call::SendIpi(true)));
+    assert_eq!(call(eid::IPI, 0, &[1, 0], Xlen::Rv64), Ok(Call::SendIpi(true)));
+    assert_eq!(call(eid::IPI, 0, &[0, 0], Xlen::Rv64), Ok(Call::SendIpi(false)));
+    assert_eq!(call(eid::IPI, 0, &[2, 0], Xlen::Rv64), Err(error::INVALID_PARAM));
+    assert_eq!(call(eid::RFENCE, 0, &[1, 1], Xlen::Rv64), Err(error::INVALID_PARAM));
+    assert_eq!(call(eid::RFENCE, 2, &[1, 0, 0, 0x1000, 7], Xlen::Rv64), Ok(Call::SfenceVma));
+    assert_eq!(call(eid::RFENCE, 3, &[1, 0], Xlen::Rv64), Err(error::NOT_SUPPORTED));
+
+    assert_eq!(call(eid::HSM, 0, &[0, 0x8020_0000], Xlen::Rv64), Err(error::ALREADY_AVAILABLE));
+    assert_eq!(call(eid::HSM, 0, &[1, 0x8020_0000], Xlen::Rv64), Err(error::INVALID_PARAM));
+    assert_eq!(call(eid::HSM, 2, &[0], Xlen::Rv64), Ok(Call::HartStatus(hart_state::STARTED)));
+    assert_eq!(
+        call(eid::HSM, 3, &[suspend::NON_RETENTIVE as u64, 0x8020_0000, 5], Xlen::Rv64),
+        Ok(Call::HartSuspend {
+            kind: suspend::NON_RETENTIVE,
+            resume: 0x8020_0000,
+            opaque: 5
+        })
+    );
+    assert_eq!(call(eid::HSM, 3, &[1], Xlen::Rv64), Err(error::INVALID_PARAM));
+    assert_eq!(
+        call(eid::SRST, 0, &[reset::COLD_REBOOT as u64, 1], Xlen::Rv64),
+        Ok(Call::SystemReset {
+            kind: reset::COLD_REBOOT,
+            reason: 1
+        })
+    );
+    assert_eq!(call(eid::SRST, 0, &[3, 0], Xlen::Rv64), Err(error::INVALID_PARAM));
+}
+
+#[test]
+fn supervisor_timer() {
+    let mut c = Clint {
+        mtimecmp: 500,
+        stimecmp: 200,
+        ..Clint::default()
+    };
+    // The earlier of the two timers sets the deadline
+    assert_eq!(c.due(100), 200);
+    assert_eq!(c.pending(200), Interrupt::SupervisorTimer.bit());
+    assert_eq!(c.due(200), 500);
+    c.stimecmp = u64::MAX;
+    assert_eq!(c.pending(200), 0);
+    assert_eq!(c.due(200), 500);
+}
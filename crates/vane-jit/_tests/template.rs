@@ -0,0 +1,197 @@
+use alloc::string::ToString;
+use alloc::vec::Vec;
+
+use crate::arch::Riscv;
+use crate::flate::DebugFlate;
+use crate::template::wasm::BlockCtx;
+use crate::template::{CoreJS, Flags, Labels, Params, RegCache, Region, TemplateJit};
+use crate::{Heat, JitOpcode, Mem, WasmJit};
+
+/// `code` at `0x1000`
+fn load(code: &[u32]) -> Mem {
+    let mut mem = Mem::default();
+    for (i, b) in code.iter().flat_map(|c| c.to_le_bytes()).enumerate() {
+        mem.write_byte(0x1000 + i as u64, b);
+    }
+    mem
+}
+
+/// Four `addi a0, a0, 1` and an `ecall`
+fn straight_line() -> Mem {
+    load(&[
+        0x0015_0513,
+        0x0015_0513,
+        0x0015_0513,
+        0x0015_0513,
+        0x0000_0073,
+    ])
+}
+
+/// A block at `0x1000` that has nothing cached, counting against `region`
+fn jit<'a>(
+    mem: &'a Mem,
+    regs: &'a RegCache,
+    region: &'a Region,
+    labels: &'a Labels<'a>,
+    flags: Flags,
+) -> TemplateJit<'a> {
+    fn trial(_: u64) -> Heat {
+        Heat::New
+    }
+    TemplateJit {
+        params: Params {
+            react: mem,
+            trial: &trial,
+            flate: &DebugFlate {},
+            regs,
+            region,
+            root: 0x1000,
+            flags,
+        },
+        pc: 0x1000,
+        labels,
+        depth: 0,
+    }
+}
+
+#[test]
+fn registers_live_in_locals() {
+    // add a0, a0, a1; ecall
+    let (mem, regs, labels) = (
+        load(&[0x00b5_0533, 0x0000_0073]),
+        RegCache::default(),
+        Labels::default(),
+    );
+    let region = Region::default();
+    let js = CoreJS {
+        content: &Riscv(&jit(&mem, &regs, &region, &labels, Flags::default())),
+        flate: &DebugFlate {},
+        regs: &regs,
+        region: &region,
+        root: 0x1000,
+        flags: Flags::default(),
+    }
+    .to_string();
+    assert_eq!((regs.used(), regs.dirty()), (3 << 10, 1 << 10));
+    // Both are loaded at entry, but only `a0` is stored back, before the trap
+    assert!(
+        js.contains("let p,x10=$._r.x10??0n,x11=$._r.x11??0n;"),
+        "{js}"
+    );
+    assert!(js.contains("const sync=()=>{$._r.x10=x10;};"), "{js}");
+    assert!(js.contains("sync();return $.ecall(4100n);"), "{js}");
+    assert!(js.contains("catch(e){sync();"), "{js}");
+}
+
+#[test]
+fn regions_stop_at_the_budget() {
+    let (mem, regs, labels) = (straight_line(), RegCache::default(), Labels::default());
+
+    // Two instructions, then a jump to the third
+    let region = Region::default();
+    let flags = Flags::default().with_region_budget(Some(2), None);
+    let js = Riscv(&jit(&mem, &regs, &region, &labels, flags)).to_string();
+    assert_eq!(region.insts(), 2);
+    assert!(js.contains("x4100: for(;;)"), "{js}");
+    assert!(
+        js.contains("sync();return links[4104]??link(4104n);"),
+        "{js}"
+    );
+    assert!(!js.contains("x4104: for(;;)"), "{js}");
+
+    // The root is compiled however small the byte budget
+    let region = Region::default();
+    let flags = Flags::default().with_region_budget(None, Some(1));
+    let js = CoreJS {
+        content: &Riscv(&jit(&mem, &regs, &region, &labels, flags)),
+        flate: &DebugFlate {},
+        regs: &regs,
+        region: &region,
+        root: 0x1000,
+        flags,
+    }
+    .to_string();
+    assert_eq!(region.insts(), 1);
+    assert!(region.bytes() > 0);
+    assert!(js.contains("x4096: for(;;)"), "{js}");
+    // Exits link to the block they go to once they have looked it up
+    assert!(
+        js.contains("link=p=>{const b=J(p);if(hold(links,p,b,4096n))links[p]=b;return b}"),
+        "{js}"
+    );
+    // Each holder is filed under the block that owns it
+    assert!(js.contains("[p]??=new Map).set(t,o)"), "{js}");
+    assert!(js.contains("[o]??=new Set).add(p)"), "{js}");
+    assert!(
+        js.contains("sync();return links[4100]??link(4100n);"),
+        "{js}"
+    );
+
+    // WebAssembly blocks count instructions the same way
+    let region = Region::default();
+    let flags = Flags::default().with_region_budget(Some(3), None);
+    let block = jit(&mem, &regs, &region, &labels, flags);
+    let ctx = BlockCtx::default();
+    let loops: Vec<_> = Riscv(&block)
+        .jit(&ctx)
+        .filter_map(|o| match o {
+            JitOpcode::Loop { label } => Some(label),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(loops, [0x1000, 0x1004, 0x1008]);
+}
+
+#[test]
+fn indirect_jumps_chain_through_caches() {
+    let (regs, region, labels) = (RegCache::default(), Region::default(), Labels::default());
+    let render = |code| {
+        let mem = load(&[code]);
+        Riscv(&jit(&mem, &regs, &region, &labels, Flags::default())).to_string()
+    };
+
+    // jalr ra, 0(a0): a call pushes its return address
+    let js = render(0x0005_00e7);
+    assert!(js.contains("($._s??=[]).push([4100n,site(4100n)])"), "{js}");
+    assert!(js.contains("return chain(site(4096n),v)"), "{js}");
+
+    // ret: a return tries the prediction first
+    let js = render(0x0000_8067);
+    assert!(js.contains("const e=$._s?.pop();"), "{js}");
+    assert!(
+        js.contains("return chain(e?.[0]===v?e[1]:site(4096n),v)"),
+        "{js}"
+    );
+    assert!(!js.contains("push"), "{js}");
+
+    // jalr zero, 0(a0): neither
+    let js = render(0x0005_0067);
+    assert!(!js.contains("$._s"), "{js}");
+
+    // Caches are kept by site, and a target missing from one is looked up
+    let js = CoreJS {
+        content: &Riscv(&jit(
+            &load(&[0x0000_8067]),
+            &regs,
+            &region,
+            &labels,
+            Flags::default(),
+        )),
+        flate: &DebugFlate {},
+        regs: &regs,
+        region: &region,
+        root: 0x1000,
+        flags: Flags::default(),
+    }
+    .to_string();
+    assert!(
+        js.contains(
+            "cache=new Map,site=p=>cache.get(p)??cache.set(p,Object.assign([],{o:4096n})).get(p)"
+        ),
+        "{js}"
+    );
+    assert!(
+        js.contains("const b=J(v);if(c.length<8&&hold(c,v,b,c.o))c.push(v,b);return b"),
+        "{js}"
+    );
+}
@@ -0,0 +1,147 @@
+use alloc::string::ToString;
+use rv_asm::Xlen;
+
+use crate::arch::Riscv;
+use crate::csr::*;
+use crate::ext::ExtInst;
+use crate::flate::DebugFlate;
+use crate::template::{Flags, Labels, Params, RegCache, Region, TemplateJit};
+use crate::trap::*;
+use crate::{Heat, Mem};
+
+#[test]
+fn decode_mret_wfi() {
+    for (code, text) in [(0x30200073, "mret"), (0x10500073, "wfi")] {
+        assert!(rv_asm::Inst::decode(code, Xlen::Rv64).is_err(), "{text}");
+        let (i, _) = ExtInst::decode(code, Xlen::Rv32).unwrap();
+        assert_eq!(i.to_string(), text);
+    }
+}
+
+#[test]
+fn trap_values() {
+    // Compressed encodings report 16 bits
+    assert_eq!(Trap::illegal(0xdead_0000).tval, 0);
+    assert_eq!(Trap::illegal(0xffff_ffff).tval, 0xffff_ffff);
+    let t = Trap::exception(Exception::ecall(Priv::User), 0);
+    assert_eq!(t.as_exception(), Some(Exception::EcallU));
+    assert!(t.as_exception().unwrap().is_ecall());
+    let irq = Trap {
+        code: 7,
+        interrupt: true,
+        tval: 0,
+    };
+    assert_eq!(irq.cause(Xlen::Rv32), 0x8000_0007);
+    assert_eq!(irq.cause(Xlen::Rv64), 0x8000_0000_0000_0007);
+    assert_eq!(irq.as_exception(), None);
+    assert_eq!(
+        Trap::exception(Exception::LoadMisaligned, 0x1002).to_string(),
+        "LoadMisaligned (tval 0x1002)"
+    );
+}
+
+#[test]
+fn ebreak_stops_for_the_host() {
+    assert_eq!(BreakAction::from_name("step"), Some(BreakAction::Step));
+    assert_eq!(BreakAction::from_name("resume"), Some(BreakAction::Resume));
+    assert_eq!(BreakAction::from_name("abort"), Some(BreakAction::Abort));
+    assert_eq!(BreakAction::from_name("continue"), None);
+    // `ebreak` and `c.ebreak` end the block, naming the pc to resume at
+    for (code, next) in [(0x0010_0073u32, 0x1004), (0x9002, 0x1002)] {
+        let mut mem = Mem::default();
+        for (i, b) in code.to_le_bytes().into_iter().enumerate() {
+            mem.write_byte(0x1000 + i as u64, b);
+        }
+        let jit = TemplateJit {
+            params: Params {
+                react: &mem,
+                trial: &|_| Heat::New,
+                flate: &DebugFlate {},
+                regs: &RegCache::default(),
+                region: &Region::default(),
+                root: 0x1000,
+                flags: Flags::default(),
+            },
+            pc: 0x1000,
+            labels: &Labels::default(),
+            depth: 0,
+        };
+        let js = Riscv(&jit).to_string();
+        assert!(js.contains(&alloc::format!("return $.ebreak(4096n,{next}n);")), "{js}");
+    }
+}
+
+#[test]
+fn trap_and_mret() {
+    let mut file = CsrFile::default();
+    file.write(MTVEC, 0x8000_0100, Xlen::Rv64).unwrap();
+    file.write(MEPC, 0x4000, Xlen::Rv64).unwrap();
+    // Drop to user mode with interrupts enabled on return
+    file.write(MSTATUS, mstatus::MPIE, Xlen::Rv64).unwrap();
+    assert_eq!(file.mret(), Some(0x4000));
+    assert_eq!(file.mode, Priv::User);
+    assert_eq!(file.mstatus & (mstatus::MIE | mstatus::MPIE), mstatus::MIE | mstatus::MPIE);
+
+    let t = Trap::exception(Exception::EcallU, 0);
+    assert_eq!(file.trap(&t, 0x4010, Xlen::Rv64), 0x8000_0100);
+    assert_eq!(file.mode, Priv::Machine);
+    assert_eq!((file.mepc, file.mcause, file.mtval), (0x4010, 8, 0));
+    assert_eq!(file.mstatus & mstatus::MIE, 0);
+    assert_eq!(file.mstatus & mstatus::MPIE, mstatus::MPIE);
+    assert_eq!(file.mstatus & mstatus::MPP, 0);
+
+    // Vectored mode only offsets interrupts
+    file.write(MTVEC, 0x8000_0101, Xlen::Rv64).unwrap();
+    let irq = Trap {
+        code: 7,
+        interrupt: true,
+        tval: 0,
+    };
+    assert_eq!(file.trap(&irq, 0x4020, Xlen::Rv32), 0x8000_011c);
+    assert_eq!(file.mcause, 0x8000_0007);
+    assert_eq!(file.mstatus & mstatus::MPP, mstatus::MPP);
+    assert_eq!(file.trap(&t, 0x4020, Xlen::Rv32), 0x8000_0100);
+}
+
+#[test]
+fn privilege() {
+    let mut file = CsrFile::default();
+    assert_eq!(file.mret(), Some(0));
+    assert_eq!(file.mode, Priv::User);
+    assert_eq!(file.read(MSTATUS, 0, Xlen::Rv64), Err(CsrError::Privileged(MSTATUS)));
+    assert_eq!(file.write(MEPC, 0, Xlen::Rv64), Err(CsrError::Privileged(MEPC)));
+    assert_eq!(file.read(INSTRET, 5, Xlen::Rv64), Ok(5));
+    assert_eq!(file.read(FCSR, 0, Xlen::Rv64), Ok(0));
+    assert_eq!(file.mret(), None);
+}
+
+#[test]
+fn warl_fields() {
+    let mut file = CsrFile::default();
+    // MPP has no encoding 2 here
+    file.write(MSTATUS, 2 << 11 | mstatus::MIE, Xlen::Rv64).unwrap();
+    assert_eq!(file.mstatus, mstatus::MIE);
+    // SD summarises a dirty FS; UXL is fixed at 64 bits
+    file.write(MSTATUS, mstatus::FS, Xlen::Rv64).unwrap();
+    assert_eq!(
+        file.read(MSTATUS, 0, Xlen::Rv64),
+        Ok(1 << 63 | 2 << 32 | mstatus::FS)
+    );
+    assert_eq!(file.read(MSTATUS, 0, Xlen::Rv32), Ok(1 << 31 | mstatus::FS));
+    file.write(MTVEC, 0x1003, Xlen::Rv64).unwrap();
+    assert_eq!(file.mtvec, 0x1001);
+    file.write(MEPC, 0x1003, Xlen::Rv64).unwrap();
+    assert_eq!(file.mepc, 0x1002);
+    file.write(MIE, u64::MAX, Xlen::Rv64).unwrap();
+    assert_eq!(file.mie, 0xaaa);
+
+    let misa = file.read(MISA, 0, Xlen::Rv64).unwrap();
+    assert_eq!(misa >> 62, 2);
+    for c in b"IMAFDCUB" {
+        assert_ne!(misa & 1 << (c - b'A'), 0, "{}", *c as char);
+    }
+    file.write(MISA, 0, Xlen::Rv64).unwrap();
+    assert_eq!(file.read(MISA, 0, Xlen::Rv32).unwrap() >> 30, 1);
+    assert_eq!(file.read(MHARTID, 0, Xlen::Rv64), Ok(0));
+    assert_eq!(file.write(MHARTID, 1, Xlen::Rv64), Err(CsrError::ReadOnly(MHARTID)));
+}
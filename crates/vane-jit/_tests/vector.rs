@@ -0,0 +1,273 @@
+//! Vector configuration, decoding and known-answer tests for [`crate::vector`].
+use alloc::string::ToString;
+use alloc::vec::Vec;
+use rv_asm::Xlen;
+
+use crate::csr::*;
+use crate::ext::ExtInst;
+use crate::mmu::Access;
+use crate::trap::{Exception, Trap};
+use crate::vector::*;
+use crate::Mem;
+
+const E32_M1: u32 = 0xcd027057; // vsetivli zero, 4, e32, m1, ta, ma
+
+/// Run `code` with guest memory from `mem`; pages from `fault` up trap
+fn run_mem(v: &mut Vector, mem: &mut Mem, fault: u64, code: u32, a: u64, b: u64) -> Result<u64, Trap> {
+    let Some((ExtInst::Vector(i), _)) = ExtInst::decode(code, Xlen::Rv64) else {
+        panic!("{code:#x} is not a vector instruction");
+    };
+    exec(i, code, v, a, b, Xlen::Rv64, &mut |addr, access| match addr >= fault {
+        true => Err(Trap::exception(access.page_fault(), addr)),
+        false => Ok(mem.get_page(addr)),
+    })
+}
+
+fn run(v: &mut Vector, code: u32, a: u64, b: u64) -> Result<u64, Trap> {
+    run_mem(v, &mut Mem::default(), u64::MAX, code, a, b)
+}
+
+fn set(v: &mut Vector, reg: u8, elems: &[u32]) {
+    let bytes: Vec<u8> = elems.iter().flat_map(|e| e.to_le_bytes()).collect();
+    v.reg_mut(reg)[..bytes.len()].copy_from_slice(&bytes);
+}
+
+fn get(v: &Vector, reg: u8) -> [u32; 4] {
+    core::array::from_fn(|i| u32::from_le_bytes(v.reg(reg)[4 * i..][..4].try_into().unwrap()))
+}
+
+#[test]
+fn vtype_and_vsetvl() {
+    let t = VType::decode(0xd0).unwrap();
+    assert_eq!(t.to_string(), "e32, m1, ta, ma");
+    assert_eq!(t.vlmax(128), 4);
+    assert_eq!(VType::decode(0x07).unwrap().vlmax(256), 16);
+    // LMUL 4 is reserved, as is SEW 64 at mf8
+    assert_eq!(VType::decode(0x04), None);
+    assert_eq!(VType::decode(0x1d), None);
+    assert_eq!(VType::decode(0x100), None);
+
+    let mut v = Vector::default();
+    assert_eq!(v.read_csr(VTYPE, Xlen::Rv64), Some(1 << 63));
+    assert_eq!(v.read_csr(VTYPE, Xlen::Rv32), Some(1 << 31));
+    assert_eq!(v.read_csr(VLENB, Xlen::Rv64), Some(16));
+    // vsetvli a0, a1, e32, m1, ta, ma
+    assert_eq!(run(&mut v, 0x0d05f557, 10, 0), Ok(4));
+    assert_eq!(v.read_csr(VTYPE, Xlen::Rv64), Some(0xd0));
+    // vsetivli t0, 5, e8, mf2, tu, mu
+    assert_eq!(run(&mut v, 0xc072f2d7, 0, 0), Ok(5));
+    // vsetvli a0, zero, e8, m8 asks for VLMAX
+    assert_eq!(run(&mut v, 0x0c307557, 0, 0), Ok(128));
+    // vsetvl a0, a1, a2 with a reserved vtype
+    assert_eq!(run(&mut v, 0x80c5f557, 3, 0x04), Ok(0));
+    assert_eq!(v.vtype(), None);
+    // vadd.vv v1, v2, v3 is illegal while vill is set
+    assert_eq!(run(&mut v, 0x022180d7, 0, 0), Err(Trap::illegal(0x022180d7)));
+
+    assert_eq!(v.write_csr(VL, 1), Err(CsrError::ReadOnly(VL)));
+    v.write_csr(VCSR, 0b101).unwrap();
+    assert_eq!((v.vxrm, v.vxsat), (2, true));
+    assert_eq!(v.read_csr(VXRM, Xlen::Rv64), Some(2));
+    v.write_csr(VSTART, u64::MAX).unwrap();
+    assert_eq!(v.vstart, 127);
+}
+
+#[test]
+fn decode_and_display() {
+    for (code, text) in [
+        (0x0d05f557, "vsetvli a0, a1, e32, m1, ta, ma"),
+        (0xc072f2d7, "vsetivli t0, 5, e8, mf2, tu, mu"),
+        (0x80c5f557, "vsetvl a0, a1, a2"),
+        (0x022180d7, "vadd.vv v1, v2, v3"),
+        (0x00854257, "vadd.vx v4, v8, a0, v0.t"),
+        (0x028eb257, "vadd.vi v4, v8, -3"),
+        (0x964fb157, "vsll.vi v2, v4, 31"),
+        (0x62834057, "vmseq.vx v0, v8, t1"),
+        (0x40430157, "vadc.vvm v2, v4, v6, v0"),
+        (0x5c43b157, "vmerge.vim v2, v4, 7, v0"),
+        (0x5e054457, "vmv.v.x v8, a0"),
+        (0x42802557, "vmv.x.s a0, v8"),
+        (0x42056457, "vmv.s.x v8, a0"),
+        (0x0221a0d7, "vredsum.vs v1, v2, v3"),
+        (0xd2432157, "vwaddu.wv v2, v4, v6"),
+        (0xbe41b157, "vnclip.wi v2, v4, 3"),
+        (0xb6456157, "vmacc.vx v2, a0, v4"),
+        (0x40082557, "vcpop.m a0, v0, v0.t"),
+        (0x4228a557, "vfirst.m a0, v2"),
+        (0x5208a257, "vid.v v4"),
+        (0x4a432157, "vzext.vf2 v2, v4"),
+        (0x4a42a157, "vsext.vf4 v2, v4"),
+        (0x6621a0d7, "vmand.mm v1, v2, v3"),
+        (0x5e21a0d7, "vcompress.vm v1, v2, v3"),
+        (0x3a41b157, "vslideup.vi v2, v4, 3"),
+        (0x3e45e157, "vslide1down.vx v2, v4, a1"),
+        (0x3a430157, "vrgatherei16.vv v2, v4, v6"),
+        (0x9e40b157, "vmv2r.v v2, v4"),
+        (0x02056087, "vle32.v v1, (a0)"),
+        (0x01050087, "vle8ff.v v1, (a0), v0.t"),
+        (0x42055087, "vlseg3e16.v v1, (a0)"),
+        (0x0ab57107, "vlse64.v v2, (a0), a1"),
+        (0x06256087, "vluxei32.v v1, (a0), v2"),
+        (0x0e4581a7, "vsoxei8.v v3, (a1), v4"),
+        (0x02b50007, "vlm.v v0, (a0)"),
+        (0x02b50027, "vsm.v v0, (a0)"),
+        (0x22856107, "vl2re32.v v2, (a0)"),
+        (0x62850227, "vs4r.v v4, (a0)"),
+        (0x0a5500a7, "vsse8.v v1, (a0), t0"),
+    ] {
+        assert!(rv_asm::Inst::decode(code, Xlen::Rv64).is_err(), "{text}");
+        let (i, _) = ExtInst::decode(code, Xlen::Rv64).unwrap();
+        assert_eq!(i.to_string(), text);
+    }
+    // flw and fld still decode as scalar loads
+    assert_eq!(ExtInst::decode(0x0005a507, Xlen::Rv64).unwrap().0.to_string(), "flw f10, 0(a1)");
+    // OPFVV is not implemented
+    assert!(ExtInst::decode(0x022110d7, Xlen::Rv64).is_none());
+}
+
+#[test]
+fn integer_arithmetic() {
+    let mut v = Vector::default();
+    run(&mut v, E32_M1, 0, 0).unwrap();
+    set(&mut v, 2, &[1, 2, 3, u32::MAX]);
+    set(&mut v, 3, &[10, 20, 30, 1]);
+    // vadd.vv v1, v2, v3
+    run(&mut v, 0x022180d7, 0, 0).unwrap();
+    assert_eq!(get(&v, 1), [11, 22, 33, 0]);
+    // vadd.vx v4, v8, a0, v0.t leaves inactive elements alone
+    set(&mut v, 0, &[0b0101]);
+    set(&mut v, 4, &[7; 4]);
+    set(&mut v, 8, &[100, 200, 300, 400]);
+    run(&mut v, 0x00854257, (-1i64) as u64, 0).unwrap();
+    assert_eq!(get(&v, 4), [99, 7, 299, 7]);
+    // vsaddu.vx v1, v2, a0 saturates and sets vxsat
+    run(&mut v, 0x822540d7, 2, 0).unwrap();
+    assert_eq!(get(&v, 1), [3, 4, 5, u32::MAX]);
+    assert!(v.vxsat);
+    // vdiv.vv v1, v2, v3 by zero gives all ones
+    set(&mut v, 3, &[0, 2, (-1i32) as u32, 1]);
+    run(&mut v, 0x8621a0d7, 0, 0).unwrap();
+    assert_eq!(get(&v, 1), [u32::MAX, 1, (-3i32) as u32, u32::MAX]);
+    // vsmul.vv v1, v2, v3 in Q31: -1 * -1 saturates
+    set(&mut v, 2, &[1 << 30, i32::MIN as u32, 0, 0]);
+    set(&mut v, 3, &[1 << 30, i32::MIN as u32, 0, 0]);
+    v.vxsat = false;
+    run(&mut v, 0x9e2180d7, 0, 0).unwrap();
+    assert_eq!(get(&v, 1)[..2], [1 << 29, i32::MAX as u32]);
+    assert!(v.vxsat);
+    // vredsum.vs v1, v2, v3 only writes element 0
+    set(&mut v, 2, &[1, 2, 3, 4]);
+    set(&mut v, 3, &[5, 0, 0, 0]);
+    run(&mut v, 0x0221a0d7, 0, 0).unwrap();
+    assert_eq!(get(&v, 1), [15, i32::MAX as u32, 0, 0]);
+
+    // vwmul.vx v2, v4, a0 writes a two-register group of 64-bit products
+    set(&mut v, 4, &[3, (-2i32) as u32, 0x8000_0000, 1]);
+    run(&mut v, 0xee456157, (-3i64) as u64, 0).unwrap();
+    let wide: Vec<u64> = (v.reg(2).chunks(8).chain(v.reg(3).chunks(8)))
+        .map(|c| u64::from_le_bytes(c.try_into().unwrap()))
+        .collect();
+    assert_eq!(wide, [(-9i64) as u64, 6, 0x1_8000_0000, (-3i64) as u64]);
+    // Odd destination groups are illegal
+    assert_eq!(run(&mut v, 0xee4560d7, 0, 0), Err(Trap::illegal(0xee4560d7)));
+
+    // vnclip.wi v2, v4, 3 at e16 rounds to nearest-up and saturates
+    run(&mut v, 0xcc81f057, 0, 0).unwrap();
+    set(&mut v, 4, &[100, i32::MAX as u32, (-9i32) as u32, 0]);
+    v.vxrm = 0;
+    v.vxsat = false;
+    run(&mut v, 0xbe41b157, 0, 0).unwrap();
+    let narrow: Vec<u16> = v.reg(2)[..6].chunks(2).map(|c| u16::from_le_bytes([c[0], c[1]])).collect();
+    assert_eq!(narrow, [13, i16::MAX as u16, (-1i16) as u16]);
+    assert!(v.vxsat);
+}
+
+#[test]
+fn masks_and_permutations() {
+    let mut v = Vector::default();
+    run(&mut v, E32_M1, 0, 0).unwrap();
+    // vid.v v4
+    run(&mut v, 0x5208a257, 0, 0).unwrap();
+    assert_eq!(get(&v, 4), [0, 1, 2, 3]);
+    // vmseq.vx v0, v8, t1
+    set(&mut v, 8, &[5, 6, 5, 5]);
+    run(&mut v, 0x62834057, 5, 0).unwrap();
+    assert_eq!(v.reg(0)[0] & 0xf, 0b1101);
+    // vcpop.m a0, v0, v0.t and vfirst.m a0, v2
+    assert_eq!(run(&mut v, 0x40082557, 0, 0), Ok(3));
+    set(&mut v, 2, &[0b1000]);
+    assert_eq!(run(&mut v, 0x4228a557, 0, 0), Ok(3));
+    set(&mut v, 2, &[0]);
+    assert_eq!(run(&mut v, 0x4228a557, 0, 0), Ok(u64::MAX));
+    // vmand.mm v1, v2, v3
+    set(&mut v, 2, &[0b0110]);
+    set(&mut v, 3, &[0b1100]);
+    set(&mut v, 1, &[0]);
+    run(&mut v, 0x6621a0d7, 0, 0).unwrap();
+    assert_eq!(v.reg(1)[0], 0b0100);
+
+    // vcompress.vm v1, v2, v3 packs the selected elements
+    set(&mut v, 1, &[9; 4]);
+    set(&mut v, 2, &[10, 11, 12, 13]);
+    set(&mut v, 3, &[0b1010]);
+    run(&mut v, 0x5e21a0d7, 0, 0).unwrap();
+    assert_eq!(get(&v, 1), [11, 13, 9, 9]);
+    // vslideup.vi v2, v4, 3
+    run(&mut v, 0x3a41b157, 0, 0).unwrap();
+    assert_eq!(get(&v, 2), [10, 11, 12, 0]);
+    // vslide1down.vx v2, v4, a1
+    run(&mut v, 0x3e45e157, 42, 0).unwrap();
+    assert_eq!(get(&v, 2), [1, 2, 3, 42]);
+    // vmv.x.s a0, v8 sign-extends
+    set(&mut v, 8, &[0x8000_0000]);
+    assert_eq!(run(&mut v, 0x42802557, 0, 0), Ok(0xffff_ffff_8000_0000));
+    // vmv2r.v v2, v4 ignores vtype and vl
+    run(&mut v, 0xcd817057, 0, 0).unwrap();
+    v.reg_mut(5).fill(0xab);
+    run(&mut v, 0x9e40b157, 0, 0).unwrap();
+    assert_eq!(v.reg(3), v.reg(5));
+}
+
+#[test]
+fn loads_and_stores() {
+    let mut v = Vector::default();
+    let mut mem = Mem::default();
+    for a in 0..0x40 {
+        mem.write_byte(0x2fe0 + a, a as u8);
+    }
+    run(&mut v, E32_M1, 0, 0).unwrap();
+    // vle32.v v1, (a0)
+    run_mem(&mut v, &mut mem, u64::MAX, 0x02056087, 0x2fe0, 0).unwrap();
+    assert_eq!(get(&v, 1), [0x03020100, 0x07060504, 0x0b0a0908, 0x0f0e0d0c]);
+    // vlse64.v v2, (a0), a1 at e64 with a negative stride
+    run(&mut v, 0xcd817057, 0, 0).unwrap();
+    run_mem(&mut v, &mut mem, u64::MAX, 0x0ab57107, 0x2ff0, (-16i64) as u64).unwrap();
+    assert_eq!(get(&v, 2), [0x13121110, 0x17161514, 0x03020100, 0x07060504]);
+    // vluxei32.v v1, (a0), v2 at e32, with byte offsets from v2
+    run(&mut v, E32_M1, 0, 0).unwrap();
+    set(&mut v, 2, &[0, 4, 0x20, 1]);
+    run_mem(&mut v, &mut mem, u64::MAX, 0x06256087, 0x2fe0, 0).unwrap();
+    assert_eq!(get(&v, 1), [0x03020100, 0x07060504, 0x23222120, 0x04030201]);
+    // vsse8.v v1, (a0), t0 at e8
+    run(&mut v, 0xcc087057, 0, 0).unwrap();
+    run_mem(&mut v, &mut mem, u64::MAX, 0x0a5500a7, 0x1000, 2).unwrap();
+    assert_eq!((mem.read_byte(0x1000), mem.read_byte(0x1002), mem.read_byte(0x1001)), (0, 1, 0));
+    assert_eq!(mem.read_byte(0x101e), 0x04);
+
+    // A fault part way leaves vstart at the faulting element
+    run(&mut v, E32_M1, 0, 0).unwrap();
+    let r = run_mem(&mut v, &mut mem, 0x3000, 0x02056087, 0x2ff8, 0);
+    assert_eq!(r, Err(Trap::exception(Exception::LoadPageFault, 0x3000)));
+    assert_eq!(v.vstart, 2);
+    set(&mut v, 1, &[0; 4]);
+    run_mem(&mut v, &mut mem, u64::MAX, 0x02056087, 0x2ff8, 0).unwrap();
+    assert_eq!(get(&v, 1), [0, 0, 0x23222120, 0x27262524]);
+    assert_eq!(v.vstart, 0);
+    // vle8ff.v v1, (a0), v0.t shortens vl instead
+    run(&mut v, 0xcc087057, 0, 0).unwrap();
+    v.reg_mut(0).fill(0xff);
+    run_mem(&mut v, &mut mem, 0x3000, 0x01050087, 0x2ffa, 0).unwrap();
+    assert_eq!(v.vl, 6);
+    let r = run_mem(&mut v, &mut mem, 0x3000, 0x01050087, 0x3000, 0);
+    assert_eq!(r, Err(Trap::exception(Access::Read.page_fault(), 0x3000)));
+}
@@ -0,0 +1,183 @@
+use alloc::string::String;
+use alloc::vec::Vec;
+use rv_asm::Xlen;
+
+use crate::fdt::Fdt;
+use crate::plic::{self, Plic};
+use crate::trap::Interrupt;
+use crate::uart::{self, Uart};
+use crate::virt::*;
+
+#[test]
+fn uart_registers() {
+    let mut u = Uart::default();
+    assert_eq!(u.read(uart::LSR), uart::lsr::THRE | uart::lsr::TEMT);
+    for b in b"hi" {
+        u.write(uart::RBR, *b);
+    }
+    assert_eq!(u.output, b"hi");
+    assert!(!u.interrupt());
+
+    // Received data raises the interrupt until it is read
+    u.write(uart::IER, uart::ier::ERBFI);
+    u.input(b"ok");
+    assert!(u.interrupt());
+    assert_eq!(u.read(uart::LSR) & uart::lsr::DR, uart::lsr::DR);
+    assert_eq!(u.read(uart::IIR), 0x4);
+    assert_eq!((u.read(uart::RBR), u.read(uart::RBR)), (b'o', b'k'));
+    assert!(!u.interrupt());
+
+    // The transmitter interrupt clears when `IIR` reports it
+    u.write(uart::IIR, 1);
+    u.write(uart::IER, uart::ier::ETBEI);
+    assert_eq!(u.read(uart::IIR), 0xc2);
+    assert_eq!(u.read(uart::IIR), 0xc1);
+    u.write(uart::RBR, b'!');
+    assert!(u.interrupt());
+
+    // The divisor latch hides the data registers
+    u.write(uart::LCR, 0x83);
+    u.write(uart::RBR, 1);
+    u.write(uart::IER, 0);
+    assert_eq!((u.divisor, u.output.len()), ([1, 0], 3));
+    u.write(uart::LCR, 0x03);
+    assert_eq!(u.read(uart::IER), uart::ier::ETBEI);
+
+    // Loopback sends output back to the receiver
+    u.write(uart::MCR, 0x10 | 0x3);
+    u.write(uart::RBR, b'x');
+    assert_eq!(u.read(uart::RBR), b'x');
+    assert_eq!(u.read(uart::MSR), 0x30);
+}
+
+#[test]
+fn plic_claims_by_priority() {
+    let mut p = Plic::default();
+    p.set_level(3, true);
+    p.set_level(5, true);
+    assert_eq!(p.read(plic::PENDING), 1 << 3 | 1 << 5);
+    // Nothing is enabled, and priority 0 never interrupts
+    assert_eq!(p.interrupts(), 0);
+    p.write(plic::ENABLE, 1 << 3 | 1 << 5 | 1);
+    assert_eq!(p.read(plic::ENABLE), 1 << 3 | 1 << 5);
+    p.write(plic::PRIORITY + 12, 1);
+    p.write(plic::PRIORITY + 20, 9);
+    assert_eq!(p.read(plic::PRIORITY + 20), 1);
+    // Source 0 has no priority
+    p.write(plic::PRIORITY, 5);
+    assert_eq!(p.read(plic::PRIORITY), 0);
+    assert_eq!(p.interrupts(), Interrupt::MachineExternal.bit());
+
+    // Equal priorities go to the lower source
+    assert_eq!(p.read(plic::CLAIM), 3);
+    assert_eq!(p.read(plic::PENDING), 1 << 5);
+    assert_eq!(p.read(plic::CLAIM), 5);
+    assert_eq!(p.read(plic::CLAIM), 0);
+    assert_eq!(p.interrupts(), 0);
+    // A level source pends again once completed
+    p.write(plic::CLAIM, 3);
+    assert_eq!(p.read(plic::PENDING), 1 << 3);
+    p.set_level(5, false);
+    p.write(plic::CLAIM, 5);
+    assert_eq!(p.read(plic::PENDING), 1 << 3);
+
+    // The threshold masks, per context
+    p.write(plic::THRESHOLD, 1);
+    assert_eq!(p.interrupts(), 0);
+    p.write(plic::ENABLE + 0x80, 1 << 3);
+    assert_eq!(p.interrupts(), Interrupt::SupervisorExternal.bit());
+    assert_eq!(p.read(plic::CLAIM + 0x1000), 3);
+}
+
+/// Walk a device tree blob into `(path, property, value)` triples
+fn walk(dtb: &[u8]) -> Vec<(String, String, Vec<u8>)> {
+    let word = |at: usize| u32::from_be_bytes(dtb[at..at + 4].try_into().unwrap()) as usize;
+    assert_eq!(word(0), 0xd00d_feed);
+    assert_eq!(word(4), dtb.len());
+    let (strings, mut at) = (word(12), word(8));
+    let cstr = |at: usize| {
+        let end = dtb[at..].iter().position(|&b| b == 0).unwrap();
+        String::from_utf8(dtb[at..at + end].to_vec()).unwrap()
+    };
+    let mut path: Vec<String> = Vec::new();
+    let mut out = Vec::new();
+    loop {
+        let token = word(at);
+        at += 4;
+        match token {
+            1 => {
+                let name = cstr(at);
+                at += (name.len() + 4) & !3;
+                path.push(name);
+            }
+            2 => {
+                path.pop();
+            }
+            3 => {
+                let (len, name) = (word(at), cstr(strings + word(at + 4)));
+                out.push((path.join("/"), name, dtb[at + 8..at + 8 + len].to_vec()));
+                at += (8 + len + 3) & !3;
+            }
+            9 => return out,
+            t => panic!("token {t}"),
+        }
+    }
+}
+
+#[test]
+fn device_tree() {
+    let mut t = Fdt::new();
+    t.begin_node("");
+    t.prop_u32("a", 7);
+    t.begin_node("n@1");
+    t.prop_strs("a", &["x", "yz"]);
+    t.prop_empty("b");
+    t.end_node();
+    t.end_node();
+    let props = walk(&t.finish());
+    assert_eq!(props.len(), 3);
+    assert_eq!(props[0], ("".into(), "a".into(), alloc::vec![0, 0, 0, 7]));
+    assert_eq!(props[1], ("/n@1".into(), "a".into(), b"x\0yz\0".to_vec()));
+    assert_eq!(props[2], ("/n@1".into(), "b".into(), Vec::new()));
+
+    let board = Virt::new(0x800_0000);
+    let props = walk(&board.device_tree(Xlen::Rv64, "console=ttyS0"));
+    let get = |path: &str, name: &str| {
+        props
+            .iter()
+            .find(|(p, n, _)| p == path && n == name)
+            .map(|(_, _, v)| v.clone())
+            .unwrap_or_else(|| panic!("{path} {name}"))
+    };
+    assert_eq!(get("/chosen", "bootargs"), b"console=ttyS0\0");
+    assert_eq!(get("/chosen", "stdout-path"), b"/soc/serial@10000000\0");
+    let reg: Vec<u8> = [0x8000_0000u64, 0x800_0000].iter().flat_map(|v| v.to_be_bytes()).collect();
+    assert_eq!(get("/memory@80000000", "reg"), reg);
+    assert!(get("/cpus/cpu@0", "riscv,isa").starts_with(b"rv64imafdcv_zicntr_zicsr"));
+    assert_eq!(get("/soc/serial@10000000", "interrupts"), [0, 0, 0, 10]);
+    assert_eq!(get("/soc/plic@c000000", "riscv,ndev"), [0, 0, 0, 95]);
+
+    assert_eq!(board.region(0x8000_0000), Region::Ram);
+    assert_eq!(board.region(0x87ff_ffff), Region::Ram);
+    assert_eq!(board.region(0x8800_0000), Region::Unmapped);
+    assert_eq!(board.region(0x1000_0005), Region::Device);
+    assert_eq!(board.region(0x200_bff8), Region::Device);
+    assert_eq!(board.region(0xc20_0004), Region::Device);
+    assert_eq!(board.region(0x1000), Region::Unmapped);
+    assert_eq!(board.device_tree_addr(0x1234), 0x87ff_e000);
+}
+
+#[test]
+fn uart_interrupts_through_the_plic() {
+    let mut board = Virt::new(0x1000_0000);
+    board.write(PLIC_BASE + 4 * UART_IRQ as u64, 4, 1);
+    board.write(PLIC_BASE + plic::ENABLE + 0x80, 4, 1 << UART_IRQ);
+    board.write(UART_BASE + uart::IER, 1, uart::ier::ERBFI as u64);
+    assert_eq!(board.interrupts(), 0);
+    board.uart.input(b"k");
+    assert_eq!(board.interrupts(), Interrupt::SupervisorExternal.bit());
+    assert_eq!(board.read(PLIC_BASE + plic::CLAIM + 0x1000, 4), UART_IRQ as u64);
+    assert_eq!(board.read(UART_BASE, 1), b'k' as u64);
+    board.write(PLIC_BASE + plic::CLAIM + 0x1000, 4, UART_IRQ as u64);
+    assert_eq!(board.interrupts(), 0);
+}
@@ -0,0 +1,543 @@
+use alloc::string::ToString;
+use alloc::vec::Vec;
+use rv_asm::{Reg, Xlen};
+use wasmparser::{Operator, ValType};
+
+use crate::arch::Riscv;
+use crate::flate::DebugFlate;
+use crate::template::wasm::{Assembled, AssemblyError, BlockCtx, ModuleBuilder, REGS, import};
+use crate::template::{Flags, Labels, Params, RegCache, Region, TemplateJit};
+use crate::{Heat, JitOpcode, Mem, WasmJit, WasmJitCtx};
+
+/// Compile the block at `0x1000` holding `code`, with `cached` already
+/// compiled, and hand its opcodes and context to `check`
+fn compile(
+    code: &[u32],
+    xlen: Xlen,
+    cached: u64,
+    check: impl FnOnce(Vec<JitOpcode<'_>>, &BlockCtx),
+) {
+    let mut mem = Mem::default();
+    for (i, b) in code.iter().flat_map(|c| c.to_le_bytes()).enumerate() {
+        mem.write_byte(0x1000 + i as u64, b);
+    }
+    let trial = move |pc| match pc == cached {
+        true => Heat::Cached,
+        false => Heat::New,
+    };
+    let jit = TemplateJit {
+        params: Params {
+            react: &mem,
+            trial: &trial,
+            flate: &DebugFlate {},
+            regs: &RegCache::default(),
+            region: &Region::default(),
+            root: 0x1000,
+            flags: Flags::default().with_xlen(xlen),
+        },
+        pc: 0x1000,
+        labels: &Labels::default(),
+        depth: 0,
+    };
+    let ctx = BlockCtx::default();
+    check(Riscv(&jit).jit(&ctx).collect(), &ctx);
+}
+
+fn op(op: Operator<'static>) -> JitOpcode<'static> {
+    JitOpcode::Operator { op }
+}
+
+#[test]
+fn loops_branch_back() {
+    // 1: addi a0, a0, -1; bnez a0, 1b; ecall
+    compile(
+        &[0xfff5_0513, 0xfe05_1ee3, 0x0000_0073],
+        Xlen::Rv64,
+        u64::MAX,
+        |ops, ctx| {
+            let loops: Vec<_> = ops
+                .iter()
+                .filter_map(|o| match o {
+                    JitOpcode::Loop { label } => Some(*label),
+                    _ => None,
+                })
+                .collect();
+            assert_eq!(loops, [0x1000, 0x1004, 0x1008]);
+            assert!(ops.contains(&op(Operator::I64Add)));
+            // The taken arm branches back to the first loop
+            let taken = ops.iter().position(|o| *o == op(Operator::I64Ne)).unwrap();
+            assert_eq!(ops[taken + 2], JitOpcode::Br { label: 0x1000 });
+            let calls = |import| {
+                ops.iter()
+                    .filter(|&o| *o == JitOpcode::Call { import })
+                    .count()
+            };
+            assert_eq!(calls(import::ECALL), 1);
+            // Every instruction checks the interrupt deadline first
+            assert_eq!(calls(import::INTERRUPT), 3);
+            let pcs = ops
+                .iter()
+                .filter(|o| matches!(o, JitOpcode::Pc { .. }))
+                .count();
+            assert_eq!(pcs, 3);
+            assert_eq!(ctx.pcs(), [0x1000, 0x1004, 0x1008]);
+        },
+    );
+}
+
+#[test]
+fn blocks_end_where_wasm_cannot_go() {
+    let exit = |pc: i64| [op(Operator::I64Const { value: pc }), op(Operator::Return)];
+    // amoadd.w a0, a1, (a2)
+    compile(&[0x00b6_252f], Xlen::Rv64, u64::MAX, |ops, _| {
+        assert_eq!(ops[1..ops.len() - 1], exit(0x1000))
+    });
+    compile(&[0xfff5_0513], Xlen::Rv32, u64::MAX, |ops, _| {
+        assert_eq!(ops[1..ops.len() - 1], exit(0x1000))
+    });
+    // A block compiled already is left to the host
+    compile(&[0xfff5_0513], Xlen::Rv64, 0x1004, |ops, _| {
+        assert_eq!(ops[ops.len() - 3..ops.len() - 1], exit(0x1004))
+    });
+}
+
+#[test]
+fn modules_validate() {
+    type Compiled<'a, 'b> = (Vec<JitOpcode<'a>>, &'b BlockCtx);
+    fn assemble(a: Compiled<'_, '_>, b: Compiled<'_, '_>) -> Assembled {
+        let mut module = ModuleBuilder::new();
+        module.block(0x1000, a.1, a.0);
+        module.block(0x2000, b.1, b.0);
+        module.finish().unwrap()
+    }
+    let mut assembled = None;
+    // 1: addi a0, a0, -1; bnez a0, 1b; ecall
+    compile(
+        &[0xfff5_0513, 0xfe05_1ee3, 0x0000_0073],
+        Xlen::Rv64,
+        u64::MAX,
+        |a, actx| {
+            // ld a0, 8(a1); sd a0, 0(a1); jalr a0
+            compile(
+                &[0x0085_b503, 0x00a5_b023, 0x0005_00e7],
+                Xlen::Rv64,
+                u64::MAX,
+                |b, bctx| assembled = Some(assemble((a, actx), (b, bctx))),
+            )
+        },
+    );
+    let assembled = assembled.unwrap();
+    wasmparser::validate(&assembled.bytes).unwrap();
+    // Each block's map starts at its first instruction and runs in order
+    assert_eq!(assembled.pcs.len(), 2);
+    for map in &assembled.pcs {
+        assert_eq!(map[0].1, 0x1000);
+        assert!(map.windows(2).all(|w| w[0].0 < w[1].0));
+    }
+}
+
+#[test]
+fn blocks_link_by_pc() {
+    let call = |pc| {
+        [
+            op(Operator::LocalGet { local_index: 0 }),
+            JitOpcode::CallBlock { pc },
+            op(Operator::Return),
+        ]
+    };
+    let ctx = BlockCtx::default();
+    let mut module = ModuleBuilder::new();
+    module.block(0x1000, &ctx, call(0x2000));
+    module.block(0x2000, &ctx, call(0x3000));
+    let assembled = module.finish().unwrap();
+    wasmparser::validate(&assembled.bytes).unwrap();
+    // 0x3000 is imported, so it comes before both blocks
+    let imports = wasmparser::Parser::new(0)
+        .parse_all(&assembled.bytes)
+        .find_map(|p| match p.unwrap() {
+            wasmparser::Payload::ImportSection(s) => Some(s),
+            _ => None,
+        })
+        .unwrap();
+    let names: Vec<_> = imports
+        .into_iter()
+        .map(|i| {
+            let i = i.unwrap();
+            (i.module, i.name)
+        })
+        .collect();
+    assert_eq!(names.len(), import::ALL.len() + 2);
+    assert_eq!(names.last(), Some(&("b", "12288")));
+}
+
+#[test]
+fn unresolved_names_fail_to_assemble() {
+    let ctx = BlockCtx::default();
+    let mut module = ModuleBuilder::new();
+    module.block(0x1000, &ctx, [JitOpcode::Br { label: 0x1000 }]);
+    assert!(matches!(module.finish(), Err(AssemblyError::Label(0x1000))));
+    let mut module = ModuleBuilder::new();
+    module.block(0x1000, &ctx, [JitOpcode::Call { import: "nope" }]);
+    assert!(matches!(module.finish(), Err(AssemblyError::Import(_))));
+}
+
+#[test]
+fn locals_come_from_the_context() {
+    // mulh a0, a1, a2; jalr a0
+    compile(
+        &[0x02c5_9533, 0x0005_00e7],
+        Xlen::Rv64,
+        u64::MAX,
+        |_, ctx| {
+            // Both instructions share the scratch locals, each register has
+            // its own, and the check of their code on entry holds its page
+            // in another
+            let mut locals = [ValType::I64; 8];
+            locals[7] = ValType::I32;
+            assert_eq!(ctx.locals(), locals);
+        },
+    );
+    // Locals are numbered after `regs`, and a register keeps its own
+    let ctx = BlockCtx::default();
+    let a0 = ctx.reg_local(Reg::A0);
+    assert_eq!(a0, REGS + 1);
+    assert_eq!(ctx.local(ValType::I32), a0 + 1);
+    assert_eq!(ctx.reg_local(Reg::A0), a0);
+    assert_eq!(ctx.locals(), [ValType::I64, ValType::I32]);
+    assert_eq!(ctx.import(import::ECALL), Some(5));
+    assert_eq!(ctx.import("nope"), None);
+}
+
+/// What a block run by [`instantiate`] asked of the host
+#[derive(Default)]
+struct Host {
+    memory: Option<wasmi::Memory>,
+    pages: u32,
+    loads: u32,
+    /// `a0` in the register file at each load the host served
+    seen: Vec<u64>,
+    stores: Vec<(u64, u64)>,
+    dropped: Option<u64>,
+}
+
+type Instance = (wasmi::Store<Host>, wasmi::Memory, wasmi::Instance);
+
+/// Compile `code` at `0x1000` and instantiate it with `code` in guest RAM
+fn instantiate(code: &[u32]) -> Instance {
+    let mut bytes = Vec::new();
+    compile(code, Xlen::Rv64, u64::MAX, |ops, ctx| {
+        let mut module = ModuleBuilder::new();
+        module.block(0x1000, ctx, ops);
+        bytes = module.finish().unwrap().bytes;
+    });
+    link(&bytes, code, &[])
+}
+
+/// Instantiate the module `bytes` with `code` at `0x1000`, importing each
+/// `(pc, next)` in `blocks` as a block that returns `next`
+///
+/// Guest RAM is the imported memory from `0x1000` up, at its own
+/// addresses; anything else is a device whose loads read `0x5a`.
+fn link(bytes: &[u8], code: &[u32], blocks: &[(u64, i64)]) -> Instance {
+    use wasmi::{Caller, Engine, Error, Linker, Memory, MemoryType, Module, Store};
+
+    let engine = Engine::default();
+    let module = Module::new(&engine, bytes).unwrap();
+    let mut store = Store::new(&engine, Host::default());
+    let memory = Memory::new(&mut store, MemoryType::new(1, None).unwrap()).unwrap();
+    let code: Vec<u8> = code.iter().flat_map(|c| c.to_le_bytes()).collect();
+    memory.write(&mut store, 0x1000, &code).unwrap();
+    store.data_mut().memory = Some(memory);
+    let mut linker = <Linker<Host>>::new(&engine);
+    linker.define("v", "memory", memory).unwrap();
+    linker
+        .func_wrap(
+            "v",
+            import::PAGE,
+            |mut host: Caller<'_, Host>, a: i64, _: i32| {
+                host.data_mut().pages += 1;
+                match a {
+                    0x1000..0x10000 => a as i32 & !0xfff,
+                    _ => 0,
+                }
+            },
+        )
+        .unwrap();
+    linker
+        .func_wrap(
+            "v",
+            import::LOAD,
+            |mut host: Caller<'_, Host>, _: i64, _: i32| {
+                let mut a0 = [0; 8];
+                let memory = host.data().memory.unwrap();
+                memory.read(&host, reg(10), &mut a0).unwrap();
+                let host = host.data_mut();
+                host.loads += 1;
+                host.seen.push(u64::from_le_bytes(a0));
+                0x5a_i64
+            },
+        )
+        .unwrap();
+    linker
+        .func_wrap(
+            "v",
+            import::STORE,
+            |mut host: Caller<'_, Host>, a: i64, _: i32, v: i64| {
+                host.data_mut().stores.push((a as u64, v as u64))
+            },
+        )
+        .unwrap();
+    linker
+        .func_wrap(
+            "v",
+            import::DROP_BLOCK,
+            |mut host: Caller<'_, Host>, root: i64| host.data_mut().dropped = Some(root as u64),
+        )
+        .unwrap();
+    linker.func_wrap("v", import::ECALL, |pc: i64| pc).unwrap();
+    // Nothing else is reached without a fault or an interrupt
+    let host = |name: &'static str| Error::new(name);
+    linker
+        .func_wrap(
+            "v",
+            import::INTERRUPT,
+            move |_: i64| -> Result<i64, Error> { Err(host(import::INTERRUPT)) },
+        )
+        .unwrap();
+    linker
+        .func_wrap(
+            "v",
+            import::TRAP,
+            move |_: i64, _: i64, _: i64| -> Result<i64, Error> { Err(host(import::TRAP)) },
+        )
+        .unwrap();
+    linker
+        .func_wrap(
+            "v",
+            import::EBREAK,
+            move |_: i64, _: i64| -> Result<i64, Error> { Err(host(import::EBREAK)) },
+        )
+        .unwrap();
+    for &(pc, next) in blocks {
+        linker
+            .func_wrap("b", &pc.to_string(), move |_: i32| next)
+            .unwrap();
+    }
+    let instance = linker
+        .instantiate(&mut store, &module)
+        .unwrap()
+        .start(&mut store)
+        .unwrap();
+    (store, memory, instance)
+}
+
+fn reg(r: u8) -> usize {
+    (crate::template::wasm::offset::X + 8 * r as u64) as usize
+}
+
+/// Enter the block at `0x1000` as the host does, with `regs` set, returning
+/// where it left
+fn enter((store, memory, instance): &mut Instance, regs: &[(u8, u64)]) -> i64 {
+    use crate::template::wasm::offset;
+
+    for &(r, v) in regs {
+        memory.write(&mut *store, reg(r), &v.to_le_bytes()).unwrap();
+    }
+    // No deadline, and empty page caches
+    let rest = alloc::vec![0xff; (offset::SIZE - offset::DEADLINE) as usize];
+    memory
+        .write(&mut *store, offset::DEADLINE as usize, &rest)
+        .unwrap();
+    let run = instance
+        .get_typed_func::<i32, i64>(&*store, "4096")
+        .unwrap();
+    run.call(&mut *store, 0).unwrap()
+}
+
+fn read((store, memory, _): &Instance, a: usize) -> u64 {
+    let mut out = [0; 8];
+    memory.read(store, a, &mut out).unwrap();
+    u64::from_le_bytes(out)
+}
+
+/// Run the block `code; ecall` as Wasm once for each `(a1, a2)`, returning
+/// `a0` after each
+fn execute(code: u32, operands: &[(u64, u64)]) -> Vec<u64> {
+    let mut instance = instantiate(&[code, 0x0000_0073]);
+    operands
+        .iter()
+        .map(|&(a, b)| {
+            assert_eq!(enter(&mut instance, &[(11, a), (12, b)]), 0x1004);
+            read(&instance, reg(10))
+        })
+        .collect()
+}
+
+#[test]
+fn ram_is_accessed_directly() {
+    // ld a0, 8(a1); sd a0, 0(a1); ld a0, 0(a1); ecall
+    let mut instance = instantiate(&[0x0085_b503, 0x00a5_b023, 0x0005_b503, 0x0000_0073]);
+    let v = 0x1122_3344_5566_7788_u64;
+    instance
+        .1
+        .write(&mut instance.0, 0x2008, &v.to_le_bytes())
+        .unwrap();
+    assert_eq!(enter(&mut instance, &[(11, 0x2000)]), 0x100c);
+    assert_eq!((read(&instance, 0x2000), read(&instance, reg(10))), (v, v));
+    // One page each to run, to load and to store, without the slow path
+    let host = instance.0.data();
+    assert_eq!((host.pages, host.loads, &host.stores[..]), (3, 0, &[][..]));
+
+    // Devices go through the host
+    assert_eq!(enter(&mut instance, &[(11, 0x2_0000)]), 0x100c);
+    let host = instance.0.data();
+    assert_eq!((host.loads, &host.stores[..]), (2, &[(0x2_0000, 0x5a)][..]));
+    assert_eq!(read(&instance, reg(10)), 0x5a);
+}
+
+#[test]
+fn registers_are_flushed_before_the_host_runs() {
+    // addi a0, a0, 1; ld a1, 0(a2); ecall
+    let mut instance = instantiate(&[0x0015_0513, 0x0006_3583, 0x0000_0073]);
+    assert_eq!(enter(&mut instance, &[(10, 5), (12, 0x2_0000)]), 0x1008);
+    assert_eq!(instance.0.data().seen, [6]);
+    assert_eq!(
+        (read(&instance, reg(10)), read(&instance, reg(11))),
+        (6, 0x5a)
+    );
+}
+
+#[test]
+fn changed_code_is_caught_on_entry() {
+    // addi a0, a0, -1; ecall
+    let mut instance = instantiate(&[0xfff5_0513, 0x0000_0073]);
+    assert_eq!(enter(&mut instance, &[(10, 5)]), 0x1004);
+    assert_eq!(instance.0.data().dropped, None);
+    // addi a0, a0, 1
+    let code = 0x0015_0513_u32.to_le_bytes();
+    instance.1.write(&mut instance.0, 0x1000, &code).unwrap();
+    // The block runs nothing and drops itself
+    assert_eq!(enter(&mut instance, &[(10, 5)]), 0x1000);
+    assert_eq!(instance.0.data().dropped, Some(0x1000));
+    assert_eq!(read(&instance, reg(10)), 5);
+}
+
+#[test]
+fn linked_calls_land_in_their_block() {
+    let ret = |value| [op(Operator::I64Const { value }), op(Operator::Return)];
+    let call = |pc| {
+        [
+            op(Operator::LocalGet { local_index: REGS }),
+            JitOpcode::CallBlock { pc },
+            op(Operator::Return),
+        ]
+    };
+    let ctx = BlockCtx::default();
+    let mut module = ModuleBuilder::new();
+    module.block(0x1000, &ctx, call(0x3000));
+    module.block(0x2000, &ctx, ret(0x2222));
+    module.block(0x3000, &ctx, ret(0x3333));
+    module.block(0x4000, &ctx, call(0x5000));
+    let bytes = module.finish().unwrap().bytes;
+    let (mut store, _, instance) = link(&bytes, &[], &[(0x5000, 0x5555)]);
+    let mut run = |pc: u64| {
+        let f = instance
+            .get_typed_func::<i32, i64>(&store, &pc.to_string())
+            .unwrap();
+        f.call(&mut store, 0).unwrap()
+    };
+    // Past the imported block, in the module, and out to the host
+    assert_eq!(run(0x1000), 0x3333);
+    assert_eq!(run(0x2000), 0x2222);
+    assert_eq!(run(0x4000), 0x5555);
+}
+
+#[test]
+fn blocks_compute_like_the_interpreter() {
+    // The reactor keeps its registers in JavaScript, so each reference is
+    // the interpreter's arm for the instruction, run on the host
+    let sext = |v: u32| v as i32 as i64 as u64;
+    let lo = |v: u64| v as u32;
+    // `op a0, a1, a2`
+    let r = |funct7: u32, funct3: u32, opcode: u32| {
+        funct7 << 25 | 12 << 20 | 11 << 15 | funct3 << 12 | 10 << 7 | opcode
+    };
+    type Reference<'a> = &'a dyn Fn(u64, u64) -> u64;
+    let cases: [(&str, u32, Reference<'_>); 15] = [
+        ("mulh", r(1, 1, 0x33), &|a, b| {
+            ((a as i64 as i128 * b as i64 as i128) >> 64) as u64
+        }),
+        ("mulhsu", r(1, 2, 0x33), &|a, b| {
+            ((a as i64 as i128 * b as i128) >> 64) as u64
+        }),
+        ("mulhu", r(1, 3, 0x33), &|a, b| {
+            ((a as u128 * b as u128) >> 64) as u64
+        }),
+        ("div", r(1, 4, 0x33), &|a, b| match b as i64 {
+            0 => u64::MAX,
+            d => (a as i64).wrapping_div(d) as u64,
+        }),
+        ("divu", r(1, 5, 0x33), &|a, b| {
+            a.checked_div(b).unwrap_or(u64::MAX)
+        }),
+        ("rem", r(1, 6, 0x33), &|a, b| match b as i64 {
+            0 => a,
+            d => (a as i64).wrapping_rem(d) as u64,
+        }),
+        ("remu", r(1, 7, 0x33), &|a, b| a.checked_rem(b).unwrap_or(a)),
+        ("addw", r(0, 0, 0x3b), &|a, b| {
+            sext(lo(a).wrapping_add(lo(b)))
+        }),
+        ("subw", r(0x20, 0, 0x3b), &|a, b| {
+            sext(lo(a).wrapping_sub(lo(b)))
+        }),
+        ("mulw", r(1, 0, 0x3b), &|a, b| {
+            sext(lo(a).wrapping_mul(lo(b)))
+        }),
+        ("divw", r(1, 4, 0x3b), &|a, b| {
+            sext(match lo(b) as i32 {
+                0 => u32::MAX,
+                d => (lo(a) as i32).wrapping_div(d) as u32,
+            })
+        }),
+        ("divuw", r(1, 5, 0x3b), &|a, b| {
+            sext(lo(a).checked_div(lo(b)).unwrap_or(u32::MAX))
+        }),
+        ("remw", r(1, 6, 0x3b), &|a, b| {
+            sext(match lo(b) as i32 {
+                0 => lo(a),
+                d => (lo(a) as i32).wrapping_rem(d) as u32,
+            })
+        }),
+        ("remuw", r(1, 7, 0x3b), &|a, b| {
+            sext(lo(a).checked_rem(lo(b)).unwrap_or(lo(a)))
+        }),
+        ("mul", r(1, 0, 0x33), &|a, b| a.wrapping_mul(b)),
+    ];
+    let values = [
+        0,
+        1,
+        2,
+        7,
+        -1i64 as u64,
+        -7i64 as u64,
+        i64::MIN as u64,
+        i64::MAX as u64,
+        0x7fff_ffff,
+        0x8000_0000,
+        0xffff_ffff,
+        0x1_0000_0000,
+        i32::MIN as i64 as u64,
+        0x1234_5678_9abc_def0,
+    ];
+    let operands: Vec<_> = values
+        .iter()
+        .flat_map(|&a| values.iter().map(move |&b| (a, b)))
+        .collect();
+    for (name, code, reference) in cases {
+        let got = execute(code, &operands);
+        for (&(a, b), got) in operands.iter().zip(got) {
+            assert_eq!(got, reference(a, b), "{name} {a:#x}, {b:#x}");
+        }
+    }
+}
@@ -0,0 +1,172 @@
+//! Zba, Zbb, Zbs and Zbc semantics shared by the interpreter and tests.
+//!
+//! The JIT lowers each [`BitOp`] to JavaScript directly; this is the
+//! reference it has to agree with.
+use rv_asm::Xlen;
+
+use crate::xlen_mask;
+
+/// A bit-manipulation operation
+///
+/// Immediate forms reuse the register form's operation with the shift
+/// amount as the second operand. `W` operations exist on RV64 only.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum BitOp {
+    // Zba
+    AddUw,
+    Sh1add,
+    Sh2add,
+    Sh3add,
+    Sh1addUw,
+    Sh2addUw,
+    Sh3addUw,
+    SlliUw,
+    // Zbb
+    Andn,
+    Orn,
+    Xnor,
+    Clz,
+    Clzw,
+    Ctz,
+    Ctzw,
+    Cpop,
+    Cpopw,
+    Max,
+    Maxu,
+    Min,
+    Minu,
+    SextB,
+    SextH,
+    ZextH,
+    Rol,
+    Rolw,
+    Ror,
+    Rorw,
+    OrcB,
+    Rev8,
+    // Zbc
+    Clmul,
+    Clmulh,
+    Clmulr,
+    // Zbs
+    Bclr,
+    Bext,
+    Binv,
+    Bset,
+}
+
+impl BitOp {
+    /// Mnemonic of the register form
+    pub fn mnemonic(self) -> &'static str {
+        match self {
+            BitOp::AddUw => "add.uw",
+            BitOp::Sh1add => "sh1add",
+            BitOp::Sh2add => "sh2add",
+            BitOp::Sh3add => "sh3add",
+            BitOp::Sh1addUw => "sh1add.uw",
+            BitOp::Sh2addUw => "sh2add.uw",
+            BitOp::Sh3addUw => "sh3add.uw",
+            BitOp::SlliUw => "slli.uw",
+            BitOp::Andn => "andn",
+            BitOp::Orn => "orn",
+            BitOp::Xnor => "xnor",
+            BitOp::Clz => "clz",
+            BitOp::Clzw => "clzw",
+            BitOp::Ctz => "ctz",
+            BitOp::Ctzw => "ctzw",
+            BitOp::Cpop => "cpop",
+            BitOp::Cpopw => "cpopw",
+            BitOp::Max => "max",
+            BitOp::Maxu => "maxu",
+            BitOp::Min => "min",
+            BitOp::Minu => "minu",
+            BitOp::SextB => "sext.b",
+            BitOp::SextH => "sext.h",
+            BitOp::ZextH => "zext.h",
+            BitOp::Rol => "rol",
+            BitOp::Rolw => "rolw",
+            BitOp::Ror => "ror",
+            BitOp::Rorw => "rorw",
+            BitOp::OrcB => "orc.b",
+            BitOp::Rev8 => "rev8",
+            BitOp::Clmul => "clmul",
+            BitOp::Clmulh => "clmulh",
+            BitOp::Clmulr => "clmulr",
+            BitOp::Bclr => "bclr",
+            BitOp::Bext => "bext",
+            BitOp::Binv => "binv",
+            BitOp::Bset => "bset",
+        }
+    }
+}
+
+/// Carry-less product of two 64-bit values
+fn clmul(a: u64, b: u64) -> u128 {
+    (0..64)
+        .filter(|i| b >> i & 1 != 0)
+        .fold(0, |r, i| r ^ ((a as u128) << i))
+}
+
+/// Apply `op` to zero-extended XLEN-bit operands; unary operations ignore `b`
+pub fn exec(op: BitOp, a: u64, b: u64, xlen: Xlen) -> u64 {
+    let bits = match xlen {
+        Xlen::Rv32 => 32,
+        Xlen::Rv64 => 64,
+    };
+    let mask = xlen_mask(xlen);
+    let sh = b as u32 & (bits - 1);
+    let signed = |v: u64| ((v << (64 - bits)) as i64) >> (64 - bits);
+    let sext32 = |v: u32| v as i32 as i64 as u64;
+    let uw = a as u32 as u64;
+    let rot = |left: bool| match xlen {
+        Xlen::Rv32 if left => (a as u32).rotate_left(sh) as u64,
+        Xlen::Rv32 => (a as u32).rotate_right(sh) as u64,
+        Xlen::Rv64 if left => a.rotate_left(sh),
+        Xlen::Rv64 => a.rotate_right(sh),
+    };
+    let v = match op {
+        BitOp::AddUw => uw.wrapping_add(b),
+        BitOp::Sh1add => (a << 1).wrapping_add(b),
+        BitOp::Sh2add => (a << 2).wrapping_add(b),
+        BitOp::Sh3add => (a << 3).wrapping_add(b),
+        BitOp::Sh1addUw => (uw << 1).wrapping_add(b),
+        BitOp::Sh2addUw => (uw << 2).wrapping_add(b),
+        BitOp::Sh3addUw => (uw << 3).wrapping_add(b),
+        BitOp::SlliUw => uw << (b & 63),
+        BitOp::Andn => a & !b,
+        BitOp::Orn => a | !b,
+        BitOp::Xnor => !(a ^ b),
+        BitOp::Clz => ((a & mask).leading_zeros() - (64 - bits)) as u64,
+        BitOp::Clzw => (a as u32).leading_zeros() as u64,
+        BitOp::Ctz => (a & mask).trailing_zeros().min(bits) as u64,
+        BitOp::Ctzw => (a as u32).trailing_zeros() as u64,
+        BitOp::Cpop => (a & mask).count_ones() as u64,
+        BitOp::Cpopw => (a as u32).count_ones() as u64,
+        BitOp::Max => if signed(a) > signed(b) { a } else { b },
+        BitOp::Maxu => if a & mask > b & mask { a } else { b },
+        BitOp::Min => if signed(a) < signed(b) { a } else { b },
+        BitOp::Minu => if a & mask < b & mask { a } else { b },
+        BitOp::SextB => a as i8 as i64 as u64,
+        BitOp::SextH => a as i16 as i64 as u64,
+        BitOp::ZextH => a as u16 as u64,
+        BitOp::Rol => rot(true),
+        BitOp::Ror => rot(false),
+        BitOp::Rolw => sext32((a as u32).rotate_left(b as u32 & 31)),
+        BitOp::Rorw => sext32((a as u32).rotate_right(b as u32 & 31)),
+        BitOp::OrcB => (0..8)
+            .filter(|i| a >> (i * 8) & 0xff != 0)
+            .fold(0, |r, i| r | 0xff << (i * 8)),
+        BitOp::Rev8 => match xlen {
+            Xlen::Rv32 => (a as u32).swap_bytes() as u64,
+            Xlen::Rv64 => a.swap_bytes(),
+        },
+        BitOp::Clmul => clmul(a & mask, b & mask) as u64,
+        BitOp::Clmulh => (clmul(a & mask, b & mask) >> bits) as u64,
+        BitOp::Clmulr => (clmul(a & mask, b & mask) >> (bits - 1)) as u64,
+        BitOp::Bclr => a & !(1 << sh),
+        BitOp::Bext => a >> sh & 1,
+        BitOp::Binv => a ^ (1 << sh),
+        BitOp::Bset => a | (1 << sh),
+    };
+    v & mask
+}
@@ -0,0 +1,139 @@
+//! The core-local interruptor: `msip`, `mtimecmp` and `mtime`.
+//!
+//! The registers sit at the SiFive-compatible offsets from [`BASE`], which
+//! the reactor serves as device memory instead of a RAM page. Time is
+//! counted in retired instructions, so `mtime` is `instret` plus an offset
+//! that writing `mtime` adjusts, and the timer fires at a known instruction
+//! count. Both engines compare `instret` with the reactor's interrupt
+//! deadline before each instruction; [`Clint::due`] is what sets it.
+//!
+//! `stimecmp` is not a CLINT register: it is the supervisor timer the
+//! [SBI](crate::sbi) `set_timer` call programs, kept here so both timers
+//! share one deadline.
+use crate::trap::Interrupt;
+
+/// Where the CLINT is mapped in physical memory
+pub const BASE: u64 = 0x200_0000;
+/// The size of the CLINT's physical window
+pub const SIZE: u64 = 0x1_0000;
+/// Offset of hart 0's `msip`
+pub const MSIP: u64 = 0;
+/// Offset of hart 0's `mtimecmp`
+pub const MTIMECMP: u64 = 0x4000;
+/// Offset of `mtime`
+pub const MTIME: u64 = 0xbff8;
+
+/// CLINT state for the single hart
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct Clint {
+    pub msip: bool,
+    pub mtimecmp: u64,
+    /// When `STIP` rises, as set through the SBI
+    pub stimecmp: u64,
+    /// What `mtime` reads ahead of `instret`
+    pub offset: u64,
+}
+
+impl Default for Clint {
+    fn default() -> Self {
+        Clint {
+            msip: false,
+            mtimecmp: u64::MAX,
+            stimecmp: u64::MAX,
+            offset: 0,
+        }
+    }
+}
+
+impl Clint {
+    /// Whether physical address `pa` falls in the CLINT
+    pub fn contains(pa: u64) -> bool {
+        pa.wrapping_sub(BASE) < SIZE
+    }
+
+    pub fn mtime(&self, instret: u64) -> u64 {
+        instret.wrapping_add(self.offset)
+    }
+
+    /// Read `size` bytes at `offset` into the window
+    ///
+    /// Unmapped offsets read as zero.
+    pub fn read(&self, offset: u64, size: u64, instret: u64) -> u64 {
+        let (reg, at) = match register(offset) {
+            Some(r) => r,
+            None => return 0,
+        };
+        let value = match reg {
+            MSIP => self.msip as u64,
+            MTIMECMP => self.mtimecmp,
+            _ => self.mtime(instret),
+        };
+        (value >> (at * 8)) & mask(size)
+    }
+
+    /// Write `size` bytes at `offset` into the window
+    ///
+    /// Partial writes replace only the bytes they cover, so RV32 guests can
+    /// set the 64-bit registers a word at a time.
+    pub fn write(&mut self, offset: u64, size: u64, value: u64, instret: u64) {
+        let (reg, at) = match register(offset) {
+            Some(r) => r,
+            None => return,
+        };
+        let merge = |old: u64| {
+            let m = mask(size) << (at * 8);
+            (old & !m) | ((value << (at * 8)) & m)
+        };
+        match reg {
+            MSIP => self.msip = merge(self.msip as u64) & 1 != 0,
+            MTIMECMP => self.mtimecmp = merge(self.mtimecmp),
+            _ => self.offset = merge(self.mtime(instret)).wrapping_sub(instret),
+        }
+    }
+
+    /// The `mip` bits the CLINT drives
+    pub fn pending(&self, instret: u64) -> u64 {
+        let mut bits = 0;
+        if self.msip {
+            bits |= Interrupt::MachineSoftware.bit();
+        }
+        if self.mtime(instret) >= self.mtimecmp {
+            bits |= Interrupt::MachineTimer.bit();
+        }
+        if self.mtime(instret) >= self.stimecmp {
+            bits |= Interrupt::SupervisorTimer.bit();
+        }
+        bits
+    }
+
+    /// The `instret` at which a timer next fires, or `u64::MAX` if both
+    /// already have or never will
+    ///
+    /// A compare value of all ones, as after reset, never fires.
+    pub fn due(&self, instret: u64) -> u64 {
+        let now = self.mtime(instret);
+        [self.mtimecmp, self.stimecmp]
+            .into_iter()
+            .filter(|&cmp| now < cmp && cmp != u64::MAX)
+            .map(|cmp| instret.saturating_add(cmp - now))
+            .min()
+            .unwrap_or(u64::MAX)
+    }
+}
+
+/// The register containing `offset`, and the byte within it
+fn register(offset: u64) -> Option<(u64, u64)> {
+    match offset {
+        0..=3 => Some((MSIP, offset - MSIP)),
+        0x4000..=0x4007 => Some((MTIMECMP, offset - MTIMECMP)),
+        0xbff8..=0xbfff => Some((MTIME, offset - MTIME)),
+        _ => None,
+    }
+}
+
+fn mask(size: u64) -> u64 {
+    match size {
+        8.. => u64::MAX,
+        _ => (1 << (size * 8)) - 1,
+    }
+}
@@ -0,0 +1,330 @@
+//! Scalar cryptography: Zbkb, Zbkx, Zknh, Zkne, Zknd, Zksh and Zksed.
+//!
+//! Zbkc and the parts of Zbkb that Zbb already has (`andn`, `rol`, `rev8`
+//! and so on) decode as [`BitOp`](crate::bit::BitOp)s. The S-boxes are built
+//! at compile time from their field definitions rather than written out.
+use rv_asm::Xlen;
+
+use crate::xlen_mask;
+
+/// A scalar cryptography operation
+///
+/// `aes32*` and `sm4*` take a byte select and `aes64ks1i` a round number as
+/// well as their registers. Which operations exist depends on XLEN; see
+/// [`ExtInst::decode`](crate::ext::ExtInst::decode).
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum CryptoOp {
+    // Zbkb
+    Pack,
+    Packh,
+    Packw,
+    Brev8,
+    Zip,
+    Unzip,
+    // Zbkx
+    Xperm4,
+    Xperm8,
+    // Zknh
+    Sha256Sig0,
+    Sha256Sig1,
+    Sha256Sum0,
+    Sha256Sum1,
+    Sha512Sig0,
+    Sha512Sig1,
+    Sha512Sum0,
+    Sha512Sum1,
+    Sha512Sig0h,
+    Sha512Sig0l,
+    Sha512Sig1h,
+    Sha512Sig1l,
+    Sha512Sum0r,
+    Sha512Sum1r,
+    // Zkne and Zknd
+    Aes32Esi,
+    Aes32Esmi,
+    Aes32Dsi,
+    Aes32Dsmi,
+    Aes64Es,
+    Aes64Esm,
+    Aes64Ds,
+    Aes64Dsm,
+    Aes64Im,
+    Aes64Ks1i,
+    Aes64Ks2,
+    // Zksh
+    Sm3P0,
+    Sm3P1,
+    // Zksed
+    Sm4Ed,
+    Sm4Ks,
+}
+
+impl CryptoOp {
+    pub fn mnemonic(self) -> &'static str {
+        match self {
+            CryptoOp::Pack => "pack",
+            CryptoOp::Packh => "packh",
+            CryptoOp::Packw => "packw",
+            CryptoOp::Brev8 => "brev8",
+            CryptoOp::Zip => "zip",
+            CryptoOp::Unzip => "unzip",
+            CryptoOp::Xperm4 => "xperm4",
+            CryptoOp::Xperm8 => "xperm8",
+            CryptoOp::Sha256Sig0 => "sha256sig0",
+            CryptoOp::Sha256Sig1 => "sha256sig1",
+            CryptoOp::Sha256Sum0 => "sha256sum0",
+            CryptoOp::Sha256Sum1 => "sha256sum1",
+            CryptoOp::Sha512Sig0 => "sha512sig0",
+            CryptoOp::Sha512Sig1 => "sha512sig1",
+            CryptoOp::Sha512Sum0 => "sha512sum0",
+            CryptoOp::Sha512Sum1 => "sha512sum1",
+            CryptoOp::Sha512Sig0h => "sha512sig0h",
+            CryptoOp::Sha512Sig0l => "sha512sig0l",
+            CryptoOp::Sha512Sig1h => "sha512sig1h",
+            CryptoOp::Sha512Sig1l => "sha512sig1l",
+            CryptoOp::Sha512Sum0r => "sha512sum0r",
+            CryptoOp::Sha512Sum1r => "sha512sum1r",
+            CryptoOp::Aes32Esi => "aes32esi",
+            CryptoOp::Aes32Esmi => "aes32esmi",
+            CryptoOp::Aes32Dsi => "aes32dsi",
+            CryptoOp::Aes32Dsmi => "aes32dsmi",
+            CryptoOp::Aes64Es => "aes64es",
+            CryptoOp::Aes64Esm => "aes64esm",
+            CryptoOp::Aes64Ds => "aes64ds",
+            CryptoOp::Aes64Dsm => "aes64dsm",
+            CryptoOp::Aes64Im => "aes64im",
+            CryptoOp::Aes64Ks1i => "aes64ks1i",
+            CryptoOp::Aes64Ks2 => "aes64ks2",
+            CryptoOp::Sm3P0 => "sm3p0",
+            CryptoOp::Sm3P1 => "sm3p1",
+            CryptoOp::Sm4Ed => "sm4ed",
+            CryptoOp::Sm4Ks => "sm4ks",
+        }
+    }
+
+    /// Whether `rs2` is unused
+    pub fn is_unary(self) -> bool {
+        use CryptoOp::*;
+        matches!(
+            self,
+            Brev8
+                | Zip
+                | Unzip
+                | Sha256Sig0
+                | Sha256Sig1
+                | Sha256Sum0
+                | Sha256Sum1
+                | Sha512Sig0
+                | Sha512Sig1
+                | Sha512Sum0
+                | Sha512Sum1
+                | Aes64Im
+                | Aes64Ks1i
+                | Sm3P0
+                | Sm3P1
+        )
+    }
+}
+
+/// Multiply in GF(2^8) reduced by `x^8 + poly`
+const fn gf_mul(mut a: u8, mut b: u8, poly: u8) -> u8 {
+    let mut r = 0;
+    while b != 0 {
+        if b & 1 != 0 {
+            r ^= a;
+        }
+        let carry = a & 0x80 != 0;
+        a <<= 1;
+        if carry {
+            a ^= poly;
+        }
+        b >>= 1;
+    }
+    r
+}
+
+/// `a^254`, the multiplicative inverse with zero mapped to itself
+const fn gf_inv(a: u8, poly: u8) -> u8 {
+    let mut r = 1;
+    let mut i = 0;
+    while i < 254 {
+        r = gf_mul(r, a, poly);
+        i += 1;
+    }
+    r
+}
+
+const fn aes_sbox() -> [u8; 256] {
+    let mut t = [0; 256];
+    let mut i = 0;
+    while i < 256 {
+        let b = gf_inv(i as u8, 0x1b);
+        t[i] = b ^ b.rotate_left(1) ^ b.rotate_left(2) ^ b.rotate_left(3) ^ b.rotate_left(4) ^ 0x63;
+        i += 1;
+    }
+    t
+}
+
+const fn invert(s: &[u8; 256]) -> [u8; 256] {
+    let mut t = [0; 256];
+    let mut i = 0;
+    while i < 256 {
+        t[s[i] as usize] = i as u8;
+        i += 1;
+    }
+    t
+}
+
+/// SM4's affine map: the circulant matrix with first row `0xa7`, plus `0xd3`
+const fn sm4_affine(x: u8) -> u8 {
+    let mut r = 0;
+    let mut i = 0;
+    while i < 8 {
+        r |= (((x & 0xa7u8.rotate_left(i)).count_ones() & 1) as u8) << i;
+        i += 1;
+    }
+    r ^ 0xd3
+}
+
+const fn sm4_sbox() -> [u8; 256] {
+    let mut t = [0; 256];
+    let mut i = 0;
+    while i < 256 {
+        t[i] = sm4_affine(gf_inv(sm4_affine(i as u8), 0xf5));
+        i += 1;
+    }
+    t
+}
+
+const AES_SBOX: [u8; 256] = aes_sbox();
+const AES_INV_SBOX: [u8; 256] = invert(&AES_SBOX);
+const SM4_SBOX: [u8; 256] = sm4_sbox();
+const AES_RCON: [u8; 10] = [0x01, 0x02, 0x04, 0x08, 0x10, 0x20, 0x40, 0x80, 0x1b, 0x36];
+
+fn sub_bytes(x: u64, sbox: &[u8; 256]) -> u64 {
+    u64::from_le_bytes(x.to_le_bytes().map(|b| sbox[b as usize]))
+}
+
+/// One AES column times the circulant matrix whose first row is `m`
+fn mix_column(x: u32, m: [u8; 4]) -> u32 {
+    let b = x.to_le_bytes();
+    u32::from_le_bytes(core::array::from_fn(|i| {
+        (0..4).fold(0, |r, j| r ^ gf_mul(b[j], m[(j + 4 - i) % 4], 0x1b))
+    }))
+}
+
+fn mix_columns(x: u64, m: [u8; 4]) -> u64 {
+    (mix_column((x >> 32) as u32, m) as u64) << 32 | mix_column(x as u32, m) as u64
+}
+
+const MIX: [u8; 4] = [2, 3, 1, 1];
+const INV_MIX: [u8; 4] = [0xe, 0xb, 0xd, 0x9];
+
+/// The first two columns of `ShiftRows` (or its inverse) applied to the
+/// state whose columns 0-1 are in `lo` and 2-3 in `hi`
+fn shift_rows(lo: u64, hi: u64, inverse: bool) -> u64 {
+    let state = ((hi as u128) << 64 | lo as u128).to_le_bytes();
+    u64::from_le_bytes(core::array::from_fn(|k| {
+        let (col, row) = (k / 4, k % 4);
+        let from = match inverse {
+            false => (col + row) % 4,
+            true => (col + 4 - row) % 4,
+        };
+        state[from * 4 + row]
+    }))
+}
+
+fn sext32(v: u32) -> u64 {
+    v as i32 as i64 as u64
+}
+
+/// Apply `op` to XLEN-bit operands `a` (`rs1`) and `b` (`rs2`)
+///
+/// `imm` is the byte select of `aes32*` and `sm4*` or the round number of
+/// `aes64ks1i`, which must be at most 10.
+pub fn exec(op: CryptoOp, a: u64, b: u64, imm: u8, xlen: Xlen) -> u64 {
+    let bits = match xlen {
+        Xlen::Rv32 => 32,
+        Xlen::Rv64 => 64,
+    };
+    let (x, y) = (a as u32, b as u32);
+    let shamt = (imm as u32 * 8) & 31;
+    let byte = (y >> shamt) as u8;
+    let v = match op {
+        CryptoOp::Pack if xlen.is_32() => (a & 0xffff) | (b & 0xffff) << 16,
+        CryptoOp::Pack => (a & 0xffff_ffff) | b << 32,
+        CryptoOp::Packh => (a & 0xff) | (b & 0xff) << 8,
+        CryptoOp::Packw => sext32((x & 0xffff) | (y & 0xffff) << 16),
+        CryptoOp::Brev8 => a.swap_bytes().reverse_bits(),
+        CryptoOp::Zip => (0..16).fold(0, |r, i| r | (a >> i & 1) << (2 * i) | (a >> (i + 16) & 1) << (2 * i + 1)),
+        CryptoOp::Unzip => (0..16).fold(0, |r, i| r | (a >> (2 * i) & 1) << i | (a >> (2 * i + 1) & 1) << (i + 16)),
+        CryptoOp::Xperm4 => (0..bits / 4).fold(0, |r, i| {
+            let index = b >> (4 * i) & 0xf;
+            match index < bits / 4 {
+                true => r | (a >> (4 * index) & 0xf) << (4 * i),
+                false => r,
+            }
+        }),
+        CryptoOp::Xperm8 => (0..bits / 8).fold(0, |r, i| {
+            let index = b >> (8 * i) & 0xff;
+            match index < bits / 8 {
+                true => r | (a >> (8 * index) & 0xff) << (8 * i),
+                false => r,
+            }
+        }),
+        CryptoOp::Sha256Sig0 => sext32(x.rotate_right(7) ^ x.rotate_right(18) ^ x >> 3),
+        CryptoOp::Sha256Sig1 => sext32(x.rotate_right(17) ^ x.rotate_right(19) ^ x >> 10),
+        CryptoOp::Sha256Sum0 => sext32(x.rotate_right(2) ^ x.rotate_right(13) ^ x.rotate_right(22)),
+        CryptoOp::Sha256Sum1 => sext32(x.rotate_right(6) ^ x.rotate_right(11) ^ x.rotate_right(25)),
+        CryptoOp::Sha512Sig0 => a.rotate_right(1) ^ a.rotate_right(8) ^ a >> 7,
+        CryptoOp::Sha512Sig1 => a.rotate_right(19) ^ a.rotate_right(61) ^ a >> 6,
+        CryptoOp::Sha512Sum0 => a.rotate_right(28) ^ a.rotate_right(34) ^ a.rotate_right(39),
+        CryptoOp::Sha512Sum1 => a.rotate_right(14) ^ a.rotate_right(18) ^ a.rotate_right(41),
+        // RV32 computes each 64-bit result a half at a time
+        CryptoOp::Sha512Sig0h => (x >> 1 ^ x >> 7 ^ x >> 8 ^ y << 31 ^ y << 24) as u64,
+        CryptoOp::Sha512Sig0l => (x >> 1 ^ x >> 7 ^ x >> 8 ^ y << 31 ^ y << 25 ^ y << 24) as u64,
+        CryptoOp::Sha512Sig1h => (x << 3 ^ x >> 6 ^ x >> 19 ^ y >> 29 ^ y << 13) as u64,
+        CryptoOp::Sha512Sig1l => (x << 3 ^ x >> 6 ^ x >> 19 ^ y >> 29 ^ y << 26 ^ y << 13) as u64,
+        CryptoOp::Sha512Sum0r => (x << 25 ^ x << 30 ^ x >> 28 ^ y >> 7 ^ y >> 2 ^ y << 4) as u64,
+        CryptoOp::Sha512Sum1r => (x << 23 ^ x >> 14 ^ x >> 18 ^ y >> 9 ^ y << 18 ^ y << 14) as u64,
+        CryptoOp::Aes32Esi | CryptoOp::Aes32Esmi | CryptoOp::Aes32Dsi | CryptoOp::Aes32Dsmi => {
+            let mixed = match op {
+                CryptoOp::Aes32Esi => AES_SBOX[byte as usize] as u32,
+                CryptoOp::Aes32Esmi => mix_column(AES_SBOX[byte as usize] as u32, MIX),
+                CryptoOp::Aes32Dsi => AES_INV_SBOX[byte as usize] as u32,
+                _ => mix_column(AES_INV_SBOX[byte as usize] as u32, INV_MIX),
+            };
+            sext32(x ^ mixed.rotate_left(shamt))
+        }
+        CryptoOp::Aes64Es => sub_bytes(shift_rows(a, b, false), &AES_SBOX),
+        CryptoOp::Aes64Esm => mix_columns(sub_bytes(shift_rows(a, b, false), &AES_SBOX), MIX),
+        CryptoOp::Aes64Ds => sub_bytes(shift_rows(a, b, true), &AES_INV_SBOX),
+        CryptoOp::Aes64Dsm => mix_columns(sub_bytes(shift_rows(a, b, true), &AES_INV_SBOX), INV_MIX),
+        CryptoOp::Aes64Im => mix_columns(a, INV_MIX),
+        CryptoOp::Aes64Ks1i => {
+            let (word, rcon) = match imm {
+                10 => ((a >> 32) as u32, 0),
+                _ => (((a >> 32) as u32).rotate_right(8), AES_RCON[imm as usize]),
+            };
+            let w = sub_bytes(word as u64, &AES_SBOX) as u32 ^ rcon as u32;
+            (w as u64) << 32 | w as u64
+        }
+        CryptoOp::Aes64Ks2 => {
+            let w0 = (a >> 32) as u32 ^ y;
+            let w1 = w0 ^ (b >> 32) as u32;
+            (w1 as u64) << 32 | w0 as u64
+        }
+        CryptoOp::Sm3P0 => sext32(x ^ x.rotate_left(9) ^ x.rotate_left(17)),
+        CryptoOp::Sm3P1 => sext32(x ^ x.rotate_left(15) ^ x.rotate_left(23)),
+        CryptoOp::Sm4Ed | CryptoOp::Sm4Ks => {
+            let s = SM4_SBOX[byte as usize] as u32;
+            // SM4's linear transforms L and L', which never wrap a single byte
+            let t = match op {
+                CryptoOp::Sm4Ed => s ^ s << 2 ^ s << 10 ^ s << 18 ^ s << 24,
+                _ => s ^ s << 13 ^ s << 23,
+            };
+            sext32(x ^ t.rotate_left(shamt))
+        }
+    };
+    v & xlen_mask(xlen)
+}
@@ -0,0 +1,491 @@
+//! The Zicsr control and status register file.
+//!
+//! Both engines execute CSR instructions through the reactor's `csr` method,
+//! which calls into here, so they always see the same values. CSRs that are
+//! not built in can be supplied by the embedder with a [`CsrHook`].
+use alloc::collections::BTreeMap;
+use alloc::rc::Rc;
+
+use rv_asm::Xlen;
+
+use crate::mmu::SatpMode;
+use crate::trap::{Interrupt, Priv, Trap};
+use crate::xlen_mask;
+
+pub const FFLAGS: u16 = 0x001;
+pub const FRM: u16 = 0x002;
+pub const FCSR: u16 = 0x003;
+pub const VSTART: u16 = 0x008;
+pub const VXSAT: u16 = 0x009;
+pub const VXRM: u16 = 0x00a;
+pub const VCSR: u16 = 0x00f;
+pub const CYCLE: u16 = 0xc00;
+pub const TIME: u16 = 0xc01;
+pub const INSTRET: u16 = 0xc02;
+pub const CYCLEH: u16 = 0xc80;
+pub const TIMEH: u16 = 0xc81;
+pub const INSTRETH: u16 = 0xc82;
+pub const VL: u16 = 0xc20;
+pub const VTYPE: u16 = 0xc21;
+pub const VLENB: u16 = 0xc22;
+pub const SSTATUS: u16 = 0x100;
+pub const SIE: u16 = 0x104;
+pub const STVEC: u16 = 0x105;
+pub const SCOUNTEREN: u16 = 0x106;
+pub const SSCRATCH: u16 = 0x140;
+pub const SEPC: u16 = 0x141;
+pub const SCAUSE: u16 = 0x142;
+pub const STVAL: u16 = 0x143;
+pub const SIP: u16 = 0x144;
+pub const SATP: u16 = 0x180;
+pub const MSTATUS: u16 = 0x300;
+pub const MISA: u16 = 0x301;
+pub const MEDELEG: u16 = 0x302;
+pub const MIDELEG: u16 = 0x303;
+pub const MIE: u16 = 0x304;
+pub const MTVEC: u16 = 0x305;
+pub const MCOUNTEREN: u16 = 0x306;
+pub const MSTATUSH: u16 = 0x310;
+pub const MSCRATCH: u16 = 0x340;
+pub const MEPC: u16 = 0x341;
+pub const MCAUSE: u16 = 0x342;
+pub const MTVAL: u16 = 0x343;
+pub const MIP: u16 = 0x344;
+pub const MCYCLE: u16 = 0xb00;
+pub const MINSTRET: u16 = 0xb02;
+pub const MCYCLEH: u16 = 0xb80;
+pub const MINSTRETH: u16 = 0xb82;
+pub const MVENDORID: u16 = 0xf11;
+pub const MARCHID: u16 = 0xf12;
+pub const MIMPID: u16 = 0xf13;
+pub const MHARTID: u16 = 0xf14;
+
+/// `mstatus` fields
+pub mod mstatus {
+    pub const SIE: u64 = 1 << 1;
+    pub const MIE: u64 = 1 << 3;
+    pub const SPIE: u64 = 1 << 5;
+    pub const MPIE: u64 = 1 << 7;
+    pub const SPP: u64 = 1 << 8;
+    pub const VS: u64 = 3 << 9;
+    pub const MPP: u64 = 3 << 11;
+    pub const FS: u64 = 3 << 13;
+    pub const MPRV: u64 = 1 << 17;
+    pub const SUM: u64 = 1 << 18;
+    pub const MXR: u64 = 1 << 19;
+    pub const TVM: u64 = 1 << 20;
+    pub const TW: u64 = 1 << 21;
+    pub const TSR: u64 = 1 << 22;
+    /// Bits the guest may write
+    pub const WRITABLE: u64 =
+        SIE | MIE | SPIE | MPIE | SPP | VS | MPP | FS | MPRV | SUM | MXR | TVM | TW | TSR;
+    /// Bits visible through `sstatus`
+    pub const SSTATUS: u64 = SIE | SPIE | SPP | VS | FS | SUM | MXR;
+}
+
+/// Interrupt bits of `mie` and `mip`
+const INTERRUPTS: u64 = 0xaaa;
+/// Supervisor interrupt bits, the only ones `mideleg` can delegate
+const S_INTERRUPTS: u64 = 0x222;
+/// Exceptions `medeleg` can delegate; an `ecall` from M-mode never is
+const DELEGABLE: u64 = 0xb3ff;
+
+/// How a CSR instruction combines the old value with its operand
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum CsrOp {
+    /// `csrrw`: replace
+    Rw,
+    /// `csrrs`: set the operand's bits
+    Rs,
+    /// `csrrc`: clear the operand's bits
+    Rc,
+}
+
+impl CsrOp {
+    /// The new CSR value
+    pub fn apply(self, old: u64, value: u64) -> u64 {
+        match self {
+            CsrOp::Rw => value,
+            CsrOp::Rs => old | value,
+            CsrOp::Rc => old & !value,
+        }
+    }
+    pub fn mnemonic(self) -> &'static str {
+        match self {
+            CsrOp::Rw => "csrrw",
+            CsrOp::Rs => "csrrs",
+            CsrOp::Rc => "csrrc",
+        }
+    }
+}
+
+/// Embedder callbacks backing one CSR
+///
+/// The callbacks run without the reactor locked, so they may call back into it.
+#[derive(Clone)]
+pub struct CsrHook {
+    pub read: Rc<dyn Fn() -> u64>,
+    /// `None` makes the CSR read-only
+    pub write: Option<Rc<dyn Fn(u64)>>,
+}
+
+/// Why a CSR access raises an illegal-instruction exception
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum CsrError {
+    /// No such CSR
+    Missing(u16),
+    /// Write to a read-only CSR
+    ReadOnly(u16),
+    /// The CSR needs a higher privilege level
+    Privileged(u16),
+}
+
+impl core::fmt::Display for CsrError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            CsrError::Missing(c) => write!(f, "no such csr: {c:#x}"),
+            CsrError::ReadOnly(c) => write!(f, "csr is read-only: {c:#x}"),
+            CsrError::Privileged(c) => write!(f, "csr needs higher privilege: {c:#x}"),
+        }
+    }
+}
+
+/// CSR state kept alongside the rest of the core
+///
+/// The hart starts in machine mode with `mtvec` zero, which the reactor
+/// takes to mean no guest trap vector is installed.
+pub struct CsrFile {
+    /// Current privilege level
+    pub mode: Priv,
+    /// `fflags` in bits 4:0, `frm` in bits 7:5
+    pub fcsr: u32,
+    pub mstatus: u64,
+    pub mtvec: u64,
+    pub mepc: u64,
+    pub mcause: u64,
+    pub mtval: u64,
+    pub mscratch: u64,
+    pub mie: u64,
+    /// The software-writable bits of `mip`
+    pub mip: u64,
+    /// Interrupts pending from outside the hart, ORed into `mip` as read
+    ///
+    /// The reactor sets these from its CLINT and the host's interrupt lines.
+    pub lines: u64,
+    pub mcounteren: u64,
+    pub medeleg: u64,
+    pub mideleg: u64,
+    pub stvec: u64,
+    pub sepc: u64,
+    pub scause: u64,
+    pub stval: u64,
+    pub sscratch: u64,
+    pub scounteren: u64,
+    /// Raw `satp`; only supported translation modes are ever stored
+    pub satp: u64,
+    hooks: BTreeMap<u16, CsrHook>,
+}
+
+impl Default for CsrFile {
+    fn default() -> Self {
+        CsrFile {
+            mode: Priv::Machine,
+            fcsr: 0,
+            // `FS` and `VS` are reported dirty; both units are always on
+            mstatus: mstatus::FS | mstatus::VS,
+            mtvec: 0,
+            mepc: 0,
+            mcause: 0,
+            mtval: 0,
+            mscratch: 0,
+            mie: 0,
+            mip: 0,
+            lines: 0,
+            mcounteren: 0,
+            medeleg: 0,
+            mideleg: 0,
+            stvec: 0,
+            sepc: 0,
+            scause: 0,
+            stval: 0,
+            sscratch: 0,
+            scounteren: 0,
+            satp: 0,
+            hooks: BTreeMap::new(),
+        }
+    }
+}
+
+/// `misa` extension bits for what both engines implement
+fn misa(xlen: Xlen) -> u64 {
+    let ext = b"ABCDFIMSUV"
+        .iter()
+        .fold(0, |m, &c| m | 1 << (c - b'A'));
+    match xlen {
+        Xlen::Rv32 => 1 << 30 | ext,
+        Xlen::Rv64 => 2 << 62 | ext,
+    }
+}
+
+impl CsrFile {
+    /// Attach `hook` to `csr`, replacing any built-in behaviour
+    pub fn register(&mut self, csr: u16, hook: CsrHook) {
+        self.hooks.insert(csr & 0xfff, hook);
+    }
+    pub fn unregister(&mut self, csr: u16) -> Option<CsrHook> {
+        self.hooks.remove(&(csr & 0xfff))
+    }
+    pub fn hook(&self, csr: u16) -> Option<CsrHook> {
+        self.hooks.get(&csr).cloned()
+    }
+
+    /// Check the privilege level encoded in bits 9:8 of the CSR number
+    ///
+    /// `mstatus.TVM` additionally keeps `satp` from supervisor mode.
+    pub fn check_access(&self, csr: u16) -> Result<(), CsrError> {
+        let tvm = csr == SATP && self.mode == Priv::Supervisor && self.mstatus & mstatus::TVM != 0;
+        match (csr >> 8 & 3) as u8 > self.mode as u8 || tvm {
+            true => Err(CsrError::Privileged(csr)),
+            false => Ok(()),
+        }
+    }
+
+    /// The privilege loads and stores are checked at, after `mstatus.MPRV`
+    pub fn data_priv(&self) -> Priv {
+        match self.mstatus & mstatus::MPRV != 0 {
+            true => Priv::from_bits(self.mstatus >> 11),
+            false => self.mode,
+        }
+    }
+
+    /// `mstatus` as read, with the `SD` and `UXL` fields filled in
+    fn mstatus_view(&self, xlen: Xlen) -> u64 {
+        let dirty = |field| self.mstatus & field == field;
+        let sd = match dirty(mstatus::FS) || dirty(mstatus::VS) {
+            true => 1 << (xlen_mask(xlen).count_ones() - 1),
+            false => 0,
+        };
+        // UXL reads as 64-bit on RV64
+        let uxl = if xlen.is_64() { 2 << 32 } else { 0 };
+        (self.mstatus | sd | uxl) & xlen_mask(xlen)
+    }
+
+    /// Read a built-in CSR
+    ///
+    /// `cycle` and `time` both read `instret`, the count passed in; the
+    /// reactor passes its CLINT's `mtime` for `time`.
+    pub fn read(&self, csr: u16, instret: u64, xlen: Xlen) -> Result<u64, CsrError> {
+        self.check_access(csr)?;
+        Ok(match csr {
+            FFLAGS => (self.fcsr & 0x1f) as u64,
+            FRM => (self.fcsr >> 5 & 0x7) as u64,
+            FCSR => (self.fcsr & 0xff) as u64,
+            CYCLE | TIME | INSTRET | MCYCLE | MINSTRET => instret & xlen_mask(xlen),
+            CYCLEH | TIMEH | INSTRETH | MCYCLEH | MINSTRETH if xlen.is_32() => instret >> 32,
+            MSTATUS => self.mstatus_view(xlen),
+            SSTATUS => {
+                let sd = 1 << (xlen_mask(xlen).count_ones() - 1);
+                self.mstatus_view(xlen) & (mstatus::SSTATUS | sd | 3 << 32)
+            }
+            SIE => self.mie & self.mideleg,
+            SIP => self.pending() & self.mideleg,
+            STVEC => self.stvec,
+            SCOUNTEREN => self.scounteren,
+            SSCRATCH => self.sscratch,
+            SEPC => self.sepc,
+            SCAUSE => self.scause,
+            STVAL => self.stval,
+            SATP => self.satp,
+            MEDELEG => self.medeleg,
+            MIDELEG => self.mideleg,
+            MSTATUSH if xlen.is_32() => 0,
+            MISA => misa(xlen),
+            MIE => self.mie,
+            MTVEC => self.mtvec,
+            MCOUNTEREN => self.mcounteren,
+            MSCRATCH => self.mscratch,
+            MEPC => self.mepc,
+            MCAUSE => self.mcause,
+            MTVAL => self.mtval,
+            MIP => self.pending(),
+            MVENDORID | MARCHID | MIMPID | MHARTID => 0,
+            _ => return Err(CsrError::Missing(csr)),
+        })
+    }
+
+    /// Write a built-in CSR
+    ///
+    /// Writes to `misa` and to the machine counters are ignored, as are
+    /// writes selecting an unsupported `satp` mode.
+    pub fn write(&mut self, csr: u16, value: u64, xlen: Xlen) -> Result<(), CsrError> {
+        self.check_access(csr)?;
+        if csr >> 10 == 3 {
+            return Err(CsrError::ReadOnly(csr));
+        }
+        match csr {
+            FFLAGS => self.fcsr = (self.fcsr & !0x1f) | (value as u32 & 0x1f),
+            FRM => self.fcsr = (self.fcsr & 0x1f) | ((value as u32 & 0x7) << 5),
+            FCSR => self.fcsr = value as u32 & 0xff,
+            MSTATUS => {
+                let mut v = value & mstatus::WRITABLE;
+                // MPP only holds implemented modes
+                if Priv::from_bits(v >> 11) as u64 != v >> 11 & 3 {
+                    v &= !mstatus::MPP;
+                }
+                self.mstatus = v;
+            }
+            SSTATUS => {
+                self.mstatus = (self.mstatus & !mstatus::SSTATUS) | (value & mstatus::SSTATUS);
+            }
+            SIE => self.mie = (self.mie & !self.mideleg) | (value & self.mideleg),
+            SIP => self.mip = (self.mip & !self.mideleg) | (value & self.mideleg & 2),
+            // Only direct and vectored modes exist
+            STVEC => self.stvec = value & !(value & 2),
+            SCOUNTEREN => self.scounteren = value & 0xffff_ffff,
+            SSCRATCH => self.sscratch = value,
+            SEPC => self.sepc = value & !1,
+            SCAUSE => self.scause = value,
+            STVAL => self.stval = value,
+            SATP => {
+                if SatpMode::from_satp(value, xlen).is_some() {
+                    self.satp = value;
+                }
+            }
+            MEDELEG => self.medeleg = value & DELEGABLE,
+            MIDELEG => self.mideleg = value & S_INTERRUPTS,
+            MSTATUSH | MISA | MCYCLE | MINSTRET | MCYCLEH | MINSTRETH => {}
+            MIE => self.mie = value & INTERRUPTS,
+            MTVEC => self.mtvec = value & !(value & 2),
+            MCOUNTEREN => self.mcounteren = value & 0xffff_ffff,
+            MSCRATCH => self.mscratch = value,
+            MEPC => self.mepc = value & !1,
+            MCAUSE => self.mcause = value,
+            MTVAL => self.mtval = value,
+            // The machine bits are driven by the CLINT and the host
+            MIP => self.mip = value & S_INTERRUPTS,
+            _ => return Err(CsrError::Missing(csr)),
+        }
+        Ok(())
+    }
+
+    /// `mip` as read: the written bits plus the external lines
+    pub fn pending(&self) -> u64 {
+        (self.mip | self.lines) & INTERRUPTS
+    }
+
+    /// The interrupt the hart would take before its next instruction
+    ///
+    /// Interrupts not delegated by `mideleg` are taken below machine mode,
+    /// and in machine mode when `mstatus.MIE` is set. Delegated ones are
+    /// taken in user mode, and in supervisor mode when `mstatus.SIE` is set.
+    pub fn pending_interrupt(&self) -> Option<Trap> {
+        let pending = self.pending() & self.mie;
+        let m = match self.mode < Priv::Machine || self.mstatus & mstatus::MIE != 0 {
+            true => pending & !self.mideleg,
+            false => 0,
+        };
+        let s = match self.mode {
+            Priv::User => pending & self.mideleg,
+            Priv::Supervisor if self.mstatus & mstatus::SIE != 0 => pending & self.mideleg,
+            _ => 0,
+        };
+        let i = Interrupt::ALL
+            .into_iter()
+            .find(|i| m & i.bit() != 0)
+            .or_else(|| Interrupt::ALL.into_iter().find(|i| s & i.bit() != 0))?;
+        Some(Trap::interrupt(i))
+    }
+
+    /// Take `trap`, returning the handler address
+    ///
+    /// Traps from below machine mode go to supervisor mode when `medeleg`
+    /// or `mideleg` delegates them.
+    pub fn trap(&mut self, trap: &Trap, pc: u64, xlen: Xlen) -> u64 {
+        let mask = xlen_mask(xlen);
+        let deleg = match trap.interrupt {
+            true => self.mideleg,
+            false => self.medeleg,
+        };
+        let tvec = match self.mode != Priv::Machine && deleg >> trap.code & 1 == 1 {
+            true => {
+                self.sepc = pc & mask;
+                self.scause = trap.cause(xlen);
+                self.stval = trap.tval & mask;
+                let sie = self.mstatus & mstatus::SIE != 0;
+                self.mstatus &= !(mstatus::SIE | mstatus::SPIE | mstatus::SPP);
+                if sie {
+                    self.mstatus |= mstatus::SPIE;
+                }
+                if self.mode == Priv::Supervisor {
+                    self.mstatus |= mstatus::SPP;
+                }
+                self.mode = Priv::Supervisor;
+                self.stvec
+            }
+            false => {
+                self.mepc = pc & mask;
+                self.mcause = trap.cause(xlen);
+                self.mtval = trap.tval & mask;
+                let mie = self.mstatus & mstatus::MIE != 0;
+                self.mstatus &= !(mstatus::MIE | mstatus::MPIE | mstatus::MPP);
+                if mie {
+                    self.mstatus |= mstatus::MPIE;
+                }
+                self.mstatus |= (self.mode as u64) << 11;
+                self.mode = Priv::Machine;
+                self.mtvec
+            }
+        };
+        let base = tvec & !3;
+        match trap.interrupt && tvec & 1 == 1 {
+            true => base.wrapping_add(4 * trap.code) & mask,
+            false => base,
+        }
+    }
+
+    /// Return from a machine-mode trap, or `None` if `mret` is illegal here
+    pub fn mret(&mut self) -> Option<u64> {
+        if self.mode != Priv::Machine {
+            return None;
+        }
+        self.mode = Priv::from_bits(self.mstatus >> 11);
+        let mpie = self.mstatus & mstatus::MPIE != 0;
+        self.mstatus &= !(mstatus::MIE | mstatus::MPP);
+        self.mstatus |= mstatus::MPIE;
+        if mpie {
+            self.mstatus |= mstatus::MIE;
+        }
+        if self.mode != Priv::Machine {
+            self.mstatus &= !mstatus::MPRV;
+        }
+        Some(self.mepc)
+    }
+
+    /// Return from a supervisor trap, or `None` if `sret` is illegal here
+    pub fn sret(&mut self) -> Option<u64> {
+        let tsr = self.mstatus & mstatus::TSR != 0;
+        if self.mode < Priv::Supervisor || (self.mode == Priv::Supervisor && tsr) {
+            return None;
+        }
+        self.mode = match self.mstatus & mstatus::SPP != 0 {
+            true => Priv::Supervisor,
+            false => Priv::User,
+        };
+        let spie = self.mstatus & mstatus::SPIE != 0;
+        self.mstatus &= !(mstatus::SIE | mstatus::SPP | mstatus::MPRV);
+        self.mstatus |= mstatus::SPIE;
+        if spie {
+            self.mstatus |= mstatus::SIE;
+        }
+        Some(self.sepc)
+    }
+
+    /// Whether `sfence.vma` may execute
+    pub fn sfence_allowed(&self) -> bool {
+        match self.mode {
+            Priv::Machine => true,
+            Priv::Supervisor => self.mstatus & mstatus::TVM == 0,
+            Priv::User => false,
+        }
+    }
+}
@@ -0,0 +1,734 @@
+//! Decoding for instructions that `rv_asm` does not understand.
+//!
+//! `rv_asm` covers RV32/RV64 IMA and [`crate::rvc`] the integer part of C.
+//! Everything else the engines support is decoded here, and both engines try
+//! [`ExtInst::decode`] whenever [`crate::rvc::decode`] fails.
+use core::fmt::{self, Display, Formatter};
+
+use rv_asm::{AmoOp, AmoOrdering, Imm, Inst, IsCompressed, Reg, Xlen};
+
+use crate::bit::BitOp;
+use crate::crypto::CryptoOp;
+use crate::csr::CsrOp;
+use crate::fp::{FpFmt, FpOp};
+use crate::vector::VInst;
+
+/// An instruction decoded by this crate rather than by `rv_asm`
+///
+/// Floating-point register operands are stored in [`Reg`] as well; which
+/// register file a field refers to is given by the variant and operation.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum ExtInst {
+    /// `flw` / `fld`
+    FLoad {
+        fmt: FpFmt,
+        offset: Imm,
+        dest: Reg,
+        base: Reg,
+    },
+    /// `fsw` / `fsd`
+    FStore {
+        fmt: FpFmt,
+        offset: Imm,
+        src: Reg,
+        base: Reg,
+    },
+    /// Every other F/D instruction
+    Fp {
+        op: FpOp,
+        fmt: FpFmt,
+        rm: u8,
+        dest: Reg,
+        src1: Reg,
+        src2: Reg,
+        src3: Reg,
+    },
+    /// Load-Reserved Doubleword (**RV64 only**)
+    LrD {
+        order: AmoOrdering,
+        dest: Reg,
+        addr: Reg,
+    },
+    /// Store-Conditional Doubleword (**RV64 only**)
+    ScD {
+        order: AmoOrdering,
+        dest: Reg,
+        addr: Reg,
+        src: Reg,
+    },
+    /// Atomic Memory Operation on a doubleword (**RV64 only**)
+    AmoD {
+        order: AmoOrdering,
+        op: AmoOp,
+        dest: Reg,
+        addr: Reg,
+        src: Reg,
+    },
+    /// `csrrw` / `csrrs` / `csrrc`
+    Csr {
+        op: CsrOp,
+        csr: u16,
+        dest: Reg,
+        src: Reg,
+    },
+    /// `csrrwi` / `csrrsi` / `csrrci`
+    CsrI {
+        op: CsrOp,
+        csr: u16,
+        dest: Reg,
+        uimm: u8,
+    },
+    /// Register-register Zba/Zbb/Zbs/Zbc operation
+    Bit {
+        op: BitOp,
+        dest: Reg,
+        src1: Reg,
+        src2: Reg,
+    },
+    /// `slli.uw`, `rori`, `roriw`, `bclri`, `bexti`, `binvi` and `bseti`
+    BitImm {
+        op: BitOp,
+        dest: Reg,
+        src1: Reg,
+        shamt: u8,
+    },
+    /// Single-operand Zbb operation such as `clz` or `rev8`
+    BitUnary { op: BitOp, dest: Reg, src: Reg },
+    /// Scalar cryptography; `src2` is `x0` for unary operations and `imm`
+    /// is the byte select or round number of the few that take one
+    Crypto {
+        op: CryptoOp,
+        dest: Reg,
+        src1: Reg,
+        src2: Reg,
+        imm: u8,
+    },
+    /// Machine-mode trap return
+    Mret,
+    /// Supervisor-mode trap return
+    Sret,
+    /// Address-translation fence; `vaddr` and `asid` are only read
+    SfenceVma { vaddr: Reg, asid: Reg },
+    /// Wait for interrupt; both engines treat it as a `nop`
+    Wfi,
+    /// Instruction-fetch fence; drops compiled blocks whose code was stored to
+    FenceI,
+    /// Any V-extension instruction; see [`crate::vector`]
+    Vector(VInst),
+}
+
+impl ExtInst {
+    /// Whether a Zicsr instruction reads and writes its CSR
+    ///
+    /// `csrrw` with `rd == x0` does not read, and a set or clear whose
+    /// operand is `x0` or zero does not write, so neither has side effects.
+    pub fn csr_effects(&self) -> Option<(bool, bool)> {
+        let (op, dest, zero_src) = match *self {
+            ExtInst::Csr { op, dest, src, .. } => (op, dest, src.0 == 0),
+            ExtInst::CsrI { op, dest, uimm, .. } => (op, dest, uimm == 0),
+            _ => return None,
+        };
+        Some(match op {
+            CsrOp::Rw => (dest.0 != 0, true),
+            CsrOp::Rs | CsrOp::Rc => (true, !zero_src),
+        })
+    }
+}
+
+/// Either an `rv_asm` instruction or one of ours
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub enum AnyInst {
+    Base(Inst),
+    Ext(ExtInst),
+}
+
+/// Decode with [`crate::rvc::decode`] first, falling back to [`ExtInst::decode`]
+pub fn decode(code: u32, xlen: Xlen) -> Result<(AnyInst, IsCompressed), rv_asm::DecodeError> {
+    match crate::rvc::decode(code, xlen) {
+        Ok((i, c)) => Ok((AnyInst::Base(i), c)),
+        Err(e) => match ExtInst::decode(code, xlen) {
+            Some((i, c)) => Ok((AnyInst::Ext(i), c)),
+            None => Err(e),
+        },
+    }
+}
+
+fn bits(code: u32, lo: u32, hi: u32) -> u32 {
+    (code >> lo) & ((1 << (hi - lo + 1)) - 1)
+}
+
+fn reg(code: u32, lo: u32) -> Reg {
+    Reg(bits(code, lo, lo + 4) as u8)
+}
+
+/// `rd'`/`rs1'`/`rs2'` of a compressed instruction
+fn creg(code: u32, lo: u32) -> Reg {
+    Reg(bits(code, lo, lo + 2) as u8 + 8)
+}
+
+/// Gather an unsigned compressed immediate from `(lo, hi, shift)` fields
+fn cimm(code: u32, fields: &[(u32, u32, u32)]) -> Imm {
+    Imm::new_u32(
+        fields
+            .iter()
+            .fold(0, |acc, &(lo, hi, to)| acc | (bits(code, lo, hi) << to)),
+    )
+}
+
+fn imm_i(code: u32) -> Imm {
+    Imm::new_i32(code as i32 >> 20)
+}
+
+fn imm_s(code: u32) -> Imm {
+    Imm::new_i32(((code & 0xfe00_0000) as i32 >> 20) | bits(code, 7, 11) as i32)
+}
+
+impl ExtInst {
+    /// Decode an instruction from four bytes, which may hold a compressed one
+    pub fn decode(code: u32, xlen: Xlen) -> Option<(ExtInst, IsCompressed)> {
+        if code & 0b11 != 0b11 {
+            return Some((Self::decode_compressed(code & 0xffff, xlen)?, IsCompressed::Yes));
+        }
+        Some((Self::decode_normal(code, xlen)?, IsCompressed::No))
+    }
+
+    fn decode_compressed(code: u32, xlen: Xlen) -> Option<ExtInst> {
+        let sp = Reg::SP;
+        let d_off = [(10, 12, 3), (5, 6, 6)];
+        let w_off = [(10, 12, 3), (6, 6, 2), (5, 5, 6)];
+        Some(match (code & 0b11, bits(code, 13, 15)) {
+            // C.FLD
+            (0b00, 0b001) => ExtInst::FLoad {
+                fmt: FpFmt::D,
+                offset: cimm(code, &d_off),
+                dest: creg(code, 2),
+                base: creg(code, 7),
+            },
+            // C.FSD
+            (0b00, 0b101) => ExtInst::FStore {
+                fmt: FpFmt::D,
+                offset: cimm(code, &d_off),
+                src: creg(code, 2),
+                base: creg(code, 7),
+            },
+            // C.FLW (RV32 only; C.LD on RV64)
+            (0b00, 0b011) if xlen.is_32() => ExtInst::FLoad {
+                fmt: FpFmt::S,
+                offset: cimm(code, &w_off),
+                dest: creg(code, 2),
+                base: creg(code, 7),
+            },
+            // C.FSW (RV32 only; C.SD on RV64)
+            (0b00, 0b111) if xlen.is_32() => ExtInst::FStore {
+                fmt: FpFmt::S,
+                offset: cimm(code, &w_off),
+                src: creg(code, 2),
+                base: creg(code, 7),
+            },
+            // C.FLDSP
+            (0b10, 0b001) => ExtInst::FLoad {
+                fmt: FpFmt::D,
+                offset: cimm(code, &[(12, 12, 5), (5, 6, 3), (2, 4, 6)]),
+                dest: reg(code, 7),
+                base: sp,
+            },
+            // C.FSDSP
+            (0b10, 0b101) => ExtInst::FStore {
+                fmt: FpFmt::D,
+                offset: cimm(code, &[(10, 12, 3), (7, 9, 6)]),
+                src: reg(code, 2),
+                base: sp,
+            },
+            // C.FLWSP (RV32 only)
+            (0b10, 0b011) if xlen.is_32() => ExtInst::FLoad {
+                fmt: FpFmt::S,
+                offset: cimm(code, &[(12, 12, 5), (4, 6, 2), (2, 3, 6)]),
+                dest: reg(code, 7),
+                base: sp,
+            },
+            // C.FSWSP (RV32 only)
+            (0b10, 0b111) if xlen.is_32() => ExtInst::FStore {
+                fmt: FpFmt::S,
+                offset: cimm(code, &[(9, 12, 2), (7, 8, 6)]),
+                src: reg(code, 2),
+                base: sp,
+            },
+            _ => return None,
+        })
+    }
+
+    fn decode_normal(code: u32, xlen: Xlen) -> Option<ExtInst> {
+        let funct3 = bits(code, 12, 14);
+        let funct7 = bits(code, 25, 31);
+        let (rd, rs1, rs2) = (reg(code, 7), reg(code, 15), reg(code, 20));
+        let ls_fmt = match funct3 {
+            0b010 => Some(FpFmt::S),
+            0b011 => Some(FpFmt::D),
+            _ => None,
+        };
+        let fmt = match funct7 & 0b11 {
+            0b00 => Some(FpFmt::S),
+            0b01 => Some(FpFmt::D),
+            _ => None,
+        };
+        let fp = |op: FpOp, fmt: FpFmt| {
+            // Reserved static rounding modes are illegal
+            if op.uses_rm() && matches!(funct3, 0b101 | 0b110) {
+                return None;
+            }
+            Some(ExtInst::Fp {
+                op,
+                fmt,
+                rm: funct3 as u8,
+                dest: rd,
+                src1: rs1,
+                src2: rs2,
+                src3: reg(code, 27),
+            })
+        };
+        let bit = |op: BitOp| {
+            Some(ExtInst::Bit {
+                op,
+                dest: rd,
+                src1: rs1,
+                src2: rs2,
+            })
+        };
+        let unary = |op: BitOp| Some(ExtInst::BitUnary { op, dest: rd, src: rs1 });
+        let crypto = |op: CryptoOp, src2: Reg, imm: u8| {
+            Some(ExtInst::Crypto {
+                op,
+                dest: rd,
+                src1: rs1,
+                src2,
+                imm,
+            })
+        };
+        // `aes32*` and `sm4*` keep their byte select in funct7's top bits
+        let bs = (funct7 >> 5) as u8;
+        let shamt = bits(code, 20, 25) as u8;
+        let imm = |op: BitOp, shamt: u8| {
+            Some(ExtInst::BitImm {
+                op,
+                dest: rd,
+                src1: rs1,
+                shamt,
+            })
+        };
+        match code & 0x7f {
+            // OP; `rv_asm` covers the base and M encodings
+            0b0110011 => match (funct7, funct3) {
+                (0b0010000, 0b010) => bit(BitOp::Sh1add),
+                (0b0010000, 0b100) => bit(BitOp::Sh2add),
+                (0b0010000, 0b110) => bit(BitOp::Sh3add),
+                (0b0100000, 0b111) => bit(BitOp::Andn),
+                (0b0100000, 0b110) => bit(BitOp::Orn),
+                (0b0100000, 0b100) => bit(BitOp::Xnor),
+                (0b0000101, 0b001) => bit(BitOp::Clmul),
+                (0b0000101, 0b010) => bit(BitOp::Clmulr),
+                (0b0000101, 0b011) => bit(BitOp::Clmulh),
+                (0b0000101, 0b100) => bit(BitOp::Min),
+                (0b0000101, 0b101) => bit(BitOp::Minu),
+                (0b0000101, 0b110) => bit(BitOp::Max),
+                (0b0000101, 0b111) => bit(BitOp::Maxu),
+                (0b0110000, 0b001) => bit(BitOp::Rol),
+                (0b0110000, 0b101) => bit(BitOp::Ror),
+                (0b0100100, 0b001) => bit(BitOp::Bclr),
+                (0b0100100, 0b101) => bit(BitOp::Bext),
+                (0b0110100, 0b001) => bit(BitOp::Binv),
+                (0b0010100, 0b001) => bit(BitOp::Bset),
+                (0b0000100, 0b100) if rs2.0 == 0 && xlen.is_32() => unary(BitOp::ZextH),
+                (0b0000100, 0b100) => crypto(CryptoOp::Pack, rs2, 0),
+                (0b0000100, 0b111) => crypto(CryptoOp::Packh, rs2, 0),
+                (0b0010100, 0b010) => crypto(CryptoOp::Xperm4, rs2, 0),
+                (0b0010100, 0b100) => crypto(CryptoOp::Xperm8, rs2, 0),
+                (0b0101000, 0b000) if xlen.is_32() => crypto(CryptoOp::Sha512Sum0r, rs2, 0),
+                (0b0101001, 0b000) if xlen.is_32() => crypto(CryptoOp::Sha512Sum1r, rs2, 0),
+                (0b0101010, 0b000) if xlen.is_32() => crypto(CryptoOp::Sha512Sig0l, rs2, 0),
+                (0b0101011, 0b000) if xlen.is_32() => crypto(CryptoOp::Sha512Sig1l, rs2, 0),
+                (0b0101110, 0b000) if xlen.is_32() => crypto(CryptoOp::Sha512Sig0h, rs2, 0),
+                (0b0101111, 0b000) if xlen.is_32() => crypto(CryptoOp::Sha512Sig1h, rs2, 0),
+                (0b0011001, 0b000) if xlen.is_64() => crypto(CryptoOp::Aes64Es, rs2, 0),
+                (0b0011011, 0b000) if xlen.is_64() => crypto(CryptoOp::Aes64Esm, rs2, 0),
+                (0b0011101, 0b000) if xlen.is_64() => crypto(CryptoOp::Aes64Ds, rs2, 0),
+                (0b0011111, 0b000) if xlen.is_64() => crypto(CryptoOp::Aes64Dsm, rs2, 0),
+                (0b0111111, 0b000) if xlen.is_64() => crypto(CryptoOp::Aes64Ks2, rs2, 0),
+                (f7, 0b000) => match f7 & 0x1f {
+                    0b10001 if xlen.is_32() => crypto(CryptoOp::Aes32Esi, rs2, bs),
+                    0b10011 if xlen.is_32() => crypto(CryptoOp::Aes32Esmi, rs2, bs),
+                    0b10101 if xlen.is_32() => crypto(CryptoOp::Aes32Dsi, rs2, bs),
+                    0b10111 if xlen.is_32() => crypto(CryptoOp::Aes32Dsmi, rs2, bs),
+                    0b11000 => crypto(CryptoOp::Sm4Ed, rs2, bs),
+                    0b11010 => crypto(CryptoOp::Sm4Ks, rs2, bs),
+                    _ => None,
+                },
+                _ => None,
+            },
+            // OP-32
+            0b0111011 if xlen.is_64() => match (funct7, funct3) {
+                (0b0000100, 0b000) => bit(BitOp::AddUw),
+                (0b0000100, 0b100) if rs2.0 == 0 => unary(BitOp::ZextH),
+                (0b0000100, 0b100) => crypto(CryptoOp::Packw, rs2, 0),
+                (0b0010000, 0b010) => bit(BitOp::Sh1addUw),
+                (0b0010000, 0b100) => bit(BitOp::Sh2addUw),
+                (0b0010000, 0b110) => bit(BitOp::Sh3addUw),
+                (0b0110000, 0b001) => bit(BitOp::Rolw),
+                (0b0110000, 0b101) => bit(BitOp::Rorw),
+                _ => None,
+            },
+            // OP-IMM; RV32 shift amounts must fit in five bits
+            0b0010011 => match (bits(code, 20, 31), funct3) {
+                (0x600, 0b001) => unary(BitOp::Clz),
+                (0x601, 0b001) => unary(BitOp::Ctz),
+                (0x602, 0b001) => unary(BitOp::Cpop),
+                (0x604, 0b001) => unary(BitOp::SextB),
+                (0x605, 0b001) => unary(BitOp::SextH),
+                (0x287, 0b101) => unary(BitOp::OrcB),
+                (0x698, 0b101) if xlen.is_32() => unary(BitOp::Rev8),
+                (0x6b8, 0b101) if xlen.is_64() => unary(BitOp::Rev8),
+                (0x687, 0b101) => crypto(CryptoOp::Brev8, Reg::ZERO, 0),
+                (0x08f, 0b001) if xlen.is_32() => crypto(CryptoOp::Zip, Reg::ZERO, 0),
+                (0x08f, 0b101) if xlen.is_32() => crypto(CryptoOp::Unzip, Reg::ZERO, 0),
+                (0x100, 0b001) => crypto(CryptoOp::Sha256Sum0, Reg::ZERO, 0),
+                (0x101, 0b001) => crypto(CryptoOp::Sha256Sum1, Reg::ZERO, 0),
+                (0x102, 0b001) => crypto(CryptoOp::Sha256Sig0, Reg::ZERO, 0),
+                (0x103, 0b001) => crypto(CryptoOp::Sha256Sig1, Reg::ZERO, 0),
+                (0x104, 0b001) if xlen.is_64() => crypto(CryptoOp::Sha512Sum0, Reg::ZERO, 0),
+                (0x105, 0b001) if xlen.is_64() => crypto(CryptoOp::Sha512Sum1, Reg::ZERO, 0),
+                (0x106, 0b001) if xlen.is_64() => crypto(CryptoOp::Sha512Sig0, Reg::ZERO, 0),
+                (0x107, 0b001) if xlen.is_64() => crypto(CryptoOp::Sha512Sig1, Reg::ZERO, 0),
+                (0x108, 0b001) => crypto(CryptoOp::Sm3P0, Reg::ZERO, 0),
+                (0x109, 0b001) => crypto(CryptoOp::Sm3P1, Reg::ZERO, 0),
+                (0x300, 0b001) if xlen.is_64() => crypto(CryptoOp::Aes64Im, Reg::ZERO, 0),
+                // Round numbers above 10 are reserved
+                (0x310..=0x31a, 0b001) if xlen.is_64() => crypto(CryptoOp::Aes64Ks1i, Reg::ZERO, (code >> 20 & 0xf) as u8),
+                _ if xlen.is_32() && shamt >= 32 => None,
+                (i, f3) => match (i >> 6, f3) {
+                    (0b010010, 0b001) => imm(BitOp::Bclr, shamt),
+                    (0b010010, 0b101) => imm(BitOp::Bext, shamt),
+                    (0b011010, 0b001) => imm(BitOp::Binv, shamt),
+                    (0b001010, 0b001) => imm(BitOp::Bset, shamt),
+                    (0b011000, 0b101) => imm(BitOp::Ror, shamt),
+                    _ => None,
+                },
+            },
+            // OP-IMM-32
+            0b0011011 if xlen.is_64() => match (bits(code, 20, 31), funct3) {
+                (0x600, 0b001) => unary(BitOp::Clzw),
+                (0x601, 0b001) => unary(BitOp::Ctzw),
+                (0x602, 0b001) => unary(BitOp::Cpopw),
+                (i, 0b001) if i >> 6 == 0b000010 => imm(BitOp::SlliUw, shamt),
+                (i, 0b101) if i >> 5 == 0b0110000 => imm(BitOp::Rorw, shamt),
+                _ => None,
+            },
+            // MISC-MEM; `rv_asm` only knows `fence`
+            0b0001111 if funct3 == 0b001 => Some(ExtInst::FenceI),
+            // Vector loads and stores share LOAD-FP and STORE-FP
+            0b0000111 | 0b0100111 if ls_fmt.is_none() => VInst::decode(code).map(ExtInst::Vector),
+            // OP-V
+            0b1010111 => VInst::decode(code).map(ExtInst::Vector),
+            // LOAD-FP
+            0b0000111 => Some(ExtInst::FLoad {
+                fmt: ls_fmt?,
+                offset: imm_i(code),
+                dest: rd,
+                base: rs1,
+            }),
+            // STORE-FP
+            0b0100111 => Some(ExtInst::FStore {
+                fmt: ls_fmt?,
+                offset: imm_s(code),
+                src: rs2,
+                base: rs1,
+            }),
+            // AMO; `rv_asm` only knows the word forms
+            0b0101111 if funct3 == 0b011 && xlen.is_64() => {
+                let order = AmoOrdering::from_aq_rl(bits(code, 26, 26) == 1, bits(code, 25, 25) == 1);
+                let op = match funct7 >> 2 {
+                    0b00010 if rs2.0 == 0 => {
+                        return Some(ExtInst::LrD {
+                            order,
+                            dest: rd,
+                            addr: rs1,
+                        })
+                    }
+                    0b00011 => {
+                        return Some(ExtInst::ScD {
+                            order,
+                            dest: rd,
+                            addr: rs1,
+                            src: rs2,
+                        })
+                    }
+                    0b00001 => AmoOp::Swap,
+                    0b00000 => AmoOp::Add,
+                    0b00100 => AmoOp::Xor,
+                    0b01100 => AmoOp::And,
+                    0b01000 => AmoOp::Or,
+                    0b10000 => AmoOp::Min,
+                    0b10100 => AmoOp::Max,
+                    0b11000 => AmoOp::Minu,
+                    0b11100 => AmoOp::Maxu,
+                    _ => return None,
+                };
+                Some(ExtInst::AmoD {
+                    order,
+                    op,
+                    dest: rd,
+                    addr: rs1,
+                    src: rs2,
+                })
+            }
+            // SYSTEM; `rv_asm` only knows `ecall` and `ebreak`
+            0b1110011 => {
+                match code {
+                    0x30200073 => return Some(ExtInst::Mret),
+                    0x10200073 => return Some(ExtInst::Sret),
+                    0x10500073 => return Some(ExtInst::Wfi),
+                    _ if code & 0xfe007fff == 0x12000073 => {
+                        return Some(ExtInst::SfenceVma {
+                            vaddr: rs1,
+                            asid: rs2,
+                        })
+                    }
+                    _ => {}
+                }
+                let op = match funct3 & 0b11 {
+                    0b01 => CsrOp::Rw,
+                    0b10 => CsrOp::Rs,
+                    0b11 => CsrOp::Rc,
+                    _ => return None,
+                };
+                let csr = bits(code, 20, 31) as u16;
+                Some(match funct3 & 0b100 {
+                    0 => ExtInst::Csr {
+                        op,
+                        csr,
+                        dest: rd,
+                        src: rs1,
+                    },
+                    _ => ExtInst::CsrI {
+                        op,
+                        csr,
+                        dest: rd,
+                        uimm: rs1.0,
+                    },
+                })
+            }
+            0b1000011 => fp(FpOp::Madd, fmt?),
+            0b1000111 => fp(FpOp::Msub, fmt?),
+            0b1001011 => fp(FpOp::Nmsub, fmt?),
+            0b1001111 => fp(FpOp::Nmadd, fmt?),
+            // OP-FP
+            0b1010011 => {
+                let fmt = fmt?;
+                let rs2n = rs2.0;
+                let op = match (funct7 >> 2, funct3, rs2n) {
+                    (0b00000, _, _) => FpOp::Add,
+                    (0b00001, _, _) => FpOp::Sub,
+                    (0b00010, _, _) => FpOp::Mul,
+                    (0b00011, _, _) => FpOp::Div,
+                    (0b01011, _, 0) => FpOp::Sqrt,
+                    (0b00100, 0b000, _) => FpOp::Sgnj,
+                    (0b00100, 0b001, _) => FpOp::Sgnjn,
+                    (0b00100, 0b010, _) => FpOp::Sgnjx,
+                    (0b00101, 0b000, _) => FpOp::Min,
+                    (0b00101, 0b001, _) => FpOp::Max,
+                    // fcvt.s.d takes a D source, fcvt.d.s an S source
+                    (0b01000, _, 1) if fmt == FpFmt::S => FpOp::Cvt,
+                    (0b01000, _, 0) if fmt == FpFmt::D => FpOp::Cvt,
+                    (0b10100, 0b010, _) => FpOp::Eq,
+                    (0b10100, 0b001, _) => FpOp::Lt,
+                    (0b10100, 0b000, _) => FpOp::Le,
+                    (0b11000, _, 0) => FpOp::ToW,
+                    (0b11000, _, 1) => FpOp::ToWu,
+                    (0b11000, _, 2) if xlen.is_64() => FpOp::ToL,
+                    (0b11000, _, 3) if xlen.is_64() => FpOp::ToLu,
+                    (0b11010, _, 0) => FpOp::FromW,
+                    (0b11010, _, 1) => FpOp::FromWu,
+                    (0b11010, _, 2) if xlen.is_64() => FpOp::FromL,
+                    (0b11010, _, 3) if xlen.is_64() => FpOp::FromLu,
+                    (0b11100, 0b000, 0) if fmt == FpFmt::S || xlen.is_64() => FpOp::MvToX,
+                    (0b11100, 0b001, 0) => FpOp::Class,
+                    (0b11110, 0b000, 0) if fmt == FpFmt::S || xlen.is_64() => FpOp::MvFromX,
+                    _ => return None,
+                };
+                fp(op, fmt)
+            }
+            _ => None,
+        }
+    }
+}
+
+struct FReg(Reg);
+impl Display for FReg {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "f{}", self.0.0)
+    }
+}
+
+impl Display for FpFmt {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            FpFmt::S => write!(f, "s"),
+            FpFmt::D => write!(f, "d"),
+        }
+    }
+}
+
+/// Prints the instruction in disassembled form.
+impl Display for ExtInst {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match *self {
+            ExtInst::FLoad {
+                fmt,
+                offset,
+                dest,
+                base,
+            } => {
+                let w = if fmt == FpFmt::S { "w" } else { "d" };
+                write!(f, "fl{w} {}, {}({base})", FReg(dest), offset.as_i64())
+            }
+            ExtInst::FStore {
+                fmt,
+                offset,
+                src,
+                base,
+            } => {
+                let w = if fmt == FpFmt::S { "w" } else { "d" };
+                write!(f, "fs{w} {}, {}({base})", FReg(src), offset.as_i64())
+            }
+            ExtInst::Fp {
+                op,
+                fmt,
+                dest,
+                src1,
+                src2,
+                src3,
+                ..
+            } => {
+                let m = op.mnemonic();
+                match op {
+                    FpOp::FromW | FpOp::FromWu | FpOp::FromL | FpOp::FromLu => {
+                        let m = m.trim_start_matches("fcvt.");
+                        write!(f, "fcvt.{fmt}.{m} {}, {src1}", FReg(dest))
+                    }
+                    FpOp::ToW | FpOp::ToWu | FpOp::ToL | FpOp::ToLu => {
+                        write!(f, "{m}.{fmt} {dest}, {}", FReg(src1))
+                    }
+                    FpOp::Cvt => {
+                        let from = if fmt == FpFmt::S { "d" } else { "s" };
+                        write!(f, "fcvt.{fmt}.{from} {}, {}", FReg(dest), FReg(src1))
+                    }
+                    FpOp::MvToX => {
+                        let w = if fmt == FpFmt::S { "w" } else { "d" };
+                        write!(f, "{m}.{w} {dest}, {}", FReg(src1))
+                    }
+                    FpOp::MvFromX => {
+                        let w = if fmt == FpFmt::S { "w" } else { "d" };
+                        write!(f, "{m}.{w}.x {}, {src1}", FReg(dest))
+                    }
+                    FpOp::Eq | FpOp::Lt | FpOp::Le => {
+                        write!(f, "{m}.{fmt} {dest}, {}, {}", FReg(src1), FReg(src2))
+                    }
+                    FpOp::Class => write!(f, "{m}.{fmt} {dest}, {}", FReg(src1)),
+                    FpOp::Sqrt => write!(f, "{m}.{fmt} {}, {}", FReg(dest), FReg(src1)),
+                    FpOp::Madd | FpOp::Msub | FpOp::Nmsub | FpOp::Nmadd => write!(
+                        f,
+                        "{m}.{fmt} {}, {}, {}, {}",
+                        FReg(dest),
+                        FReg(src1),
+                        FReg(src2),
+                        FReg(src3)
+                    ),
+                    _ => write!(
+                        f,
+                        "{m}.{fmt} {}, {}, {}",
+                        FReg(dest),
+                        FReg(src1),
+                        FReg(src2)
+                    ),
+                }
+            }
+            ExtInst::LrD { order, dest, addr } => write!(f, "lr.d{order} {dest}, ({addr})"),
+            ExtInst::ScD {
+                order,
+                dest,
+                addr,
+                src,
+            } => write!(f, "sc.d{order} {dest}, {src}, ({addr})"),
+            ExtInst::AmoD {
+                order,
+                op,
+                dest,
+                addr,
+                src,
+            } => write!(f, "amo{op}.d{order} {dest}, {src}, ({addr})"),
+            ExtInst::Csr { op, csr, dest, src } => {
+                write!(f, "{} {dest}, {csr:#x}, {src}", op.mnemonic())
+            }
+            ExtInst::CsrI {
+                op,
+                csr,
+                dest,
+                uimm,
+            } => write!(f, "{}i {dest}, {csr:#x}, {uimm}", op.mnemonic()),
+            ExtInst::Bit {
+                op,
+                dest,
+                src1,
+                src2,
+            } => write!(f, "{} {dest}, {src1}, {src2}", op.mnemonic()),
+            ExtInst::BitImm {
+                op,
+                dest,
+                src1,
+                shamt,
+            } => match op {
+                BitOp::SlliUw => write!(f, "slli.uw {dest}, {src1}, {shamt}"),
+                BitOp::Rorw => write!(f, "roriw {dest}, {src1}, {shamt}"),
+                op => write!(f, "{}i {dest}, {src1}, {shamt}", op.mnemonic()),
+            },
+            ExtInst::BitUnary { op, dest, src } => write!(f, "{} {dest}, {src}", op.mnemonic()),
+            ExtInst::Crypto {
+                op,
+                dest,
+                src1,
+                src2,
+                imm,
+            } => match op {
+                CryptoOp::Aes64Ks1i => write!(f, "aes64ks1i {dest}, {src1}, {imm}"),
+                op if op.is_unary() => write!(f, "{} {dest}, {src1}", op.mnemonic()),
+                CryptoOp::Aes32Esi
+                | CryptoOp::Aes32Esmi
+                | CryptoOp::Aes32Dsi
+                | CryptoOp::Aes32Dsmi
+                | CryptoOp::Sm4Ed
+                | CryptoOp::Sm4Ks => write!(f, "{} {dest}, {src1}, {src2}, {imm}", op.mnemonic()),
+                op => write!(f, "{} {dest}, {src1}, {src2}", op.mnemonic()),
+            },
+            ExtInst::Mret => write!(f, "mret"),
+            ExtInst::Sret => write!(f, "sret"),
+            ExtInst::SfenceVma { vaddr, asid } => write!(f, "sfence.vma {vaddr}, {asid}"),
+            ExtInst::Wfi => write!(f, "wfi"),
+            ExtInst::FenceI => write!(f, "fence.i"),
+            ExtInst::Vector(i) => Display::fmt(&i, f),
+        }
+    }
+}
+
+impl fmt::Debug for ExtInst {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        Display::fmt(self, f)
+    }
+}
+
+impl Display for AnyInst {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            AnyInst::Base(i) => Display::fmt(i, f),
+            AnyInst::Ext(i) => Display::fmt(i, f),
+        }
+    }
+}
@@ -0,0 +1,138 @@
+//! A writer for flattened device tree blobs (DTB, version 17).
+//!
+//! Nodes and properties are written in order, as `dtc` would lay them out;
+//! [`Fdt::finish`] adds the header, an empty memory reservation map and the
+//! strings block. Everything is big-endian, per the devicetree spec.
+use alloc::vec::Vec;
+
+const MAGIC: u32 = 0xd00d_feed;
+const BEGIN_NODE: u32 = 1;
+const END_NODE: u32 = 2;
+const PROP: u32 = 3;
+const END: u32 = 9;
+const HEADER_SIZE: usize = 40;
+
+/// A device tree under construction
+#[derive(Clone, Default, Debug)]
+pub struct Fdt {
+    structure: Vec<u8>,
+    strings: Vec<u8>,
+    depth: usize,
+}
+
+impl Fdt {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn token(&mut self, t: u32) {
+        self.structure.extend_from_slice(&t.to_be_bytes());
+    }
+
+    fn pad(&mut self) {
+        while !self.structure.len().is_multiple_of(4) {
+            self.structure.push(0);
+        }
+    }
+
+    /// Open a node; the root node's name is empty
+    pub fn begin_node(&mut self, name: &str) {
+        self.token(BEGIN_NODE);
+        self.structure.extend_from_slice(name.as_bytes());
+        self.structure.push(0);
+        self.pad();
+        self.depth += 1;
+    }
+
+    pub fn end_node(&mut self) {
+        assert!(self.depth > 0, "no node to end");
+        self.token(END_NODE);
+        self.depth -= 1;
+    }
+
+    /// Offset of `name` in the strings block, adding it if new
+    fn string(&mut self, name: &str) -> u32 {
+        let mut at = 0;
+        for s in self.strings.split(|&b| b == 0) {
+            if s == name.as_bytes() && at < self.strings.len() {
+                return at as u32;
+            }
+            at += s.len() + 1;
+        }
+        let at = self.strings.len();
+        self.strings.extend_from_slice(name.as_bytes());
+        self.strings.push(0);
+        at as u32
+    }
+
+    pub fn prop(&mut self, name: &str, value: &[u8]) {
+        let name = self.string(name);
+        self.token(PROP);
+        self.token(value.len() as u32);
+        self.token(name);
+        self.structure.extend_from_slice(value);
+        self.pad();
+    }
+
+    /// A property with no value, such as `interrupt-controller`
+    pub fn prop_empty(&mut self, name: &str) {
+        self.prop(name, &[]);
+    }
+
+    pub fn prop_u32(&mut self, name: &str, value: u32) {
+        self.prop_cells(name, &[value]);
+    }
+
+    pub fn prop_cells(&mut self, name: &str, cells: &[u32]) {
+        let value: Vec<u8> = cells.iter().flat_map(|c| c.to_be_bytes()).collect();
+        self.prop(name, &value);
+    }
+
+    /// A `reg`-style property of 64-bit (two-cell) values
+    pub fn prop_u64s(&mut self, name: &str, values: &[u64]) {
+        let value: Vec<u8> = values.iter().flat_map(|c| c.to_be_bytes()).collect();
+        self.prop(name, &value);
+    }
+
+    pub fn prop_str(&mut self, name: &str, value: &str) {
+        self.prop_strs(name, &[value]);
+    }
+
+    /// A string list, such as `compatible`
+    pub fn prop_strs(&mut self, name: &str, values: &[&str]) {
+        let mut value = Vec::new();
+        for v in values {
+            value.extend_from_slice(v.as_bytes());
+            value.push(0);
+        }
+        self.prop(name, &value);
+    }
+
+    /// Close the tree and lay out the blob
+    pub fn finish(mut self) -> Vec<u8> {
+        assert_eq!(self.depth, 0, "unclosed device tree node");
+        self.token(END);
+        let rsvmap = HEADER_SIZE;
+        // An empty reservation map is one all-zero entry
+        let structure = rsvmap + 16;
+        let strings = structure + self.structure.len();
+        let total = strings + self.strings.len();
+        let header = [
+            MAGIC,
+            total as u32,
+            structure as u32,
+            strings as u32,
+            rsvmap as u32,
+            17,
+            16,
+            0,
+            self.strings.len() as u32,
+            self.structure.len() as u32,
+        ];
+        let mut out: Vec<u8> = header.iter().flat_map(|w| w.to_be_bytes()).collect();
+        out.extend_from_slice(&[0; 16]);
+        out.extend_from_slice(&self.structure);
+        out.extend_from_slice(&self.strings);
+        out
+    }
+}
@@ -22,6 +22,20 @@ impl Flate for ReleaseFlate {
             "signed" => Box::new("s"),
             "unsigned" => Box::new("u"),
             "data" => Box::new("d"),
+            "store" => Box::new("y"),
+            "device" => Box::new("h"),
+            "ctz" => Box::new("t"),
+            "cpop" => Box::new("c"),
+            "orc" => Box::new("o"),
+            "bswap" => Box::new("w"),
+            "clmul" => Box::new("k"),
+            "sync" => Box::new("q"),
+            "cache" => Box::new("z"),
+            "chain" => Box::new("j"),
+            "links" => Box::new("K"),
+            "link" => Box::new("L"),
+            "hold" => Box::new("H"),
+            "site" => Box::new("Z"),
             a => Box::new(a)
         }
     }
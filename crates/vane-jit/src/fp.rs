@@ -0,0 +1,882 @@
+//! Shared semantics for the F and D floating-point extensions.
+//!
+//! Both the interpreter and the JavaScript templates funnel arithmetic through
+//! [`exec`], so the two engines agree bit-for-bit on results and `fflags`.
+//!
+//! Results are computed with the host's round-to-nearest-even arithmetic and
+//! then corrected for the requested rounding mode. The correction compares the
+//! exact result against the neighbouring representable values using
+//! error-free transformations, which also gives exact inexact/underflow flags.
+//!
+//! Single-precision values are NaN-boxed: a register holding an `f32` has its
+//! upper 32 bits set, and anything else reads back as the canonical NaN.
+use core::cmp::Ordering;
+
+/// Inexact
+pub const FFLAG_NX: u32 = 1 << 0;
+/// Underflow
+pub const FFLAG_UF: u32 = 1 << 1;
+/// Overflow
+pub const FFLAG_OF: u32 = 1 << 2;
+/// Divide by zero
+pub const FFLAG_DZ: u32 = 1 << 3;
+/// Invalid operation
+pub const FFLAG_NV: u32 = 1 << 4;
+
+/// The `rm` field value selecting the dynamic rounding mode from `frm`
+pub const RM_DYN: u8 = 0b111;
+
+/// Floating-point operand format
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum FpFmt {
+    /// Single precision (F)
+    S,
+    /// Double precision (D)
+    D,
+}
+
+/// A floating-point operation, independent of its format
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum FpOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Sqrt,
+    Sgnj,
+    Sgnjn,
+    Sgnjx,
+    Min,
+    Max,
+    Eq,
+    Lt,
+    Le,
+    Class,
+    /// `fcvt.w.*`
+    ToW,
+    /// `fcvt.wu.*`
+    ToWu,
+    /// `fcvt.l.*`
+    ToL,
+    /// `fcvt.lu.*`
+    ToLu,
+    /// `fcvt.*.w`
+    FromW,
+    /// `fcvt.*.wu`
+    FromWu,
+    /// `fcvt.*.l`
+    FromL,
+    /// `fcvt.*.lu`
+    FromLu,
+    /// `fcvt.s.d` / `fcvt.d.s`; the format is the destination format
+    Cvt,
+    /// `fmv.x.w` / `fmv.x.d`
+    MvToX,
+    /// `fmv.w.x` / `fmv.d.x`
+    MvFromX,
+    Madd,
+    Msub,
+    Nmsub,
+    Nmadd,
+}
+
+impl FpOp {
+    /// Whether the destination is an integer register
+    pub fn dest_is_x(self) -> bool {
+        matches!(
+            self,
+            FpOp::Eq
+                | FpOp::Lt
+                | FpOp::Le
+                | FpOp::Class
+                | FpOp::ToW
+                | FpOp::ToWu
+                | FpOp::ToL
+                | FpOp::ToLu
+                | FpOp::MvToX
+        )
+    }
+    /// Whether the first source is an integer register
+    pub fn src1_is_x(self) -> bool {
+        matches!(
+            self,
+            FpOp::FromW | FpOp::FromWu | FpOp::FromL | FpOp::FromLu | FpOp::MvFromX
+        )
+    }
+    /// Whether the instruction has a rounding mode field
+    pub fn uses_rm(self) -> bool {
+        matches!(
+            self,
+            FpOp::Add
+                | FpOp::Sub
+                | FpOp::Mul
+                | FpOp::Div
+                | FpOp::Sqrt
+                | FpOp::ToW
+                | FpOp::ToWu
+                | FpOp::ToL
+                | FpOp::ToLu
+                | FpOp::FromW
+                | FpOp::FromWu
+                | FpOp::FromL
+                | FpOp::FromLu
+                | FpOp::Cvt
+                | FpOp::Madd
+                | FpOp::Msub
+                | FpOp::Nmsub
+                | FpOp::Nmadd
+        )
+    }
+    /// Assembler mnemonic without the format suffix
+    pub fn mnemonic(self) -> &'static str {
+        match self {
+            FpOp::Add => "fadd",
+            FpOp::Sub => "fsub",
+            FpOp::Mul => "fmul",
+            FpOp::Div => "fdiv",
+            FpOp::Sqrt => "fsqrt",
+            FpOp::Sgnj => "fsgnj",
+            FpOp::Sgnjn => "fsgnjn",
+            FpOp::Sgnjx => "fsgnjx",
+            FpOp::Min => "fmin",
+            FpOp::Max => "fmax",
+            FpOp::Eq => "feq",
+            FpOp::Lt => "flt",
+            FpOp::Le => "fle",
+            FpOp::Class => "fclass",
+            FpOp::ToW => "fcvt.w",
+            FpOp::ToWu => "fcvt.wu",
+            FpOp::ToL => "fcvt.l",
+            FpOp::ToLu => "fcvt.lu",
+            FpOp::FromW => "fcvt.w",
+            FpOp::FromWu => "fcvt.wu",
+            FpOp::FromL => "fcvt.l",
+            FpOp::FromLu => "fcvt.lu",
+            FpOp::Cvt => "fcvt",
+            FpOp::MvToX => "fmv.x",
+            FpOp::MvFromX => "fmv",
+            FpOp::Madd => "fmadd",
+            FpOp::Msub => "fmsub",
+            FpOp::Nmsub => "fnmsub",
+            FpOp::Nmadd => "fnmadd",
+        }
+    }
+}
+
+/// A static rounding mode
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Rm {
+    /// Round to nearest, ties to even
+    Rne,
+    /// Round towards zero
+    Rtz,
+    /// Round down
+    Rdn,
+    /// Round up
+    Rup,
+    /// Round to nearest, ties to max magnitude
+    Rmm,
+}
+
+impl Rm {
+    /// Resolve an instruction `rm` field against the current `frm`
+    ///
+    /// Returns `None` for the reserved encodings, which are illegal.
+    pub fn resolve(rm: u8, frm: u8) -> Option<Rm> {
+        match if rm == RM_DYN { frm } else { rm } {
+            0 => Some(Rm::Rne),
+            1 => Some(Rm::Rtz),
+            2 => Some(Rm::Rdn),
+            3 => Some(Rm::Rup),
+            4 => Some(Rm::Rmm),
+            _ => None,
+        }
+    }
+}
+
+/// NaN-box a single-precision value
+pub fn box_s(bits: u32) -> u64 {
+    0xffff_ffff_0000_0000 | bits as u64
+}
+
+/// Read a single-precision value out of a register, honouring NaN-boxing
+pub fn unbox_s(reg: u64) -> u32 {
+    if reg >> 32 == 0xffff_ffff {
+        reg as u32
+    } else {
+        CANONICAL_NAN_S
+    }
+}
+
+const CANONICAL_NAN_S: u32 = 0x7fc0_0000;
+const CANONICAL_NAN_D: u64 = 0x7ff8_0000_0000_0000;
+
+trait Float: Copy + PartialOrd {
+    const MAX: Self;
+    const MIN_POSITIVE: Self;
+    fn from_f64(a: f64) -> Self;
+    fn to_f64(self) -> f64;
+    fn next_up(self) -> Self;
+    fn next_down(self) -> Self;
+    fn is_infinite(self) -> bool;
+    fn is_odd(self) -> bool;
+    fn neg(self) -> Self;
+}
+impl Float for f32 {
+    const MAX: Self = f32::MAX;
+    const MIN_POSITIVE: Self = f32::MIN_POSITIVE;
+    fn from_f64(a: f64) -> Self {
+        a as f32
+    }
+    fn to_f64(self) -> f64 {
+        self as f64
+    }
+    fn next_up(self) -> Self {
+        f32::next_up(self)
+    }
+    fn next_down(self) -> Self {
+        f32::next_down(self)
+    }
+    fn is_infinite(self) -> bool {
+        f32::is_infinite(self)
+    }
+    fn is_odd(self) -> bool {
+        self.to_bits() & 1 != 0
+    }
+    fn neg(self) -> Self {
+        -self
+    }
+}
+impl Float for f64 {
+    const MAX: Self = f64::MAX;
+    const MIN_POSITIVE: Self = f64::MIN_POSITIVE;
+    fn from_f64(a: f64) -> Self {
+        a
+    }
+    fn to_f64(self) -> f64 {
+        self
+    }
+    fn next_up(self) -> Self {
+        f64::next_up(self)
+    }
+    fn next_down(self) -> Self {
+        f64::next_down(self)
+    }
+    fn is_infinite(self) -> bool {
+        f64::is_infinite(self)
+    }
+    fn is_odd(self) -> bool {
+        self.to_bits() & 1 != 0
+    }
+    fn neg(self) -> Self {
+        -self
+    }
+}
+
+fn two_sum(a: f64, b: f64) -> (f64, f64) {
+    let s = a + b;
+    let bb = s - a;
+    (s, (a - (s - bb)) + (b - bb))
+}
+
+fn two_prod(a: f64, b: f64) -> (f64, f64) {
+    let p = a * b;
+    (p, libm::fma(a, b, -p))
+}
+
+/// Exact sign of a sum of finite doubles
+fn expansion_sign(terms: &[f64]) -> Ordering {
+    let mut e = [0f64; 8];
+    let mut n = 0;
+    for &t in terms {
+        let mut q = t;
+        for c in e[..n].iter_mut() {
+            let (s, err) = two_sum(q, *c);
+            *c = err;
+            q = s;
+        }
+        e[n] = q;
+        n += 1;
+    }
+    e[..n]
+        .iter()
+        .rev()
+        .find(|c| **c != 0.0)
+        .map_or(Ordering::Equal, |c| c.partial_cmp(&0.0).unwrap())
+}
+
+/// Round an exact value to `F`
+///
+/// `approx` must round (to nearest) to one of the two representable values
+/// bracketing the exact result, or overflow to infinity or underflow to zero
+/// with the right sign. The exact value itself is only seen through
+/// `cmp(x, h)`, which reports the sign of `exact - (x + h)`. Both arguments,
+/// and the exact value, are scaled by `2^exp` so callers can keep their error
+/// terms clear of the subnormal and overflow ranges of `f64`.
+fn round<F: Float>(approx: f64, exp: i32, rm: Rm, cmp: impl Fn(f64, f64) -> Ordering) -> (F, u32) {
+    let positive = approx.is_sign_positive();
+    let mag = |o: Ordering| if positive { o } else { o.reverse() };
+    let max = F::MAX.to_f64();
+    // Infinity stands for the value one ulp above `F::MAX`
+    let scaled = |v: F| {
+        let v = v.to_f64();
+        match v.is_infinite() {
+            true => {
+                let s = libm::scalbn(max, exp) + libm::scalbn(max - F::MAX.next_down().to_f64(), exp);
+                if v > 0.0 { s } else { -s }
+            }
+            false => libm::scalbn(v, exp),
+        }
+    };
+    let mut r = F::from_f64(approx);
+    if r.is_infinite() {
+        if mag(cmp(scaled(r), 0.0)) != Ordering::Less {
+            return overflow(positive, rm);
+        }
+        r = if positive { F::MAX } else { F::MAX.neg() };
+    }
+    let (lo, hi) = match cmp(scaled(r), 0.0) {
+        Ordering::Equal => return (r, 0),
+        Ordering::Greater => (r, r.next_up()),
+        Ordering::Less => (r.next_down(), r),
+    };
+    let out = match rm {
+        Rm::Rtz => {
+            if positive {
+                lo
+            } else {
+                hi
+            }
+        }
+        Rm::Rdn => lo,
+        Rm::Rup => hi,
+        Rm::Rne | Rm::Rmm => {
+            let (l, h) = (scaled(lo), scaled(hi));
+            match cmp(l, (h - l) / 2.0) {
+                Ordering::Less => lo,
+                Ordering::Greater => hi,
+                Ordering::Equal => match rm {
+                    Rm::Rmm if positive => hi,
+                    Rm::Rmm => lo,
+                    _ if lo.is_odd() => hi,
+                    _ => lo,
+                },
+            }
+        }
+    };
+    if out.is_infinite() {
+        return overflow(positive, rm);
+    }
+    // A zero result keeps the sign of the exact value
+    let out = match (out.to_f64() == 0.0, positive) {
+        (true, true) => F::from_f64(0.0),
+        (true, false) => F::from_f64(-0.0),
+        _ => out,
+    };
+    let min = F::MIN_POSITIVE.to_f64();
+    let tiny = match out.to_f64().abs() {
+        a if a < min => true,
+        a if a > min => false,
+        // Tininess is detected after rounding with an unbounded exponent,
+        // where the grid just below `MIN_POSITIVE` is twice as fine
+        _ => {
+            let m = libm::scalbn(if positive { min } else { -min }, exp);
+            let step = libm::scalbn(F::from_f64(0.0).next_up().to_f64(), exp) / 2.0;
+            let below = if positive { m - step } else { m + step };
+            match mag(cmp(m, 0.0)) {
+                Ordering::Greater | Ordering::Equal => false,
+                Ordering::Less => match mag(cmp(below, 0.0)) {
+                    Ordering::Less | Ordering::Equal => true,
+                    Ordering::Greater => match rm {
+                        Rm::Rtz => true,
+                        Rm::Rdn => positive,
+                        Rm::Rup => !positive,
+                        Rm::Rne | Rm::Rmm => {
+                            let half = if positive { step / 2.0 } else { -step / 2.0 };
+                            mag(cmp(below, half)) == Ordering::Less
+                        }
+                    },
+                },
+            }
+        }
+    };
+    let flags = match tiny {
+        true => FFLAG_NX | FFLAG_UF,
+        false => FFLAG_NX,
+    };
+    (out, flags)
+}
+
+fn overflow<F: Float>(positive: bool, rm: Rm) -> (F, u32) {
+    let inf = F::from_f64(f64::INFINITY);
+    let mag = match (rm, positive) {
+        (Rm::Rne | Rm::Rmm, _) | (Rm::Rup, true) | (Rm::Rdn, false) => inf,
+        _ => F::MAX,
+    };
+    (
+        if positive { mag } else { mag.neg() },
+        FFLAG_OF | FFLAG_NX,
+    )
+}
+
+/// Zero produced by an exact cancellation of two terms with the given signs
+fn exact_zero(a_neg: bool, b_neg: bool, rm: Rm) -> f64 {
+    match (a_neg && b_neg) || (a_neg != b_neg && rm == Rm::Rdn) {
+        true => -0.0,
+        false => 0.0,
+    }
+}
+
+/// Operand access for one format
+#[derive(Clone, Copy)]
+struct Operand {
+    fmt: FpFmt,
+    bits: u64,
+}
+impl Operand {
+    fn new(fmt: FpFmt, reg: u64) -> Self {
+        Operand {
+            fmt,
+            bits: match fmt {
+                FpFmt::S => unbox_s(reg) as u64,
+                FpFmt::D => reg,
+            },
+        }
+    }
+    fn value(self) -> f64 {
+        match self.fmt {
+            FpFmt::S => f32::from_bits(self.bits as u32) as f64,
+            FpFmt::D => f64::from_bits(self.bits),
+        }
+    }
+    fn is_nan(self) -> bool {
+        self.value().is_nan()
+    }
+    fn is_snan(self) -> bool {
+        self.is_nan()
+            && match self.fmt {
+                FpFmt::S => self.bits & 0x0040_0000 == 0,
+                FpFmt::D => self.bits & 0x0008_0000_0000_0000 == 0,
+            }
+    }
+    fn sign(self) -> bool {
+        match self.fmt {
+            FpFmt::S => self.bits & 0x8000_0000 != 0,
+            FpFmt::D => self.bits & 0x8000_0000_0000_0000 != 0,
+        }
+    }
+}
+
+fn canonical_nan(fmt: FpFmt) -> u64 {
+    match fmt {
+        FpFmt::S => box_s(CANONICAL_NAN_S),
+        FpFmt::D => CANONICAL_NAN_D,
+    }
+}
+
+fn pack(fmt: FpFmt, v: f64) -> u64 {
+    match fmt {
+        FpFmt::S => box_s((v as f32).to_bits()),
+        FpFmt::D => v.to_bits(),
+    }
+}
+
+/// Round an exact value into `fmt`, returning register bits and flags
+fn round_to(
+    fmt: FpFmt,
+    approx: f64,
+    exp: i32,
+    rm: Rm,
+    cmp: impl Fn(f64, f64) -> Ordering,
+) -> (u64, u32) {
+    match fmt {
+        FpFmt::S => {
+            let (v, fl) = round::<f32>(approx, exp, rm, cmp);
+            (box_s(v.to_bits()), fl)
+        }
+        FpFmt::D => {
+            let (v, fl) = round::<f64>(approx, exp, rm, cmp);
+            (v.to_bits(), fl)
+        }
+    }
+}
+
+/// Split a finite nonzero value into a mantissa in `[1, 2)` and an exponent
+fn norm(v: f64) -> (f64, i32) {
+    let e = libm::ilogb(v);
+    (libm::scalbn(v, -e), e)
+}
+
+/// Scale `v` by `2^-e`, where `2^e` is the magnitude of the dominant term
+///
+/// A term too small to affect anything but the sticky bit is replaced by a
+/// tiny stand-in of the same sign, so it cannot underflow.
+fn relative(v: f64, e: i32) -> f64 {
+    if v == 0.0 || libm::ilogb(v) >= e - 400 {
+        libm::scalbn(v, -e)
+    } else {
+        libm::copysign(libm::scalbn(1.0, -600), v)
+    }
+}
+
+fn nan_flags(ops: &[Operand]) -> u32 {
+    match ops.iter().any(|o| o.is_snan()) {
+        true => FFLAG_NV,
+        false => 0,
+    }
+}
+
+fn add(fmt: FpFmt, a: Operand, b: Operand, rm: Rm) -> (u64, u32) {
+    if a.is_nan() || b.is_nan() {
+        return (canonical_nan(fmt), nan_flags(&[a, b]));
+    }
+    let (x, y) = (a.value(), b.value());
+    if x.is_infinite() || y.is_infinite() {
+        if x.is_infinite() && y.is_infinite() && a.sign() != b.sign() {
+            return (canonical_nan(fmt), FFLAG_NV);
+        }
+        return (pack(fmt, x + y), 0);
+    }
+    if expansion_sign(&[x, y]) == Ordering::Equal {
+        return (pack(fmt, exact_zero(x.is_sign_negative(), y.is_sign_negative(), rm)), 0);
+    }
+    let e = libm::ilogb(if x.abs() > y.abs() { x } else { y });
+    let (x, y) = (relative(x, e), relative(y, e));
+    round_to(fmt, libm::scalbn(x + y, e), -e, rm, |c, h| expansion_sign(&[x, y, -c, -h]))
+}
+
+fn mul(fmt: FpFmt, a: Operand, b: Operand, rm: Rm) -> (u64, u32) {
+    if a.is_nan() || b.is_nan() {
+        return (canonical_nan(fmt), nan_flags(&[a, b]));
+    }
+    let (x, y) = (a.value(), b.value());
+    if x.is_infinite() || y.is_infinite() {
+        if x == 0.0 || y == 0.0 {
+            return (canonical_nan(fmt), FFLAG_NV);
+        }
+        return (pack(fmt, x * y), 0);
+    }
+    if x == 0.0 || y == 0.0 {
+        return (pack(fmt, x * y), 0);
+    }
+    let ((x, ex), (y, ey)) = (norm(x), norm(y));
+    let (ph, pl) = two_prod(x, y);
+    round_to(fmt, libm::scalbn(ph, ex + ey), -(ex + ey), rm, |c, h| {
+        expansion_sign(&[ph, pl, -c, -h])
+    })
+}
+
+fn div(fmt: FpFmt, a: Operand, b: Operand, rm: Rm) -> (u64, u32) {
+    if a.is_nan() || b.is_nan() {
+        return (canonical_nan(fmt), nan_flags(&[a, b]));
+    }
+    let (x, y) = (a.value(), b.value());
+    if (x == 0.0 && y == 0.0) || (x.is_infinite() && y.is_infinite()) {
+        return (canonical_nan(fmt), FFLAG_NV);
+    }
+    if y == 0.0 {
+        return (pack(fmt, x / y), FFLAG_DZ);
+    }
+    if x.is_infinite() || y.is_infinite() || x == 0.0 {
+        return (pack(fmt, x / y), 0);
+    }
+    let ((x, ex), (y, ey)) = (norm(x), norm(y));
+    let sign: fn(Ordering) -> Ordering = if y < 0.0 {
+        Ordering::reverse
+    } else {
+        core::convert::identity
+    };
+    round_to(fmt, libm::scalbn(x / y, ex - ey), ey - ex, rm, |c, h| {
+        let (mh, ml) = two_prod(c, y);
+        sign(expansion_sign(&[x, -mh, -ml, -(h * y)]))
+    })
+}
+
+fn sqrt(fmt: FpFmt, a: Operand, rm: Rm) -> (u64, u32) {
+    if a.is_nan() {
+        return (canonical_nan(fmt), nan_flags(&[a]));
+    }
+    let x = a.value();
+    if x == 0.0 || x == f64::INFINITY {
+        return (pack(fmt, x), 0);
+    }
+    if x < 0.0 {
+        return (canonical_nan(fmt), FFLAG_NV);
+    }
+    // Keep the exponent even so the square root scales exactly
+    let k = libm::ilogb(x).div_euclid(2);
+    let x = libm::scalbn(x, -2 * k);
+    round_to(fmt, libm::scalbn(libm::sqrt(x), k), -k, rm, |c, h| {
+        let (sh, sl) = two_prod(c, c);
+        expansion_sign(&[x, -sh, -sl, -(2.0 * c * h), -(h * h)])
+    })
+}
+
+fn fma(fmt: FpFmt, a: Operand, b: Operand, c: Operand, neg_prod: bool, neg_add: bool, rm: Rm) -> (u64, u32) {
+    let (x, y, z) = (a.value(), b.value(), c.value());
+    if (x.is_infinite() && y == 0.0) || (x == 0.0 && y.is_infinite()) {
+        return (canonical_nan(fmt), FFLAG_NV);
+    }
+    if a.is_nan() || b.is_nan() || c.is_nan() {
+        return (canonical_nan(fmt), nan_flags(&[a, b, c]));
+    }
+    let x = if neg_prod { -x } else { x };
+    let z = if neg_add { -z } else { z };
+    if x.is_infinite() || y.is_infinite() || z.is_infinite() {
+        let p = x * y;
+        if p.is_infinite() && z.is_infinite() && (p > 0.0) != (z > 0.0) {
+            return (canonical_nan(fmt), FFLAG_NV);
+        }
+        return (pack(fmt, if p.is_infinite() { p } else { z }), 0);
+    }
+    if x == 0.0 || y == 0.0 {
+        let p = x * y;
+        if z == 0.0 {
+            return (pack(fmt, exact_zero(p.is_sign_negative(), z.is_sign_negative(), rm)), 0);
+        }
+        return (pack(fmt, z), 0);
+    }
+    let ((x, ex), (y, ey)) = (norm(x), norm(y));
+    let e = match z == 0.0 {
+        true => ex + ey,
+        false => (ex + ey).max(libm::ilogb(z)),
+    };
+    // Scale the product through one factor so it stays exact
+    let (x, z) = (relative(x, e - ex - ey), relative(z, e));
+    let (ph, pl) = two_prod(x, y);
+    if expansion_sign(&[ph, pl, z]) == Ordering::Equal {
+        return (pack(fmt, exact_zero(ph.is_sign_negative(), z.is_sign_negative(), rm)), 0);
+    }
+    round_to(fmt, libm::scalbn(libm::fma(x, y, z), e), -e, rm, |cc, h| {
+        expansion_sign(&[ph, pl, z, -cc, -h])
+    })
+}
+
+fn min_max(fmt: FpFmt, a: Operand, b: Operand, max: bool) -> (u64, u32) {
+    let flags = nan_flags(&[a, b]);
+    let bits = match (a.is_nan(), b.is_nan()) {
+        (true, true) => return (canonical_nan(fmt), flags),
+        (true, false) => b.bits,
+        (false, true) => a.bits,
+        (false, false) => {
+            let (x, y) = (a.value(), b.value());
+            let a_first = if x == y {
+                // -0.0 orders below +0.0
+                a.sign() != max
+            } else {
+                (x < y) != max
+            };
+            if a_first { a.bits } else { b.bits }
+        }
+    };
+    (
+        match fmt {
+            FpFmt::S => box_s(bits as u32),
+            FpFmt::D => bits,
+        },
+        flags,
+    )
+}
+
+fn compare(a: Operand, b: Operand, op: FpOp) -> (u64, u32) {
+    if a.is_nan() || b.is_nan() {
+        let flags = match op {
+            FpOp::Eq => nan_flags(&[a, b]),
+            _ => FFLAG_NV,
+        };
+        return (0, flags);
+    }
+    let (x, y) = (a.value(), b.value());
+    let r = match op {
+        FpOp::Eq => x == y,
+        FpOp::Lt => x < y,
+        _ => x <= y,
+    };
+    (r as u64, 0)
+}
+
+fn class(a: Operand) -> u64 {
+    let x = a.value();
+    let neg = a.sign();
+    let bit = if x.is_infinite() {
+        if neg { 0 } else { 7 }
+    } else if x.is_nan() {
+        if a.is_snan() { 8 } else { 9 }
+    } else if x == 0.0 {
+        if neg { 3 } else { 4 }
+    } else if x.abs()
+        < match a.fmt {
+            FpFmt::S => f32::MIN_POSITIVE as f64,
+            FpFmt::D => f64::MIN_POSITIVE,
+        }
+    {
+        if neg { 2 } else { 5 }
+    } else if neg {
+        1
+    } else {
+        6
+    };
+    1 << bit
+}
+
+fn sign_inject(fmt: FpFmt, a: Operand, b: Operand, op: FpOp) -> u64 {
+    let sign = match fmt {
+        FpFmt::S => 0x8000_0000,
+        FpFmt::D => 0x8000_0000_0000_0000,
+    };
+    let s = match op {
+        FpOp::Sgnj => b.bits & sign,
+        FpOp::Sgnjn => !b.bits & sign,
+        _ => (a.bits ^ b.bits) & sign,
+    };
+    let bits = (a.bits & !sign) | s;
+    match fmt {
+        FpFmt::S => box_s(bits as u32),
+        FpFmt::D => bits,
+    }
+}
+
+/// Round a float to an integer in `[min, max]` with saturation
+fn to_int(a: Operand, rm: Rm, min: i128, max: i128) -> (i128, u32) {
+    if a.is_nan() {
+        return (max, FFLAG_NV);
+    }
+    let x = a.value();
+    if x.is_infinite() {
+        return (if x > 0.0 { max } else { min }, FFLAG_NV);
+    }
+    let t = libm::trunc(x);
+    let frac = x - t;
+    let r = if frac == 0.0 {
+        t
+    } else {
+        match rm {
+            Rm::Rtz => t,
+            Rm::Rdn => libm::floor(x),
+            Rm::Rup => libm::ceil(x),
+            Rm::Rmm => libm::round(x),
+            Rm::Rne => {
+                let half = frac.abs() == 0.5;
+                let r = libm::round(x);
+                if half && libm::fmod(r, 2.0) != 0.0 { t } else { r }
+            }
+        }
+    };
+    // Every float with a fractional part is well inside the i128 range
+    let v = r as i128;
+    if v < min {
+        (min, FFLAG_NV)
+    } else if v > max {
+        (max, FFLAG_NV)
+    } else if frac != 0.0 {
+        (v, FFLAG_NX)
+    } else {
+        (v, 0)
+    }
+}
+
+fn from_int(fmt: FpFmt, v: i128, rm: Rm) -> (u64, u32) {
+    // Split into two exactly representable halves
+    let hi = ((v >> 32) << 32) as f64;
+    let lo = (v & 0xffff_ffff) as f64;
+    round_to(fmt, hi + lo, 0, rm, |c, h| expansion_sign(&[hi, lo, -c, -h]))
+}
+
+/// Execute a floating-point operation
+///
+/// `a`, `b` and `c` are the raw source register values, integer or float
+/// as appropriate for `op`. Returns the value to write to the destination
+/// (NaN-boxed for single-precision results, sign-extended from 32 bits for
+/// word-sized integer results) and accumulates exception flags into `fcsr`.
+///
+/// Returns `None` when the rounding mode is reserved, which is an illegal instruction.
+pub fn exec(op: FpOp, fmt: FpFmt, rm: u8, a: u64, b: u64, c: u64, fcsr: &mut u32) -> Option<u64> {
+    let rm = match op.uses_rm() {
+        true => Rm::resolve(rm, ((*fcsr >> 5) & 7) as u8)?,
+        false => Rm::Rne,
+    };
+    let (oa, ob, oc) = (Operand::new(fmt, a), Operand::new(fmt, b), Operand::new(fmt, c));
+    let (out, flags) = match op {
+        FpOp::Add => add(fmt, oa, ob, rm),
+        FpOp::Sub => add(
+            fmt,
+            oa,
+            Operand {
+                bits: ob.bits
+                    ^ match fmt {
+                        FpFmt::S => 0x8000_0000,
+                        FpFmt::D => 0x8000_0000_0000_0000,
+                    },
+                ..ob
+            },
+            rm,
+        ),
+        FpOp::Mul => mul(fmt, oa, ob, rm),
+        FpOp::Div => div(fmt, oa, ob, rm),
+        FpOp::Sqrt => sqrt(fmt, oa, rm),
+        FpOp::Sgnj | FpOp::Sgnjn | FpOp::Sgnjx => (sign_inject(fmt, oa, ob, op), 0),
+        FpOp::Min => min_max(fmt, oa, ob, false),
+        FpOp::Max => min_max(fmt, oa, ob, true),
+        FpOp::Eq | FpOp::Lt | FpOp::Le => compare(oa, ob, op),
+        FpOp::Class => (class(oa), 0),
+        FpOp::ToW => {
+            let (v, fl) = to_int(oa, rm, i32::MIN as i128, i32::MAX as i128);
+            (v as i32 as i64 as u64, fl)
+        }
+        FpOp::ToWu => {
+            let (v, fl) = to_int(oa, rm, 0, u32::MAX as i128);
+            (v as u32 as i32 as i64 as u64, fl)
+        }
+        FpOp::ToL => {
+            let (v, fl) = to_int(oa, rm, i64::MIN as i128, i64::MAX as i128);
+            (v as i64 as u64, fl)
+        }
+        FpOp::ToLu => {
+            let (v, fl) = to_int(oa, rm, 0, u64::MAX as i128);
+            (v as u64, fl)
+        }
+        FpOp::FromW => from_int(fmt, a as i32 as i128, rm),
+        FpOp::FromWu => from_int(fmt, a as u32 as i128, rm),
+        FpOp::FromL => from_int(fmt, a as i64 as i128, rm),
+        FpOp::FromLu => from_int(fmt, a as i128, rm),
+        FpOp::Cvt => {
+            let src = Operand::new(
+                match fmt {
+                    FpFmt::S => FpFmt::D,
+                    FpFmt::D => FpFmt::S,
+                },
+                a,
+            );
+            if src.is_nan() {
+                (canonical_nan(fmt), nan_flags(&[src]))
+            } else {
+                let x = src.value();
+                match fmt {
+                    FpFmt::D => (x.to_bits(), 0),
+                    FpFmt::S if x.is_infinite() || x == 0.0 => (pack(fmt, x), 0),
+                    FpFmt::S => round_to(fmt, x, 0, rm, |c, h| expansion_sign(&[x, -c, -h])),
+                }
+            }
+        }
+        FpOp::MvToX => (
+            match fmt {
+                FpFmt::S => a as u32 as i32 as i64 as u64,
+                FpFmt::D => a,
+            },
+            0,
+        ),
+        FpOp::MvFromX => (
+            match fmt {
+                FpFmt::S => box_s(a as u32),
+                FpFmt::D => a,
+            },
+            0,
+        ),
+        FpOp::Madd => fma(fmt, oa, ob, oc, false, false, rm),
+        FpOp::Msub => fma(fmt, oa, ob, oc, false, true, rm),
+        FpOp::Nmsub => fma(fmt, oa, ob, oc, true, false, rm),
+        FpOp::Nmadd => fma(fmt, oa, ob, oc, true, true, rm),
+    };
+    *fcsr |= flags;
+    Some(out)
+}
@@ -1,17 +1,76 @@
 #![no_std]
 use alloc::format;
-use alloc::{boxed::Box, collections::btree_map::BTreeMap};
+use alloc::{
+    boxed::Box,
+    collections::{btree_map::BTreeMap, btree_set::BTreeSet},
+};
 #[doc(hidden)]
 pub use core;
 use core::fmt::Display;
 use core::fmt::Formatter;
 #[doc(hidden)]
 pub use paste;
+#[doc(hidden)]
+pub use rv_asm;
 use rv_asm::{Inst, Reg, Xlen};
 #[doc(hidden)]
 pub extern crate alloc;
 pub mod flate;
+#[cfg(test)]
+#[path = "../_tests/amo.rs"]
+mod amo_tests;
+#[cfg(test)]
+#[path = "../_tests/fence.rs"]
+mod fence_tests;
+pub mod bit;
+#[cfg(test)]
+#[path = "../_tests/bit.rs"]
+mod bit_tests;
+pub mod clint;
+#[cfg(test)]
+#[path = "../_tests/clint.rs"]
+mod clint_tests;
+pub mod crypto;
+#[cfg(test)]
+#[path = "../_tests/crypto.rs"]
+mod crypto_tests;
+pub mod csr;
+#[cfg(test)]
+#[path = "../_tests/csr.rs"]
+mod csr_tests;
+pub mod ext;
+pub mod fdt;
+pub mod fp;
+#[cfg(test)]
+#[path = "../_tests/fp.rs"]
+mod fp_tests;
 pub mod hint;
+pub mod mmu;
+#[cfg(test)]
+#[path = "../_tests/mmu.rs"]
+mod mmu_tests;
+pub mod plic;
+pub mod rvc;
+#[cfg(test)]
+#[path = "../_tests/rvc.rs"]
+mod rvc_tests;
+pub mod sbi;
+#[cfg(test)]
+#[path = "../_tests/sbi.rs"]
+mod sbi_tests;
+pub mod trap;
+#[cfg(test)]
+#[path = "../_tests/trap.rs"]
+mod trap_tests;
+pub mod uart;
+pub mod vector;
+#[cfg(test)]
+#[path = "../_tests/vector.rs"]
+mod vector_tests;
+pub mod virt;
+#[cfg(test)]
+#[path = "../_tests/virt.rs"]
+mod virt_tests;
 /// Paging mode selector
 ///
 /// Determines which paging system to use for memory translation.
@@ -85,6 +144,18 @@ pub struct Mem {
     
     /// Use multi-level (3-level) page tables instead of single-level
     pub use_multilevel_paging: bool,
+
+    /// LR/SC reservation: the 8-byte granule reserved by the last `lr`
+    ///
+    /// Any access through [`Mem::get_page`] or [`Mem::write_byte`] that
+    /// overlaps the granule breaks the reservation.
+    pub reservation: Option<u64>,
+
+    /// Zifencei: the roots of the compiled blocks read from each 64KB page
+    pub code_pages: BTreeMap<u64, BTreeSet<u64>>,
+
+    /// Pages in `code_pages` stored to since the last `fence.i`
+    pub stale_code: BTreeSet<u64>,
 }
 impl Mem {
     /// Get a pointer to a specific address in memory (legacy system)
@@ -103,6 +174,7 @@ impl Mem {
     /// # Returns
     /// Raw pointer to the byte at the virtual address
     pub fn get_page(&mut self, a: u64) -> *mut u8 {
+        self.break_reservation(a);
         match self
             .pages
             .entry(a >> 16)
@@ -320,6 +392,8 @@ impl Mem {
 
     /// Safe interface to write a byte to memory
     pub fn write_byte(&mut self, addr: u64, value: u8) {
+        self.break_reservation(addr);
+        self.note_store(addr);
         let page = self
             .pages
             .entry(addr >> 16)
@@ -327,6 +401,52 @@ impl Mem {
         page[(addr & 0xffff) as usize] = value;
     }
 
+    /// Reserve the granule holding `addr` for a later store-conditional
+    ///
+    /// Call this after the load, since the load itself goes through [`Mem::get_page`].
+    pub fn reserve(&mut self, addr: u64) {
+        self.reservation = Some(addr & !7);
+    }
+
+    /// Consume the reservation, returning whether it still covers `addr`
+    pub fn check_reservation(&mut self, addr: u64) -> bool {
+        self.reservation.take() == Some(addr & !7)
+    }
+
+    /// Drop the reservation if an access of up to 8 bytes at `addr` may overlap it
+    fn break_reservation(&mut self, addr: u64) {
+        if let Some(r) = self.reservation
+            && addr.wrapping_sub(r).wrapping_add(7) < 15
+        {
+            self.reservation = None;
+        }
+    }
+
+    /// Record that the block compiled at `root` read code from `addr`
+    pub fn note_code(&mut self, addr: u64, root: u64) {
+        self.code_pages.entry(addr >> 16).or_default().insert(root);
+    }
+
+    /// Mark compiled code on the page holding `addr` as stale
+    ///
+    /// Guest stores reach memory through raw pointers, so the reactor calls
+    /// this whenever it hands out a page for writing.
+    pub fn note_store(&mut self, addr: u64) {
+        let page = addr >> 16;
+        if self.code_pages.contains_key(&page) {
+            self.stale_code.insert(page);
+        }
+    }
+
+    /// `fence.i`: forget and return the roots of blocks read from stale pages
+    pub fn fence_i(&mut self) -> BTreeSet<u64> {
+        let mut roots = BTreeSet::new();
+        for page in core::mem::take(&mut self.stale_code) {
+            roots.extend(self.code_pages.remove(&page).unwrap_or_default());
+        }
+        roots
+    }
+
     /// Safe interface to read a byte from memory
     pub fn read_byte(&self, addr: u64) -> u8 {
         self.pages
@@ -335,6 +455,16 @@ impl Mem {
             .unwrap_or(0)
     }
 }
+/// All-ones mask for a guest register of the given width
+///
+/// RV32 register values are kept zero-extended to 64 bits by both the
+/// interpreter and the generated JavaScript, so every result is masked with this.
+pub fn xlen_mask(xlen: Xlen) -> u64 {
+    match xlen {
+        Xlen::Rv32 => 0xffff_ffff,
+        Xlen::Rv64 => u64::MAX,
+    }
+}
 #[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 pub enum Heat {
     New,
@@ -351,10 +481,51 @@ impl JitCtx for Mem {
         }))
     }
 }
-pub trait WasmJitCtx {}
-#[derive(Clone)]
+/// What a Wasm template asks of the module it is compiled into
+///
+/// Templates get their locals, memory and host functions from here rather
+/// than assuming them; [`BlockCtx`](template::wasm::BlockCtx) is the one
+/// [`ModuleBuilder`](template::wasm::ModuleBuilder) takes.
+pub trait WasmJitCtx {
+    /// The memory holding the register file
+    fn memory(&self) -> u32;
+    /// The local holding the register file's address
+    fn regs(&self) -> u32;
+    /// A new local of type `ty`
+    fn local(&self, ty: wasmparser::ValType) -> u32;
+    /// The `i64` local caching guest register `r`, allocated on first use
+    fn reg_local(&self, r: Reg) -> u32;
+    /// The function index of the host function imported as `name`
+    fn import(&self, name: &str) -> Option<u32>;
+    /// Note that the block holds code for the guest instruction at `pc`
+    fn mark_pc(&self, pc: u64);
+}
+/// What a Wasm template emits
+///
+/// Besides plain operators, templates refer to loops, host functions and
+/// other blocks by name; assembling the module (see
+/// [`ModuleBuilder`](template::wasm::ModuleBuilder)) resolves these to
+/// branch depths and function indices.
+#[derive(Clone, PartialEq, Debug)]
 pub enum JitOpcode<'a> {
     Operator { op: wasmparser::Operator<'a> },
+    /// Open a loop that a [`Br`](JitOpcode::Br) to `label` continues; closed
+    /// by an `End` like any other
+    Loop { label: u64 },
+    /// Branch to the enclosing loop opened for `label`
+    Br { label: u64 },
+    /// Call the host function imported as `import`
+    Call { import: &'a str },
+    /// The code for the guest instruction at `pc` starts here
+    Pc { pc: u64 },
+    /// Call the block compiled at `pc` with the same register file, leaving
+    /// the pc it returns
+    CallBlock { pc: u64 },
+}
+impl<'a> From<wasmparser::Operator<'a>> for JitOpcode<'a> {
+    fn from(op: wasmparser::Operator<'a>) -> Self {
+        JitOpcode::Operator { op }
+    }
 }
 pub trait WasmJit {
     fn jit<'a>(
@@ -364,3 +535,9 @@ pub trait WasmJit {
 }
 pub mod arch;
 pub mod template;
+#[cfg(test)]
+#[path = "../_tests/template.rs"]
+mod template_tests;
+#[cfg(test)]
+#[path = "../_tests/wasm.rs"]
+mod wasm_tests;
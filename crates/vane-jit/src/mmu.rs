@@ -0,0 +1,219 @@
+//! The standard RISC-V MMU: Sv32 on RV32 and Sv39, Sv48 and Sv57 on RV64.
+//!
+//! Translation sits beneath the reactor's `get_page`, so the interpreter and
+//! the generated `data` accessor share it. It composes with vane's own
+//! [`PagingMode`](crate::PagingMode)s: whatever address those hand to
+//! `get_page` is the one walked here. There is no TLB; every access walks the
+//! tables, so `sfence.vma` has nothing to flush.
+use rv_asm::Xlen;
+
+use crate::csr::{mstatus, CsrFile};
+use crate::trap::{Exception, Priv, Trap};
+use crate::{xlen_mask, Mem};
+
+/// Page-table entry bits
+pub mod pte {
+    pub const V: u64 = 1 << 0;
+    pub const R: u64 = 1 << 1;
+    pub const W: u64 = 1 << 2;
+    pub const X: u64 = 1 << 3;
+    pub const U: u64 = 1 << 4;
+    pub const G: u64 = 1 << 5;
+    pub const A: u64 = 1 << 6;
+    pub const D: u64 = 1 << 7;
+}
+
+/// The kind of memory access being translated
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum Access {
+    Read,
+    /// Stores and AMOs
+    Write,
+    Execute,
+}
+
+impl Access {
+    /// The page fault this access raises
+    pub fn page_fault(self) -> Exception {
+        match self {
+            Access::Read => Exception::LoadPageFault,
+            Access::Write => Exception::StorePageFault,
+            Access::Execute => Exception::InstructionPageFault,
+        }
+    }
+    /// The access fault this access raises
+    pub fn access_fault(self) -> Exception {
+        match self {
+            Access::Read => Exception::LoadAccessFault,
+            Access::Write => Exception::StoreAccessFault,
+            Access::Execute => Exception::InstructionAccessFault,
+        }
+    }
+}
+
+/// A `satp` translation mode
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum SatpMode {
+    Bare,
+    Sv32,
+    Sv39,
+    Sv48,
+    Sv57,
+}
+
+impl SatpMode {
+    /// The mode selected by a `satp` value, or `None` if it is unsupported
+    pub fn from_satp(satp: u64, xlen: Xlen) -> Option<SatpMode> {
+        Some(match xlen {
+            Xlen::Rv32 => match satp >> 31 & 1 {
+                0 => SatpMode::Bare,
+                _ => SatpMode::Sv32,
+            },
+            Xlen::Rv64 => match satp >> 60 {
+                0 => SatpMode::Bare,
+                8 => SatpMode::Sv39,
+                9 => SatpMode::Sv48,
+                10 => SatpMode::Sv57,
+                _ => return None,
+            },
+        })
+    }
+    pub fn levels(self) -> u32 {
+        match self {
+            SatpMode::Bare => 0,
+            SatpMode::Sv32 => 2,
+            SatpMode::Sv39 => 3,
+            SatpMode::Sv48 => 4,
+            SatpMode::Sv57 => 5,
+        }
+    }
+}
+
+impl Mem {
+    fn read_pte(&self, addr: u64, size: u64) -> u64 {
+        (0..size).fold(0, |v, i| v | (self.read_byte(addr + i) as u64) << (8 * i))
+    }
+
+    /// Translate `vaddr` for `access` at the hart's current privilege
+    ///
+    /// Machine mode and `Bare` use the address as is. The leaf PTE's
+    /// accessed and dirty bits are set as needed unless `peek` is set, as
+    /// when the JIT reads code to compile it.
+    pub fn translate(
+        &mut self,
+        vaddr: u64,
+        access: Access,
+        csrs: &CsrFile,
+        xlen: Xlen,
+        peek: bool,
+    ) -> Result<u64, Trap> {
+        let vaddr = vaddr & xlen_mask(xlen);
+        let level = match access {
+            Access::Execute => csrs.mode,
+            _ => csrs.data_priv(),
+        };
+        let mode = SatpMode::from_satp(csrs.satp, xlen).unwrap_or(SatpMode::Bare);
+        if level == Priv::Machine || mode == SatpMode::Bare {
+            return Ok(vaddr);
+        }
+        let fault = Err(Trap::exception(access.page_fault(), vaddr));
+        let (vpn_bits, pte_size, ppn_mask) = match mode {
+            SatpMode::Sv32 => (10, 4, 0x3f_ffff),
+            _ => (9, 8, (1 << 44) - 1),
+        };
+        let levels = mode.levels();
+        // Addresses must be sign-extended from the top VPN bit
+        if xlen.is_64() {
+            let top = (vaddr as i64) >> (12 + vpn_bits * levels - 1);
+            if top != 0 && top != -1 {
+                return fault;
+            }
+        }
+        let mut table = (csrs.satp & ppn_mask) << 12;
+        for i in (0..levels).rev() {
+            let vpn = vaddr >> (12 + vpn_bits * i) & ((1 << vpn_bits) - 1);
+            let addr = table + vpn * pte_size;
+            let entry = self.read_pte(addr, pte_size);
+            // Reserved, Svpbmt and Svnapot bits must be clear
+            if entry & pte::V == 0 || entry & (pte::R | pte::W) == pte::W || entry >> 54 != 0 {
+                return fault;
+            }
+            let ppn = entry >> 10 & ppn_mask;
+            if entry & (pte::R | pte::X) == 0 {
+                if entry & (pte::D | pte::A | pte::U) != 0 {
+                    return fault;
+                }
+                table = ppn << 12;
+                continue;
+            }
+            let user = entry & pte::U != 0;
+            let allowed = match level {
+                Priv::User => user,
+                // Supervisor code never runs from user pages
+                Priv::Supervisor => !user || (access != Access::Execute && csrs.mstatus & mstatus::SUM != 0),
+                Priv::Machine => true,
+            } && match access {
+                Access::Read => entry & pte::R != 0 || (csrs.mstatus & mstatus::MXR != 0 && entry & pte::X != 0),
+                Access::Write => entry & pte::W != 0,
+                Access::Execute => entry & pte::X != 0,
+            };
+            // Superpages must be aligned to their size
+            if !allowed || ppn & ((1 << (vpn_bits * i)) - 1) != 0 {
+                return fault;
+            }
+            let dirty = match access {
+                Access::Write => pte::D,
+                _ => 0,
+            };
+            let updated = entry | pte::A | dirty;
+            if !peek && updated != entry {
+                for b in 0..pte_size {
+                    self.write_byte(addr + b, (updated >> (8 * b)) as u8);
+                }
+            }
+            let offset = (1 << (12 + vpn_bits * i)) - 1;
+            return Ok(ppn << 12 | vaddr & offset);
+        }
+        fault
+    }
+}
+
+/// Copy between guest memory at `addr` and `buf`, a 4 KiB page at a time
+///
+/// Pages that are adjacent to the guest need not be in host memory, so a run
+/// is never handed to `mem` across a 4 KiB boundary. A store checks that it
+/// may write the last page before writing the first, leaving memory as it
+/// was if that faults.
+pub fn transfer(
+    mem: &mut dyn FnMut(u64, Access) -> Result<*mut u8, Trap>,
+    addr: u64,
+    buf: &mut [u8],
+    store: bool,
+    mask: u64,
+) -> Result<(), Trap> {
+    let access = match store {
+        true => Access::Write,
+        false => Access::Read,
+    };
+    if store && !buf.is_empty() {
+        let last = addr.wrapping_add(buf.len() as u64 - 1) & mask;
+        if (addr ^ last) >> 12 != 0 {
+            mem(last, access)?;
+        }
+    }
+    let mut k = 0;
+    while k < buf.len() {
+        let a = addr.wrapping_add(k as u64) & mask;
+        let n = ((0x1000 - (a & 0xfff)) as usize).min(buf.len() - k);
+        let p = mem(a, access)?;
+        // SAFETY: `p` points into a 64 KiB page, and the run stays in one 4 KiB page
+        unsafe {
+            match store {
+                true => core::ptr::copy_nonoverlapping(buf[k..].as_ptr(), p, n),
+                false => core::ptr::copy_nonoverlapping(p, buf[k..].as_mut_ptr(), n),
+            }
+        }
+        k += n;
+    }
+    Ok(())
+}
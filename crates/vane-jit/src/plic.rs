@@ -0,0 +1,157 @@
+//! The platform-level interrupt controller, laid out as on QEMU's `virt`.
+//!
+//! There are [`NDEV`] level-triggered sources and two contexts for the one
+//! hart: context 0 drives `MEIP` and context 1 drives `SEIP`. A source is
+//! pending while its line is high and it is not claimed; claiming takes the
+//! highest-priority pending source enabled for the context and above its
+//! threshold, and completing it lets it pend again.
+use crate::trap::Interrupt;
+
+/// Number of interrupt sources, numbered from 1
+pub const NDEV: u32 = 95;
+/// Number of contexts: machine then supervisor mode of hart 0
+pub const CONTEXTS: usize = 2;
+/// Offset of the source priorities
+pub const PRIORITY: u64 = 0;
+/// Offset of the pending bits
+pub const PENDING: u64 = 0x1000;
+/// Offset of context 0's enable bits; each context has 0x80 bytes
+pub const ENABLE: u64 = 0x2000;
+/// Offset of context 0's threshold; each context has 0x1000 bytes
+pub const THRESHOLD: u64 = 0x20_0000;
+/// Offset of context 0's claim/complete register
+pub const CLAIM: u64 = 0x20_0004;
+
+/// Priorities and thresholds are three bits wide
+const PRIORITY_MASK: u32 = 7;
+/// Bits of sources 1 to `NDEV`
+const SOURCES: u128 = ((1 << (NDEV + 1)) - 1) & !1;
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct Plic {
+    pub priority: [u32; NDEV as usize + 1],
+    /// Source lines, one bit per source
+    pub levels: u128,
+    claimed: u128,
+    pub enable: [u128; CONTEXTS],
+    pub threshold: [u32; CONTEXTS],
+}
+
+impl Default for Plic {
+    fn default() -> Self {
+        Plic {
+            priority: [0; NDEV as usize + 1],
+            levels: 0,
+            claimed: 0,
+            enable: [0; CONTEXTS],
+            threshold: [0; CONTEXTS],
+        }
+    }
+}
+
+impl Plic {
+    pub fn set_level(&mut self, source: u32, high: bool) {
+        if source == 0 || source > NDEV {
+            return;
+        }
+        match high {
+            true => self.levels |= 1 << source,
+            false => self.levels &= !(1 << source),
+        }
+    }
+
+    pub fn pending(&self) -> u128 {
+        self.levels & !self.claimed & SOURCES
+    }
+
+    /// The source context `ctx` would claim, or 0
+    fn best(&self, ctx: usize) -> u32 {
+        let candidates = self.pending() & self.enable[ctx];
+        (1..=NDEV)
+            .filter(|&s| candidates >> s & 1 != 0 && self.priority[s as usize] > self.threshold[ctx])
+            // The lowest-numbered source wins a tie
+            .max_by_key(|&s| (self.priority[s as usize], core::cmp::Reverse(s)))
+            .unwrap_or(0)
+    }
+
+    /// The `mip` bits the contexts drive
+    pub fn interrupts(&self) -> u64 {
+        let mut bits = 0;
+        if self.best(0) != 0 {
+            bits |= Interrupt::MachineExternal.bit();
+        }
+        if self.best(1) != 0 {
+            bits |= Interrupt::SupervisorExternal.bit();
+        }
+        bits
+    }
+
+    /// The context whose threshold or claim register is at `offset`
+    fn context(offset: u64, base: u64) -> Option<usize> {
+        let ctx = (offset.checked_sub(base)? / 0x1000) as usize;
+        match (offset - base).is_multiple_of(0x1000) && ctx < CONTEXTS {
+            true => Some(ctx),
+            false => None,
+        }
+    }
+
+    /// Read the 32-bit register at `offset`; reading a claim register claims
+    pub fn read(&mut self, offset: u64) -> u32 {
+        let word = |bits: u128, at: u64| (bits >> (at / 4 * 32)) as u32;
+        match offset {
+            0..0x1000 => self.priority.get(offset as usize / 4).copied().unwrap_or(0),
+            0x1000..0x1010 => word(self.pending(), offset - PENDING),
+            0x2000..0x2100 => {
+                let ctx = ((offset - ENABLE) / 0x80) as usize;
+                match (offset - ENABLE) % 0x80 {
+                    at @ 0..0x10 => word(self.enable[ctx], at),
+                    _ => 0,
+                }
+            }
+            _ => {
+                if let Some(ctx) = Self::context(offset, THRESHOLD) {
+                    return self.threshold[ctx];
+                }
+                match Self::context(offset, CLAIM) {
+                    Some(ctx) => {
+                        let s = self.best(ctx);
+                        self.claimed |= (1 << s) & SOURCES;
+                        s
+                    }
+                    None => 0,
+                }
+            }
+        }
+    }
+
+    /// Write the 32-bit register at `offset`; writing a claim register completes
+    pub fn write(&mut self, offset: u64, value: u32) {
+        let set_word = |bits: &mut u128, at: u64| {
+            let shift = at / 4 * 32;
+            *bits = (*bits & !((u32::MAX as u128) << shift) | (value as u128) << shift) & SOURCES;
+        };
+        match offset {
+            0..0x1000 => {
+                if let Some(p) = self.priority.get_mut(offset as usize / 4).filter(|_| offset >= 4) {
+                    *p = value & PRIORITY_MASK;
+                }
+            }
+            0x2000..0x2100 => {
+                let ctx = ((offset - ENABLE) / 0x80) as usize;
+                if let at @ 0..0x10 = (offset - ENABLE) % 0x80 {
+                    set_word(&mut self.enable[ctx], at);
+                }
+            }
+            _ => {
+                if let Some(ctx) = Self::context(offset, THRESHOLD) {
+                    self.threshold[ctx] = value & PRIORITY_MASK;
+                } else if let Some(ctx) = Self::context(offset, CLAIM) {
+                    // Completing a source not enabled for the context is ignored
+                    if value <= NDEV && self.enable[ctx] >> value & 1 != 0 {
+                        self.claimed &= !(1 << value);
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,296 @@
+//! The C extension, decoded into the base instructions it expands to.
+//!
+//! `rv_asm`'s own compressed decoder has C.JAL where RV64 has C.ADDIW, takes
+//! C.LDSP's offset from the C.LWSP bit layout, zero-extends C.ANDI and lacks
+//! C.LD, C.SD, C.ADDW and C.SUBW, so both engines decode through here
+//! instead. The floating-point loads and stores are left to
+//! [`ExtInst`](crate::ext::ExtInst), which already handles them.
+use rv_asm::{DecodeError, Imm, Inst, IsCompressed, Reg, Xlen};
+
+/// [`Inst::decode`], with compressed instructions decoded by [`decode_compressed`]
+pub fn decode(code: u32, xlen: Xlen) -> Result<(Inst, IsCompressed), DecodeError> {
+    if code & 0b11 != 0b11 {
+        return Ok((decode_compressed(code as u16, xlen)?, IsCompressed::Yes));
+    }
+    Ok((Inst::decode_normal(code, xlen)?, IsCompressed::No))
+}
+
+fn bits(code: u32, lo: u32, hi: u32) -> u32 {
+    (code >> lo) & ((1 << (hi - lo + 1)) - 1)
+}
+
+/// A full register field starting at bit `lo`
+fn reg(code: u32, lo: u32) -> Reg {
+    Reg(bits(code, lo, lo + 4) as u8)
+}
+
+/// `rd'`/`rs1'`/`rs2'`, one of x8 to x15
+fn creg(code: u32, lo: u32) -> Reg {
+    Reg(bits(code, lo, lo + 2) as u8 + 8)
+}
+
+/// Gather an immediate from `(lo, hi, shift)` fields
+fn gather(code: u32, fields: &[(u32, u32, u32)]) -> u32 {
+    fields
+        .iter()
+        .fold(0, |acc, &(lo, hi, to)| acc | (bits(code, lo, hi) << to))
+}
+
+fn uimm(code: u32, fields: &[(u32, u32, u32)]) -> Imm {
+    Imm::new_u32(gather(code, fields))
+}
+
+/// Like [`uimm`], sign-extended from bit `width - 1`
+fn simm(code: u32, fields: &[(u32, u32, u32)], width: u32) -> Imm {
+    let shift = 32 - width;
+    Imm::new_i32((gather(code, fields) << shift) as i32 >> shift)
+}
+
+/// Decode the two bytes of a compressed instruction
+///
+/// Reserved encodings are errors, as are the floating-point forms. HINTs
+/// decode to the no-op they expand to.
+pub fn decode_compressed(code: u16, xlen: Xlen) -> Result<Inst, DecodeError> {
+    let c = code as u32;
+    let err = |unexpected_field| {
+        Err(DecodeError {
+            instruction: c,
+            unexpected_field,
+        })
+    };
+    if c == 0 {
+        return err("null instruction");
+    }
+    let rd = reg(c, 7);
+    let rs2 = reg(c, 2);
+    // rs1'/rd' and rs2'/rd'
+    let (rs1c, rs2c) = (creg(c, 7), creg(c, 2));
+    let imm6 = simm(c, &[(2, 6, 0), (12, 12, 5)], 6);
+    let shamt = uimm(c, &[(2, 6, 0), (12, 12, 5)]);
+    let shamt_ok = xlen.is_64() || bits(c, 12, 12) == 0;
+    let w_off = [(10, 12, 3), (6, 6, 2), (5, 5, 6)];
+    let d_off = [(10, 12, 3), (5, 6, 6)];
+    let j_off = simm(
+        c,
+        &[(3, 5, 1), (11, 11, 4), (2, 2, 5), (7, 7, 6), (6, 6, 7), (9, 10, 8), (8, 8, 10), (12, 12, 11)],
+        12,
+    );
+    let b_off = simm(c, &[(3, 4, 1), (10, 11, 3), (2, 2, 5), (5, 6, 6), (12, 12, 8)], 9);
+    Ok(match (c & 0b11, bits(c, 13, 15)) {
+        // C.ADDI4SPN
+        (0b00, 0b000) => {
+            let imm = uimm(c, &[(6, 6, 2), (5, 5, 3), (11, 12, 4), (7, 10, 6)]);
+            if imm.as_u32() == 0 {
+                return err("C.ADDI4SPN zero immediate");
+            }
+            Inst::Addi {
+                imm,
+                dest: rs2c,
+                src1: Reg::SP,
+            }
+        }
+        // C.LW
+        (0b00, 0b010) => Inst::Lw {
+            offset: uimm(c, &w_off),
+            dest: rs2c,
+            base: rs1c,
+        },
+        // C.LD
+        (0b00, 0b011) if xlen.is_64() => Inst::Ld {
+            offset: uimm(c, &d_off),
+            dest: rs2c,
+            base: rs1c,
+        },
+        // C.SW
+        (0b00, 0b110) => Inst::Sw {
+            offset: uimm(c, &w_off),
+            src: rs2c,
+            base: rs1c,
+        },
+        // C.SD
+        (0b00, 0b111) if xlen.is_64() => Inst::Sd {
+            offset: uimm(c, &d_off),
+            src: rs2c,
+            base: rs1c,
+        },
+        // C.ADDI and C.NOP
+        (0b01, 0b000) => Inst::Addi {
+            imm: imm6,
+            dest: rd,
+            src1: rd,
+        },
+        // C.JAL
+        (0b01, 0b001) if xlen.is_32() => Inst::Jal {
+            offset: j_off,
+            dest: Reg::RA,
+        },
+        // C.ADDIW
+        (0b01, 0b001) => {
+            if rd.0 == 0 {
+                return err("C.ADDIW rd must not be zero");
+            }
+            Inst::AddiW {
+                imm: imm6,
+                dest: rd,
+                src1: rd,
+            }
+        }
+        // C.LI
+        (0b01, 0b010) => Inst::Addi {
+            imm: imm6,
+            dest: rd,
+            src1: Reg::ZERO,
+        },
+        // C.ADDI16SP
+        (0b01, 0b011) if rd == Reg::SP => {
+            let imm = simm(c, &[(6, 6, 4), (2, 2, 5), (5, 5, 6), (3, 4, 7), (12, 12, 9)], 10);
+            if imm.as_u32() == 0 {
+                return err("C.ADDI16SP zero immediate");
+            }
+            Inst::Addi {
+                imm,
+                dest: Reg::SP,
+                src1: Reg::SP,
+            }
+        }
+        // C.LUI
+        (0b01, 0b011) => {
+            let uimm = simm(c, &[(2, 6, 12), (12, 12, 17)], 18);
+            if uimm.as_u32() == 0 {
+                return err("C.LUI zero immediate");
+            }
+            Inst::Lui { uimm, dest: rd }
+        }
+        (0b01, 0b100) => match (bits(c, 10, 11), bits(c, 12, 12), bits(c, 5, 6)) {
+            (0b00 | 0b01, ..) if !shamt_ok => return err("C.SRLI/C.SRAI shamt[5] on RV32"),
+            // C.SRLI
+            (0b00, ..) => Inst::Srli {
+                imm: shamt,
+                dest: rs1c,
+                src1: rs1c,
+            },
+            // C.SRAI
+            (0b01, ..) => Inst::Srai {
+                imm: shamt,
+                dest: rs1c,
+                src1: rs1c,
+            },
+            // C.ANDI
+            (0b10, ..) => Inst::Andi {
+                imm: imm6,
+                dest: rs1c,
+                src1: rs1c,
+            },
+            (_, 0, op) => {
+                let (dest, src1, src2) = (rs1c, rs1c, rs2c);
+                match op {
+                    0b00 => Inst::Sub { dest, src1, src2 },
+                    0b01 => Inst::Xor { dest, src1, src2 },
+                    0b10 => Inst::Or { dest, src1, src2 },
+                    _ => Inst::And { dest, src1, src2 },
+                }
+            }
+            (_, _, op) if xlen.is_64() && op < 0b10 => {
+                let (dest, src1, src2) = (rs1c, rs1c, rs2c);
+                match op {
+                    0b00 => Inst::SubW { dest, src1, src2 },
+                    _ => Inst::AddW { dest, src1, src2 },
+                }
+            }
+            _ => return err("C1 arithmetic funct"),
+        },
+        // C.J
+        (0b01, 0b101) => Inst::Jal {
+            offset: j_off,
+            dest: Reg::ZERO,
+        },
+        // C.BEQZ
+        (0b01, 0b110) => Inst::Beq {
+            offset: b_off,
+            src1: rs1c,
+            src2: Reg::ZERO,
+        },
+        // C.BNEZ
+        (0b01, 0b111) => Inst::Bne {
+            offset: b_off,
+            src1: rs1c,
+            src2: Reg::ZERO,
+        },
+        // C.SLLI
+        (0b10, 0b000) => {
+            if !shamt_ok {
+                return err("C.SLLI shamt[5] on RV32");
+            }
+            Inst::Slli {
+                imm: shamt,
+                dest: rd,
+                src1: rd,
+            }
+        }
+        // C.LWSP
+        (0b10, 0b010) => {
+            if rd.0 == 0 {
+                return err("C.LWSP rd must not be zero");
+            }
+            Inst::Lw {
+                offset: uimm(c, &[(4, 6, 2), (12, 12, 5), (2, 3, 6)]),
+                dest: rd,
+                base: Reg::SP,
+            }
+        }
+        // C.LDSP
+        (0b10, 0b011) if xlen.is_64() => {
+            if rd.0 == 0 {
+                return err("C.LDSP rd must not be zero");
+            }
+            Inst::Ld {
+                offset: uimm(c, &[(5, 6, 3), (12, 12, 5), (2, 4, 6)]),
+                dest: rd,
+                base: Reg::SP,
+            }
+        }
+        (0b10, 0b100) => match (bits(c, 12, 12), rd.0, rs2.0) {
+            (0, 0, 0) => return err("C.JR rs1 must not be zero"),
+            // C.JR
+            (0, _, 0) => Inst::Jalr {
+                offset: Imm::ZERO,
+                base: rd,
+                dest: Reg::ZERO,
+            },
+            // C.MV
+            (0, ..) => Inst::Add {
+                dest: rd,
+                src1: Reg::ZERO,
+                src2: rs2,
+            },
+            // C.EBREAK
+            (_, 0, 0) => Inst::Ebreak,
+            // C.JALR
+            (_, _, 0) => Inst::Jalr {
+                offset: Imm::ZERO,
+                base: rd,
+                dest: Reg::RA,
+            },
+            // C.ADD
+            _ => Inst::Add {
+                dest: rd,
+                src1: rd,
+                src2: rs2,
+            },
+        },
+        // C.SWSP
+        (0b10, 0b110) => Inst::Sw {
+            offset: uimm(c, &[(9, 12, 2), (7, 8, 6)]),
+            src: rs2,
+            base: Reg::SP,
+        },
+        // C.SDSP
+        (0b10, 0b111) if xlen.is_64() => Inst::Sd {
+            offset: uimm(c, &[(10, 12, 3), (7, 9, 6)]),
+            src: rs2,
+            base: Reg::SP,
+        },
+        (0b11, _) => return err("instruction is not compressed"),
+        _ => return err("compressed funct3"),
+    })
+}
@@ -0,0 +1,184 @@
+//! The Supervisor Binary Interface, v2.0, for the one hart.
+//!
+//! The reactor can answer a supervisor `ecall` itself instead of running
+//! firmware: `a7` names the extension, `a6` the function and `a0`-`a5`
+//! carry the arguments. [`Call::decode`] turns those into a [`Call`]; the
+//! reactor carries it out and returns an [`SbiRet`] in `a0` and `a1`. The
+//! base, timer, IPI, RFENCE, HSM, system reset and debug console
+//! extensions are implemented; the legacy v0.1 calls are not.
+use rv_asm::Xlen;
+
+use crate::xlen_mask;
+
+/// Extension IDs, as passed in `a7`
+pub mod eid {
+    pub const BASE: u64 = 0x10;
+    pub const TIME: u64 = 0x5449_4d45;
+    pub const IPI: u64 = 0x0073_5049;
+    pub const RFENCE: u64 = 0x5246_4e43;
+    pub const HSM: u64 = 0x0048_534d;
+    pub const SRST: u64 = 0x5352_5354;
+    pub const DBCN: u64 = 0x4442_434e;
+    pub const ALL: [u64; 7] = [BASE, TIME, IPI, RFENCE, HSM, SRST, DBCN];
+}
+
+/// Error codes, as returned in `a0`
+pub mod error {
+    pub const SUCCESS: i64 = 0;
+    pub const FAILED: i64 = -1;
+    pub const NOT_SUPPORTED: i64 = -2;
+    pub const INVALID_PARAM: i64 = -3;
+    pub const DENIED: i64 = -4;
+    pub const INVALID_ADDRESS: i64 = -5;
+    pub const ALREADY_AVAILABLE: i64 = -6;
+}
+
+/// SBI 2.0: major version in bits 30:24
+pub const SPEC_VERSION: u64 = 2 << 24;
+/// Not a registered implementation ID; "vane" in ASCII
+pub const IMPL_ID: u64 = 0x7661_6e65;
+pub const IMPL_VERSION: u64 = 1;
+
+/// HSM hart states
+pub mod hart_state {
+    pub const STARTED: u64 = 0;
+}
+
+/// `hart_suspend` types
+pub mod suspend {
+    pub const RETENTIVE: u32 = 0;
+    pub const NON_RETENTIVE: u32 = 0x8000_0000;
+}
+
+/// `system_reset` types
+pub mod reset {
+    pub const SHUTDOWN: u32 = 0;
+    pub const COLD_REBOOT: u32 = 1;
+    pub const WARM_REBOOT: u32 = 2;
+}
+
+/// What an SBI call returns in `a0` and `a1`
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct SbiRet {
+    pub error: i64,
+    pub value: u64,
+}
+
+impl SbiRet {
+    pub fn ok(value: u64) -> Self {
+        SbiRet { error: error::SUCCESS, value }
+    }
+    pub fn err(error: i64) -> Self {
+        SbiRet { error, value: 0 }
+    }
+}
+
+/// A decoded SBI call
+///
+/// Hart masks have already been checked against the one hart, so the
+/// remote fences and IPIs only say whether they reach it.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum Call {
+    /// A base extension call, answered without side effects
+    Base(SbiRet),
+    SetTimer(u64),
+    /// `send_ipi`; `true` if it targets this hart
+    SendIpi(bool),
+    /// `remote_fence_i`
+    FenceI(bool),
+    /// `remote_sfence_vma` and `remote_sfence_vma_asid`; there is no TLB
+    SfenceVma,
+    HartStop,
+    HartStatus(u64),
+    HartSuspend { kind: u32, resume: u64, opaque: u64 },
+    SystemReset { kind: u32, reason: u32 },
+    ConsoleWrite { len: u64, addr: u64 },
+    ConsoleRead { len: u64, addr: u64 },
+    ConsoleWriteByte(u8),
+}
+
+impl Call {
+    /// Decode the call in `a7`, `a6` and `args` (`a0`-`a5`)
+    ///
+    /// Calls that fail before doing anything give their error instead.
+    pub fn decode(eid: u64, fid: u64, args: [u64; 6], xlen: Xlen) -> Result<Call, i64> {
+        let [a0, a1, a2, ..] = args;
+        // 64-bit values and addresses take two registers on RV32
+        let wide = |lo: u64, hi: u64| match xlen {
+            Xlen::Rv32 => lo & 0xffff_ffff | hi << 32,
+            Xlen::Rv64 => lo,
+        };
+        Ok(match (eid, fid) {
+            (eid::BASE, _) => Call::Base(base(fid, a0)),
+            (eid::TIME, 0) => Call::SetTimer(wide(a0, a1)),
+            (eid::IPI, 0) => Call::SendIpi(targets(a0, a1, xlen)?),
+            (eid::RFENCE, 0) => Call::FenceI(targets(a0, a1, xlen)?),
+            (eid::RFENCE, 1 | 2) => {
+                targets(a0, a1, xlen)?;
+                Call::SfenceVma
+            }
+            // The one hart is always running
+            (eid::HSM, 0) => match a0 {
+                0 => return Err(error::ALREADY_AVAILABLE),
+                _ => return Err(error::INVALID_PARAM),
+            },
+            (eid::HSM, 1) => Call::HartStop,
+            (eid::HSM, 2) => match a0 {
+                0 => Call::HartStatus(hart_state::STARTED),
+                _ => return Err(error::INVALID_PARAM),
+            },
+            (eid::HSM, 3) => match a0 as u32 {
+                kind @ (suspend::RETENTIVE | suspend::NON_RETENTIVE) if a0 >> 32 == 0 => Call::HartSuspend {
+                    kind,
+                    resume: a1,
+                    opaque: a2,
+                },
+                _ => return Err(error::INVALID_PARAM),
+            },
+            (eid::SRST, 0) => match (a0, a1) {
+                (0..=2, 0..=1) => Call::SystemReset {
+                    kind: a0 as u32,
+                    reason: a1 as u32,
+                },
+                _ => return Err(error::INVALID_PARAM),
+            },
+            (eid::DBCN, 0) => Call::ConsoleWrite {
+                len: a0,
+                addr: wide(a1, a2),
+            },
+            (eid::DBCN, 1) => Call::ConsoleRead {
+                len: a0,
+                addr: wide(a1, a2),
+            },
+            (eid::DBCN, 2) => Call::ConsoleWriteByte(a0 as u8),
+            _ => return Err(error::NOT_SUPPORTED),
+        })
+    }
+}
+
+/// Answer base extension function `fid` with argument `a0`
+fn base(fid: u64, a0: u64) -> SbiRet {
+    match fid {
+        0 => SbiRet::ok(SPEC_VERSION),
+        1 => SbiRet::ok(IMPL_ID),
+        2 => SbiRet::ok(IMPL_VERSION),
+        3 => SbiRet::ok(eid::ALL.contains(&a0) as u64),
+        // `mvendorid`, `marchid` and `mimpid` all read zero
+        4..=6 => SbiRet::ok(0),
+        _ => SbiRet::err(error::NOT_SUPPORTED),
+    }
+}
+
+/// Whether `hart_mask` from `hart_mask_base` includes hart 0
+///
+/// A base of all ones means every hart. Naming any other hart is an error.
+fn targets(mask: u64, base: u64, xlen: Xlen) -> Result<bool, i64> {
+    if base == xlen_mask(xlen) {
+        return Ok(true);
+    }
+    match (mask, base) {
+        (0, _) => Ok(false),
+        (1, 0) => Ok(true),
+        _ => Err(error::INVALID_PARAM),
+    }
+}
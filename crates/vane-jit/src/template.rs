@@ -1,4 +1,5 @@
-use core::array;
+use alloc::string::String;
+use core::{array, cell::Cell};
 
 use wasmparser::Operator;
 
@@ -21,6 +22,8 @@ pub struct Params<'a> {
     pub react: &'a (dyn JitCtx + 'a),
     pub trial: &'a (dyn Fn(u64) -> Heat + 'a),
     pub flate: &'a (dyn Flate + 'a),
+    pub regs: &'a RegCache,
+    pub region: &'a Region,
     pub root: u64,
     pub flags: Flags,
 }
@@ -34,6 +37,22 @@ pub struct Flags {
     pub shared_security_directory_vaddr: Option<u64>,
     pub use_32bit_paging: bool,
     pub use_multilevel_paging: bool,
+    /// Guest register width; `None` means RV64
+    pub xlen: Option<Xlen>,
+    /// Raise address-misaligned exceptions rather than split misaligned accesses
+    pub trap_misaligned: bool,
+    /// Entries a block runs in the interpreter before it is compiled to
+    /// JavaScript; 0 compiles it on first entry
+    pub js_threshold: u32,
+    /// Entries a JavaScript block runs before it moves to WebAssembly, when
+    /// that tier is on
+    pub wasm_threshold: u32,
+    /// Instructions a block may inline before the rest is left to blocks of
+    /// their own; `None` is unbounded
+    pub region_insts: Option<u32>,
+    /// Bytes of JavaScript a block may grow to before the same; it counts
+    /// only under [`CoreJS`]
+    pub region_bytes: Option<usize>,
 }
 
 impl Flags {
@@ -63,8 +82,57 @@ impl Flags {
             shared_security_directory_vaddr,
             use_32bit_paging,
             use_multilevel_paging,
+            xlen: None,
+            trap_misaligned: false,
+            js_threshold: 0,
+            wasm_threshold: 0,
+            region_insts: None,
+            region_bytes: None,
         }
     }
+
+    /// Set the guest register width
+    pub fn with_xlen(self, xlen: Xlen) -> Self {
+        Self {
+            xlen: Some(xlen),
+            ..self
+        }
+    }
+
+    /// Trap on misaligned loads and stores instead of performing them
+    pub fn with_trap_misaligned(self, trap_misaligned: bool) -> Self {
+        Self {
+            trap_misaligned,
+            ..self
+        }
+    }
+
+    /// Set the entry counts at which blocks move up a tier
+    pub fn with_tiering(self, js_threshold: u32, wasm_threshold: u32) -> Self {
+        Self {
+            js_threshold,
+            wasm_threshold,
+            ..self
+        }
+    }
+
+    /// Bound the size of each compiled block
+    pub fn with_region_budget(
+        self,
+        region_insts: Option<u32>,
+        region_bytes: Option<usize>,
+    ) -> Self {
+        Self {
+            region_insts,
+            region_bytes,
+            ..self
+        }
+    }
+
+    /// The guest register width, defaulting to RV64
+    pub fn xlen(&self) -> Xlen {
+        self.xlen.unwrap_or(Xlen::Rv64)
+    }
 }
 
 pub struct TemplateJit<'a> {
@@ -80,19 +148,72 @@ pub trait TemplateJS {
     fn template_jit_wasm<'a>(&self, j: &'a TemplateJit<'_>) -> Self::Wasm<'a>;
 }
 
+/// The integer registers a block uses, kept in `let` locals while it runs
+///
+/// [`TemplateReg`] records each register as it is rendered; [`CoreJS`] then
+/// loads the used ones at entry, and `sync` stores the written ones back to
+/// `$._r` before every exit.
+#[derive(Default)]
+pub struct RegCache {
+    used: Cell<u32>,
+    dirty: Cell<u32>,
+}
+impl RegCache {
+    /// Registers read or written, as a bit per register
+    pub fn used(&self) -> u32 {
+        self.used.get()
+    }
+    /// Registers written, as a bit per register
+    pub fn dirty(&self) -> u32 {
+        self.dirty.get()
+    }
+}
+/// How much of its budget a block has used, shared by everything inlined into it
+#[derive(Default)]
+pub struct Region {
+    insts: Cell<u32>,
+    bytes: Cell<usize>,
+}
+impl Region {
+    /// Instructions compiled so far
+    pub fn insts(&self) -> u32 {
+        self.insts.get()
+    }
+    /// Bytes of JavaScript written so far
+    pub fn bytes(&self) -> usize {
+        self.bytes.get()
+    }
+}
+/// Writes the block to a string, counting the bytes against its [`Region`]
+struct Counted<'a> {
+    out: String,
+    region: &'a Region,
+}
+impl core::fmt::Write for Counted<'_> {
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        self.region.bytes.set(self.region.bytes.get() + s.len());
+        self.out.push_str(s);
+        Ok(())
+    }
+}
 struct TemplateReg<'a, const N: usize = 32> {
     reg: &'a Reg,
     value: Option<&'a (dyn Display + 'a)>,
     n: [(); N],
-    flate: &'a (dyn Flate + 'a),
+    regs: &'a RegCache,
 }
 impl<'a, const N: usize> Display for TemplateReg<'a, N> {
     fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         let r = self.reg.0 & ((N - 1) & 0xff) as u8;
         if r != 0 {
+            let regs = self.regs;
+            regs.used.set(regs.used.get() | 1 << r);
             match self.value.as_deref() {
-                None => write!(f, "(($._r??=$.r)[`x{r}`]??=0n)"),
-                Some(a) => write!(f, "(($._r??=$.r)[`x{r}`]={a})"),
+                None => write!(f, "x{r}"),
+                Some(a) => {
+                    regs.dirty.set(regs.dirty.get() | 1 << r);
+                    write!(f, "(x{r}={a})")
+                }
             }
         } else {
             match self.value.as_deref() {
@@ -102,44 +223,57 @@ impl<'a, const N: usize> Display for TemplateReg<'a, N> {
         }
     }
 }
+/// A floating-point register, holding raw (NaN-boxed) bits
+struct TemplateFReg<'a> {
+    reg: &'a Reg,
+    value: Option<&'a (dyn Display + 'a)>,
+}
+impl<'a> Display for TemplateFReg<'a> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        let r = self.reg.0 & 31;
+        match self.value {
+            None => write!(f, "(($._r??=$.r)[`f{r}`]??=0n)"),
+            Some(a) => write!(f, "(($._r??=$.r)[`f{r}`]={a})"),
+        }
+    }
+}
 pub mod riscv;
+pub mod wasm;
 impl<'b> TemplateJit<'b> {
-    pub fn jit_wasm<'a>(
-        &'a self,
-        go: impl for<'c> FnOnce(Labels<'c>, u32) -> Box<dyn Iterator<Item = JitOpcode<'a>> + 'a>,
-    ) -> Box<dyn Iterator<Item = JitOpcode<'a>> + 'a> {
+    /// The WebAssembly counterpart of `jit_js`: open a loop for this pc, or
+    /// branch back to the one already open
+    ///
+    /// `render` gets the labels with this pc added and the depth inside the
+    /// loop. Loops are labelled by pc, so assembling the module works out
+    /// how far each branch goes.
+    pub fn jit_wasm(
+        &self,
+        out: &mut wasm::Ops,
+        render: impl FnOnce(&mut wasm::Ops, Labels<'_>, u32),
+    ) {
+        match (self.params.trial)(self.pc) {
+            Heat::New => {}
+            Heat::Cached => return wasm::exit(out, self.pc),
+        }
+
         let mut labels = self.labels.clone();
         match labels.0.entry(self.pc) {
             alloc::collections::btree_map::Entry::Vacant(vacant_entry) => {
+                if self.spent() {
+                    return wasm::exit(out, self.pc);
+                }
                 let label_name = format!("x{}", self.pc);
                 vacant_entry.insert(Label {
                     ident_name: &label_name,
                     index: self.depth,
                 });
                 let nd = self.depth + 1;
-                // let mut i: Vec<_> = Default::default();
-                let i = go(labels, nd);
-                Box::new(
-                    [JitOpcode::Operator {
-                        op: Operator::Loop {
-                            blockty: wasmparser::BlockType::Empty,
-                        },
-                    }]
-                    .into_iter()
-                    .chain(i)
-                    .chain([JitOpcode::Operator { op: Operator::End }]),
-                )
+                out.push(JitOpcode::Loop { label: self.pc });
+                render(out, labels, nd);
+                out.push(Operator::End);
             }
-            alloc::collections::btree_map::Entry::Occupied(occupied_entry) => {
-                let Label { index, ident_name } = occupied_entry.get();
-                Box::new(
-                    [JitOpcode::Operator {
-                        op: Operator::Br {
-                            relative_depth: self.depth - index,
-                        },
-                    }]
-                    .into_iter(),
-                )
+            alloc::collections::btree_map::Entry::Occupied(_) => {
+                out.push(JitOpcode::Br { label: self.pc });
             }
         }
     }
@@ -151,13 +285,17 @@ impl<'b> TemplateJit<'b> {
         match (self.params.trial)(self.pc) {
             Heat::New => {}
             Heat::Cached => {
-                return write!(f, "return J({}n);", self.pc);
+                return self.link(f);
             }
         }
 
         let mut labels = self.labels.clone();
         match labels.0.entry(self.pc) {
             alloc::collections::btree_map::Entry::Vacant(vacant_entry) => {
+                // Past its budget the block ends, and the rest compiles on its own
+                if self.spent() {
+                    return self.link(f);
+                }
                 let label_name = format!("x{}", self.pc);
                 vacant_entry.insert(Label {
                     ident_name: &label_name,
@@ -174,22 +312,57 @@ impl<'b> TemplateJit<'b> {
             }
         }
     }
+    /// The statement storing written registers back to `$._r`, for before
+    /// each `return` out of the block
+    fn sync(&self) -> String {
+        format!("{}();", self.params.flate.flate("sync"))
+    }
+    /// Leave the block for the one at this pc, linking to it directly once
+    /// it has been looked up
+    fn link(&self, f: &mut Formatter) -> core::fmt::Result {
+        let links = self.params.flate.flate("links");
+        let link = self.params.flate.flate("link");
+        let pc = self.pc;
+        write!(f, "{}return {links}[{pc}]??{link}({pc}n);", self.sync())
+    }
+    /// Whether the block has used up its budget, counting this instruction
+    /// against it if not; the root instruction is always compiled
+    fn spent(&self) -> bool {
+        let Params { region, flags, root, .. } = self.params;
+        let spent = self.pc != root
+            && (flags.region_insts.is_some_and(|n| region.insts() >= n)
+                || flags.region_bytes.is_some_and(|n| region.bytes() >= n));
+        if !spent {
+            region.insts.set(region.insts() + 1);
+        }
+        spent
+    }
 }
 /// Core JavaScript code generator with paging support
 ///
 /// This struct generates JavaScript code that includes:
 /// - Helper functions for 64-bit arithmetic
-/// - Memory access through the paging system via `$.get_page()`
+/// - Memory access through the paging system via `$.get_page()`, or
+///   `$.store_page()` for stores
 ///
 /// # Paging in JavaScript
 /// The generated `data` function performs address translation based on paging_mode:
 /// - Legacy: `data = (p => { p = $.get_page(p); return new DataView(...); })`
 /// - Shared/Both: Uses inline page table translation
 ///
+/// It takes the access width as a second argument, so accesses crossing a
+/// page can be split (see `write_access_function`).
+///
 /// See PAGING.md for detailed documentation on the paging system.
 pub struct CoreJS<'a> {
     pub content: &'a (dyn Display + 'a),
     pub flate: &'a (dyn Flate + 'a),
+    /// Shared with the content's [`Params`], to find its registers
+    pub regs: &'a RegCache,
+    /// Shared with the content's [`Params`], to count its bytes
+    pub region: &'a Region,
+    /// The content's [`Params::root`], which owns the links it makes
+    pub root: u64,
     pub flags: Flags,
 }
 impl<'a> Display for CoreJS<'a> {
@@ -199,16 +372,100 @@ impl<'a> Display for CoreJS<'a> {
         let signed = self.flate.flate("signed");
         let unsigned = self.flate.flate("unsigned");
         let data = self.flate.flate("data");
-        
+        let store = self.flate.flate("store");
+        let device = self.flate.flate("device");
+        let ctz = self.flate.flate("ctz");
+        let cpop = self.flate.flate("cpop");
+        let orc = self.flate.flate("orc");
+        let bswap = self.flate.flate("bswap");
+        let clmul = self.flate.flate("clmul");
+        // `max64`, `signed` and `unsigned` follow the guest XLEN, so RV32 register values wrap at 32 bits
+        let (mask, bits): (&dyn Display, u32) = match self.flags.xlen() {
+            Xlen::Rv32 => (&"0xffff_ffffn", 32),
+            Xlen::Rv64 => (&"$.f", 64),
+        };
+
+        let cache = self.flate.flate("cache");
+        let chain = self.flate.flate("chain");
+        let links = self.flate.flate("links");
+        let link = self.flate.flate("link");
+        let hold = self.flate.flate("hold");
+        let site = self.flate.flate("site");
+        // Blocks go straight on to the ones after them through links kept
+        // with the compiled block: one per direct successor in `links`, and
+        // caches of up to four targets for each indirect jump, by its pc in
+        // `cache` (see `rv_core_js`). `hold` files each under its target in
+        // `$._l`, with the block that owns it, for `drop_block` to cut when
+        // the target goes; `$._o` lists the targets each block holds, so its
+        // own go with it. A cache is owned by the block that made it, kept
+        // as its `o`, even when another block fills it: a callee returns
+        // through its caller's. Only blocks the reactor keeps are held, not
+        // the thunks a single step runs.
+        let root = self.root;
+        write!(
+            fmt,
+            "let {hold}=(t,p,b,o)=>($._p??=$.p)[p]===b&&((($._l??=Object.create(null))[p]??=new Map).set(t,o),\
+(($._o??=Object.create(null))[o]??=new Set).add(p)),\
+{links}=Object.create(null),{link}=p=>{{const b=J(p);if({hold}({links},p,b,{root}n)){links}[p]=b;return b}},\
+{cache}=new Map,{site}=p=>{cache}.get(p)??{cache}.set(p,Object.assign([],{{o:{root}n}})).get(p),\
+{chain}=(c,v)=>{{for(let i=0;i<c.length;i+=2)if(c[i]===v)return c[i+1];\
+const b=J(v);if(c.length<8&&{hold}(c,v,b,c.o))c.push(v,b);return b}};",
+        )?;
         write!(
             fmt,
-            "return async function(){{let {max64}=$.f,{max32}=0xffff_ffffn,{signed}=(a=>BigInt.asIntN(64,a)),{unsigned}=(a=>BigInt.asUintN(64,a)),",
+            "return async function(){{let {max64}={mask},{max32}=0xffff_ffffn,{signed}=(a=>BigInt.asIntN({bits},a)),{unsigned}=(a=>BigInt.asUintN({bits},a)),",
         )?;
         
+        // Device registers have no page; each access goes through the
+        // reactor's `load` and `store` at the width of the method called
+        write!(
+            fmt,
+            "{device}=p=>{{const l=(n,g)=>()=>{{const b=new DataView(new ArrayBuffer(8));b.setBigUint64(0,$.load(p,n),true);return b[g](0,true)}},\
+s=n=>(_,v)=>$.store(p,n,BigInt(v));\
+return{{getInt8:l(1n,`getInt8`),getUint8:l(1n,`getUint8`),getInt16:l(2n,`getInt16`),getUint16:l(2n,`getUint16`),\
+getInt32:l(4n,`getInt32`),getUint32:l(4n,`getUint32`),getBigUint64:l(8n,`getBigUint64`),\
+setUint8:s(1n),setUint16:s(2n),setUint32:s(4n),setBigUint64:s(8n)}}}},"
+        )?;
+
         // Generate the data function based on paging mode
-        self.write_data_function(fmt, &data)?;
+        self.write_access_function(fmt, &data, "get_page", false)?;
+        write!(fmt, ",")?;
+        self.write_access_function(fmt, &store, "store_page", true)?;
         
-        write!(fmt, ";{}}}", &self.content)
+        // Helpers for the bit-manipulation extensions, on 32-bit numbers except `clmul`
+        write!(
+            fmt,
+            ",{ctz}=x=>x?31-Math.clz32(x&-x):32,\
+{cpop}=x=>(x-=x>>>1&0x55555555,x=(x&0x33333333)+(x>>>2&0x33333333),Math.imul(x+(x>>>4)&0xf0f0f0f,0x1010101)>>>24),\
+{orc}=x=>((((x&0x7f7f7f7f)+0x7f7f7f7f|x)&0x80808080)>>>7)*255,\
+{bswap}=x=>((x&255)<<24|(x&0xff00)<<8|x>>>8&0xff00|x>>>24)>>>0,\
+{clmul}=(a,b)=>{{let r=0n;for(;b;b>>=1n,a<<=1n)if(b&1n)r^=a;return r}}"
+        )?;
+
+        // The content goes first, so the registers it uses are known
+        let mut content = Counted {
+            out: String::new(),
+            region: self.region,
+        };
+        core::fmt::Write::write_fmt(&mut content, format_args!("{}", self.content))?;
+        let content = content.out;
+        let sync = self.flate.flate("sync");
+
+        // `i` counts instructions started, for the `cycle`/`time`/`instret` CSRs.
+        // Page faults throw out of the reactor with the trap pending; `p` holds
+        // the pc of the instruction that raised it.
+        write!(fmt, ";($._r??=$.r).i??=0;let p")?;
+        for r in (1..32).filter(|r| self.regs.used() & 1 << r != 0) {
+            write!(fmt, ",x{r}=$._r.x{r}??0n")?;
+        }
+        write!(fmt, ";const {sync}=()=>{{")?;
+        for r in (1..32).filter(|r| self.regs.dirty() & 1 << r != 0) {
+            write!(fmt, "$._r.x{r}=x{r};")?;
+        }
+        write!(
+            fmt,
+            "}};try{{{content}}}catch(e){{{sync}();if(!$.trapped)throw e;return $.raise(p)}}}}"
+        )
     }
 }
 
@@ -291,14 +548,58 @@ impl<'a> CoreJS<'a> {
         Ok(())
     }
 
-    /// Write the data function based on paging configuration
-    fn write_data_function(&self, f: &mut Formatter<'_>, data_var: &dyn Display) -> core::fmt::Result {
+    /// Write `data_var`, giving a `DataView` for an `n`-byte access at `p`
+    ///
+    /// `n` defaults to one byte. Guest pages need not be adjacent in wasm
+    /// memory, so an access crossing a 4 KiB boundary goes a byte at a time:
+    /// a load through a scratch view, a store through a stand-in whose setters
+    /// check that the last byte is writable before writing any. With
+    /// `trap_misaligned`, a misaligned access raises instead. Where the page
+    /// function finds a device, the `device` stand-in takes the access.
+    fn write_access_function(
+        &self,
+        f: &mut Formatter<'_>,
+        data_var: &dyn Display,
+        page: &str,
+        store: bool,
+    ) -> core::fmt::Result {
+        let max64 = self.flate.flate("max64");
+        let device = self.flate.flate("device");
+        write!(f, "{data_var}=(pg=>(p,n=1n)=>{{")?;
+        if self.flags.trap_misaligned {
+            write!(f, "if(p&n-1n)$.misaligned(p,{store});")?;
+        } else if store {
+            write!(
+                f,
+                "if((p&0xfffn)>4096n-n){{pg((p+n-1n)&{max64});const b=new DataView(new ArrayBuffer(8)),\
+w=(_,v)=>{{b.setBigUint64(0,BigInt(v),true);for(let i=0n;i<n;i++)pg((p+i)&{max64}).setUint8(0,b.getUint8(Number(i)))}};\
+return{{setUint16:w,setUint32:w,setBigUint64:w}}}}"
+            )?;
+        } else {
+            write!(
+                f,
+                "if((p&0xfffn)>4096n-n){{const b=new DataView(new ArrayBuffer(8));\
+for(let i=0n;i<n;i++)b.setUint8(Number(i),pg((p+i)&{max64}).getUint8(0));return b}}"
+            )?;
+        }
+        write!(f, "return pg(p)||{device}(p)}})(")?;
+        self.write_page_function(f, page)?;
+        write!(f, ")")
+    }
+
+    /// Write the function giving a page's `DataView`, based on paging configuration
+    ///
+    /// `page` names the reactor method giving the final page pointer:
+    /// `get_page` for loads and `store_page` for stores, which the guest's
+    /// page tables may permit differently. A null pointer means a device,
+    /// for which the function gives `0`.
+    fn write_page_function(&self, f: &mut Formatter<'_>, page: &str) -> core::fmt::Result {
         match self.flags.paging_mode {
             Some(PagingMode::Shared) | Some(PagingMode::Both) => {
                 let pt_vaddr = self.flags.shared_page_table_vaddr.unwrap_or(0);
                 let sd_vaddr = self.flags.shared_security_directory_vaddr.unwrap_or(0);
 
-                writeln!(f, "{data_var}=(v=>{{")?;
+                writeln!(f, "(v=>{{")?;
                 self.write_read_helpers(f)?;
                 
                 if self.flags.use_multilevel_paging {
@@ -308,12 +609,13 @@ impl<'a> CoreJS<'a> {
                 }
                 
                 writeln!(f, "    let p=phys_page_base+(v&0xFFFFn);")?;
-                writeln!(f, "    return new DataView($._sys(`memory`).buffer,$.get_page(p));")?;
+                writeln!(f, "    p=$.{page}(p);")?;
+                writeln!(f, "    return p&&new DataView($._sys(`memory`).buffer,p);")?;
                 write!(f, "}})")
             }
             _ => {
                 // Legacy mode (default)
-                write!(f, "{data_var}=(p=>{{p=$.get_page(p);return new DataView($._sys(`memory`).buffer,p);}})")
+                write!(f, "(p=>{{p=$.{page}(p);return p&&new DataView($._sys(`memory`).buffer,p);}})")
             }
         }
     }
@@ -1,24 +1,46 @@
-use core::iter::empty;
-
 use crate::arch::{RiscvWasmJit, TemplateRiscv};
+use crate::ext::ExtInst;
 use crate::hint;
+use crate::rvc;
+use crate::trap::Trap;
 
 use super::*;
 
+mod amo;
+mod bit;
+mod crypto;
+mod ext;
+mod wasm;
+
+use amo::AmoKind;
+
 impl<'b> RiscvWasmJit for TemplateJit<'b> {
     fn Riscv<'a>(
         &'a self,
         ctx: &'a (dyn WasmJitCtx + 'a),
     ) -> Box<dyn Iterator<Item = JitOpcode<'a>> + 'a> {
-        self.jit_wasm(|labels, nd| {
-            let mut i = self.params.react.bytes(self.pc);
-            let inst_code = u32::from_le_bytes(array::from_fn(|_| i.next().unwrap()));
-            let i = Inst::decode(inst_code, Xlen::Rv64);
-            return Box::new(empty());
-        })
+        let mut out = crate::template::wasm::Ops::new(ctx);
+        self.rv_wasm(&mut out);
+        Box::new(out.finish(self.pc).into_iter())
     }
 }
+/// Whether `r` holds a return address by convention, `ra` or `t0`
+fn link(r: Reg) -> bool {
+    r == Reg::RA || r == Reg::T0
+}
 impl<'a> TemplateJit<'a> {
+    /// For a call, which links through `dest`, push the return address
+    /// `next` and a cache for it onto `$._s`, the return-address stack
+    ///
+    /// The stack keeps the newest 64 calls; returns check that the
+    /// address they popped is the one they jump to.
+    fn call(&self, dest: Reg, next: u64) -> String {
+        if !link(dest) {
+            return String::new();
+        }
+        let site = self.params.flate.flate("site");
+        format!("($._s??=[]).push([{next}n,{site}({next}n)])>64&&$._s.shift();")
+    }
     fn rv_core_js<Target: TemplateJS>(
         &self,
         a: Inst,
@@ -33,6 +55,15 @@ impl<'a> TemplateJit<'a> {
         let signed = self.params.flate.flate("signed");
         let unsigned = self.params.flate.flate("unsigned");
         let data = self.params.flate.flate("data");
+        let store = self.params.flate.flate("store");
+        let xlen = self.params.flags.xlen();
+        let mask = xlen_mask(xlen);
+        let mut i = self.params.react.bytes(self.pc);
+        let inst_code = u32::from_le_bytes(array::from_fn(|_| i.next().unwrap()));
+        let (xbits, shamt) = match xlen {
+            Xlen::Rv32 => ("32n", "31n"),
+            Xlen::Rv64 => ("64n", "63n"),
+        };
         macro_rules! j {
             ($jp:literal, $src1:ident, $src2:ident, $offset:ident) => {{
                 write!(
@@ -41,13 +72,13 @@ impl<'a> TemplateJit<'a> {
                     &format_args!(
                         $jp,
                         TemplateReg {
-                            flate: self.params.flate,
+                            regs: self.params.regs,
                             n: [(); 32],
                             reg: &$src1,
                             value: None
                         },
                         TemplateReg {
-                            flate: self.params.flate,
+                            regs: self.params.regs,
                             n: [(); 32],
                             reg: &$src2,
                             value: None
@@ -56,7 +87,7 @@ impl<'a> TemplateJit<'a> {
                     target.template_jit_js(&TemplateJit {
                         params: self.params,
                         labels: &labels,
-                        pc: self.pc.wrapping_add_signed(($offset).as_i64() * 2),
+                        pc: self.pc.wrapping_add_signed(($offset).as_i64()) & mask,
                         depth: nd,
                         // root:self.root,
                     }),
@@ -79,15 +110,15 @@ impl<'a> TemplateJit<'a> {
                             Inst::$arith { dest, src1, src2 } => break 'a write!(
                                 f,
                                 "{}",
-                                TemplateReg{flate: self.params.flate, n: [(); 32],
+                                TemplateReg{regs: self.params.regs, n: [(); 32],
                                     reg: &dest,
                                     value: Some(&format_args!(
                                         $ap,
-                                        TemplateReg{flate: self.params.flate, n: [(); 32],
+                                        TemplateReg{regs: self.params.regs, n: [(); 32],
                                             reg: &src1,
                                             value: None
                                         },
-                                        TemplateReg{flate: self.params.flate, n: [(); 32],
+                                        TemplateReg{regs: self.params.regs, n: [(); 32],
                                             reg: &src2,
                                             value: None
                                         }
@@ -97,11 +128,11 @@ impl<'a> TemplateJit<'a> {
                             $(Inst::[<$arith i>] { imm, dest, src1 } => break 'a match $ip{_=>write!(
                                 f,
                                 "{}",
-                                TemplateReg{flate: self.params.flate, n: [(); 32],
+                                TemplateReg{regs: self.params.regs, n: [(); 32],
                                     reg: &dest,
                                     value: Some(&format_args!(
                                         $ap,
-                                        TemplateReg{flate: self.params.flate, n: [(); 32],
+                                        TemplateReg{regs: self.params.regs, n: [(); 32],
                                             reg: &src1,
                                             value: None
                                         },
@@ -112,15 +143,15 @@ impl<'a> TemplateJit<'a> {
                             $(Inst::[<$arith W>] { dest, src1, src2 } => break 'a write!(
                                 f,
                                 "{}",
-                                TemplateReg{flate: self.params.flate, n: [(); 32],
+                                TemplateReg{regs: self.params.regs, n: [(); 32],
                                     reg: &dest,
                                     value: Some(&format_args!(
                                         $bp,
-                                        TemplateReg{flate: self.params.flate, n: [(); 32],
+                                        TemplateReg{regs: self.params.regs, n: [(); 32],
                                             reg: &src1,
                                             value: None
                                         },
-                                        TemplateReg{flate: self.params.flate, n: [(); 32],
+                                        TemplateReg{regs: self.params.regs, n: [(); 32],
                                             reg: &src2,
                                             value: None
                                         }
@@ -131,11 +162,11 @@ impl<'a> TemplateJit<'a> {
                             $(Inst::[<$arith iW>] { imm, dest, src1 } => break 'a write!(
                                 f,
                                 "{}",
-                                TemplateReg{flate: self.params.flate, n: [(); 32],
+                                TemplateReg{regs: self.params.regs, n: [(); 32],
                                     reg: &dest,
                                     value: Some(&format_args!(
                                         $iwp,
-                                        TemplateReg{flate: self.params.flate, n: [(); 32],
+                                        TemplateReg{regs: self.params.regs, n: [(); 32],
                                             reg: &src1,
                                             value: None
                                         },
@@ -161,10 +192,10 @@ impl<'a> TemplateJit<'a> {
                                 f,
                                 "{}",
                                 TemplateReg {
-                                    flate: self.params.flate,
+                                    regs: self.params.regs,
                                     n: [(); 32],
                                     reg: &dest,
-                                    value: Some(&format_args!("{}n", uimm.as_u64()))
+                                    value: Some(&format_args!("{}n", uimm.as_u64() & mask))
                                 }
                             )
                         }
@@ -173,12 +204,33 @@ impl<'a> TemplateJit<'a> {
                                 f,
                                 "{}",
                                 TemplateReg {
-                                    flate: self.params.flate,
+                                    regs: self.params.regs,
                                     n: [(); 32],
                                     reg: &dest,
                                     value: Some(&format_args!(
                                         "{}n",
-                                        uimm.as_u64().wrapping_add(self.pc)
+                                        uimm.as_u64().wrapping_add(self.pc) & mask
+                                    ))
+                                }
+                            )
+                        }
+                        Inst::Sltiu { imm, dest, src1 } => {
+                            write!(
+                                f,
+                                "{}",
+                                TemplateReg {
+                                    regs: self.params.regs,
+                                    n: [(); 32],
+                                    reg: &dest,
+                                    value: Some(&format_args!(
+                                        "(({})<{}n)?1n:0n",
+                                        TemplateReg {
+                                            regs: self.params.regs,
+                                            n: [(); 32],
+                                            reg: &src1,
+                                            value: None
+                                        },
+                                        imm.as_u64() & mask
                                     ))
                                 }
                             )
@@ -186,42 +238,58 @@ impl<'a> TemplateJit<'a> {
                         Inst::Jal { offset, dest } => {
                             write!(
                                 f,
-                                "{};{};break;}}",
+                                "{};{}{}",
                                 TemplateReg {
-                                    flate: self.params.flate,
+                                    regs: self.params.regs,
                                     n: [(); 32],
                                     reg: &dest,
                                     value: Some(&format_args!("{}n", next))
                                 },
+                                self.call(dest, next),
                                 target.template_jit_js(&TemplateJit {
                                     params: self.params,
                                     labels: &labels,
-                                    pc: self.pc.wrapping_add_signed(offset.as_i64() * 2),
+                                    pc: self.pc.wrapping_add_signed(offset.as_i64()) & mask,
                                     depth: nd,
                                 })
                             )?;
                             return Ok(());
                         }
                         Inst::Jalr { offset, base, dest } => {
+                            // A return takes the cache its call pushed, if it predicted
+                            // this target; other jumps keep a cache of their own
+                            let site = self.params.flate.flate("site");
+                            let chain = self.params.flate.flate("chain");
+                            let (pop, site) = match link(base) && !link(dest) {
+                                true => (
+                                    "const e=$._s?.pop();",
+                                    format!("e?.[0]===v?e[1]:{site}({}n)", self.pc),
+                                ),
+                                false => ("", format!("{site}({}n)", self.pc)),
+                            };
+                            // The target is read before `dest` is written, as `dest` may be `base`
                             write!(
                                 f,
-                                "{};return ()=>J({});}}",
-                                TemplateReg {
-                                    flate: self.params.flate,
-                                    n: [(); 32],
-                                    reg: &dest,
-                                    value: Some(&format_args!("{}n", next))
-                                },
+                                "{{const v={};{pop}{};{}{}return {chain}({site},v)}}",
                                 &format_args!(
-                                    "({}+{})&{max64}",
-                                    (offset.as_i64() * 2) as u64,
+                                    "({}n+{})&{}n",
+                                    offset.as_i64() as u64,
                                     TemplateReg {
-                                        flate: self.params.flate,
+                                        regs: self.params.regs,
                                         n: [(); 32],
                                         reg: &base,
                                         value: None
-                                    }
-                                )
+                                    },
+                                    mask & !1
+                                ),
+                                TemplateReg {
+                                    regs: self.params.regs,
+                                    n: [(); 32],
+                                    reg: &dest,
+                                    value: Some(&format_args!("{}n", next))
+                                },
+                                self.call(dest, next),
+                                self.sync()
                             )?;
                             return Ok(());
                         }
@@ -229,14 +297,14 @@ impl<'a> TemplateJit<'a> {
                             f,
                             "{}",
                             TemplateReg {
-                                flate: self.params.flate,
+                                regs: self.params.regs,
                                 n: [(); 32],
                                 reg: &dest,
                                 value: Some(&format_args!(
-                                    "{unsigned}(BigInt({data}(({}n+{})&f).getInt8(0,true)))",
+                                    "{unsigned}(BigInt({data}(({}n+{})&{max64}).getInt8(0,true)))",
                                     offset.as_i64() as u64,
                                     TemplateReg {
-                                        flate: self.params.flate,
+                                        regs: self.params.regs,
                                         n: [(); 32],
                                         reg: &base,
                                         value: None
@@ -248,14 +316,14 @@ impl<'a> TemplateJit<'a> {
                             f,
                             "{}",
                             TemplateReg {
-                                flate: self.params.flate,
+                                regs: self.params.regs,
                                 n: [(); 32],
                                 reg: &dest,
                                 value: Some(&format_args!(
-                                    "BigInt({data}(({}n+{})&f).getUint8(0,true))",
+                                    "BigInt({data}(({}n+{})&{max64}).getUint8(0,true))",
                                     offset.as_i64() as u64,
                                     TemplateReg {
-                                        flate: self.params.flate,
+                                        regs: self.params.regs,
                                         n: [(); 32],
                                         reg: &base,
                                         value: None
@@ -267,14 +335,14 @@ impl<'a> TemplateJit<'a> {
                             f,
                             "{}",
                             TemplateReg {
-                                flate: self.params.flate,
+                                regs: self.params.regs,
                                 n: [(); 32],
                                 reg: &dest,
                                 value: Some(&format_args!(
-                                    "{unsigned}(BigInt({data}(({}n+{})&f).getInt16(0,true)))",
+                                    "{unsigned}(BigInt({data}(({}n+{})&{max64},2n).getInt16(0,true)))",
                                     offset.as_i64() as u64,
                                     TemplateReg {
-                                        flate: self.params.flate,
+                                        regs: self.params.regs,
                                         n: [(); 32],
                                         reg: &base,
                                         value: None
@@ -286,14 +354,14 @@ impl<'a> TemplateJit<'a> {
                             f,
                             "{}",
                             TemplateReg {
-                                flate: self.params.flate,
+                                regs: self.params.regs,
                                 n: [(); 32],
                                 reg: &dest,
                                 value: Some(&format_args!(
-                                    "BigInt({data}(({}n+{})&f).getUint16(0,true))",
+                                    "BigInt({data}(({}n+{})&{max64},2n).getUint16(0,true))",
                                     offset.as_i64() as u64,
                                     TemplateReg {
-                                        flate: self.params.flate,
+                                        regs: self.params.regs,
                                         n: [(); 32],
                                         reg: &base,
                                         value: None
@@ -305,14 +373,14 @@ impl<'a> TemplateJit<'a> {
                             f,
                             "{}",
                             TemplateReg {
-                                flate: self.params.flate,
+                                regs: self.params.regs,
                                 n: [(); 32],
                                 reg: &dest,
                                 value: Some(&format_args!(
-                                    "{unsigned}(BigInt({data}(({}n+{})&f).getInt32(0,true)))",
+                                    "{unsigned}(BigInt({data}(({}n+{})&{max64},4n).getInt32(0,true)))",
                                     offset.as_i64() as u64,
                                     TemplateReg {
-                                        flate: self.params.flate,
+                                        regs: self.params.regs,
                                         n: [(); 32],
                                         reg: &base,
                                         value: None
@@ -324,14 +392,14 @@ impl<'a> TemplateJit<'a> {
                             f,
                             "{}",
                             TemplateReg {
-                                flate: self.params.flate,
+                                regs: self.params.regs,
                                 n: [(); 32],
                                 reg: &dest,
                                 value: Some(&format_args!(
-                                    "BigInt({data}(({}n+{})&f).getUint32(0,true))",
+                                    "BigInt({data}(({}n+{})&{max64},4n).getUint32(0,true))",
                                     offset.as_i64() as u64,
                                     TemplateReg {
-                                        flate: self.params.flate,
+                                        regs: self.params.regs,
                                         n: [(); 32],
                                         reg: &base,
                                         value: None
@@ -343,14 +411,14 @@ impl<'a> TemplateJit<'a> {
                             f,
                             "{}",
                             TemplateReg {
-                                flate: self.params.flate,
+                                regs: self.params.regs,
                                 n: [(); 32],
                                 reg: &dest,
                                 value: Some(&format_args!(
-                                    "{data}(({}n+{})&f).getBigUint64(0,true)",
+                                    "{data}(({}n+{})&{max64},8n).getBigUint64(0,true)",
                                     offset.as_i64() as u64,
                                     TemplateReg {
-                                        flate: self.params.flate,
+                                        regs: self.params.regs,
                                         n: [(); 32],
                                         reg: &base,
                                         value: None
@@ -360,16 +428,16 @@ impl<'a> TemplateJit<'a> {
                         ),
                         Inst::Sb { offset, src, base } => write!(
                             f,
-                            "{data}({}n+{}).setUint8(0,Number({}&{max32}),true)",
+                            "{store}(({}n+{})&{max64}).setUint8(0,Number({}&{max32}),true)",
                             offset.as_i64() as u64,
                             TemplateReg {
-                                flate: self.params.flate,
+                                regs: self.params.regs,
                                 n: [(); 32],
                                 reg: &base,
                                 value: None
                             },
                             TemplateReg {
-                                flate: self.params.flate,
+                                regs: self.params.regs,
                                 n: [(); 32],
                                 reg: &src,
                                 value: None
@@ -377,16 +445,16 @@ impl<'a> TemplateJit<'a> {
                         ),
                         Inst::Sh { offset, src, base } => write!(
                             f,
-                            "{data}({}n+{}).setUint16(0,Number({}&{max32}),true)",
+                            "{store}(({}n+{})&{max64},2n).setUint16(0,Number({}&{max32}),true)",
                             offset.as_i64() as u64,
                             TemplateReg {
-                                flate: self.params.flate,
+                                regs: self.params.regs,
                                 n: [(); 32],
                                 reg: &base,
                                 value: None
                             },
                             TemplateReg {
-                                flate: self.params.flate,
+                                regs: self.params.regs,
                                 n: [(); 32],
                                 reg: &src,
                                 value: None
@@ -394,16 +462,16 @@ impl<'a> TemplateJit<'a> {
                         ),
                         Inst::Sw { offset, src, base } => write!(
                             f,
-                            "{data}({}n+{}).setUint32(0,Number({}&{max32}),true)",
+                            "{store}(({}n+{})&{max64},4n).setUint32(0,Number({}&{max32}),true)",
                             offset.as_i64() as u64,
                             TemplateReg {
-                                flate: self.params.flate,
+                                regs: self.params.regs,
                                 n: [(); 32],
                                 reg: &base,
                                 value: None
                             },
                             TemplateReg {
-                                flate: self.params.flate,
+                                regs: self.params.regs,
                                 n: [(); 32],
                                 reg: &src,
                                 value: None
@@ -411,45 +479,73 @@ impl<'a> TemplateJit<'a> {
                         ),
                         Inst::Sd { offset, src, base } => write!(
                             f,
-                            "{data}({}n+{}).setBigUint64(0,{},true)",
+                            "{store}(({}n+{})&{max64},8n).setBigUint64(0,{},true)",
                             offset.as_i64() as u64,
                             TemplateReg {
-                                flate: self.params.flate,
+                                regs: self.params.regs,
                                 n: [(); 32],
                                 reg: &base,
                                 value: None
                             },
                             TemplateReg {
-                                flate: self.params.flate,
+                                regs: self.params.regs,
                                 n: [(); 32],
                                 reg: &src,
                                 value: None
                             }
                         ),
+                        Inst::LrW { dest, addr, .. } => {
+                            self.rv_amo_js(f, AmoKind::Lr, false, dest, addr, Reg::ZERO)
+                        }
+                        Inst::ScW {
+                            dest, addr, src, ..
+                        } => self.rv_amo_js(f, AmoKind::Sc, false, dest, addr, src),
+                        Inst::AmoW {
+                            op,
+                            dest,
+                            addr,
+                            src,
+                            ..
+                        } => self.rv_amo_js(f, AmoKind::Amo(op), false, dest, addr, src),
                         Inst::Fence { .. } => Ok(()),
-                        Inst::Ecall => write!(f, "await $.ecall();"),
-                        op => write!(f, "throw new TypeError(`op:{op}`)"),
+                        // Traps end the block; the reactor says where to continue
+                        Inst::Ecall => {
+                            return write!(f, "{}return $.ecall({}n);", self.sync(), self.pc)
+                        }
+                        Inst::Ebreak => {
+                            let sync = self.sync();
+                            return write!(f, "{sync}return $.ebreak({}n,{next}n);", self.pc);
+                        }
+                        _ => {
+                            return write!(
+                                f,
+                                "{}return $.trap(2n,{}n,{}n);",
+                                self.sync(),
+                                Trap::illegal(inst_code).tval,
+                                self.pc
+                            )
+                        }
                     },
                 }
             };
         }
         ops!(a => [
-            Add => "({}+{})&{max64}" i "" w "(({}&{max32})+({}&{max32}))&{max32}" iw  "(({}&{max32})+({}&{max32}))&{max32}",
-            Mul => "({}*{})&{max64}" w "(({}&{max32})*({}&{max32}))&{max32}",
-            Mulhu => "(({}*{})>>64n)&{max64}",
-            Mulhsu => "{unsigned}(({signed}({})*{})>>64n)&{max64}",
-            Mulh => "{unsigned}(({signed}({})*{signed}({}))>>64n)&{max64}",
-            Sub => "({}-{})&{max64}" w "(({}&{max32})-({}&{max32}))&{max32}",
-            Divu => "{1}==0?{unsigned}(-1n):({}/{})&{max64}" w "{1}==0?{unsigned}(-1n):(({}&{max32})/({}&{max32}))&{max32}",
-            Remu => "{1}==0?{unsigned}(-1n):({}%{})&{max64}" w "{1}==0?{unsigned}(-1n):(({}&{max32})%({}&{max32}))&{max32}",
-            Div => "{1}==0?{unsigned}(-1n):{unsigned}({signed}({})/{signed}({}))&{max64}" w "{1}==0?{unsigned}(-1n):{unsigned}({signed}({}&{max32})/{signed}({}&{max32}))&{max32}",
-            Rem => "{1}==0?{unsigned}(-1n):{unsigned}({signed}({})%{signed}({}))&{max64}" w "{1}==0?{unsigned}(-1n):{unsigned}({signed}({}&{max32})%{signed}({}&{max32}))&{max32}",
+            Add => "({}+{})&{max64}" i "" w "{unsigned}(BigInt.asIntN(32,{}+{}))&{max64}" iw "{unsigned}(BigInt.asIntN(32,{}+{}))&{max64}",
+            Mul => "({}*{})&{max64}" w "{unsigned}(BigInt.asIntN(32,({}&{max32})*({}&{max32})))&{max64}",
+            Mulhu => "(({}*{})>>{xbits})&{max64}",
+            Mulhsu => "{unsigned}(({signed}({})*{})>>{xbits})&{max64}",
+            Mulh => "{unsigned}(({signed}({})*{signed}({}))>>{xbits})&{max64}",
+            Sub => "({}-{})&{max64}" w "{unsigned}(BigInt.asIntN(32,{}-{}))&{max64}",
+            Divu => "{1}==0?{unsigned}(-1n):({}/{})&{max64}" w "({1}&{max32})==0?{unsigned}(-1n):{unsigned}(BigInt.asIntN(32,({}&{max32})/({}&{max32})))&{max64}",
+            Remu => "{1}==0?{0}:({}%{})&{max64}" w "({1}&{max32})==0?{unsigned}(BigInt.asIntN(32,{0}))&{max64}:{unsigned}(BigInt.asIntN(32,({}&{max32})%({}&{max32})))&{max64}",
+            Div => "{1}==0?{unsigned}(-1n):{unsigned}({signed}({})/{signed}({}))&{max64}" w "({1}&{max32})==0?{unsigned}(-1n):{unsigned}(BigInt.asIntN(32,BigInt.asIntN(32,{})/BigInt.asIntN(32,{})))&{max64}",
+            Rem => "{1}==0?{0}:{unsigned}({signed}({})%{signed}({}))&{max64}" w "({1}&{max32})==0?{unsigned}(BigInt.asIntN(32,{0}))&{max64}:{unsigned}(BigInt.asIntN(32,BigInt.asIntN(32,{})%BigInt.asIntN(32,{})))&{max64}",
             And => "({}&{})&{max64}" i "",
             Or => "({}|{})&{max64}" i "",
             Xor => "({}^{})&{max64}" i "",
-            Sll => "({}<<{})&{max64}" i "" w "(({}&{max32})<<({}&{max32}))&{max32}" iw  "(({}&{max32})<<({}&{max32}))&{max32}",
-            Srl => "({}>>{})&{max64}" i "" w "(({}&{max32})>>({}&{max32}))&{max32}" iw  "(({}&{max32})>>({}&{max32}))&{max32}",
-            Sra => "{unsigned}({signed}({})>>{signed}({}))&{max64}" i "" w "{unsigned}({signed}({}&{max32})>>{signed}({}&{max32}))&{max32}" iw  "{unsigned}({signed}({}&{max32})>>{signed}({}&{max32}))&{max32}",
+            Sll => "({}<<({}&{shamt}))&{max64}" i "" w "{unsigned}(BigInt.asIntN(32,({}&{max32})<<({}&31n)))&{max64}" iw "{unsigned}(BigInt.asIntN(32,({}&{max32})<<({}&31n)))&{max64}",
+            Srl => "({}>>({}&{shamt}))&{max64}" i "" w "{unsigned}(BigInt.asIntN(32,({}&{max32})>>({}&31n)))&{max64}" iw "{unsigned}(BigInt.asIntN(32,({}&{max32})>>({}&31n)))&{max64}",
+            Sra => "{unsigned}({signed}({})>>({}&{shamt}))&{max64}" i "" w "{unsigned}(BigInt.asIntN(32,BigInt.asIntN(32,{})>>({}&31n)))&{max64}" iw "{unsigned}(BigInt.asIntN(32,BigInt.asIntN(32,{})>>({}&31n)))&{max64}",
             Sltu => "(({})<({}))?1n:0n",
             Slt => "({signed}({})<{signed}({}))?1n:0n" i ""
             ]
@@ -477,17 +573,44 @@ impl<'a> RiscvDisplay for TemplateJit<'a> {
     fn Riscv(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         // if tget(self.react.clone(), self.pc) != JsValue::UNDEFINED {
         return self.jit_js(f, |f,_label_name,labels,nd|{
-               let d =  self.params.flate.flate("data");
             let mut i = self.params.react.bytes(self.pc);
             let inst_code = u32::from_le_bytes(array::from_fn(|_| i.next().unwrap()));
-            let i = Inst::decode(inst_code, Xlen::Rv64);
+            let i = rvc::decode(inst_code, self.params.flags.xlen());
+            // `p` is the prologue's, so a fault thrown by the reactor is raised here;
+            // `fetch` gives only the low half of a compressed instruction
+            let fetched = match inst_code & 3 {
+                3 => inst_code,
+                _ => inst_code & 0xffff,
+            };
+            // `t` is the reactor's interrupt deadline, in retired instructions
             write!(
                 f,
-                "const p={}n;if({d}(p).getUint32(0,true)!={inst_code}){{delete $.p[`{}`];return J(p);}};",
-                self.pc, self.params.root
+                "if($._r.i>=$._r.t){{{2}return $.interrupt({0}n)}}\
+p={0}n;if($.fetch(p)!={fetched}){{$.drop_block({1}n);{2}return J(p);}};$._r.i++;",
+                self.pc,
+                self.params.root,
+                self.sync()
             )?;
+            let next = |b| {
+                (match b {
+                    rv_asm::IsCompressed::Yes => 2,
+                    rv_asm::IsCompressed::No => 4,
+                } + self.pc)
+                    & xlen_mask(self.params.flags.xlen())
+            };
             match i {
-                Err(e) => write!(f, "throw new TypeError(`decoding: {e}`);"),
+                Err(_) => match ExtInst::decode(inst_code, self.params.flags.xlen()) {
+                    Some((a, b)) => {
+                        self.rv_ext_js(a, next(b), labels, nd, f, TemplateRiscv)
+                    }
+                    None => write!(
+                        f,
+                        "{}return $.trap(2n,{}n,{}n);",
+                        self.sync(),
+                        Trap::illegal(inst_code).tval,
+                        self.pc
+                    ),
+                },
                 Ok((a, b)) => {
                     // Emit HINT logging if test_mode is enabled
                     if self.params.flags.test_mode {
@@ -499,11 +622,7 @@ impl<'a> RiscvDisplay for TemplateJit<'a> {
                             )?;
                         }
                     }
-                    let next = match b {
-                        rv_asm::IsCompressed::Yes => 2,
-                        rv_asm::IsCompressed::No => 4,
-                    } + self.pc;
-                    self.rv_core_js(a, next, labels, nd, f,TemplateRiscv)?;
+                    self.rv_core_js(a, next(b), labels, nd, f,TemplateRiscv)?;
                     Ok(())
                 }
             }
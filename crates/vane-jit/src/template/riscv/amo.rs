@@ -0,0 +1,103 @@
+use alloc::string::String;
+use rv_asm::AmoOp;
+
+use super::*;
+
+/// The access performed by an A-extension instruction
+#[derive(Clone, Copy)]
+pub(super) enum AmoKind {
+    Lr,
+    Sc,
+    Amo(AmoOp),
+}
+
+impl<'a> TemplateJit<'a> {
+    /// Render `lr`, `sc` or an AMO on a word (`double == false`) or doubleword
+    ///
+    /// The reservation lives in `Mem`, reached through `$.lr` and `$.sc`; the
+    /// `data` and `store` accessors go through the reactor, which breaks it. Block locals
+    /// avoid the names `ReleaseFlate` gives the prologue helpers.
+    pub(super) fn rv_amo_js(
+        &self,
+        f: &mut Formatter,
+        kind: AmoKind,
+        double: bool,
+        dest: Reg,
+        addr: Reg,
+        src: Reg,
+    ) -> core::fmt::Result {
+        let max32 = self.params.flate.flate("max32");
+        let unsigned = self.params.flate.flate("unsigned");
+        let data = self.params.flate.flate("data");
+        let store_page = self.params.flate.flate("store");
+        let reg = |reg: &Reg, value: Option<&dyn Display>| {
+            format!(
+                "{}",
+                TemplateReg {
+                    regs: self.params.regs,
+                    n: [(); 32],
+                    reg,
+                    value
+                }
+            )
+        };
+        let bits = if double { 64 } else { 32 };
+        // Misaligned reservations and AMOs trap rather than split
+        let align = |cause: u8| {
+            format!(
+                "if(a&{}n){{{}return $.trap({cause}n,a,{}n)}}",
+                bits / 8 - 1,
+                self.sync(),
+                self.pc
+            )
+        };
+        let load: &dyn Display = match double {
+            false => &format_args!("{unsigned}(BigInt(m.getInt32(0,true)))"),
+            true => &"m.getBigUint64(0,true)",
+        };
+        let store = |v: &str| match double {
+            false => format!("m.setUint32(0,Number(({v})&{max32}),true)"),
+            true => format!("m.setBigUint64(0,BigInt.asUintN(64,{v}),true)"),
+        };
+        match kind {
+            AmoKind::Lr => write!(
+                f,
+                "{{const a={};{}const m={data}(a);{};$.lr(a)}}",
+                reg(&addr, None),
+                align(4),
+                reg(&dest, Some(load))
+            ),
+            AmoKind::Sc => write!(
+                f,
+                "{{const a={};{}if($.sc(a)){{const m={store_page}(a);{};{}}}else{{{}}}}}",
+                reg(&addr, None),
+                align(6),
+                store(&reg(&src, None)),
+                reg(&dest, Some(&"0n")),
+                reg(&dest, Some(&"1n"))
+            ),
+            AmoKind::Amo(op) => {
+                let new: String = match op {
+                    AmoOp::Swap => "b".into(),
+                    AmoOp::Add => "v+b".into(),
+                    AmoOp::Xor => "v^b".into(),
+                    AmoOp::And => "v&b".into(),
+                    AmoOp::Or => "v|b".into(),
+                    AmoOp::Min => format!("BigInt.asIntN({bits},v)<BigInt.asIntN({bits},b)?v:b"),
+                    AmoOp::Max => format!("BigInt.asIntN({bits},v)>BigInt.asIntN({bits},b)?v:b"),
+                    AmoOp::Minu => format!("BigInt.asUintN({bits},v)<BigInt.asUintN({bits},b)?v:b"),
+                    AmoOp::Maxu => format!("BigInt.asUintN({bits},v)>BigInt.asUintN({bits},b)?v:b"),
+                };
+                write!(
+                    f,
+                    "{{const a={};{}const b={},m={store_page}(a),v={load};{};{}}}",
+                    reg(&addr, None),
+                    align(6),
+                    reg(&src, None),
+                    store(&new),
+                    reg(&dest, Some(&"v"))
+                )
+            }
+        }
+    }
+}
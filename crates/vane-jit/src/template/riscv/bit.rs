@@ -0,0 +1,96 @@
+use crate::bit::BitOp;
+
+use super::*;
+
+impl<'a> TemplateJit<'a> {
+    /// Render a Zba/Zbb/Zbs/Zbc operation writing `dest`
+    ///
+    /// `b` is the second register or, for immediate forms, a BigInt literal.
+    /// Counting, `orc.b` and `rev8` split the value into 32-bit numbers for the
+    /// `ctz`/`cpop`/`orc`/`bswap` helpers from [`CoreJS`], as BigInt has no
+    /// bit-counting operations of its own.
+    pub(super) fn rv_bit_js(
+        &self,
+        f: &mut Formatter,
+        op: BitOp,
+        dest: Reg,
+        a: &dyn Display,
+        b: &dyn Display,
+    ) -> core::fmt::Result {
+        let max64 = self.params.flate.flate("max64");
+        let max32 = self.params.flate.flate("max32");
+        let signed = self.params.flate.flate("signed");
+        let unsigned = self.params.flate.flate("unsigned");
+        let ctz = self.params.flate.flate("ctz");
+        let cpop = self.params.flate.flate("cpop");
+        let orc = self.params.flate.flate("orc");
+        let bswap = self.params.flate.flate("bswap");
+        let clmul = self.params.flate.flate("clmul");
+        let rv32 = self.params.flags.xlen().is_32();
+        let (xbits, shamt) = match rv32 {
+            true => ("32n", "31n"),
+            false => ("64n", "63n"),
+        };
+        macro_rules! bitops {
+            ($($op:ident $(if $c:expr)? => $t:literal),* $(,)?) => {
+                match op {
+                    $(BitOp::$op $(if $c)? => format!($t),)*
+                }
+            };
+        }
+        let value = bitops! {
+            AddUw => "(({a}&{max32})+{b})&{max64}",
+            Sh1add => "(({a}<<1n)+{b})&{max64}",
+            Sh2add => "(({a}<<2n)+{b})&{max64}",
+            Sh3add => "(({a}<<3n)+{b})&{max64}",
+            Sh1addUw => "((({a}&{max32})<<1n)+{b})&{max64}",
+            Sh2addUw => "((({a}&{max32})<<2n)+{b})&{max64}",
+            Sh3addUw => "((({a}&{max32})<<3n)+{b})&{max64}",
+            SlliUw => "(({a}&{max32})<<{b})&{max64}",
+            Andn => "{a}&~{b}",
+            Orn => "({a}|~{b})&{max64}",
+            Xnor => "~({a}^{b})&{max64}",
+            Clz if rv32 => "BigInt(Math.clz32(Number({a})))",
+            Clz => "BigInt({a}>>32n?Math.clz32(Number({a}>>32n)):32+Math.clz32(Number({a}&{max32})))",
+            Clzw => "BigInt(Math.clz32(Number({a}&{max32})))",
+            Ctz if rv32 => "BigInt({ctz}(Number({a})))",
+            Ctz => "BigInt({a}&{max32}?{ctz}(Number({a}&{max32})):32+{ctz}(Number({a}>>32n)))",
+            Ctzw => "BigInt({ctz}(Number({a}&{max32})))",
+            Cpop if rv32 => "BigInt({cpop}(Number({a})))",
+            Cpop => "BigInt({cpop}(Number({a}&{max32}))+{cpop}(Number({a}>>32n)))",
+            Cpopw => "BigInt({cpop}(Number({a}&{max32})))",
+            Max => "{signed}({a})>{signed}({b})?{a}:{b}",
+            Maxu => "{a}>{b}?{a}:{b}",
+            Min => "{signed}({a})<{signed}({b})?{a}:{b}",
+            Minu => "{a}<{b}?{a}:{b}",
+            SextB => "{unsigned}(BigInt.asIntN(8,{a}))",
+            SextH => "{unsigned}(BigInt.asIntN(16,{a}))",
+            ZextH => "{a}&0xffffn",
+            Rol => "(({a}<<({b}&{shamt}))|({a}>>(({xbits}-({b}&{shamt}))&{shamt})))&{max64}",
+            Ror => "(({a}>>({b}&{shamt}))|({a}<<(({xbits}-({b}&{shamt}))&{shamt})))&{max64}",
+            Rolw => "{unsigned}(BigInt.asIntN(32,(({a}&{max32})<<({b}&31n))|(({a}&{max32})>>((32n-({b}&31n))&31n))))",
+            Rorw => "{unsigned}(BigInt.asIntN(32,(({a}&{max32})>>({b}&31n))|(({a}&{max32})<<((32n-({b}&31n))&31n))))",
+            OrcB if rv32 => "BigInt({orc}(Number({a})))",
+            OrcB => "BigInt({orc}(Number({a}>>32n)))<<32n|BigInt({orc}(Number({a}&{max32})))",
+            Rev8 if rv32 => "BigInt({bswap}(Number({a})))",
+            Rev8 => "BigInt({bswap}(Number({a}&{max32})))<<32n|BigInt({bswap}(Number({a}>>32n)))",
+            Clmul => "{clmul}({a},{b})&{max64}",
+            Clmulh => "{clmul}({a},{b})>>{xbits}",
+            Clmulr => "({clmul}({a},{b})>>({xbits}-1n))&{max64}",
+            Bclr => "{a}&~(1n<<({b}&{shamt}))",
+            Bext => "({a}>>({b}&{shamt}))&1n",
+            Binv => "{a}^(1n<<({b}&{shamt}))",
+            Bset => "{a}|(1n<<({b}&{shamt}))",
+        };
+        write!(
+            f,
+            "{}",
+            TemplateReg {
+                regs: self.params.regs,
+                n: [(); 32],
+                reg: &dest,
+                value: Some(&value)
+            }
+        )
+    }
+}
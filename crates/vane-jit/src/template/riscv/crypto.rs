@@ -0,0 +1,77 @@
+use crate::crypto::CryptoOp;
+
+use super::*;
+
+impl<'a> TemplateJit<'a> {
+    /// Render a scalar cryptography operation writing `dest`
+    ///
+    /// Packing and the SHA-2 and SM3 rotations are inlined, the 32-bit ones
+    /// on numbers. Anything built on an S-box or a bit permutation goes
+    /// through the reactor's `crypto`, so the tables exist only in Rust.
+    pub(super) fn rv_crypto_js(
+        &self,
+        f: &mut Formatter,
+        op: CryptoOp,
+        dest: Reg,
+        a: &dyn Display,
+        b: &dyn Display,
+    ) -> core::fmt::Result {
+        let max64 = self.params.flate.flate("max64");
+        let max32 = self.params.flate.flate("max32");
+        let unsigned = self.params.flate.flate("unsigned");
+        let rv32 = self.params.flags.xlen().is_32();
+        // A 32-bit result from `x`, or `x` and `y`, sign-extended to XLEN
+        let word = |body: &str| format!("{unsigned}(BigInt((x=>{body})(Number({a}&{max32}))))");
+        let words =
+            |body: &str| format!("{unsigned}(BigInt(((x,y)=>{body})(Number({a}&{max32}),Number({b}&{max32}))))");
+        let double = |body: &str| format!("(x=>({body})&{max64})({a})");
+        let value = match op {
+            CryptoOp::Pack if rv32 => format!("({a}&0xffffn)|({b}&0xffffn)<<16n"),
+            CryptoOp::Pack => format!("({a}&{max32})|({b}<<32n)&{max64}"),
+            CryptoOp::Packh => format!("({a}&0xffn)|({b}&0xffn)<<8n"),
+            CryptoOp::Packw => format!("{unsigned}(BigInt.asIntN(32,({a}&0xffffn)|({b}&0xffffn)<<16n))"),
+            CryptoOp::Sha256Sig0 => word("(x>>>7|x<<25)^(x>>>18|x<<14)^x>>>3"),
+            CryptoOp::Sha256Sig1 => word("(x>>>17|x<<15)^(x>>>19|x<<13)^x>>>10"),
+            CryptoOp::Sha256Sum0 => word("(x>>>2|x<<30)^(x>>>13|x<<19)^(x>>>22|x<<10)"),
+            CryptoOp::Sha256Sum1 => word("(x>>>6|x<<26)^(x>>>11|x<<21)^(x>>>25|x<<7)"),
+            CryptoOp::Sha512Sig0 => double("(x>>1n|x<<63n)^(x>>8n|x<<56n)^x>>7n"),
+            CryptoOp::Sha512Sig1 => double("(x>>19n|x<<45n)^(x>>61n|x<<3n)^x>>6n"),
+            CryptoOp::Sha512Sum0 => double("(x>>28n|x<<36n)^(x>>34n|x<<30n)^(x>>39n|x<<25n)"),
+            CryptoOp::Sha512Sum1 => double("(x>>14n|x<<50n)^(x>>18n|x<<46n)^(x>>41n|x<<23n)"),
+            CryptoOp::Sha512Sig0h => words("x>>>1^x>>>7^x>>>8^y<<31^y<<24"),
+            CryptoOp::Sha512Sig0l => words("x>>>1^x>>>7^x>>>8^y<<31^y<<25^y<<24"),
+            CryptoOp::Sha512Sig1h => words("x<<3^x>>>6^x>>>19^y>>>29^y<<13"),
+            CryptoOp::Sha512Sig1l => words("x<<3^x>>>6^x>>>19^y>>>29^y<<26^y<<13"),
+            CryptoOp::Sha512Sum0r => words("x<<25^x<<30^x>>>28^y>>>7^y>>>2^y<<4"),
+            CryptoOp::Sha512Sum1r => words("x<<23^x>>>14^x>>>18^y>>>9^y<<18^y<<14"),
+            CryptoOp::Sm3P0 => word("x^(x<<9|x>>>23)^(x<<17|x>>>15)"),
+            CryptoOp::Sm3P1 => word("x^(x<<15|x>>>17)^(x<<23|x>>>9)"),
+            _ => {
+                let mut i = self.params.react.bytes(self.pc);
+                let inst_code = u32::from_le_bytes(array::from_fn(|_| i.next().unwrap()));
+                return write!(
+                    f,
+                    "{{const v=$.crypto({inst_code},{a},{b});if(v===undefined){{{}return $.raise({}n)}}{}}}",
+                    self.sync(),
+                    self.pc,
+                    TemplateReg {
+                        regs: self.params.regs,
+                        n: [(); 32],
+                        reg: &dest,
+                        value: Some(&"v")
+                    }
+                );
+            }
+        };
+        write!(
+            f,
+            "{}",
+            TemplateReg {
+                regs: self.params.regs,
+                n: [(); 32],
+                reg: &dest,
+                value: Some(&value)
+            }
+        )
+    }
+}
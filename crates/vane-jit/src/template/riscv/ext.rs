@@ -0,0 +1,229 @@
+use alloc::string::ToString;
+use crate::ext::ExtInst;
+use crate::fp::{FpFmt, FpOp};
+
+use super::*;
+
+impl<'a> TemplateJit<'a> {
+    /// Render an instruction decoded by [`ExtInst`], falling through to `next`
+    pub(super) fn rv_ext_js<Target: TemplateJS>(
+        &self,
+        a: ExtInst,
+        next: u64,
+        labels: Labels<'_>,
+        nd: u32,
+        f: &mut Formatter,
+        target: Target,
+    ) -> core::fmt::Result {
+        let max64 = self.params.flate.flate("max64");
+        let max32 = self.params.flate.flate("max32");
+        let unsigned = self.params.flate.flate("unsigned");
+        let data = self.params.flate.flate("data");
+        let store = self.params.flate.flate("store");
+        let mut i = self.params.react.bytes(self.pc);
+        let inst_code = u32::from_le_bytes(array::from_fn(|_| i.next().unwrap()));
+        let xreg = |reg: &Reg| TemplateReg {
+            regs: self.params.regs,
+            n: [(); 32],
+            reg,
+            value: None,
+        }
+        .to_string();
+        let freg = |reg: &Reg| TemplateFReg { reg, value: None }.to_string();
+        // Reactor calls that can trap return `undefined` and leave the trap to `raise`
+        let sync = self.sync();
+        let raise = format!("if(v===undefined){{{sync}return $.raise({}n)}}", self.pc);
+        match a {
+            ExtInst::FLoad {
+                fmt,
+                offset,
+                dest,
+                base,
+            } => {
+                let addr = format!("({}n+{})&{max64}", offset.as_i64() as u64, xreg(&base));
+                write!(
+                    f,
+                    "{}",
+                    TemplateFReg {
+                        reg: &dest,
+                        value: Some(&match fmt {
+                            // Single-precision loads are NaN-boxed
+                            FpFmt::S => format!(
+                                "0xffffffff00000000n|BigInt({data}({addr},4n).getUint32(0,true))"
+                            ),
+                            FpFmt::D => format!("{data}({addr},8n).getBigUint64(0,true)"),
+                        })
+                    }
+                )?;
+            }
+            ExtInst::FStore {
+                fmt,
+                offset,
+                src,
+                base,
+            } => {
+                let addr = format!("({}n+{})&{max64}", offset.as_i64() as u64, xreg(&base));
+                match fmt {
+                    FpFmt::S => write!(
+                        f,
+                        "{store}({addr},4n).setUint32(0,Number({}&{max32}),true)",
+                        freg(&src)
+                    )?,
+                    FpFmt::D => write!(f, "{store}({addr},8n).setBigUint64(0,{},true)", freg(&src))?,
+                }
+            }
+            ExtInst::Fp {
+                op,
+                fmt,
+                dest,
+                src1,
+                src2,
+                src3,
+                ..
+            } => {
+                let (call, check) = match (op, fmt) {
+                    // Moves are plain bit copies and never touch `fflags`
+                    (FpOp::MvToX, FpFmt::S) => (
+                        format!("{unsigned}(BigInt.asIntN(32,{}))", freg(&src1)),
+                        "",
+                    ),
+                    (FpOp::MvToX, FpFmt::D) => (freg(&src1), ""),
+                    (FpOp::MvFromX, FpFmt::S) => (
+                        format!("0xffffffff00000000n|({}&{max32})", xreg(&src1)),
+                        "",
+                    ),
+                    (FpOp::MvFromX, FpFmt::D) => (xreg(&src1), ""),
+                    _ => (
+                        format!(
+                            "$.fpu({inst_code},{},{},{})",
+                            match op.src1_is_x() {
+                                true => xreg(&src1),
+                                false => freg(&src1),
+                            },
+                            freg(&src2),
+                            freg(&src3)
+                        ),
+                        raise.as_str(),
+                    ),
+                };
+                write!(f, "{{const v={call};{check}")?;
+                match op.dest_is_x() {
+                    true => write!(
+                        f,
+                        "{}",
+                        TemplateReg {
+                            regs: self.params.regs,
+                            n: [(); 32],
+                            reg: &dest,
+                            value: Some(&format_args!("v&{max64}"))
+                        }
+                    )?,
+                    false => write!(
+                        f,
+                        "{}",
+                        TemplateFReg {
+                            reg: &dest,
+                            value: Some(&"v")
+                        }
+                    )?,
+                }
+                write!(f, "}}")?;
+            }
+            ExtInst::LrD { dest, addr, .. } => {
+                self.rv_amo_js(f, AmoKind::Lr, true, dest, addr, Reg::ZERO)?
+            }
+            ExtInst::ScD {
+                dest, addr, src, ..
+            } => self.rv_amo_js(f, AmoKind::Sc, true, dest, addr, src)?,
+            ExtInst::AmoD {
+                op,
+                dest,
+                addr,
+                src,
+                ..
+            } => self.rv_amo_js(f, AmoKind::Amo(op), true, dest, addr, src)?,
+            // CSR state lives in the reactor, shared with the interpreter
+            ExtInst::Csr { dest, src, .. } => write!(
+                f,
+                "{{const v=$.csr({inst_code},{});{raise}{}}}",
+                xreg(&src),
+                TemplateReg {
+                    regs: self.params.regs,
+                    n: [(); 32],
+                    reg: &dest,
+                    value: Some(&"v")
+                }
+            )?,
+            ExtInst::Bit {
+                op,
+                dest,
+                src1,
+                src2,
+            } => self.rv_bit_js(f, op, dest, &xreg(&src1), &xreg(&src2))?,
+            ExtInst::BitImm {
+                op,
+                dest,
+                src1,
+                shamt,
+            } => self.rv_bit_js(f, op, dest, &xreg(&src1), &format_args!("{shamt}n"))?,
+            ExtInst::BitUnary { op, dest, src } => {
+                self.rv_bit_js(f, op, dest, &xreg(&src), &"0n")?
+            }
+            ExtInst::Crypto {
+                op,
+                dest,
+                src1,
+                src2,
+                ..
+            } => self.rv_crypto_js(f, op, dest, &xreg(&src1), &xreg(&src2))?,
+            ExtInst::CsrI { dest, uimm, .. } => write!(
+                f,
+                "{{const v=$.csr({inst_code},{uimm}n);{raise}{}}}",
+                TemplateReg {
+                    regs: self.params.regs,
+                    n: [(); 32],
+                    reg: &dest,
+                    value: Some(&"v")
+                }
+            )?,
+            // The new pc is only known at run time
+            ExtInst::Mret => return write!(f, "{{const v=$.mret();{raise}{sync}return J(v)}}"),
+            ExtInst::Sret => return write!(f, "{{const v=$.sret();{raise}{sync}return J(v)}}"),
+            ExtInst::SfenceVma { .. } => write!(
+                f,
+                "if(!$.sfence_vma({inst_code})){{{sync}return $.raise({}n)}}",
+                self.pc
+            )?,
+            ExtInst::Wfi => {}
+            // End the block so the next instruction is compiled afresh
+            ExtInst::FenceI => return write!(f, "{{$.fence_i();{sync}return J({next}n)}}"),
+            // Vector state lives in the reactor, shared with the interpreter
+            ExtInst::Vector(i) => {
+                let x = |r: Option<Reg>| r.map_or("0n".to_string(), |r| xreg(&r));
+                let (a, b) = i.x_srcs();
+                write!(
+                    f,
+                    "{{const v=$.vec({inst_code},{},{});{raise}{}}}",
+                    x(a),
+                    x(b),
+                    TemplateReg {
+                        regs: self.params.regs,
+                        n: [(); 32],
+                        reg: &i.x_dest().unwrap_or(Reg(0)),
+                        value: Some(&"v")
+                    }
+                )?
+            }
+        }
+        write!(
+            f,
+            ";{};",
+            target.template_jit_js(&TemplateJit {
+                params: self.params,
+                pc: next,
+                labels: &labels,
+                depth: nd,
+            })
+        )
+    }
+}
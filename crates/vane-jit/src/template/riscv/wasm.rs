@@ -0,0 +1,500 @@
+//! RV64IM to WebAssembly, following the JavaScript templates.
+
+use rv_asm::{Inst, Reg, Xlen};
+use wasmparser::{BlockType, Operator};
+
+use crate::ext::ExtInst;
+use crate::rvc;
+use crate::template::wasm::{self, Ops, import};
+use crate::template::{Labels, TemplateJit};
+use crate::trap::Trap;
+use crate::xlen_mask;
+
+/// A second operand: a register or an immediate
+#[derive(Clone, Copy)]
+enum Src {
+    Reg(Reg),
+    Imm(i64),
+}
+
+/// What `div`, `divu`, `rem` and `remu` compute
+#[derive(Clone, Copy, PartialEq)]
+enum DivKind {
+    Div,
+    Divu,
+    Rem,
+    Remu,
+}
+
+/// Which upper product `mulh`, `mulhsu` and `mulhu` take
+#[derive(Clone, Copy, PartialEq)]
+enum MulhKind {
+    Signed,
+    SignedUnsigned,
+    Unsigned,
+}
+
+/// The scratch locals: operands, or a jump target, and a temporary
+const A: usize = 0;
+const B: usize = 1;
+const T: usize = 2;
+
+fn push_src(out: &mut Ops, s: Src) {
+    match s {
+        Src::Reg(r) => wasm::get(out, r),
+        Src::Imm(value) => out.push(Operator::I64Const { value }),
+    }
+}
+
+/// `dest = op(src1, src2)` on 64 bits
+fn alu(out: &mut Ops, dest: Reg, src1: Reg, src2: Src, op: Operator<'static>) {
+    wasm::set(out, dest, |out| {
+        wasm::get(out, src1);
+        push_src(out, src2);
+        out.push(op);
+    });
+}
+
+/// `dest = sext(op(src1, src2))` on the low 32 bits, for the `*w` forms
+fn alu_w(out: &mut Ops, dest: Reg, src1: Reg, src2: Src, op: Operator<'static>) {
+    wasm::set(out, dest, |out| {
+        wasm::get(out, src1);
+        out.push(Operator::I32WrapI64);
+        push_src(out, src2);
+        out.extend([Operator::I32WrapI64, op, Operator::I64ExtendI32S]);
+    });
+}
+
+/// `dest = 1` if `cmp(src1, src2)`, else 0
+fn set_if(out: &mut Ops, dest: Reg, src1: Reg, src2: Src, cmp: Operator<'static>) {
+    wasm::set(out, dest, |out| {
+        wasm::get(out, src1);
+        push_src(out, src2);
+        out.extend([cmp, Operator::I64ExtendI32U]);
+    });
+}
+
+/// Push the upper 64 bits of `A * B`
+///
+/// Wasm has no 128-bit product, so the unsigned one is built from 32-bit
+/// halves and the signed forms subtract the other operand for each
+/// negative one.
+fn mulh(out: &mut Ops, kind: MulhKind) {
+    let [a, b, t] = [A, B, T].map(|i| out.scratch(i));
+    let lo = |l| {
+        [
+            Operator::LocalGet { local_index: l },
+            Operator::I64Const { value: 0xffff_ffff },
+            Operator::I64And,
+        ]
+    };
+    let hi = |l| {
+        [
+            Operator::LocalGet { local_index: l },
+            Operator::I64Const { value: 32 },
+            Operator::I64ShrU,
+        ]
+    };
+    let c = |value| Operator::I64Const { value };
+    // The middle partial products' carry into the upper half
+    out.extend(
+        lo(a)
+            .into_iter()
+            .chain(lo(b))
+            .chain([Operator::I64Mul, c(32), Operator::I64ShrU]),
+    );
+    out.extend(lo(a).into_iter().chain(hi(b)).chain([
+        Operator::I64Mul,
+        c(0xffff_ffff),
+        Operator::I64And,
+    ]));
+    out.push(Operator::I64Add);
+    out.extend(hi(a).into_iter().chain(lo(b)).chain([
+        Operator::I64Mul,
+        c(0xffff_ffff),
+        Operator::I64And,
+    ]));
+    out.extend([Operator::I64Add, Operator::LocalSet { local_index: t }]);
+    out.extend(hi(a).into_iter().chain(hi(b)).chain([Operator::I64Mul]));
+    out.extend(
+        lo(a)
+            .into_iter()
+            .chain(hi(b))
+            .chain([Operator::I64Mul, c(32), Operator::I64ShrU]),
+    );
+    out.push(Operator::I64Add);
+    out.extend(
+        hi(a)
+            .into_iter()
+            .chain(lo(b))
+            .chain([Operator::I64Mul, c(32), Operator::I64ShrU]),
+    );
+    out.push(Operator::I64Add);
+    out.extend([
+        Operator::LocalGet { local_index: t },
+        c(32),
+        Operator::I64ShrU,
+        Operator::I64Add,
+    ]);
+    // `x & (y >> 63)` is `x` if `y` is negative, else 0
+    let mut correct = |x, y| {
+        out.extend([
+            Operator::LocalGet { local_index: x },
+            Operator::LocalGet { local_index: y },
+            c(63),
+            Operator::I64ShrS,
+            Operator::I64And,
+            Operator::I64Sub,
+        ])
+    };
+    if kind != MulhKind::Unsigned {
+        correct(b, a);
+    }
+    if kind == MulhKind::Signed {
+        correct(a, b);
+    }
+}
+
+/// Push `A / B` or `A % B` as RISC-V defines them for zero and overflow
+///
+/// Wasm traps on both, so division by zero gives all ones or the dividend,
+/// and dividing by -1 negates, which wraps as the overflow case must.
+fn div(out: &mut Ops, kind: DivKind, w: bool) {
+    let [a, b] = [A, B].map(|i| out.scratch(i));
+    let get = |out: &mut Ops, l| {
+        out.push(Operator::LocalGet { local_index: l });
+        if w {
+            out.push(Operator::I32WrapI64);
+        }
+    };
+    let widen = |out: &mut Ops| {
+        if w {
+            out.push(Operator::I64ExtendI32S);
+        }
+    };
+    let result = Operator::If {
+        blockty: BlockType::Type(wasmparser::ValType::I64),
+    };
+    let op = match (kind, w) {
+        (DivKind::Div, false) => Operator::I64DivS,
+        (DivKind::Divu, false) => Operator::I64DivU,
+        (DivKind::Rem, false) => Operator::I64RemS,
+        (DivKind::Remu, false) => Operator::I64RemU,
+        (DivKind::Div, true) => Operator::I32DivS,
+        (DivKind::Divu, true) => Operator::I32DivU,
+        (DivKind::Rem, true) => Operator::I32RemS,
+        (DivKind::Remu, true) => Operator::I32RemU,
+    };
+    get(out, b);
+    out.extend([
+        if w {
+            Operator::I32Eqz
+        } else {
+            Operator::I64Eqz
+        },
+        result.clone(),
+    ]);
+    match kind {
+        DivKind::Div | DivKind::Divu => out.push(Operator::I64Const { value: -1 }),
+        DivKind::Rem | DivKind::Remu => {
+            get(out, a);
+            widen(out);
+        }
+    }
+    out.push(Operator::Else);
+    if kind == DivKind::Div {
+        get(out, b);
+        match w {
+            true => out.extend([Operator::I32Const { value: -1 }, Operator::I32Eq]),
+            false => out.extend([Operator::I64Const { value: -1 }, Operator::I64Eq]),
+        }
+        out.push(result);
+        match w {
+            true => out.push(Operator::I32Const { value: 0 }),
+            false => out.push(Operator::I64Const { value: 0 }),
+        }
+        get(out, a);
+        out.push(if w {
+            Operator::I32Sub
+        } else {
+            Operator::I64Sub
+        });
+        widen(out);
+        out.push(Operator::Else);
+    }
+    get(out, a);
+    get(out, b);
+    out.push(op);
+    widen(out);
+    if kind == DivKind::Div {
+        out.push(Operator::End);
+    }
+    out.push(Operator::End);
+}
+
+/// Put `src1` and `src2` in the scratch locals `A` and `B`
+fn operands(out: &mut Ops, src1: Reg, src2: Reg) {
+    let [a, b] = [A, B].map(|i| out.scratch(i));
+    wasm::get(out, src1);
+    out.push(Operator::LocalSet { local_index: a });
+    wasm::get(out, src2);
+    out.push(Operator::LocalSet { local_index: b });
+}
+
+/// Push `base + offset`, the address of a load or store
+fn address(out: &mut Ops, base: Reg, offset: i64) {
+    wasm::get(out, base);
+    out.extend([Operator::I64Const { value: offset }, Operator::I64Add]);
+}
+
+impl<'a> TemplateJit<'a> {
+    fn load(&self, out: &mut Ops, dest: Reg, base: Reg, offset: i64, size: u8, signed: bool) {
+        let aligned = self.params.flags.trap_misaligned;
+        wasm::set(out, dest, |out| {
+            wasm::load(out, wasm::Width { size, signed }, aligned, |out| {
+                address(out, base, offset)
+            })
+        });
+    }
+
+    fn store(&self, out: &mut Ops, src: Reg, base: Reg, offset: i64, size: u8) {
+        let aligned = self.params.flags.trap_misaligned;
+        wasm::store(
+            out,
+            size,
+            aligned,
+            |out| address(out, base, offset),
+            |out| wasm::get(out, src),
+        );
+    }
+
+    /// Emit this pc's block, or a branch back to it if it is already open
+    pub(super) fn rv_wasm(&self, out: &mut Ops) {
+        self.jit_wasm(out, |out, labels, nd| {
+            let mut i = self.params.react.bytes(self.pc);
+            let inst_code = u32::from_le_bytes(core::array::from_fn(|_| i.next().unwrap()));
+            let xlen = self.params.flags.xlen();
+            let fetched = match inst_code & 3 {
+                3 => inst_code,
+                _ => inst_code & 0xffff,
+            };
+            let next = |b| {
+                (match b {
+                    rv_asm::IsCompressed::Yes => 2,
+                    rv_asm::IsCompressed::No => 4,
+                } + self.pc)
+                    & xlen_mask(xlen)
+            };
+            match rvc::decode(inst_code, xlen) {
+                // RV32 keeps registers zero-extended, which these templates do not
+                _ if xlen == Xlen::Rv32 => wasm::exit(out, self.pc),
+                Ok((a, b)) => self.rv_core_wasm(a, fetched, next(b), labels, nd, out),
+                Err(_) => match ExtInst::decode(inst_code, xlen) {
+                    Some(_) => wasm::exit(out, self.pc),
+                    None => {
+                        wasm::prologue(out, self.pc, fetched);
+                        let tval = Trap::illegal(fetched).tval as i64;
+                        wasm::call_exit(out, &[2, tval, self.pc as i64], import::TRAP);
+                    }
+                },
+            }
+        })
+    }
+
+    /// Continue at `pc` inside `depth` blocks
+    fn rv_wasm_at(&self, pc: u64, labels: &Labels<'_>, depth: u32, out: &mut Ops) {
+        TemplateJit {
+            params: self.params,
+            pc,
+            labels,
+            depth,
+        }
+        .rv_wasm(out)
+    }
+
+    fn rv_core_wasm(
+        &self,
+        a: Inst,
+        fetched: u32,
+        next: u64,
+        labels: Labels<'_>,
+        nd: u32,
+        out: &mut Ops,
+    ) {
+        use Operator as O;
+        if matches!(a, Inst::LrW { .. } | Inst::ScW { .. } | Inst::AmoW { .. }) {
+            return wasm::exit(out, self.pc);
+        }
+        wasm::prologue(out, self.pc, fetched);
+        let pc = self.pc;
+        let imm = |i: rv_asm::Imm| Src::Imm(i.as_i64());
+        let r = Src::Reg;
+        match a {
+            Inst::Lui { uimm, dest } => wasm::set(out, dest, |out| {
+                out.push(O::I64Const {
+                    value: uimm.as_i64(),
+                })
+            }),
+            Inst::Auipc { uimm, dest } => wasm::set(out, dest, |out| {
+                out.push(O::I64Const {
+                    value: pc.wrapping_add(uimm.as_u64()) as i64,
+                })
+            }),
+            Inst::Jal { offset, dest } => {
+                wasm::set(out, dest, |out| {
+                    out.push(O::I64Const { value: next as i64 })
+                });
+                let to = pc.wrapping_add_signed(offset.as_i64());
+                return self.rv_wasm_at(to, &labels, nd, out);
+            }
+            Inst::Jalr { offset, base, dest } => {
+                // The target is read before `dest` is written, as `dest` may be `base`
+                let target = out.scratch(A);
+                address(out, base, offset.as_i64());
+                out.extend([
+                    O::I64Const { value: !1 },
+                    O::I64And,
+                    O::LocalSet {
+                        local_index: target,
+                    },
+                ]);
+                wasm::set(out, dest, |out| {
+                    out.push(O::I64Const { value: next as i64 })
+                });
+                out.flush();
+                out.extend([
+                    O::LocalGet {
+                        local_index: target,
+                    },
+                    O::Return,
+                ]);
+                return;
+            }
+            Inst::Beq { offset, src1, src2 }
+            | Inst::Bne { offset, src1, src2 }
+            | Inst::Blt { offset, src1, src2 }
+            | Inst::Bge { offset, src1, src2 }
+            | Inst::Bltu { offset, src1, src2 }
+            | Inst::Bgeu { offset, src1, src2 } => {
+                let cmp = match a {
+                    Inst::Beq { .. } => O::I64Eq,
+                    Inst::Bne { .. } => O::I64Ne,
+                    Inst::Blt { .. } => O::I64LtS,
+                    Inst::Bge { .. } => O::I64GeS,
+                    Inst::Bltu { .. } => O::I64LtU,
+                    _ => O::I64GeU,
+                };
+                wasm::get(out, src1);
+                wasm::get(out, src2);
+                out.extend([
+                    cmp,
+                    O::If {
+                        blockty: BlockType::Empty,
+                    },
+                ]);
+                // Both arms sit inside the `if`
+                self.rv_wasm_at(
+                    pc.wrapping_add_signed(offset.as_i64()),
+                    &labels,
+                    nd + 1,
+                    out,
+                );
+                out.push(O::Else);
+                self.rv_wasm_at(next, &labels, nd + 1, out);
+                out.push(O::End);
+                return;
+            }
+            Inst::Lb { offset, dest, base } => self.load(out, dest, base, offset.as_i64(), 1, true),
+            Inst::Lbu { offset, dest, base } => {
+                self.load(out, dest, base, offset.as_i64(), 1, false)
+            }
+            Inst::Lh { offset, dest, base } => self.load(out, dest, base, offset.as_i64(), 2, true),
+            Inst::Lhu { offset, dest, base } => {
+                self.load(out, dest, base, offset.as_i64(), 2, false)
+            }
+            Inst::Lw { offset, dest, base } => self.load(out, dest, base, offset.as_i64(), 4, true),
+            Inst::Lwu { offset, dest, base } => {
+                self.load(out, dest, base, offset.as_i64(), 4, false)
+            }
+            Inst::Ld { offset, dest, base } => {
+                self.load(out, dest, base, offset.as_i64(), 8, false)
+            }
+            Inst::Sb { offset, src, base } => self.store(out, src, base, offset.as_i64(), 1),
+            Inst::Sh { offset, src, base } => self.store(out, src, base, offset.as_i64(), 2),
+            Inst::Sw { offset, src, base } => self.store(out, src, base, offset.as_i64(), 4),
+            Inst::Sd { offset, src, base } => self.store(out, src, base, offset.as_i64(), 8),
+            Inst::Addi { imm: i, dest, src1 } => alu(out, dest, src1, imm(i), O::I64Add),
+            Inst::Slti { imm: i, dest, src1 } => set_if(out, dest, src1, imm(i), O::I64LtS),
+            Inst::Sltiu { imm: i, dest, src1 } => set_if(out, dest, src1, imm(i), O::I64LtU),
+            Inst::Xori { imm: i, dest, src1 } => alu(out, dest, src1, imm(i), O::I64Xor),
+            Inst::Ori { imm: i, dest, src1 } => alu(out, dest, src1, imm(i), O::I64Or),
+            Inst::Andi { imm: i, dest, src1 } => alu(out, dest, src1, imm(i), O::I64And),
+            // Wasm masks shift amounts as RISC-V does
+            Inst::Slli { imm: i, dest, src1 } => alu(out, dest, src1, imm(i), O::I64Shl),
+            Inst::Srli { imm: i, dest, src1 } => alu(out, dest, src1, imm(i), O::I64ShrU),
+            Inst::Srai { imm: i, dest, src1 } => alu(out, dest, src1, imm(i), O::I64ShrS),
+            Inst::Add { dest, src1, src2 } => alu(out, dest, src1, r(src2), O::I64Add),
+            Inst::Sub { dest, src1, src2 } => alu(out, dest, src1, r(src2), O::I64Sub),
+            Inst::Sll { dest, src1, src2 } => alu(out, dest, src1, r(src2), O::I64Shl),
+            Inst::Slt { dest, src1, src2 } => set_if(out, dest, src1, r(src2), O::I64LtS),
+            Inst::Sltu { dest, src1, src2 } => set_if(out, dest, src1, r(src2), O::I64LtU),
+            Inst::Xor { dest, src1, src2 } => alu(out, dest, src1, r(src2), O::I64Xor),
+            Inst::Srl { dest, src1, src2 } => alu(out, dest, src1, r(src2), O::I64ShrU),
+            Inst::Sra { dest, src1, src2 } => alu(out, dest, src1, r(src2), O::I64ShrS),
+            Inst::Or { dest, src1, src2 } => alu(out, dest, src1, r(src2), O::I64Or),
+            Inst::And { dest, src1, src2 } => alu(out, dest, src1, r(src2), O::I64And),
+            Inst::AddiW { imm: i, dest, src1 } => alu_w(out, dest, src1, imm(i), O::I32Add),
+            Inst::SlliW { imm: i, dest, src1 } => alu_w(out, dest, src1, imm(i), O::I32Shl),
+            Inst::SrliW { imm: i, dest, src1 } => alu_w(out, dest, src1, imm(i), O::I32ShrU),
+            Inst::SraiW { imm: i, dest, src1 } => alu_w(out, dest, src1, imm(i), O::I32ShrS),
+            Inst::AddW { dest, src1, src2 } => alu_w(out, dest, src1, r(src2), O::I32Add),
+            Inst::SubW { dest, src1, src2 } => alu_w(out, dest, src1, r(src2), O::I32Sub),
+            Inst::SllW { dest, src1, src2 } => alu_w(out, dest, src1, r(src2), O::I32Shl),
+            Inst::SrlW { dest, src1, src2 } => alu_w(out, dest, src1, r(src2), O::I32ShrU),
+            Inst::SraW { dest, src1, src2 } => alu_w(out, dest, src1, r(src2), O::I32ShrS),
+            Inst::Mul { dest, src1, src2 } => alu(out, dest, src1, r(src2), O::I64Mul),
+            Inst::MulW { dest, src1, src2 } => alu_w(out, dest, src1, r(src2), O::I32Mul),
+            Inst::Mulh { dest, src1, src2 }
+            | Inst::Mulhsu { dest, src1, src2 }
+            | Inst::Mulhu { dest, src1, src2 } => {
+                let kind = match a {
+                    Inst::Mulh { .. } => MulhKind::Signed,
+                    Inst::Mulhsu { .. } => MulhKind::SignedUnsigned,
+                    _ => MulhKind::Unsigned,
+                };
+                operands(out, src1, src2);
+                wasm::set(out, dest, |out| mulh(out, kind));
+            }
+            Inst::Div { dest, src1, src2 }
+            | Inst::Divu { dest, src1, src2 }
+            | Inst::Rem { dest, src1, src2 }
+            | Inst::Remu { dest, src1, src2 }
+            | Inst::DivW { dest, src1, src2 }
+            | Inst::DivuW { dest, src1, src2 }
+            | Inst::RemW { dest, src1, src2 }
+            | Inst::RemuW { dest, src1, src2 } => {
+                let (kind, w) = match a {
+                    Inst::Div { .. } => (DivKind::Div, false),
+                    Inst::Divu { .. } => (DivKind::Divu, false),
+                    Inst::Rem { .. } => (DivKind::Rem, false),
+                    Inst::Remu { .. } => (DivKind::Remu, false),
+                    Inst::DivW { .. } => (DivKind::Div, true),
+                    Inst::DivuW { .. } => (DivKind::Divu, true),
+                    Inst::RemW { .. } => (DivKind::Rem, true),
+                    _ => (DivKind::Remu, true),
+                };
+                operands(out, src1, src2);
+                wasm::set(out, dest, |out| div(out, kind, w));
+            }
+            Inst::Fence { .. } => {}
+            // Traps end the block; the reactor says where to continue
+            Inst::Ecall => return wasm::call_exit(out, &[pc as i64], import::ECALL),
+            Inst::Ebreak => return wasm::call_exit(out, &[pc as i64, next as i64], import::EBREAK),
+            _ => {
+                let tval = Trap::illegal(fetched).tval as i64;
+                return wasm::call_exit(out, &[2, tval, pc as i64], import::TRAP);
+            }
+        }
+        self.rv_wasm_at(next, &labels, nd, out)
+    }
+}
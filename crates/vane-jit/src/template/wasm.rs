@@ -0,0 +1,810 @@
+//! What a block compiled to WebAssembly expects of its host.
+//!
+//! Each block is one function of type `(regs: i32) -> i64`. `regs` points
+//! into the imported memory at the hart's [register file](offset); the
+//! function returns the pc to continue at, for the host to look up the next
+//! block as `J` does for JavaScript blocks. Anything the block cannot do
+//! itself, from device accesses to traps, is an [imported function](import)
+//! the host answers through the reactor.
+//!
+//! Guest RAM is in the imported memory too, so loads and stores go to it
+//! directly once [`PAGE`](import::PAGE) has said where a page is; the
+//! answers are cached in the register file for as long as the block runs.
+//! A block checks that its code is unchanged once, as it is entered.
+//!
+//! A block ends early, returning the pc of the instruction, at the first
+//! instruction the Wasm tier does not compile; when that is the block's
+//! first instruction the host must run it in another tier.
+//!
+//! Templates emit [`JitOpcode`]s into [`Ops`], naming loops by pc and host
+//! functions by import name; [`ModuleBuilder`] resolves both.
+use alloc::collections::btree_map::BTreeMap;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::cell::RefCell;
+use core::fmt::Display;
+
+use rv_asm::Reg;
+use wasm_encoder::reencode::{Error, Reencode, RoundtripReencoder};
+use wasm_encoder::{
+    CodeSection, EntityType, ExportKind, ExportSection, Function, FunctionSection, ImportSection,
+    Instruction, MemoryType, Module, TypeSection,
+};
+use wasmparser::{BlockType, MemArg, Operator, ValType};
+
+use crate::{JitOpcode, WasmJitCtx};
+
+/// Byte offsets into the register file `regs` points at
+pub mod offset {
+    /// `x1`-`x31` as little-endian `i64`s, at 8 bytes times the register number
+    pub const X: u64 = 0;
+    /// The pc of the instruction running, for the host to raise faults at
+    pub const PC: u64 = 32 * 8;
+    /// Instructions started, as `$._r.i`
+    pub const INSTRET: u64 = 33 * 8;
+    /// The interrupt deadline, as `$._r.t`
+    pub const DEADLINE: u64 = 34 * 8;
+    /// Where guest pages are in the imported memory, for loads then for
+    /// stores: each [`TLB_ENTRIES`] pairs of the `i64` page number and the
+    /// `i32` address `PAGE` gave, direct-mapped by page number
+    ///
+    /// The host fills both with all ones, which no page number matches,
+    /// whenever it enters a block.
+    pub const TLB: u64 = 35 * 8;
+    /// The entries in each of the [`TLB`]s
+    pub const TLB_ENTRIES: u64 = 16;
+    /// The size of the register file
+    pub const SIZE: u64 = TLB + 2 * TLB_ENTRIES * 16;
+}
+
+/// The host functions a block may call, imported from `v` by these names;
+/// each is the reactor method that answers it
+pub mod import {
+    use wasm_encoder::ValType::{self, I32, I64};
+
+    /// `(root: i64)`: forget the block compiled at `root`, whose code changed
+    pub const DROP_BLOCK: &str = "drop_block";
+    /// `(pc: i64) -> i64`: take the interrupt due before `pc`
+    pub const INTERRUPT: &str = "interrupt";
+    /// `(a: i64, size: i32) -> i64`: load `size` bytes at `a`, zero-extended
+    pub const LOAD: &str = "load";
+    /// `(a: i64, size: i32, v: i64)`: store the low `size` bytes of `v` at `a`
+    pub const STORE: &str = "store";
+    /// `(code: i64, tval: i64, pc: i64) -> i64`: raise exception `code` at `pc`
+    pub const TRAP: &str = "trap";
+    /// `(pc: i64) -> i64`: raise the environment call at `pc`
+    pub const ECALL: &str = "ecall";
+    /// `(pc: i64, next: i64) -> i64`: stop for the host at the `ebreak` at `pc`
+    pub const EBREAK: &str = "ebreak";
+    /// `(a: i64, access: i32) -> i32`: where the 4 KiB page holding `a`
+    /// starts in the imported memory, for [`READ`], [`WRITE`] or
+    /// [`EXECUTE`]; 0 if accesses to it must go through `load` and `store`
+    pub const PAGE: &str = "page";
+    /// All of them, in function index order
+    pub const ALL: [&str; 8] = [
+        DROP_BLOCK, INTERRUPT, LOAD, STORE, TRAP, ECALL, EBREAK, PAGE,
+    ];
+
+    /// The function index of `name`, if it is one of them
+    pub fn index(name: &str) -> Option<u32> {
+        ALL.iter().position(|&i| i == name).map(|i| i as u32)
+    }
+
+    /// The `access` arguments of [`PAGE`]
+    pub const READ: i32 = 0;
+    pub const WRITE: i32 = 1;
+    pub const EXECUTE: i32 = 2;
+
+    /// The parameters and results of the host function `name`, if there is one
+    pub fn signature(name: &str) -> Option<(&'static [ValType], &'static [ValType])> {
+        Some(match name {
+            PAGE => (&[I64, I32], &[I32]),
+            DROP_BLOCK => (&[I64], &[]),
+            INTERRUPT | ECALL => (&[I64], &[I64]),
+            LOAD => (&[I64, I32], &[I64]),
+            STORE => (&[I64, I32, I64], &[]),
+            TRAP => (&[I64, I64, I64], &[I64]),
+            EBREAK => (&[I64, I64], &[I64]),
+            _ => return None,
+        })
+    }
+}
+
+/// The `regs` parameter
+pub const REGS: u32 = 0;
+
+/// A block's part of the module: its locals and the guest instructions in it
+///
+/// Locals are numbered after `regs` in the order they are asked for.
+#[derive(Default)]
+pub struct BlockCtx {
+    locals: RefCell<Vec<ValType>>,
+    regs: RefCell<BTreeMap<u8, u32>>,
+    pcs: RefCell<Vec<u64>>,
+}
+
+impl BlockCtx {
+    /// The types of the locals asked for, after `regs`
+    pub fn locals(&self) -> Vec<ValType> {
+        self.locals.borrow().clone()
+    }
+
+    /// The guest instructions compiled, in the order their code was emitted
+    pub fn pcs(&self) -> Vec<u64> {
+        self.pcs.borrow().clone()
+    }
+}
+
+impl WasmJitCtx for BlockCtx {
+    fn memory(&self) -> u32 {
+        0
+    }
+    fn regs(&self) -> u32 {
+        REGS
+    }
+    fn local(&self, ty: ValType) -> u32 {
+        let mut locals = self.locals.borrow_mut();
+        locals.push(ty);
+        REGS + locals.len() as u32
+    }
+    fn reg_local(&self, r: Reg) -> u32 {
+        if let Some(&l) = self.regs.borrow().get(&(r.0 & 31)) {
+            return l;
+        }
+        let l = self.local(ValType::I64);
+        self.regs.borrow_mut().insert(r.0 & 31, l);
+        l
+    }
+    fn import(&self, name: &str) -> Option<u32> {
+        import::index(name)
+    }
+    fn mark_pc(&self, pc: u64) {
+        self.pcs.borrow_mut().push(pc);
+    }
+}
+
+/// The opcodes of a block being emitted, and the context they are for
+pub struct Ops<'c> {
+    pub ops: Vec<JitOpcode<'static>>,
+    pub ctx: &'c (dyn WasmJitCtx + 'c),
+    scratch: Vec<u32>,
+    scratch32: Vec<u32>,
+    /// Each instruction compiled, as its pc and the code it was compiled from
+    code: Vec<(u64, u32)>,
+    /// The registers read or written, and written, as a bit per register
+    used: u32,
+    dirty: u32,
+    /// Where in `ops` the written registers go back to the register file
+    flushes: Vec<usize>,
+}
+
+impl<'c> Ops<'c> {
+    pub fn new(ctx: &'c (dyn WasmJitCtx + 'c)) -> Self {
+        Ops {
+            ops: Vec::new(),
+            ctx,
+            scratch: Vec::new(),
+            scratch32: Vec::new(),
+            code: Vec::new(),
+            used: 0,
+            dirty: 0,
+            flushes: Vec::new(),
+        }
+    }
+
+    /// The opcodes of the block entered at `root`, behind the check that
+    /// its code is unchanged
+    ///
+    /// The check reads each instruction through the pages `PAGE` gives for
+    /// execution; if one has changed, the block drops itself and returns
+    /// `root` having run nothing, for the host to compile it afresh.
+    /// Otherwise the registers the block uses are loaded into their locals,
+    /// and the written ones are stored back at each [flush](Ops::flush).
+    pub fn finish(mut self, root: u64) -> Vec<JitOpcode<'static>> {
+        let body = core::mem::take(&mut self.ops);
+        let flushes = core::mem::take(&mut self.flushes);
+        // The pc, address and value of each half of each instruction,
+        // grouped by page in the order met
+        let mut pages: Vec<Vec<(u64, u64, u16)>> = Vec::new();
+        for (pc, code) in core::mem::take(&mut self.code) {
+            let halves = match code & 3 {
+                3 => [Some(code as u16), Some((code >> 16) as u16)],
+                _ => [Some(code as u16), None],
+            };
+            for (i, half) in halves.into_iter().enumerate() {
+                let Some(half) = half else { continue };
+                let a = pc.wrapping_add(2 * i as u64);
+                match pages.iter_mut().find(|p| p[0].1 >> 12 == a >> 12) {
+                    Some(halves) => halves.push((pc, a, half)),
+                    None => pages.push(alloc::vec![(pc, a, half)]),
+                }
+            }
+        }
+        let regs = Operator::LocalGet {
+            local_index: self.ctx.regs(),
+        };
+        for halves in pages {
+            let (pc, a, _) = halves[0];
+            let page = self.scratch32(0);
+            // A fault finding the page is the instruction's
+            self.extend([
+                regs.clone(),
+                Operator::I64Const { value: pc as i64 },
+                Operator::I64Store {
+                    memarg: self.memarg(offset::PC, 3),
+                },
+                Operator::I64Const { value: a as i64 },
+                Operator::I32Const {
+                    value: import::EXECUTE,
+                },
+            ]);
+            self.push(JitOpcode::Call {
+                import: import::PAGE,
+            });
+            self.push(Operator::LocalSet { local_index: page });
+            for (i, &(_, a, half)) in halves.iter().enumerate() {
+                self.extend([
+                    Operator::LocalGet { local_index: page },
+                    Operator::I32Load16U {
+                        memarg: self.memarg(a & 0xfff, 0),
+                    },
+                    Operator::I32Const { value: half as i32 },
+                    Operator::I32Ne,
+                ]);
+                if i > 0 {
+                    self.push(Operator::I32Or);
+                }
+            }
+            self.extend([
+                Operator::If {
+                    blockty: BlockType::Empty,
+                },
+                Operator::I64Const { value: root as i64 },
+            ]);
+            self.push(JitOpcode::Call {
+                import: import::DROP_BLOCK,
+            });
+            self.extend([
+                Operator::I64Const { value: root as i64 },
+                Operator::Return,
+                Operator::End,
+            ]);
+        }
+        let cached = |mask: u32| (1..32).filter(move |r| mask & 1 << r != 0);
+        for r in cached(self.used) {
+            self.extend([
+                regs.clone(),
+                Operator::I64Load {
+                    memarg: self.memarg(offset::X + 8 * r as u64, 3),
+                },
+                Operator::LocalSet {
+                    local_index: self.ctx.reg_local(Reg(r)),
+                },
+            ]);
+        }
+        let mut sync = Vec::new();
+        for r in cached(self.dirty) {
+            sync.extend([
+                regs.clone(),
+                Operator::LocalGet {
+                    local_index: self.ctx.reg_local(Reg(r)),
+                },
+                Operator::I64Store {
+                    memarg: self.memarg(offset::X + 8 * r as u64, 3),
+                },
+            ]);
+        }
+        let mut flushes = flushes.into_iter().peekable();
+        for (i, op) in body.into_iter().enumerate() {
+            while flushes.next_if_eq(&i).is_some() {
+                self.extend(sync.iter().cloned());
+            }
+            self.push(op);
+        }
+        self.ops
+    }
+
+    /// Store the written registers back to the register file here, before
+    /// anything that leaves the block or lets the host look at it
+    ///
+    /// Which registers those are is only known once the whole block is
+    /// emitted, so [`finish`](Ops::finish) fills these in.
+    pub fn flush(&mut self) {
+        self.flushes.push(self.ops.len());
+    }
+
+    /// Call `import`, flushing first
+    pub fn call(&mut self, import: &'static str) {
+        self.flush();
+        self.push(JitOpcode::Call { import });
+    }
+
+    pub fn push(&mut self, op: impl Into<JitOpcode<'static>>) {
+        self.ops.push(op.into());
+    }
+
+    /// The `i`th scratch `i64` local, shared by every instruction in the block
+    pub fn scratch(&mut self, i: usize) -> u32 {
+        while self.scratch.len() <= i {
+            let l = self.ctx.local(ValType::I64);
+            self.scratch.push(l);
+        }
+        self.scratch[i]
+    }
+
+    /// The `i`th scratch `i32` local, as [`scratch`](Ops::scratch)
+    pub fn scratch32(&mut self, i: usize) -> u32 {
+        while self.scratch32.len() <= i {
+            let l = self.ctx.local(ValType::I32);
+            self.scratch32.push(l);
+        }
+        self.scratch32[i]
+    }
+
+    /// Mark where the code for the guest instruction at `pc` starts
+    pub fn pc(&mut self, pc: u64) {
+        self.ctx.mark_pc(pc);
+        self.push(JitOpcode::Pc { pc });
+    }
+
+    pub(crate) fn memarg(&self, offset: u64, align: u8) -> MemArg {
+        MemArg {
+            align,
+            max_align: align,
+            offset,
+            memory: self.ctx.memory(),
+        }
+    }
+}
+
+impl<T: Into<JitOpcode<'static>>> Extend<T> for Ops<'_> {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        self.ops.extend(iter.into_iter().map(Into::into));
+    }
+}
+
+/// Push the value of `r`, from its local
+pub(crate) fn get(out: &mut Ops, r: Reg) {
+    match r.0 & 31 {
+        0 => out.push(Operator::I64Const { value: 0 }),
+        r => {
+            out.used |= 1 << r;
+            out.push(Operator::LocalGet {
+                local_index: out.ctx.reg_local(Reg(r)),
+            });
+        }
+    }
+}
+
+/// Set `r` to the `i64` that `value` pushes
+///
+/// The value is computed even for `x0`, as a load into it may still fault.
+pub(crate) fn set(out: &mut Ops, r: Reg, value: impl FnOnce(&mut Ops)) {
+    match r.0 & 31 {
+        0 => {
+            value(out);
+            out.push(Operator::Drop);
+        }
+        r => {
+            out.used |= 1 << r;
+            out.dirty |= 1 << r;
+            value(out);
+            out.push(Operator::LocalSet {
+                local_index: out.ctx.reg_local(Reg(r)),
+            });
+        }
+    }
+}
+
+/// Leave the block, continuing at `pc`
+pub(crate) fn exit(out: &mut Ops, pc: u64) {
+    out.flush();
+    out.extend([Operator::I64Const { value: pc as i64 }, Operator::Return]);
+}
+
+/// Call `import` and leave the block where it says
+pub(crate) fn call_exit(out: &mut Ops, args: &[i64], import: &'static str) {
+    out.extend(args.iter().map(|&value| Operator::I64Const { value }));
+    out.call(import);
+    out.push(Operator::Return);
+}
+
+/// The checks every instruction starts with, as the JavaScript prologue
+///
+/// Take a due interrupt, note the pc for faults, then count the instruction
+/// as started. Its code, `fetched`, is checked as the block is entered.
+pub(crate) fn prologue(out: &mut Ops, pc: u64, fetched: u32) {
+    let pc = pc as i64;
+    let regs = Operator::LocalGet {
+        local_index: out.ctx.regs(),
+    };
+    let memarg = out.memarg(0, 3);
+    let load = |offset| Operator::I64Load {
+        memarg: MemArg { offset, ..memarg },
+    };
+    let store = |offset| Operator::I64Store {
+        memarg: MemArg { offset, ..memarg },
+    };
+    out.pc(pc as u64);
+    out.code.push((pc as u64, fetched));
+    out.extend([
+        regs.clone(),
+        load(offset::INSTRET),
+        regs.clone(),
+        load(offset::DEADLINE),
+    ]);
+    out.extend([
+        Operator::I64GeU,
+        Operator::If {
+            blockty: BlockType::Empty,
+        },
+    ]);
+    call_exit(out, &[pc], import::INTERRUPT);
+    out.push(Operator::End);
+    out.extend([
+        regs.clone(),
+        Operator::I64Const { value: pc },
+        store(offset::PC),
+    ]);
+    out.extend([regs.clone(), regs, load(offset::INSTRET)]);
+    out.extend([
+        Operator::I64Const { value: 1 },
+        Operator::I64Add,
+        store(offset::INSTRET),
+    ]);
+}
+
+/// The width of a load or store, and for loads how to extend what it reads
+#[derive(Clone, Copy)]
+pub(crate) struct Width {
+    pub size: u8,
+    pub signed: bool,
+}
+
+/// Push the address of the byte at the guest address in the `i64` local
+/// `a` if it and the `size - 1` after it are in one page of guest RAM,
+/// else 0
+///
+/// Pages come from the register file's [`TLB`](offset::TLB) for `access`,
+/// asking `PAGE` on a miss. With `aligned`, misaligned addresses give 0 too,
+/// for the host to raise.
+fn page(out: &mut Ops, a: u32, size: u8, access: i32, aligned: bool) {
+    use Operator as O;
+    let [n, entry, p] = [out.scratch(2), out.scratch32(0), out.scratch32(1)];
+    let regs = out.ctx.regs();
+    let tlb = offset::TLB + access as u64 * offset::TLB_ENTRIES * 16;
+    let (tag, at) = (out.memarg(tlb, 3), out.memarg(tlb + 8, 2));
+    out.extend([
+        O::LocalGet { local_index: a },
+        O::I64Const { value: 0xfff },
+        O::I64And,
+        O::I64Const {
+            value: 4096 - size as i64,
+        },
+        O::I64LeU,
+    ]);
+    if aligned {
+        out.extend([
+            O::LocalGet { local_index: a },
+            O::I64Const {
+                value: size as i64 - 1,
+            },
+            O::I64And,
+            O::I64Eqz,
+            O::I32And,
+        ]);
+    }
+    out.extend([
+        O::If {
+            blockty: BlockType::Type(ValType::I32),
+        },
+        O::LocalGet { local_index: a },
+        O::I64Const { value: 12 },
+        O::I64ShrU,
+        O::LocalSet { local_index: n },
+        O::LocalGet { local_index: n },
+        O::I64Const {
+            value: offset::TLB_ENTRIES as i64 - 1,
+        },
+        O::I64And,
+        O::I32WrapI64,
+        O::I32Const { value: 4 },
+        O::I32Shl,
+        O::LocalGet { local_index: regs },
+        O::I32Add,
+        O::LocalTee { local_index: entry },
+        O::I64Load { memarg: tag },
+        O::LocalGet { local_index: n },
+        O::I64Ne,
+        O::If {
+            blockty: BlockType::Empty,
+        },
+        O::LocalGet { local_index: entry },
+        O::LocalGet { local_index: n },
+        O::I64Store { memarg: tag },
+        O::LocalGet { local_index: a },
+        O::I32Const { value: access },
+    ]);
+    out.call(import::PAGE);
+    out.extend([
+        O::LocalSet { local_index: p },
+        O::LocalGet { local_index: entry },
+        O::LocalGet { local_index: p },
+        O::I32Store { memarg: at },
+        O::End,
+        O::LocalGet { local_index: entry },
+        O::I32Load { memarg: at },
+        O::Else,
+        O::I32Const { value: 0 },
+        O::End,
+    ]);
+    // The byte in the page
+    out.extend([
+        O::LocalTee { local_index: p },
+        O::If {
+            blockty: BlockType::Type(ValType::I32),
+        },
+        O::LocalGet { local_index: p },
+        O::LocalGet { local_index: a },
+        O::I32WrapI64,
+        O::I32Const { value: 0xfff },
+        O::I32And,
+        O::I32Add,
+        O::Else,
+        O::I32Const { value: 0 },
+        O::End,
+    ]);
+}
+
+/// Push the `width` bytes at the guest address `address` pushes,
+/// extended to an `i64`
+///
+/// RAM is read directly, anything else through `load`.
+pub(crate) fn load(out: &mut Ops, width: Width, aligned: bool, address: impl FnOnce(&mut Ops)) {
+    use Operator as O;
+    let [a, p] = [out.scratch(0), out.scratch32(1)];
+    address(out);
+    out.push(O::LocalSet { local_index: a });
+    page(out, a, width.size, import::READ, aligned);
+    let memarg = out.memarg(0, 0);
+    let read = match width.size {
+        1 => O::I64Load8U { memarg },
+        2 => O::I64Load16U { memarg },
+        4 => O::I64Load32U { memarg },
+        _ => O::I64Load { memarg },
+    };
+    out.extend([
+        O::LocalTee { local_index: p },
+        O::If {
+            blockty: BlockType::Type(ValType::I64),
+        },
+        O::LocalGet { local_index: p },
+        read,
+        O::Else,
+        O::LocalGet { local_index: a },
+        O::I32Const {
+            value: width.size as i32,
+        },
+    ]);
+    out.call(import::LOAD);
+    out.push(O::End);
+    match (width.signed, width.size) {
+        (true, 1) => out.push(O::I64Extend8S),
+        (true, 2) => out.push(O::I64Extend16S),
+        (true, 4) => out.push(O::I64Extend32S),
+        _ => {}
+    }
+}
+
+/// Store the low `size` bytes of the `i64` `value` pushes at the guest
+/// address `address` pushes, like [`load`]
+pub(crate) fn store(
+    out: &mut Ops,
+    size: u8,
+    aligned: bool,
+    address: impl FnOnce(&mut Ops),
+    value: impl FnOnce(&mut Ops),
+) {
+    use Operator as O;
+    let [a, v, p] = [out.scratch(0), out.scratch(1), out.scratch32(1)];
+    address(out);
+    out.push(O::LocalSet { local_index: a });
+    value(out);
+    out.push(O::LocalSet { local_index: v });
+    page(out, a, size, import::WRITE, aligned);
+    let memarg = out.memarg(0, 0);
+    let write = match size {
+        1 => O::I64Store8 { memarg },
+        2 => O::I64Store16 { memarg },
+        4 => O::I64Store32 { memarg },
+        _ => O::I64Store { memarg },
+    };
+    out.extend([
+        O::LocalTee { local_index: p },
+        O::If {
+            blockty: BlockType::Empty,
+        },
+        O::LocalGet { local_index: p },
+        O::LocalGet { local_index: v },
+        write,
+        O::Else,
+        O::LocalGet { local_index: a },
+        O::I32Const { value: size as i32 },
+        O::LocalGet { local_index: v },
+    ]);
+    out.call(import::STORE);
+    out.push(O::End);
+}
+
+/// Why [`ModuleBuilder::finish`] failed; each is a bug in a template
+#[derive(Debug)]
+pub enum AssemblyError {
+    /// An operator `wasm-encoder` cannot encode
+    Encode(Error),
+    /// A branch to a label no enclosing loop was opened for
+    Label(u64),
+    /// A call to a host function the reactor does not provide
+    Import(String),
+}
+
+impl Display for AssemblyError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            AssemblyError::Encode(e) => write!(f, "cannot encode operator: {e}"),
+            AssemblyError::Label(l) => write!(f, "branch to unopened label {l:#x}"),
+            AssemblyError::Import(name) => write!(f, "no such host function: {name}"),
+        }
+    }
+}
+
+/// An assembled module
+pub struct Assembled {
+    pub bytes: Vec<u8>,
+    /// For each block, in the order added, where the code for each guest
+    /// instruction starts, as byte offsets into the function body
+    pub pcs: Vec<Vec<(u32, u64)>>,
+}
+
+/// Assembles compiled blocks into one module
+///
+/// The module imports the memory and every [host function](import) from
+/// `v`, then each block it calls but does not define from `b` by its pc in
+/// decimal. It exports its own blocks by pc the same way.
+#[derive(Default)]
+pub struct ModuleBuilder<'a> {
+    blocks: Vec<(u64, Vec<ValType>, Vec<JitOpcode<'a>>)>,
+}
+
+/// The type of blocks, first in the type section
+const BLOCK_TYPE: u32 = 0;
+
+impl<'a> ModuleBuilder<'a> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add `ops`, compiled with `ctx`, as the block at `pc`
+    pub fn block(&mut self, pc: u64, ctx: &BlockCtx, ops: impl IntoIterator<Item = JitOpcode<'a>>) {
+        self.blocks
+            .push((pc, ctx.locals(), ops.into_iter().collect()));
+    }
+
+    /// Resolve labels, imports and blocks, and encode the module
+    pub fn finish(&self) -> Result<Assembled, AssemblyError> {
+        let hosts = import::ALL.len();
+        let mut imported: Vec<u64> = Vec::new();
+        for op in self.blocks.iter().flat_map(|(_, _, ops)| ops) {
+            match op {
+                JitOpcode::CallBlock { pc }
+                    if !imported.contains(pc) && self.blocks.iter().all(|(b, _, _)| b != pc) =>
+                {
+                    imported.push(*pc)
+                }
+                _ => {}
+            }
+        }
+        let mut index = BTreeMap::new();
+        let own = self.blocks.iter().map(|(pc, _, _)| pc);
+        for (i, pc) in imported.iter().chain(own).enumerate() {
+            index.entry(*pc).or_insert((hosts + i) as u32);
+        }
+
+        use wasm_encoder::ValType as V;
+        let mut types = TypeSection::new();
+        types.ty().function([V::I32], [V::I64]);
+        let mut imports = ImportSection::new();
+        imports.import(
+            "v",
+            "memory",
+            MemoryType {
+                minimum: 1,
+                maximum: None,
+                memory64: false,
+                shared: false,
+                page_size_log2: None,
+            },
+        );
+        for (i, name) in import::ALL.into_iter().enumerate() {
+            let (params, results) = import::signature(name).unwrap_or_default();
+            // Each host function has its own type, after the block's
+            types
+                .ty()
+                .function(params.iter().copied(), results.iter().copied());
+            imports.import("v", name, EntityType::Function(i as u32 + 1));
+        }
+        for pc in &imported {
+            imports.import("b", &pc.to_string(), EntityType::Function(BLOCK_TYPE));
+        }
+
+        let mut functions = FunctionSection::new();
+        let mut exports = ExportSection::new();
+        let mut code = CodeSection::new();
+        let mut pcs = Vec::new();
+        for (pc, locals, ops) in &self.blocks {
+            let locals = locals
+                .iter()
+                .map(|&ty| Ok((1, RoundtripReencoder.val_type(ty)?)))
+                .collect::<Result<Vec<_>, Error>>()
+                .map_err(AssemblyError::Encode)?;
+            let mut f = Function::new(locals);
+            let mut map = Vec::new();
+            // The label of each enclosing block, if it is a loop opened for one
+            let mut frames: Vec<Option<u64>> = Vec::new();
+            for op in ops {
+                let instruction = match op {
+                    JitOpcode::Operator { op } => {
+                        match op {
+                            Operator::Block { .. }
+                            | Operator::Loop { .. }
+                            | Operator::If { .. } => frames.push(None),
+                            Operator::End => {
+                                frames.pop();
+                            }
+                            _ => {}
+                        }
+                        RoundtripReencoder
+                            .instruction(op.clone())
+                            .map_err(AssemblyError::Encode)?
+                    }
+                    JitOpcode::Loop { label } => {
+                        frames.push(Some(*label));
+                        Instruction::Loop(wasm_encoder::BlockType::Empty)
+                    }
+                    JitOpcode::Br { label } => {
+                        let depth = frames.iter().rev().position(|l| *l == Some(*label));
+                        Instruction::Br(depth.ok_or(AssemblyError::Label(*label))? as u32)
+                    }
+                    JitOpcode::Call { import } => match import::index(import) {
+                        Some(i) => Instruction::Call(i),
+                        None => return Err(AssemblyError::Import(import.to_string())),
+                    },
+                    JitOpcode::CallBlock { pc } => Instruction::Call(index[pc]),
+                    JitOpcode::Pc { pc } => {
+                        map.push((f.byte_len() as u32, *pc));
+                        continue;
+                    }
+                };
+                f.instruction(&instruction);
+            }
+            // The operators end in a loop, which control never falls out of
+            f.instruction(&Instruction::Unreachable);
+            f.instruction(&Instruction::End);
+            functions.function(BLOCK_TYPE);
+            code.function(&f);
+            exports.export(&pc.to_string(), ExportKind::Func, index[pc]);
+            pcs.push(map);
+        }
+
+        let mut module = Module::new();
+        module
+            .section(&types)
+            .section(&imports)
+            .section(&functions)
+            .section(&exports)
+            .section(&code);
+        Ok(Assembled {
+            bytes: module.finish(),
+            pcs,
+        })
+    }
+}
@@ -0,0 +1,209 @@
+//! Trap causes and privilege levels.
+//!
+//! Trap entry and `mret` update the CSR file (see [`CsrFile::trap`]); both
+//! engines go through the reactor's trap dispatch, which consults the
+//! embedder's handlers before delivering to the guest. An `ebreak` goes to
+//! the host's break hook first, if one is set, which answers with a
+//! [`BreakAction`].
+//!
+//! [`CsrFile::trap`]: crate::csr::CsrFile::trap
+use core::fmt::{self, Display, Formatter};
+
+use rv_asm::Xlen;
+
+/// A privilege level, numbered as in `mstatus.MPP`
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Default)]
+pub enum Priv {
+    User = 0,
+    Supervisor = 1,
+    #[default]
+    Machine = 3,
+}
+
+impl Priv {
+    /// Decode a two-bit privilege field; the reserved value 2 reads as user
+    pub fn from_bits(bits: u64) -> Priv {
+        match bits & 3 {
+            3 => Priv::Machine,
+            1 => Priv::Supervisor,
+            _ => Priv::User,
+        }
+    }
+}
+
+/// Synchronous exception codes, as written to `mcause`
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+#[non_exhaustive]
+pub enum Exception {
+    InstructionMisaligned = 0,
+    InstructionAccessFault = 1,
+    IllegalInstruction = 2,
+    Breakpoint = 3,
+    LoadMisaligned = 4,
+    LoadAccessFault = 5,
+    StoreMisaligned = 6,
+    StoreAccessFault = 7,
+    EcallU = 8,
+    EcallS = 9,
+    EcallM = 11,
+    InstructionPageFault = 12,
+    LoadPageFault = 13,
+    StorePageFault = 15,
+}
+
+impl Exception {
+    /// The environment call raised from `mode`
+    pub fn ecall(mode: Priv) -> Exception {
+        match mode {
+            Priv::User => Exception::EcallU,
+            Priv::Supervisor => Exception::EcallS,
+            Priv::Machine => Exception::EcallM,
+        }
+    }
+    pub fn from_code(code: u64) -> Option<Exception> {
+        use Exception::*;
+        Some(match code {
+            0 => InstructionMisaligned,
+            1 => InstructionAccessFault,
+            2 => IllegalInstruction,
+            3 => Breakpoint,
+            4 => LoadMisaligned,
+            5 => LoadAccessFault,
+            6 => StoreMisaligned,
+            7 => StoreAccessFault,
+            8 => EcallU,
+            9 => EcallS,
+            11 => EcallM,
+            12 => InstructionPageFault,
+            13 => LoadPageFault,
+            15 => StorePageFault,
+            _ => return None,
+        })
+    }
+    pub fn is_ecall(self) -> bool {
+        matches!(self, Exception::EcallU | Exception::EcallS | Exception::EcallM)
+    }
+}
+
+/// Interrupt codes, as written to `mcause` with the interrupt bit set
+///
+/// Listed from highest priority to lowest.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum Interrupt {
+    MachineExternal = 11,
+    MachineSoftware = 3,
+    MachineTimer = 7,
+    SupervisorExternal = 9,
+    SupervisorSoftware = 1,
+    SupervisorTimer = 5,
+}
+
+impl Interrupt {
+    /// Every interrupt, highest priority first
+    pub const ALL: [Interrupt; 6] = [
+        Interrupt::MachineExternal,
+        Interrupt::MachineSoftware,
+        Interrupt::MachineTimer,
+        Interrupt::SupervisorExternal,
+        Interrupt::SupervisorSoftware,
+        Interrupt::SupervisorTimer,
+    ];
+    pub fn from_code(code: u64) -> Option<Interrupt> {
+        Interrupt::ALL.into_iter().find(|i| *i as u64 == code)
+    }
+    /// The interrupt's bit in `mip` and `mie`
+    pub fn bit(self) -> u64 {
+        1 << self as u64
+    }
+}
+
+/// What a host break hook asks for after stopping execution
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum BreakAction {
+    /// Go on past the `ebreak`, or with the instruction a step stopped at
+    Resume,
+    /// Run one instruction, then stop for the hook again
+    Step,
+    /// End the run
+    Abort,
+}
+
+impl BreakAction {
+    /// Parse `"resume"`, `"step"` or `"abort"`
+    pub fn from_name(name: &str) -> Option<BreakAction> {
+        Some(match name {
+            "resume" => BreakAction::Resume,
+            "step" => BreakAction::Step,
+            "abort" => BreakAction::Abort,
+            _ => return None,
+        })
+    }
+}
+
+/// A pending trap
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct Trap {
+    /// Exception or interrupt code
+    pub code: u64,
+    pub interrupt: bool,
+    /// Written to `mtval`
+    pub tval: u64,
+}
+
+impl Trap {
+    pub fn exception(e: Exception, tval: u64) -> Trap {
+        Trap {
+            code: e as u64,
+            interrupt: false,
+            tval,
+        }
+    }
+    pub fn interrupt(i: Interrupt) -> Trap {
+        Trap {
+            code: i as u64,
+            interrupt: true,
+            tval: 0,
+        }
+    }
+    /// An illegal instruction, reporting its encoding in `mtval`
+    ///
+    /// Only the low 16 bits are reported for a compressed encoding.
+    pub fn illegal(code: u32) -> Trap {
+        let code = match code & 3 {
+            3 => code,
+            _ => code & 0xffff,
+        };
+        Trap::exception(Exception::IllegalInstruction, code as u64)
+    }
+    pub fn as_exception(&self) -> Option<Exception> {
+        match self.interrupt {
+            true => None,
+            false => Exception::from_code(self.code),
+        }
+    }
+    /// The value of `mcause`
+    pub fn cause(&self, xlen: Xlen) -> u64 {
+        let top = match xlen {
+            Xlen::Rv32 => 1 << 31,
+            Xlen::Rv64 => 1 << 63,
+        };
+        match self.interrupt {
+            true => self.code | top,
+            false => self.code,
+        }
+    }
+}
+
+impl Display for Trap {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self.as_exception() {
+            Some(e) => write!(f, "{e:?}")?,
+            None => match Interrupt::from_code(self.code) {
+                Some(i) if self.interrupt => write!(f, "{i:?}")?,
+                _ if self.interrupt => write!(f, "interrupt {}", self.code)?,
+                _ => write!(f, "exception {}", self.code)?,
+            },
+        }
+        write!(f, " (tval {:#x})", self.tval)
+    }
+}
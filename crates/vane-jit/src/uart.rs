@@ -0,0 +1,149 @@
+//! An NS16550A UART, as on QEMU's `virt` board.
+//!
+//! Registers are byte-wide with no spacing. Transmitted bytes go straight
+//! to [`Uart::output`] for the host to drain, so the transmitter is always
+//! empty; received bytes are queued by the host with [`Uart::input`]. The
+//! divisor latch, line control and scratch registers only hold their values.
+use alloc::collections::VecDeque;
+use alloc::vec::Vec;
+
+/// Receiver buffer / transmitter holding register; divisor low with `LCR.DLAB`
+pub const RBR: u64 = 0;
+/// Interrupt enable; divisor high with `LCR.DLAB`
+pub const IER: u64 = 1;
+/// Interrupt identification on read, FIFO control on write
+pub const IIR: u64 = 2;
+pub const LCR: u64 = 3;
+pub const MCR: u64 = 4;
+pub const LSR: u64 = 5;
+pub const MSR: u64 = 6;
+pub const SCR: u64 = 7;
+
+/// `IER` bits
+pub mod ier {
+    /// Received data available
+    pub const ERBFI: u8 = 1 << 0;
+    /// Transmitter holding register empty
+    pub const ETBEI: u8 = 1 << 1;
+}
+
+/// `LSR` bits
+pub mod lsr {
+    pub const DR: u8 = 1 << 0;
+    pub const THRE: u8 = 1 << 5;
+    pub const TEMT: u8 = 1 << 6;
+}
+
+const LCR_DLAB: u8 = 1 << 7;
+const MCR_LOOP: u8 = 1 << 4;
+
+#[derive(Clone, Default, Debug)]
+pub struct Uart {
+    pub ier: u8,
+    pub lcr: u8,
+    pub mcr: u8,
+    pub scr: u8,
+    /// The divisor latch, low byte first
+    pub divisor: [u8; 2],
+    /// `FCR` bit 0; reported in the top bits of `IIR`
+    pub fifo: bool,
+    /// The transmitter-empty interrupt is raised until `IIR` reports it
+    thre: bool,
+    rx: VecDeque<u8>,
+    /// Transmitted bytes the host has not taken yet
+    pub output: Vec<u8>,
+}
+
+impl Uart {
+    /// Queue bytes for the guest to receive
+    pub fn input(&mut self, bytes: &[u8]) {
+        self.rx.extend(bytes);
+    }
+
+    /// Whether the interrupt output is high
+    pub fn interrupt(&self) -> bool {
+        self.iir() & 1 == 0
+    }
+
+    fn iir(&self) -> u8 {
+        let id = match () {
+            _ if self.ier & ier::ERBFI != 0 && !self.rx.is_empty() => 0x4,
+            _ if self.ier & ier::ETBEI != 0 && self.thre => 0x2,
+            _ => 0x1,
+        };
+        match self.fifo {
+            true => id | 0xc0,
+            false => id,
+        }
+    }
+
+    /// Read register `offset`, which may pop a received byte or
+    /// acknowledge the transmitter interrupt
+    pub fn read(&mut self, offset: u64) -> u8 {
+        let dlab = self.lcr & LCR_DLAB != 0;
+        match offset {
+            RBR if dlab => self.divisor[0],
+            RBR => self.rx.pop_front().unwrap_or(0),
+            IER if dlab => self.divisor[1],
+            IER => self.ier,
+            IIR => {
+                let iir = self.iir();
+                if iir & 0xf == 0x2 {
+                    self.thre = false;
+                }
+                iir
+            }
+            LCR => self.lcr,
+            MCR => self.mcr,
+            LSR => {
+                let dr = match self.rx.is_empty() {
+                    true => 0,
+                    false => lsr::DR,
+                };
+                lsr::THRE | lsr::TEMT | dr
+            }
+            // In loopback the modem inputs follow the outputs; otherwise
+            // carrier, data set ready and clear to send are all up
+            MSR if self.mcr & MCR_LOOP != 0 => {
+                let m = self.mcr;
+                (m & 0x8) << 4 | (m & 0x4) << 4 | (m & 0x1) << 5 | (m & 0x2) << 3
+            }
+            MSR => 0xb0,
+            SCR => self.scr,
+            _ => 0,
+        }
+    }
+
+    pub fn write(&mut self, offset: u64, value: u8) {
+        let dlab = self.lcr & LCR_DLAB != 0;
+        match offset {
+            RBR if dlab => self.divisor[0] = value,
+            RBR => {
+                match self.mcr & MCR_LOOP != 0 {
+                    true => self.rx.push_back(value),
+                    false => self.output.push(value),
+                }
+                self.thre = true;
+            }
+            IER if dlab => self.divisor[1] = value,
+            IER => {
+                // Enabling the transmitter interrupt raises it, the holding
+                // register being empty
+                if value & !self.ier & ier::ETBEI != 0 {
+                    self.thre = true;
+                }
+                self.ier = value & 0xf;
+            }
+            IIR => {
+                self.fifo = value & 1 != 0;
+                if value & 2 != 0 {
+                    self.rx.clear();
+                }
+            }
+            LCR => self.lcr = value,
+            MCR => self.mcr = value & 0x1f,
+            SCR => self.scr = value,
+            _ => {}
+        }
+    }
+}
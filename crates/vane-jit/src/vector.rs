@@ -0,0 +1,1479 @@
+//! The V extension (RVV 1.0): vector state and shared semantics.
+//!
+//! As with F and D, both engines execute vector instructions through one
+//! function, [`exec`]: the interpreter and generated code pass the encoding
+//! and its scalar operands to the reactor, which owns the [`Vector`] state.
+//!
+//! The register file is a flat array of `32 * VLEN / 8` bytes, so a register
+//! group is a run of consecutive registers and element `i` of the group at
+//! `vd` sits `i * SEW / 8` bytes past the start of `vd`.
+//!
+//! Tail and inactive elements are always left undisturbed, which both the
+//! agnostic and undisturbed policies allow. Integer, fixed-point, mask and
+//! permutation instructions are implemented; vector floating point is not,
+//! and does not decode.
+use alloc::vec;
+use alloc::vec::Vec;
+use core::fmt::{self, Display, Formatter};
+
+use rv_asm::{Reg, Xlen};
+
+use crate::csr::{CsrError, VCSR, VL, VLENB, VSTART, VTYPE, VXRM, VXSAT};
+use crate::mmu::{transfer, Access};
+use crate::trap::Trap;
+use crate::xlen_mask;
+
+/// `vill` as kept in [`Vector::vtype`], whatever the XLEN
+const VILL: u64 = 1 << 63;
+
+/// A supported `vtype` setting
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct VType {
+    /// Element width in bits
+    pub sew: u32,
+    /// LMUL in eighths, from 1 for `mf8` to 64 for `m8`
+    pub lmul8: u32,
+    /// Tail agnostic
+    pub ta: bool,
+    /// Mask agnostic
+    pub ma: bool,
+}
+
+impl VType {
+    /// Decode a `vtype` value, or `None` if it sets `vill` or reserved bits
+    pub fn decode(v: u64) -> Option<VType> {
+        if v >> 8 != 0 {
+            return None;
+        }
+        let sew = 8 << (v >> 3 & 7);
+        let lmul8 = match v & 7 {
+            4 => return None,
+            n @ 0..=3 => 8 << n,
+            n => 1 << (n - 5),
+        };
+        // SEW may not exceed ELEN (64) times LMUL
+        if sew > 64 || sew * 8 > 64 * lmul8 {
+            return None;
+        }
+        Some(VType {
+            sew: sew as u32,
+            lmul8: lmul8 as u32,
+            ta: v >> 6 & 1 == 1,
+            ma: v >> 7 & 1 == 1,
+        })
+    }
+
+    /// Elements in a register group for a VLEN of `vlen` bits
+    pub fn vlmax(self, vlen: u32) -> u64 {
+        vlen as u64 * self.lmul8 as u64 / 8 / self.sew as u64
+    }
+}
+
+impl Display for VType {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "e{}, ", self.sew)?;
+        match self.lmul8 {
+            l @ 1..=4 => write!(f, "mf{}", 8 / l)?,
+            l => write!(f, "m{}", l / 8)?,
+        }
+        let policy = |agnostic| if agnostic { 'a' } else { 'u' };
+        write!(f, ", t{}, m{}", policy(self.ta), policy(self.ma))
+    }
+}
+
+/// The vector register file and the vector CSRs
+#[derive(Clone, Debug)]
+pub struct Vector {
+    vlen: u32,
+    regs: Vec<u8>,
+    pub vl: u64,
+    /// Raw `vtype`, with `vill` in bit 63 whatever the XLEN
+    pub vtype: u64,
+    pub vstart: u64,
+    pub vxrm: u8,
+    pub vxsat: bool,
+}
+
+impl Default for Vector {
+    fn default() -> Self {
+        Vector::new(128)
+    }
+}
+
+impl Vector {
+    /// A zeroed vector unit with `vlen`-bit registers and `vill` set
+    ///
+    /// `vlen` must be a power of two from 64 to 65536.
+    pub fn new(vlen: u32) -> Vector {
+        assert!(
+            vlen.is_power_of_two() && (64..=65536).contains(&vlen),
+            "unsupported VLEN {vlen}"
+        );
+        Vector {
+            vlen,
+            regs: vec![0; 4 * vlen as usize],
+            vl: 0,
+            vtype: VILL,
+            vstart: 0,
+            vxrm: 0,
+            vxsat: false,
+        }
+    }
+
+    pub fn vlen(&self) -> u32 {
+        self.vlen
+    }
+
+    fn vlenb(&self) -> usize {
+        self.vlen as usize / 8
+    }
+
+    /// The bytes of register `n`, least significant first
+    pub fn reg(&self, n: u8) -> &[u8] {
+        let n = (n % 32) as usize;
+        &self.regs[n * self.vlenb()..][..self.vlenb()]
+    }
+
+    pub fn reg_mut(&mut self, n: u8) -> &mut [u8] {
+        let (n, len) = ((n % 32) as usize, self.vlenb());
+        &mut self.regs[n * len..][..len]
+    }
+
+    /// The current `vtype`, or `None` while `vill` is set
+    pub fn vtype(&self) -> Option<VType> {
+        VType::decode(self.vtype)
+    }
+
+    /// Read a vector CSR, or `None` if `csr` is not one
+    pub fn read_csr(&self, csr: u16, xlen: Xlen) -> Option<u64> {
+        Some(match csr {
+            VSTART => self.vstart,
+            VXSAT => self.vxsat as u64,
+            VXRM => self.vxrm as u64,
+            VCSR => (self.vxrm as u64) << 1 | self.vxsat as u64,
+            VL => self.vl,
+            VTYPE => match self.vtype & VILL {
+                0 => self.vtype,
+                _ => 1 << (xlen_mask(xlen).count_ones() - 1),
+            },
+            VLENB => self.vlenb() as u64,
+            _ => return None,
+        })
+    }
+
+    /// Write a vector CSR; `vl`, `vtype` and `vlenb` are read-only
+    pub fn write_csr(&mut self, csr: u16, value: u64) -> Result<(), CsrError> {
+        match csr {
+            // Wide enough for any element index
+            VSTART => self.vstart = value & (self.vlen as u64 - 1),
+            VXSAT => self.vxsat = value & 1 == 1,
+            VXRM => self.vxrm = (value & 3) as u8,
+            VCSR => {
+                self.vxsat = value & 1 == 1;
+                self.vxrm = (value >> 1 & 3) as u8;
+            }
+            VL | VTYPE | VLENB => return Err(CsrError::ReadOnly(csr)),
+            _ => return Err(CsrError::Missing(csr)),
+        }
+        Ok(())
+    }
+
+    /// Install `vtype` and set `vl` from `avl`, as `vsetvl` does
+    ///
+    /// An `avl` of `None` keeps the current `vl`. Returns the new `vl`.
+    pub fn set_vl(&mut self, avl: Option<u64>, vtype: u64) -> u64 {
+        self.vstart = 0;
+        match VType::decode(vtype) {
+            Some(t) => {
+                self.vtype = vtype;
+                self.vl = avl.unwrap_or(self.vl).min(t.vlmax(self.vlen));
+            }
+            None => {
+                self.vtype = VILL;
+                self.vl = 0;
+            }
+        }
+        self.vl
+    }
+}
+
+/// Element `i`, `w` bits wide, of the group starting at `reg`
+fn elem(regs: &[u8], vlenb: usize, reg: u8, i: u64, w: u32) -> u64 {
+    let at = reg as usize * vlenb + i as usize * (w / 8) as usize;
+    regs[at..at + (w / 8) as usize]
+        .iter()
+        .rev()
+        .fold(0, |v, &b| v << 8 | b as u64)
+}
+
+fn set_elem(regs: &mut [u8], vlenb: usize, reg: u8, i: u64, w: u32, v: u64) {
+    let at = reg as usize * vlenb + i as usize * (w / 8) as usize;
+    for (k, b) in regs[at..at + (w / 8) as usize].iter_mut().enumerate() {
+        *b = (v >> (8 * k)) as u8;
+    }
+}
+
+/// Bit `i` of mask register `reg`
+fn mask_bit(regs: &[u8], vlenb: usize, reg: u8, i: u64) -> bool {
+    regs[reg as usize * vlenb + (i / 8) as usize] >> (i % 8) & 1 == 1
+}
+
+fn set_mask_bit(regs: &mut [u8], vlenb: usize, reg: u8, i: u64, bit: bool) {
+    let b = &mut regs[reg as usize * vlenb + (i / 8) as usize];
+    *b = *b & !(1 << (i % 8)) | (bit as u8) << (i % 8);
+}
+
+/// Whether `reg` can start a group spanning `emul8` eighths of a register
+fn group(reg: u8, emul8: u32) -> bool {
+    let n = (emul8 / 8).max(1);
+    (1..=64).contains(&emul8) && (reg as u32).is_multiple_of(n) && reg as u32 + n <= 32
+}
+
+fn ones(w: u32) -> u64 {
+    u64::MAX >> (64 - w)
+}
+
+/// Sign-extend the low `w` bits of `v`
+fn sx(v: u64, w: u32) -> i64 {
+    ((v << (64 - w)) as i64) >> (64 - w)
+}
+
+/// `v >> d`, rounded as `vxrm` says
+fn roundoff(v: i128, d: u32, vxrm: u8) -> i128 {
+    if d == 0 {
+        return v;
+    }
+    let bit = |n: u32| v >> n & 1;
+    let below = |n: u32| v & ((1 << n) - 1) != 0;
+    let r = match vxrm {
+        // Round to nearest, ties up
+        0 => bit(d - 1),
+        // Round to nearest, ties to even
+        1 => bit(d - 1) & (below(d - 1) as i128 | bit(d)),
+        // Truncate
+        2 => 0,
+        // Round to odd
+        _ => (bit(d) == 0 && below(d)) as i128,
+    };
+    (v >> d) + r
+}
+
+/// Clamp `v` to `w` unsigned bits, noting whether it saturated
+fn sat_u(v: i128, w: u32, sat: &mut bool) -> u64 {
+    let max = ones(w) as i128;
+    *sat |= !(0..=max).contains(&v);
+    v.clamp(0, max) as u64
+}
+
+/// Clamp `v` to `w` signed bits, noting whether it saturated
+fn sat_s(v: i128, w: u32, sat: &mut bool) -> u64 {
+    let (min, max) = (-(1i128 << (w - 1)), (1i128 << (w - 1)) - 1);
+    *sat |= !(min..=max).contains(&v);
+    v.clamp(min, max) as u64 & ones(w)
+}
+
+/// How a vector load or store forms its addresses
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum VAddr {
+    /// Consecutive elements, or segments of `nf` fields
+    Unit,
+    /// Unit-stride load that shortens `vl` rather than trap past element 0
+    FaultFirst,
+    /// `vlm.v` / `vsm.v`: the `ceil(vl / 8)` bytes of a mask
+    Mask,
+    /// `vl<nf>r` / `vs<nf>r`: whole registers, whatever `vtype` and `vl`
+    Whole,
+    /// Elements `stride` bytes apart
+    Strided(Reg),
+    /// Byte offsets from the index register group `index`
+    Indexed { ordered: bool, index: u8 },
+}
+
+/// The application vector length operand of `vsetvl` and friends
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum Avl {
+    Reg(Reg),
+    Imm(u8),
+}
+
+/// The `vtype` operand of `vsetvl` and friends
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum VtypeSrc {
+    Reg(Reg),
+    Imm(u16),
+}
+
+/// The operand an arithmetic instruction takes alongside `vs2`
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum VSrc {
+    /// `.vv`: register `vs1`
+    V(u8),
+    /// `.vx`: integer register `rs1`
+    X(Reg),
+    /// `.vi`: the raw 5-bit immediate, signed or not as the operation says
+    I(u8),
+}
+
+/// A vector arithmetic, mask or permutation operation
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum VOp {
+    Add,
+    Sub,
+    Rsub,
+    Minu,
+    Min,
+    Maxu,
+    Max,
+    And,
+    Or,
+    Xor,
+    Rgather,
+    Rgatherei16,
+    Slideup,
+    Slidedown,
+    Adc,
+    Madc,
+    Sbc,
+    Msbc,
+    Merge,
+    /// `vmv.v.v`, `vmv.v.x` and `vmv.v.i`
+    Mv,
+    Mseq,
+    Msne,
+    Msltu,
+    Mslt,
+    Msleu,
+    Msle,
+    Msgtu,
+    Msgt,
+    Saddu,
+    Sadd,
+    Ssubu,
+    Ssub,
+    Sll,
+    Smul,
+    /// `vmv<nr>r.v`; the immediate holds `nr - 1`
+    MvNr,
+    Srl,
+    Sra,
+    Ssrl,
+    Ssra,
+    Nsrl,
+    Nsra,
+    Nclipu,
+    Nclip,
+    Wredsumu,
+    Wredsum,
+    Redsum,
+    Redand,
+    Redor,
+    Redxor,
+    Redminu,
+    Redmin,
+    Redmaxu,
+    Redmax,
+    Aaddu,
+    Aadd,
+    Asubu,
+    Asub,
+    Slide1up,
+    Slide1down,
+    /// `vmv.x.s`
+    MvXS,
+    /// `vmv.s.x`
+    MvSX,
+    Cpop,
+    First,
+    /// `vzext.vf<n>`
+    Zext(u8),
+    /// `vsext.vf<n>`
+    Sext(u8),
+    Msbf,
+    Msof,
+    Msif,
+    Iota,
+    Id,
+    Compress,
+    Mandn,
+    Mand,
+    Mor,
+    Mxor,
+    Morn,
+    Mnand,
+    Mnor,
+    Mxnor,
+    Divu,
+    Div,
+    Remu,
+    Rem,
+    Mulhu,
+    Mul,
+    Mulhsu,
+    Mulh,
+    Madd,
+    Nmsub,
+    Macc,
+    Nmsac,
+    Waddu,
+    Wadd,
+    Wsubu,
+    Wsub,
+    /// `vwaddu.w`, whose `vs2` is already double width
+    WadduW,
+    WaddW,
+    WsubuW,
+    WsubW,
+    Wmulu,
+    Wmulsu,
+    Wmul,
+    Wmaccu,
+    Wmacc,
+    Wmaccus,
+    Wmaccsu,
+}
+
+impl VOp {
+    /// The mnemonic without the `v` prefix or operand suffix
+    pub fn mnemonic(self) -> &'static str {
+        use VOp::*;
+        match self {
+            Add => "add",
+            Sub => "sub",
+            Rsub => "rsub",
+            Minu => "minu",
+            Min => "min",
+            Maxu => "maxu",
+            Max => "max",
+            And => "and",
+            Or => "or",
+            Xor => "xor",
+            Rgather => "rgather",
+            Rgatherei16 => "rgatherei16",
+            Slideup => "slideup",
+            Slidedown => "slidedown",
+            Adc => "adc",
+            Madc => "madc",
+            Sbc => "sbc",
+            Msbc => "msbc",
+            Merge => "merge",
+            Mv | MvXS | MvSX | MvNr => "mv",
+            Mseq => "mseq",
+            Msne => "msne",
+            Msltu => "msltu",
+            Mslt => "mslt",
+            Msleu => "msleu",
+            Msle => "msle",
+            Msgtu => "msgtu",
+            Msgt => "msgt",
+            Saddu => "saddu",
+            Sadd => "sadd",
+            Ssubu => "ssubu",
+            Ssub => "ssub",
+            Sll => "sll",
+            Smul => "smul",
+            Srl => "srl",
+            Sra => "sra",
+            Ssrl => "ssrl",
+            Ssra => "ssra",
+            Nsrl => "nsrl",
+            Nsra => "nsra",
+            Nclipu => "nclipu",
+            Nclip => "nclip",
+            Wredsumu => "wredsumu",
+            Wredsum => "wredsum",
+            Redsum => "redsum",
+            Redand => "redand",
+            Redor => "redor",
+            Redxor => "redxor",
+            Redminu => "redminu",
+            Redmin => "redmin",
+            Redmaxu => "redmaxu",
+            Redmax => "redmax",
+            Aaddu => "aaddu",
+            Aadd => "aadd",
+            Asubu => "asubu",
+            Asub => "asub",
+            Slide1up => "slide1up",
+            Slide1down => "slide1down",
+            Cpop => "cpop",
+            First => "first",
+            Zext(_) => "zext",
+            Sext(_) => "sext",
+            Msbf => "msbf",
+            Msof => "msof",
+            Msif => "msif",
+            Iota => "iota",
+            Id => "id",
+            Compress => "compress",
+            Mandn => "mandn",
+            Mand => "mand",
+            Mor => "mor",
+            Mxor => "mxor",
+            Morn => "morn",
+            Mnand => "mnand",
+            Mnor => "mnor",
+            Mxnor => "mxnor",
+            Divu => "divu",
+            Div => "div",
+            Remu => "remu",
+            Rem => "rem",
+            Mulhu => "mulhu",
+            Mul => "mul",
+            Mulhsu => "mulhsu",
+            Mulh => "mulh",
+            Madd => "madd",
+            Nmsub => "nmsub",
+            Macc => "macc",
+            Nmsac => "nmsac",
+            Waddu | WadduW => "waddu",
+            Wadd | WaddW => "wadd",
+            Wsubu | WsubuW => "wsubu",
+            Wsub | WsubW => "wsub",
+            Wmulu => "wmulu",
+            Wmulsu => "wmulsu",
+            Wmul => "wmul",
+            Wmaccu => "wmaccu",
+            Wmacc => "wmacc",
+            Wmaccus => "wmaccus",
+            Wmaccsu => "wmaccsu",
+        }
+    }
+
+    /// Whether a `.vi` form zero-extends its immediate
+    fn unsigned_imm(self) -> bool {
+        use VOp::*;
+        matches!(
+            self,
+            Sll | Srl | Sra | Ssrl | Ssra | Nsrl | Nsra | Nclipu | Nclip | Rgather | Slideup | Slidedown | MvNr
+        )
+    }
+}
+
+/// A vector instruction
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum VInst {
+    /// `vsetvli`, `vsetivli` and `vsetvl`
+    SetVl {
+        dest: Reg,
+        avl: Avl,
+        vtype: VtypeSrc,
+    },
+    /// Vector loads and stores
+    ///
+    /// `eew` is the element width in bits, or the index width for indexed
+    /// accesses; `nf` counts segment fields, or registers for [`VAddr::Whole`].
+    Mem {
+        store: bool,
+        addr: VAddr,
+        eew: u32,
+        nf: u8,
+        vm: bool,
+        /// `vd` for loads, `vs3` for stores
+        vd: u8,
+        base: Reg,
+    },
+    /// Every other instruction; `vm` is clear when masked by `v0`
+    Op {
+        op: VOp,
+        src: VSrc,
+        vm: bool,
+        vd: u8,
+        vs2: u8,
+    },
+}
+
+fn bits(code: u32, lo: u32, hi: u32) -> u32 {
+    (code >> lo) & ((1 << (hi - lo + 1)) - 1)
+}
+
+impl VInst {
+    /// Decode a vector load, store or OP-V instruction
+    pub fn decode(code: u32) -> Option<VInst> {
+        let (vd, rs1, vs2) = (bits(code, 7, 11) as u8, bits(code, 15, 19) as u8, bits(code, 20, 24) as u8);
+        let vm = bits(code, 25, 25) == 1;
+        let funct3 = bits(code, 12, 14);
+        match code & 0x7f {
+            0b0000111 | 0b0100111 => {
+                let store = code & 0x7f == 0b0100111;
+                let eew = match funct3 {
+                    0b000 => 8,
+                    0b101 => 16,
+                    0b110 => 32,
+                    0b111 => 64,
+                    _ => return None,
+                };
+                // `mew` selects EEWs above 64
+                if bits(code, 28, 28) == 1 {
+                    return None;
+                }
+                let nf = bits(code, 29, 31) as u8 + 1;
+                let addr = match (bits(code, 26, 27), vs2, store) {
+                    (0b00, 0b00000, _) => VAddr::Unit,
+                    (0b00, 0b10000, false) => VAddr::FaultFirst,
+                    (0b00, 0b01011, _) if vm && nf == 1 && eew == 8 => VAddr::Mask,
+                    (0b00, 0b01000, _) if vm && nf.is_power_of_two() && (!store || eew == 8) => VAddr::Whole,
+                    (0b10, _, _) => VAddr::Strided(Reg(vs2)),
+                    (0b01, _, _) => VAddr::Indexed {
+                        ordered: false,
+                        index: vs2,
+                    },
+                    (0b11, _, _) => VAddr::Indexed {
+                        ordered: true,
+                        index: vs2,
+                    },
+                    _ => return None,
+                };
+                Some(VInst::Mem {
+                    store,
+                    addr,
+                    eew,
+                    nf,
+                    vm,
+                    vd,
+                    base: Reg(rs1),
+                })
+            }
+            0b1010111 => Self::decode_op(code, funct3, vd, rs1, vs2, vm),
+            _ => None,
+        }
+    }
+
+    fn decode_op(code: u32, funct3: u32, vd: u8, rs1: u8, vs2: u8, vm: bool) -> Option<VInst> {
+        use VOp::*;
+        let funct6 = bits(code, 26, 31);
+        let (src, opm) = match funct3 {
+            0b000 => (VSrc::V(rs1), false),
+            0b011 => (VSrc::I(rs1), false),
+            0b100 => (VSrc::X(Reg(rs1)), false),
+            0b010 => (VSrc::V(rs1), true),
+            0b110 => (VSrc::X(Reg(rs1)), true),
+            0b111 => {
+                let dest = Reg(vd);
+                return Some(match bits(code, 30, 31) {
+                    0b00 | 0b01 => VInst::SetVl {
+                        dest,
+                        avl: Avl::Reg(Reg(rs1)),
+                        vtype: VtypeSrc::Imm(bits(code, 20, 30) as u16),
+                    },
+                    0b11 => VInst::SetVl {
+                        dest,
+                        avl: Avl::Imm(rs1),
+                        vtype: VtypeSrc::Imm(bits(code, 20, 29) as u16),
+                    },
+                    _ if bits(code, 25, 29) == 0 => VInst::SetVl {
+                        dest,
+                        avl: Avl::Reg(Reg(rs1)),
+                        vtype: VtypeSrc::Reg(Reg(vs2)),
+                    },
+                    _ => return None,
+                });
+            }
+            // OPFVV and OPFVF
+            _ => return None,
+        };
+        let (v, x, i) = match src {
+            VSrc::V(_) => (true, false, false),
+            VSrc::X(_) => (false, true, false),
+            VSrc::I(_) => (false, false, true),
+        };
+        let op = match opm {
+            false => match funct6 {
+                0x00 => Add,
+                0x02 if !i => Sub,
+                0x03 if !v => Rsub,
+                0x04 if !i => Minu,
+                0x05 if !i => Min,
+                0x06 if !i => Maxu,
+                0x07 if !i => Max,
+                0x09 => And,
+                0x0a => Or,
+                0x0b => Xor,
+                0x0c => Rgather,
+                0x0e if v => Rgatherei16,
+                0x0e => Slideup,
+                0x0f if !v => Slidedown,
+                0x10 if !vm => Adc,
+                0x11 => Madc,
+                0x12 if !vm && !i => Sbc,
+                0x13 if !i => Msbc,
+                0x17 if !vm => Merge,
+                0x17 if vs2 == 0 => Mv,
+                0x18 => Mseq,
+                0x19 => Msne,
+                0x1a if !i => Msltu,
+                0x1b if !i => Mslt,
+                0x1c => Msleu,
+                0x1d => Msle,
+                0x1e if !v => Msgtu,
+                0x1f if !v => Msgt,
+                0x20 => Saddu,
+                0x21 => Sadd,
+                0x22 if !i => Ssubu,
+                0x23 if !i => Ssub,
+                0x25 => Sll,
+                0x27 if i && vm && matches!(rs1, 0 | 1 | 3 | 7) => MvNr,
+                0x27 if !i => Smul,
+                0x28 => Srl,
+                0x29 => Sra,
+                0x2a => Ssrl,
+                0x2b => Ssra,
+                0x2c => Nsrl,
+                0x2d => Nsra,
+                0x2e => Nclipu,
+                0x2f => Nclip,
+                0x30 if v => Wredsumu,
+                0x31 if v => Wredsum,
+                _ => return None,
+            },
+            true => match funct6 {
+                0x00 if v => Redsum,
+                0x01 if v => Redand,
+                0x02 if v => Redor,
+                0x03 if v => Redxor,
+                0x04 if v => Redminu,
+                0x05 if v => Redmin,
+                0x06 if v => Redmaxu,
+                0x07 if v => Redmax,
+                0x08 => Aaddu,
+                0x09 => Aadd,
+                0x0a => Asubu,
+                0x0b => Asub,
+                0x0e if x => Slide1up,
+                0x0f if x => Slide1down,
+                0x10 if v => match rs1 {
+                    0x00 if vm => MvXS,
+                    0x10 => Cpop,
+                    0x11 => First,
+                    _ => return None,
+                },
+                0x10 if vs2 == 0 && vm => MvSX,
+                0x12 if v => match rs1 {
+                    2..=7 => match rs1 & 1 {
+                        0 => Zext(1 << (4 - rs1 / 2)),
+                        _ => Sext(1 << (4 - rs1 / 2)),
+                    },
+                    _ => return None,
+                },
+                0x14 if v => match rs1 {
+                    0x01 => Msbf,
+                    0x02 => Msof,
+                    0x03 => Msif,
+                    0x10 => Iota,
+                    0x11 if vs2 == 0 => Id,
+                    _ => return None,
+                },
+                0x17 if v && vm => Compress,
+                0x18 if v && vm => Mandn,
+                0x19 if v && vm => Mand,
+                0x1a if v && vm => Mor,
+                0x1b if v && vm => Mxor,
+                0x1c if v && vm => Morn,
+                0x1d if v && vm => Mnand,
+                0x1e if v && vm => Mnor,
+                0x1f if v && vm => Mxnor,
+                0x20 => Divu,
+                0x21 => Div,
+                0x22 => Remu,
+                0x23 => Rem,
+                0x24 => Mulhu,
+                0x25 => Mul,
+                0x26 => Mulhsu,
+                0x27 => Mulh,
+                0x29 => Madd,
+                0x2b => Nmsub,
+                0x2d => Macc,
+                0x2f => Nmsac,
+                0x30 => Waddu,
+                0x31 => Wadd,
+                0x32 => Wsubu,
+                0x33 => Wsub,
+                0x34 => WadduW,
+                0x35 => WaddW,
+                0x36 => WsubuW,
+                0x37 => WsubW,
+                0x38 => Wmulu,
+                0x3a => Wmulsu,
+                0x3b => Wmul,
+                0x3c => Wmaccu,
+                0x3d => Wmacc,
+                0x3e if x => Wmaccus,
+                0x3f => Wmaccsu,
+                _ => return None,
+            },
+        };
+        Some(VInst::Op { op, src, vm, vd, vs2 })
+    }
+
+    /// The integer registers read, passed to [`exec`] as `a` and `b`
+    pub fn x_srcs(&self) -> (Option<Reg>, Option<Reg>) {
+        match *self {
+            VInst::SetVl { avl, vtype, .. } => (
+                match avl {
+                    Avl::Reg(r) => Some(r),
+                    Avl::Imm(_) => None,
+                },
+                match vtype {
+                    VtypeSrc::Reg(r) => Some(r),
+                    VtypeSrc::Imm(_) => None,
+                },
+            ),
+            VInst::Mem { addr, base, .. } => (
+                Some(base),
+                match addr {
+                    VAddr::Strided(r) => Some(r),
+                    _ => None,
+                },
+            ),
+            VInst::Op { src: VSrc::X(r), .. } => (Some(r), None),
+            VInst::Op { .. } => (None, None),
+        }
+    }
+
+    /// The integer register written with [`exec`]'s result, if any
+    pub fn x_dest(&self) -> Option<Reg> {
+        match *self {
+            VInst::SetVl { dest, .. } => Some(dest),
+            VInst::Op {
+                op: VOp::MvXS | VOp::Cpop | VOp::First,
+                vd,
+                ..
+            } => Some(Reg(vd)),
+            _ => None,
+        }
+    }
+}
+
+/// Execute `i`, encoded as `code`, returning the value for [`VInst::x_dest`]
+///
+/// `a` and `b` hold the registers named by [`VInst::x_srcs`]. `mem`
+/// translates a guest address for an access and finds its byte; it is never
+/// asked for a run of bytes crossing a 4 KiB boundary. A trap part way
+/// through a load or store leaves `vstart` at the faulting element.
+pub fn exec(
+    i: VInst,
+    code: u32,
+    v: &mut Vector,
+    a: u64,
+    b: u64,
+    xlen: Xlen,
+    mem: &mut dyn FnMut(u64, Access) -> Result<*mut u8, Trap>,
+) -> Result<u64, Trap> {
+    match i {
+        VInst::SetVl { dest, avl, vtype } => {
+            let vtype = match vtype {
+                VtypeSrc::Imm(t) => t as u64,
+                VtypeSrc::Reg(_) => b,
+            };
+            let avl = match avl {
+                Avl::Imm(n) => Some(n as u64),
+                Avl::Reg(r) if r.0 != 0 => Some(a),
+                // `rs1 = x0` asks for VLMAX, unless `rd` is `x0` too
+                Avl::Reg(_) if dest.0 != 0 => Some(u64::MAX),
+                Avl::Reg(_) => None,
+            };
+            Ok(v.set_vl(avl, vtype))
+        }
+        VInst::Mem {
+            store,
+            addr,
+            eew,
+            nf,
+            vm,
+            vd,
+            ..
+        } => exec_mem(v, code, store, addr, eew, nf, vm, vd, a, b, xlen, mem).map(|_| 0),
+        VInst::Op { op, src, vm, vd, vs2 } => exec_op(v, code, op, src, vm, vd, vs2, a, xlen),
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn exec_mem(
+    v: &mut Vector,
+    code: u32,
+    store: bool,
+    addr: VAddr,
+    eew: u32,
+    nf: u8,
+    vm: bool,
+    vd: u8,
+    a: u64,
+    b: u64,
+    xlen: Xlen,
+    mem: &mut dyn FnMut(u64, Access) -> Result<*mut u8, Trap>,
+) -> Result<(), Trap> {
+    let illegal = Trap::illegal(code);
+    let mask = xlen_mask(xlen);
+    let vlenb = v.vlenb();
+    let nf = nf as u32;
+    let bytes = eew / 8;
+    // Whole-register and mask accesses are plain byte runs
+    let (evl, emul8, data_eew) = match addr {
+        VAddr::Whole => {
+            if !group(vd, nf * 8) {
+                return Err(illegal);
+            }
+            ((nf as usize * vlenb) as u64 / bytes as u64, nf * 8, eew)
+        }
+        _ => {
+            let t = v.vtype().ok_or(illegal)?;
+            match addr {
+                VAddr::Mask => (v.vl.div_ceil(8), 8, 8),
+                VAddr::Indexed { index, .. } => {
+                    if !group(index, t.lmul8 * eew / t.sew) || t.lmul8 * eew < t.sew {
+                        return Err(illegal);
+                    }
+                    (v.vl, t.lmul8, t.sew)
+                }
+                _ => {
+                    if t.lmul8 * eew < t.sew {
+                        return Err(illegal);
+                    }
+                    (v.vl, t.lmul8 * eew / t.sew, eew)
+                }
+            }
+        }
+    };
+    let regs = (emul8 / 8).max(1);
+    let fields = match addr {
+        VAddr::Whole => 1,
+        _ => nf,
+    };
+    if !group(vd, emul8) || fields * regs > 8 || vd as u32 + fields * regs > 32 || (!vm && !store && vd == 0) {
+        return Err(illegal);
+    }
+    let index_regs = v.regs.clone();
+    let mut buf = [0u8; 8];
+    let size = (data_eew / 8) as usize;
+    let mut i = v.vstart;
+    while i < evl {
+        if !vm && !mask_bit(&v.regs, vlenb, 0, i) {
+            i += 1;
+            continue;
+        }
+        let start = match addr {
+            VAddr::Unit | VAddr::FaultFirst => a.wrapping_add(i * (nf * bytes) as u64),
+            VAddr::Strided(_) => a.wrapping_add(i.wrapping_mul(b)),
+            VAddr::Indexed { index, .. } => a.wrapping_add(elem(&index_regs, vlenb, index, i, eew)),
+            VAddr::Mask | VAddr::Whole => a.wrapping_add(i * bytes as u64),
+        };
+        for f in 0..fields {
+            let reg = vd + (f * regs) as u8;
+            let at = start.wrapping_add((f * data_eew / 8) as u64);
+            let buf = &mut buf[..size];
+            if store {
+                buf.copy_from_slice(&elem(&v.regs, vlenb, reg, i, data_eew).to_le_bytes()[..size]);
+            }
+            if let Err(t) = transfer(mem, at, buf, store, mask) {
+                if addr == VAddr::FaultFirst && i > 0 {
+                    v.vl = i;
+                    v.vstart = 0;
+                    return Ok(());
+                }
+                v.vstart = i;
+                return Err(t);
+            }
+            if !store {
+                let mut val = [0u8; 8];
+                val[..size].copy_from_slice(buf);
+                set_elem(&mut v.regs, vlenb, reg, i, data_eew, u64::from_le_bytes(val));
+            }
+        }
+        i += 1;
+    }
+    v.vstart = 0;
+    Ok(())
+}
+
+#[allow(clippy::too_many_arguments)]
+fn exec_op(
+    v: &mut Vector,
+    code: u32,
+    op: VOp,
+    src: VSrc,
+    vm: bool,
+    vd: u8,
+    vs2: u8,
+    a: u64,
+    xlen: Xlen,
+) -> Result<u64, Trap> {
+    use VOp::*;
+    let illegal = Trap::illegal(code);
+    let vlenb = v.vlenb();
+    let xbits = xlen_mask(xlen).count_ones();
+    if op == MvNr {
+        let n = match src {
+            VSrc::I(n) => n as u32 + 1,
+            _ => unreachable!(),
+        };
+        if !group(vd, n * 8) || !group(vs2, n * 8) {
+            return Err(illegal);
+        }
+        let len = n as usize * vlenb;
+        let from = vs2 as usize * vlenb + v.vstart as usize;
+        let to = vd as usize * vlenb + v.vstart as usize;
+        if v.vstart < len as u64 {
+            v.regs.copy_within(from..vs2 as usize * vlenb + len, to);
+        }
+        v.vstart = 0;
+        return Ok(0);
+    }
+    let t = v.vtype().ok_or(illegal)?;
+    let (sew, lmul8, vl, vstart, vxrm) = (t.sew, t.lmul8, v.vl, v.vstart, v.vxrm);
+    let vlmax = t.vlmax(v.vlen);
+    let m = ones(sew);
+    // Sources are read from a snapshot, so overlapping groups read old values
+    let old = v.regs.clone();
+    let get = |reg: u8, i: u64, w: u32| elem(&old, vlenb, reg, i, w);
+    let bit = |reg: u8, i: u64| mask_bit(&old, vlenb, reg, i);
+    let active = |i: u64| vm || bit(0, i);
+    // The scalar operand is sign-extended from XLEN, then truncated to SEW
+    let scalar = sx(a, xbits) as u64;
+    let imm = match src {
+        VSrc::I(n) if op.unsigned_imm() => n as u64,
+        VSrc::I(n) => sx(n as u64, 5) as u64,
+        _ => 0,
+    };
+    let op2 = |i: u64| match src {
+        VSrc::V(r) => get(r, i, sew),
+        VSrc::X(_) => scalar & m,
+        VSrc::I(_) => imm & m,
+    };
+    let vs1 = match src {
+        VSrc::V(r) => r,
+        _ => 0,
+    };
+    // Every group below must be aligned and in range
+    let check = |ok: bool| if ok { Ok(()) } else { Err(illegal) };
+    let src1_ok = |emul8: u32| !matches!(src, VSrc::V(r) if !group(r, emul8));
+    let dst = &mut v.regs;
+    let mut sat = false;
+    let mut result = 0;
+    match op {
+        Add | Sub | Rsub | Minu | Min | Maxu | Max | And | Or | Xor | Sll | Srl | Sra | Saddu | Sadd | Ssubu
+        | Ssub | Smul | Ssrl | Ssra | Aaddu | Aadd | Asubu | Asub | Divu | Div | Remu | Rem | Mulhu | Mul
+        | Mulhsu | Mulh | Madd | Nmsub | Macc | Nmsac | Merge | Mv | Adc | Sbc => {
+            check(group(vd, lmul8) && group(vs2, lmul8) && src1_ok(lmul8) && (vm || vd != 0))?;
+            let sh = |y: u64| (y & (sew as u64 - 1)) as u32;
+            for i in vstart..vl {
+                let (x, y) = (get(vs2, i, sew), op2(i));
+                let (xs, ys) = (sx(x, sew) as i128, sx(y, sew) as i128);
+                let (xu, yu) = (x as i128, y as i128);
+                let r = match op {
+                    // Merges and carries use `v0` as data, not as a mask
+                    Merge => match bit(0, i) {
+                        true => y,
+                        false => x,
+                    },
+                    Adc => x.wrapping_add(y).wrapping_add(bit(0, i) as u64),
+                    Sbc => x.wrapping_sub(y).wrapping_sub(bit(0, i) as u64),
+                    _ if !active(i) => continue,
+                    Add => x.wrapping_add(y),
+                    Sub => x.wrapping_sub(y),
+                    Rsub => y.wrapping_sub(x),
+                    Minu => x.min(y),
+                    Maxu => x.max(y),
+                    Min => xs.min(ys) as u64,
+                    Max => xs.max(ys) as u64,
+                    And => x & y,
+                    Or => x | y,
+                    Xor => x ^ y,
+                    Sll => x << sh(y),
+                    Srl => x >> sh(y),
+                    Sra => (xs >> sh(y)) as u64,
+                    Saddu => sat_u(xu + yu, sew, &mut sat),
+                    Sadd => sat_s(xs + ys, sew, &mut sat),
+                    Ssubu => sat_u(xu - yu, sew, &mut sat),
+                    Ssub => sat_s(xs - ys, sew, &mut sat),
+                    Smul => sat_s(roundoff(xs * ys, sew - 1, vxrm), sew, &mut sat),
+                    Ssrl => roundoff(xu, sh(y), vxrm) as u64,
+                    Ssra => roundoff(xs, sh(y), vxrm) as u64,
+                    Aaddu => roundoff(xu + yu, 1, vxrm) as u64,
+                    Aadd => roundoff(xs + ys, 1, vxrm) as u64,
+                    Asubu => roundoff(xu - yu, 1, vxrm) as u64,
+                    Asub => roundoff(xs - ys, 1, vxrm) as u64,
+                    // Division by zero and overflow follow the scalar M rules
+                    Divu => x.checked_div(y).unwrap_or(u64::MAX),
+                    Remu => x.checked_rem(y).unwrap_or(x),
+                    Div => match ys {
+                        0 => u64::MAX,
+                        _ => (xs as i64).wrapping_div(ys as i64) as u64,
+                    },
+                    Rem => match ys {
+                        0 => x,
+                        _ => (xs as i64).wrapping_rem(ys as i64) as u64,
+                    },
+                    Mul => x.wrapping_mul(y),
+                    Mulhu => ((xu * yu) >> sew) as u64,
+                    Mulh => ((xs * ys) >> sew) as u64,
+                    Mulhsu => ((xs * yu) >> sew) as u64,
+                    Madd => y.wrapping_mul(get(vd, i, sew)).wrapping_add(x),
+                    Nmsub => x.wrapping_sub(y.wrapping_mul(get(vd, i, sew))),
+                    Macc => y.wrapping_mul(x).wrapping_add(get(vd, i, sew)),
+                    Nmsac => get(vd, i, sew).wrapping_sub(y.wrapping_mul(x)),
+                    Mv => y,
+                    _ => unreachable!(),
+                };
+                set_elem(dst, vlenb, vd, i, sew, r & m);
+            }
+        }
+        Madc | Msbc | Mseq | Msne | Msltu | Mslt | Msleu | Msle | Msgtu | Msgt => {
+            check(group(vs2, lmul8) && src1_ok(lmul8))?;
+            for i in vstart..vl {
+                let (x, y) = (get(vs2, i, sew), op2(i));
+                let (xs, ys) = (sx(x, sew), sx(y, sew));
+                // Without a mask these take no carry in
+                let c = (!vm && bit(0, i)) as u128;
+                let r = match op {
+                    Madc => (x as u128 + y as u128 + c) >> sew != 0,
+                    Msbc => (x as u128) < y as u128 + c,
+                    _ if !active(i) => continue,
+                    Mseq => x == y,
+                    Msne => x != y,
+                    Msltu => x < y,
+                    Mslt => xs < ys,
+                    Msleu => x <= y,
+                    Msle => xs <= ys,
+                    Msgtu => x > y,
+                    Msgt => xs > ys,
+                    _ => unreachable!(),
+                };
+                set_mask_bit(dst, vlenb, vd, i, r);
+            }
+        }
+        Waddu | Wadd | Wsubu | Wsub | WadduW | WaddW | WsubuW | WsubW | Wmulu | Wmulsu | Wmul | Wmaccu
+        | Wmacc | Wmaccus | Wmaccsu => {
+            let wide = matches!(op, WadduW | WaddW | WsubuW | WsubW);
+            let w2 = sew * 2;
+            check(
+                sew <= 32
+                    && group(vd, lmul8 * 2)
+                    && group(vs2, if wide { lmul8 * 2 } else { lmul8 })
+                    && src1_ok(lmul8)
+                    && (vm || vd != 0),
+            )?;
+            for i in vstart..vl {
+                if !active(i) {
+                    continue;
+                }
+                let x = get(vs2, i, if wide { w2 } else { sew });
+                let y = op2(i);
+                let (xs, ys) = (sx(x, if wide { w2 } else { sew }) as i128, sx(y, sew) as i128);
+                let (xu, yu) = (x as i128, y as i128);
+                let acc = sx(get(vd, i, w2), w2) as i128;
+                let r = match op {
+                    Waddu | WadduW => xu + yu,
+                    Wadd | WaddW => xs + ys,
+                    Wsubu | WsubuW => xu - yu,
+                    Wsub | WsubW => xs - ys,
+                    Wmulu => xu * yu,
+                    Wmulsu => xs * yu,
+                    Wmul => xs * ys,
+                    Wmaccu => acc + yu * xu,
+                    Wmacc => acc + ys * xs,
+                    Wmaccsu => acc + ys * xu,
+                    Wmaccus => acc + yu * xs,
+                    _ => unreachable!(),
+                };
+                set_elem(dst, vlenb, vd, i, w2, r as u64 & ones(w2));
+            }
+        }
+        Nsrl | Nsra | Nclipu | Nclip => {
+            let w2 = sew * 2;
+            check(sew <= 32 && group(vd, lmul8) && group(vs2, lmul8 * 2) && src1_ok(lmul8) && (vm || vd != 0))?;
+            for i in vstart..vl {
+                if !active(i) {
+                    continue;
+                }
+                let x = get(vs2, i, w2);
+                let sh = (op2(i) & (w2 as u64 - 1)) as u32;
+                let r = match op {
+                    Nsrl => x >> sh,
+                    Nsra => (sx(x, w2) >> sh) as u64,
+                    Nclipu => sat_u(roundoff(x as i128, sh, vxrm), sew, &mut sat),
+                    _ => sat_s(roundoff(sx(x, w2) as i128, sh, vxrm), sew, &mut sat),
+                };
+                set_elem(dst, vlenb, vd, i, sew, r & m);
+            }
+        }
+        Redsum | Redand | Redor | Redxor | Redminu | Redmin | Redmaxu | Redmax | Wredsumu | Wredsum => {
+            let widen = matches!(op, Wredsumu | Wredsum);
+            let w = if widen { sew * 2 } else { sew };
+            check(vstart == 0 && group(vs2, lmul8) && w <= 64)?;
+            let mut acc = get(vs1, 0, w);
+            for i in (0..vl).filter(|&i| active(i)) {
+                let x = get(vs2, i, sew);
+                acc = match op {
+                    Redsum => acc.wrapping_add(x),
+                    Redand => acc & x,
+                    Redor => acc | x,
+                    Redxor => acc ^ x,
+                    Redminu => acc.min(x),
+                    Redmaxu => acc.max(x),
+                    Redmin => sx(acc, sew).min(sx(x, sew)) as u64,
+                    Redmax => sx(acc, sew).max(sx(x, sew)) as u64,
+                    Wredsumu => acc.wrapping_add(x),
+                    _ => acc.wrapping_add(sx(x, sew) as u64),
+                } & ones(w);
+            }
+            if vl > 0 {
+                set_elem(dst, vlenb, vd, 0, w, acc);
+            }
+        }
+        Rgather | Rgatherei16 => {
+            let iw = if op == Rgatherei16 { 16 } else { sew };
+            let iemul8 = lmul8 * iw / sew;
+            check(group(vd, lmul8) && group(vs2, lmul8) && src1_ok(iemul8) && iemul8 >= 1 && (vm || vd != 0))?;
+            for i in (vstart..vl).filter(|&i| active(i)) {
+                let index = match src {
+                    VSrc::V(r) => get(r, i, iw),
+                    VSrc::X(_) => a & xlen_mask(xlen),
+                    VSrc::I(_) => imm,
+                };
+                let r = match index < vlmax {
+                    true => get(vs2, index, sew),
+                    false => 0,
+                };
+                set_elem(dst, vlenb, vd, i, sew, r);
+            }
+        }
+        Slideup | Slidedown | Slide1up | Slide1down => {
+            check(group(vd, lmul8) && group(vs2, lmul8) && (vm || vd != 0))?;
+            let offset = match (op, src) {
+                (Slide1up | Slide1down, _) => 1,
+                (_, VSrc::X(_)) => a & xlen_mask(xlen),
+                _ => imm,
+            };
+            for i in (vstart..vl).filter(|&i| active(i)) {
+                let r = match op {
+                    Slideup if i < offset => continue,
+                    Slideup => get(vs2, i - offset, sew),
+                    Slide1up if i == 0 => scalar & m,
+                    Slide1up => get(vs2, i - 1, sew),
+                    Slide1down if i + 1 == vl => scalar & m,
+                    _ => match i.checked_add(offset) {
+                        Some(j) if j < vlmax => get(vs2, j, sew),
+                        _ => 0,
+                    },
+                };
+                set_elem(dst, vlenb, vd, i, sew, r);
+            }
+        }
+        Compress => {
+            check(vstart == 0 && group(vd, lmul8) && group(vs2, lmul8))?;
+            for (j, i) in (0..vl).filter(|&i| bit(vs1, i)).enumerate() {
+                set_elem(dst, vlenb, vd, j as u64, sew, get(vs2, i, sew));
+            }
+        }
+        MvXS => result = sx(get(vs2, 0, sew), sew) as u64 & xlen_mask(xlen),
+        MvSX => {
+            if vstart < vl {
+                set_elem(dst, vlenb, vd, 0, sew, scalar & m);
+            }
+        }
+        Zext(n) | Sext(n) => {
+            let w = sew / n as u32;
+            check(w >= 8 && group(vd, lmul8) && group(vs2, lmul8 / n as u32) && (vm || vd != 0))?;
+            for i in (vstart..vl).filter(|&i| active(i)) {
+                let x = get(vs2, i, w);
+                let r = match op {
+                    Zext(_) => x,
+                    _ => sx(x, w) as u64 & m,
+                };
+                set_elem(dst, vlenb, vd, i, sew, r);
+            }
+        }
+        Mandn | Mand | Mor | Mxor | Morn | Mnand | Mnor | Mxnor => {
+            for i in vstart..vl {
+                let (x, y) = (bit(vs2, i), bit(vs1, i));
+                let r = match op {
+                    Mandn => x & !y,
+                    Mand => x & y,
+                    Mor => x | y,
+                    Mxor => x ^ y,
+                    Morn => x | !y,
+                    Mnand => !(x & y),
+                    Mnor => !(x | y),
+                    _ => !(x ^ y),
+                };
+                set_mask_bit(dst, vlenb, vd, i, r);
+            }
+        }
+        Cpop | First => {
+            check(vstart == 0)?;
+            let mut set = (0..vl).filter(|&i| active(i) && bit(vs2, i));
+            result = match op {
+                Cpop => set.count() as u64,
+                _ => set.next().unwrap_or(u64::MAX) & xlen_mask(xlen),
+            };
+        }
+        Msbf | Msif | Msof => {
+            check(vstart == 0 && (vm || vd != 0))?;
+            let mut found = false;
+            for i in (0..vl).filter(|&i| active(i)) {
+                let first = !found && bit(vs2, i);
+                let r = match op {
+                    Msbf => !found && !first,
+                    Msif => !found,
+                    _ => first,
+                };
+                found |= first;
+                set_mask_bit(dst, vlenb, vd, i, r);
+            }
+        }
+        Iota | Id => {
+            check(vstart == 0 && group(vd, lmul8) && (vm || vd != 0))?;
+            let mut count = 0;
+            for i in (0..vl).filter(|&i| active(i)) {
+                set_elem(dst, vlenb, vd, i, sew, if op == Id { i } else { count } & m);
+                count += bit(vs2, i) as u64;
+            }
+        }
+        MvNr => unreachable!(),
+    }
+    v.vxsat |= sat;
+    v.vstart = 0;
+    Ok(result)
+}
+
+impl Display for VInst {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match *self {
+            VInst::SetVl { dest, avl, vtype } => {
+                match (avl, vtype) {
+                    (Avl::Reg(r), VtypeSrc::Reg(t)) => return write!(f, "vsetvl {dest}, {r}, {t}"),
+                    (Avl::Reg(r), _) => write!(f, "vsetvli {dest}, {r}, ")?,
+                    (Avl::Imm(n), _) => write!(f, "vsetivli {dest}, {n}, ")?,
+                }
+                let t = match vtype {
+                    VtypeSrc::Imm(t) => t as u64,
+                    VtypeSrc::Reg(_) => unreachable!(),
+                };
+                match VType::decode(t) {
+                    Some(t) => write!(f, "{t}"),
+                    None => write!(f, "{t:#x}"),
+                }
+            }
+            VInst::Mem {
+                store,
+                addr,
+                eew,
+                nf,
+                vm,
+                vd,
+                base,
+            } => {
+                let dir = if store { 's' } else { 'l' };
+                let seg = match nf {
+                    1 => alloc::string::String::new(),
+                    n => alloc::format!("seg{n}"),
+                };
+                match addr {
+                    VAddr::Unit => write!(f, "v{dir}{seg}e{eew}.v v{vd}, ({base})")?,
+                    VAddr::FaultFirst => write!(f, "v{dir}{seg}e{eew}ff.v v{vd}, ({base})")?,
+                    VAddr::Mask => write!(f, "v{dir}m.v v{vd}, ({base})")?,
+                    VAddr::Whole if store => write!(f, "vs{nf}r.v v{vd}, ({base})")?,
+                    VAddr::Whole => write!(f, "vl{nf}re{eew}.v v{vd}, ({base})")?,
+                    VAddr::Strided(r) => write!(f, "v{dir}s{seg}e{eew}.v v{vd}, ({base}), {r}")?,
+                    VAddr::Indexed { ordered, index } => {
+                        let o = if ordered { 'o' } else { 'u' };
+                        write!(f, "v{dir}{o}x{seg}ei{eew}.v v{vd}, ({base}), v{index}")?
+                    }
+                }
+                match vm {
+                    true => Ok(()),
+                    false => write!(f, ", v0.t"),
+                }
+            }
+            VInst::Op { op, src, vm, vd, vs2 } => fmt_op(f, op, src, vm, vd, vs2),
+        }
+    }
+}
+
+fn fmt_op(f: &mut Formatter<'_>, op: VOp, src: VSrc, vm: bool, vd: u8, vs2: u8) -> fmt::Result {
+    use VOp::*;
+    let m = op.mnemonic();
+    let operand = |f: &mut Formatter<'_>| match src {
+        VSrc::V(r) => write!(f, "v{r}"),
+        VSrc::X(r) => write!(f, "{r}"),
+        VSrc::I(n) if op.unsigned_imm() => write!(f, "{n}"),
+        VSrc::I(n) => write!(f, "{}", sx(n as u64, 5)),
+    };
+    let kind = match src {
+        VSrc::V(_) => 'v',
+        VSrc::X(_) => 'x',
+        VSrc::I(_) => 'i',
+    };
+    let masked = |f: &mut Formatter<'_>| match vm {
+        true => Ok(()),
+        false => write!(f, ", v0.t"),
+    };
+    match op {
+        Mv => {
+            write!(f, "vmv.v.{kind} v{vd}, ")?;
+            operand(f)
+        }
+        MvNr => match src {
+            VSrc::I(n) => write!(f, "vmv{}r.v v{vd}, v{vs2}", n + 1),
+            _ => unreachable!(),
+        },
+        MvXS => write!(f, "vmv.x.s {}, v{vs2}", Reg(vd)),
+        MvSX => {
+            write!(f, "vmv.s.x v{vd}, ")?;
+            operand(f)
+        }
+        Cpop | First => {
+            write!(f, "v{m}.m {}, v{vs2}", Reg(vd))?;
+            masked(f)
+        }
+        Zext(n) | Sext(n) => {
+            write!(f, "v{m}.vf{n} v{vd}, v{vs2}")?;
+            masked(f)
+        }
+        Msbf | Msof | Msif | Iota => {
+            write!(f, "v{m}.m v{vd}, v{vs2}")?;
+            masked(f)
+        }
+        Id => {
+            write!(f, "vid.v v{vd}")?;
+            masked(f)
+        }
+        Compress => {
+            write!(f, "vcompress.vm v{vd}, v{vs2}, ")?;
+            operand(f)
+        }
+        Mandn | Mand | Mor | Mxor | Morn | Mnand | Mnor | Mxnor => {
+            write!(f, "v{m}.mm v{vd}, v{vs2}, ")?;
+            operand(f)
+        }
+        // Carries and merges name `v0` as an operand
+        Adc | Sbc | Merge => {
+            write!(f, "v{m}.v{kind}m v{vd}, v{vs2}, ")?;
+            operand(f)?;
+            write!(f, ", v0")
+        }
+        Madc | Msbc if !vm => {
+            write!(f, "v{m}.v{kind}m v{vd}, v{vs2}, ")?;
+            operand(f)?;
+            write!(f, ", v0")
+        }
+        // The multiply-adds name the multiplier first
+        Madd | Nmsub | Macc | Nmsac | Wmaccu | Wmacc | Wmaccus | Wmaccsu => {
+            write!(f, "v{m}.v{kind} v{vd}, ")?;
+            operand(f)?;
+            write!(f, ", v{vs2}")?;
+            masked(f)
+        }
+        _ => {
+            let (shape, kind) = match op {
+                Redsum | Redand | Redor | Redxor | Redminu | Redmin | Redmaxu | Redmax | Wredsumu | Wredsum => {
+                    ('v', 's')
+                }
+                Nsrl | Nsra | Nclipu | Nclip | WadduW | WaddW | WsubuW | WsubW => ('w', kind),
+                _ => ('v', kind),
+            };
+            write!(f, "v{m}.{shape}{kind} v{vd}, v{vs2}, ")?;
+            operand(f)?;
+            masked(f)
+        }
+    }
+}
@@ -0,0 +1,204 @@
+//! A machine laid out like QEMU's `virt` board.
+//!
+//! RAM starts at [`RAM_BASE`], with the [CLINT](crate::clint), the
+//! [PLIC](crate::plic) and an [NS16550A](crate::uart) at QEMU's addresses.
+//! Physical addresses outside RAM and the devices raise access faults, so
+//! firmware probing the map sees what it would on QEMU. Without a board
+//! the reactor keeps vane's flat RAM, where only the CLINT is a device.
+//! [`Virt::device_tree`] describes the board for firmware and kernels.
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use rv_asm::Xlen;
+
+use crate::clint::{self, Clint};
+use crate::fdt::Fdt;
+use crate::plic::{self, Plic};
+use crate::uart::Uart;
+
+pub const UART_BASE: u64 = 0x1000_0000;
+pub const UART_SIZE: u64 = 0x100;
+/// The UART's PLIC source
+pub const UART_IRQ: u32 = 10;
+pub const PLIC_BASE: u64 = 0xc00_0000;
+pub const PLIC_SIZE: u64 = 0x60_0000;
+pub const RAM_BASE: u64 = 0x8000_0000;
+
+/// What a physical address is backed by
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum Region {
+    Ram,
+    /// Device registers, read and written through the reactor
+    Device,
+    Unmapped,
+}
+
+/// Extensions both engines implement, after the single-letter ones
+const EXTENSIONS: &[&str] = &[
+    "zicntr", "zicsr", "zifencei", "zba", "zbb", "zbc", "zbkb", "zbkx", "zbs", "zknd", "zkne", "zknh",
+    "zksed", "zksh",
+];
+
+/// The `virt` board's devices and memory size
+#[derive(Clone, Debug)]
+pub struct Virt {
+    pub ram_size: u64,
+    /// `mtime` ticks per second as reported to the guest; `mtime` counts
+    /// retired instructions, so this sets how fast guest time passes
+    pub timebase: u32,
+    pub uart: Uart,
+    pub plic: Plic,
+}
+
+impl Virt {
+    pub fn new(ram_size: u64) -> Self {
+        Virt {
+            ram_size,
+            timebase: 10_000_000,
+            uart: Uart::default(),
+            plic: Plic::default(),
+        }
+    }
+
+    pub fn region(&self, pa: u64) -> Region {
+        let within = |base: u64, size: u64| pa.wrapping_sub(base) < size;
+        match () {
+            _ if within(RAM_BASE, self.ram_size) => Region::Ram,
+            _ if Clint::contains(pa)
+                || within(PLIC_BASE, PLIC_SIZE)
+                || within(UART_BASE, UART_SIZE) =>
+            {
+                Region::Device
+            }
+            _ => Region::Unmapped,
+        }
+    }
+
+    /// Read `size` bytes of the UART or PLIC at `pa`
+    ///
+    /// The CLINT is left to the reactor, which knows `instret`.
+    pub fn read(&mut self, pa: u64, size: u64) -> u64 {
+        if pa.wrapping_sub(UART_BASE) < UART_SIZE {
+            return self.uart.read(pa - UART_BASE) as u64;
+        }
+        let offset = pa.wrapping_sub(PLIC_BASE) & !3;
+        match size {
+            8 => self.plic.read(offset) as u64 | (self.plic.read(offset + 4) as u64) << 32,
+            _ => (self.plic.read(offset) >> ((pa & 3) * 8)) as u64,
+        }
+    }
+
+    pub fn write(&mut self, pa: u64, size: u64, value: u64) {
+        if pa.wrapping_sub(UART_BASE) < UART_SIZE {
+            return self.uart.write(pa - UART_BASE, value as u8);
+        }
+        let offset = pa.wrapping_sub(PLIC_BASE) & !3;
+        self.plic.write(offset, value as u32);
+        if size == 8 {
+            self.plic.write(offset + 4, (value >> 32) as u32);
+        }
+    }
+
+    /// The `mip` bits the board's interrupt controller drives
+    pub fn interrupts(&mut self) -> u64 {
+        let uart = self.uart.interrupt();
+        self.plic.set_level(UART_IRQ, uart);
+        self.plic.interrupts()
+    }
+
+    /// Where [`Virt::device_tree`]'s blob of `len` bytes goes: the top of
+    /// RAM, 4 KiB aligned
+    pub fn device_tree_addr(&self, len: u64) -> u64 {
+        (RAM_BASE + self.ram_size).saturating_sub(len) & !0xfff
+    }
+
+    /// The device tree blob for this board, with `bootargs` in `/chosen`
+    pub fn device_tree(&self, xlen: Xlen, bootargs: &str) -> Vec<u8> {
+        let (base, mmu) = match xlen {
+            Xlen::Rv32 => ("rv32", "riscv,sv32"),
+            Xlen::Rv64 => ("rv64", "riscv,sv57"),
+        };
+        let isa: String = format!("{base}imafdcv_{}", EXTENSIONS.join("_"));
+        let letters = ["i", "m", "a", "f", "d", "c", "v"];
+        let extensions: Vec<&str> = letters.iter().chain(EXTENSIONS).copied().collect();
+        let reg = |base: u64, size: u64| [base, size];
+        // phandles
+        let (intc, plic_handle) = (1, 2);
+        let uart = format!("serial@{UART_BASE:x}");
+
+        let mut t = Fdt::new();
+        t.begin_node("");
+        t.prop_u32("#address-cells", 2);
+        t.prop_u32("#size-cells", 2);
+        t.prop_str("compatible", "riscv-virtio");
+        t.prop_str("model", "riscv-virtio,qemu");
+
+        t.begin_node("chosen");
+        t.prop_str("bootargs", bootargs);
+        t.prop_str("stdout-path", &format!("/soc/{uart}"));
+        t.end_node();
+
+        t.begin_node(&format!("memory@{RAM_BASE:x}"));
+        t.prop_str("device_type", "memory");
+        t.prop_u64s("reg", &reg(RAM_BASE, self.ram_size));
+        t.end_node();
+
+        t.begin_node("cpus");
+        t.prop_u32("#address-cells", 1);
+        t.prop_u32("#size-cells", 0);
+        t.prop_u32("timebase-frequency", self.timebase);
+        t.begin_node("cpu@0");
+        t.prop_str("device_type", "cpu");
+        t.prop_u32("reg", 0);
+        t.prop_str("status", "okay");
+        t.prop_str("compatible", "riscv");
+        t.prop_str("riscv,isa", &isa);
+        t.prop_str("riscv,isa-base", &format!("{base}i"));
+        t.prop_strs("riscv,isa-extensions", &extensions);
+        t.prop_str("mmu-type", mmu);
+        t.begin_node("interrupt-controller");
+        t.prop_u32("#interrupt-cells", 1);
+        t.prop_empty("interrupt-controller");
+        t.prop_str("compatible", "riscv,cpu-intc");
+        t.prop_u32("phandle", intc);
+        t.end_node();
+        t.end_node();
+        t.end_node();
+
+        t.begin_node("soc");
+        t.prop_u32("#address-cells", 2);
+        t.prop_u32("#size-cells", 2);
+        t.prop_str("compatible", "simple-bus");
+        t.prop_empty("ranges");
+
+        t.begin_node(&format!("clint@{:x}", clint::BASE));
+        t.prop_strs("compatible", &["sifive,clint0", "riscv,clint0"]);
+        t.prop_u64s("reg", &reg(clint::BASE, clint::SIZE));
+        t.prop_cells("interrupts-extended", &[intc, 3, intc, 7]);
+        t.end_node();
+
+        t.begin_node(&format!("plic@{PLIC_BASE:x}"));
+        t.prop_strs("compatible", &["sifive,plic-1.0.0", "riscv,plic0"]);
+        t.prop_u64s("reg", &reg(PLIC_BASE, PLIC_SIZE));
+        t.prop_u32("#address-cells", 0);
+        t.prop_u32("#interrupt-cells", 1);
+        t.prop_empty("interrupt-controller");
+        t.prop_u32("riscv,ndev", plic::NDEV);
+        t.prop_cells("interrupts-extended", &[intc, 11, intc, 9]);
+        t.prop_u32("phandle", plic_handle);
+        t.end_node();
+
+        t.begin_node(&uart);
+        t.prop_str("compatible", "ns16550a");
+        t.prop_u64s("reg", &reg(UART_BASE, UART_SIZE));
+        t.prop_u32("clock-frequency", 0x38_4000);
+        t.prop_u32("interrupts", UART_IRQ);
+        t.prop_u32("interrupt-parent", plic_handle);
+        t.end_node();
+
+        t.end_node();
+        t.end_node();
+        t.finish()
+    }
+}
@@ -17,9 +17,23 @@ macro_rules! vane_meta {
         }
         impl $crate::vane_jit::JitCtx for Reactor {
             fn bytes(&self, a: u64) -> $crate::alloc::boxed::Box<dyn Iterator<Item = u8> + '_> {
+                // Code is read through the guest's page tables without touching
+                // them; unmapped bytes read as zero and fault again when fetched
                 $crate::alloc::boxed::Box::new((a..).filter_map(move |a| {
                     let mut lock = self.core.lock();
-                    let n = lock.mem.bytes(a).next()?;
+                    let xlen = lock.xlen;
+                    let core = &mut *lock;
+                    let access = $crate::vane_jit::mmu::Access::Execute;
+                    let n = match core.mem.translate(a, access, &core.csrs, xlen, true) {
+                        Ok(a) => {
+                            // Remember which blocks `fence.i` must drop
+                            if let Some(root) = core.compiling {
+                                core.mem.note_code(a, root);
+                            }
+                            core.mem.bytes(a).next()?
+                        }
+                        Err(_) => 0,
+                    };
                     Some(n)
                 }))
             }
@@ -29,6 +43,68 @@ macro_rules! vane_meta {
             state: $crate::core::cell::OnceCell<$crate::wasm_bindgen::prelude::JsValue>,
             regs: $crate::core::cell::OnceCell<$crate::wasm_bindgen::prelude::JsValue>,
             test_mode: bool,
+            xlen: $crate::vane_jit::rv_asm::Xlen,
+            csrs: $crate::vane_jit::csr::CsrFile,
+            vector: $crate::vane_jit::vector::Vector,
+            /// The root of the block `jit_code` is rendering
+            compiling: Option<u64>,
+            /// Trap raised by a reactor call, for generated code to `raise`
+            pending_trap: Option<$crate::vane_jit::trap::Trap>,
+            /// Consulted newest first by `take_trap`; see `add_trap_handler`
+            trap_handlers: $crate::alloc::vec::Vec<
+                $crate::alloc::rc::Rc<
+                    dyn Fn(
+                        &$t,
+                        &$crate::vane_jit::trap::Trap,
+                        u64,
+                    ) -> Result<Option<u64>, $crate::wasm_bindgen::prelude::JsValue>,
+                >,
+            >,
+            /// Host debugger stop for `ebreak`; see `set_break_hook`
+            break_hook: Option<
+                $crate::alloc::rc::Rc<
+                    dyn Fn(
+                        &$t,
+                        u64,
+                        &mut [u64; 32],
+                    ) -> Result<
+                        $crate::vane_jit::trap::BreakAction,
+                        $crate::wasm_bindgen::prelude::JsValue,
+                    >,
+                >,
+            >,
+            /// The break hook asked for a single step, which runs in the interpreter
+            stepping: bool,
+            /// Misaligned loads and stores trap instead of being split
+            trap_misaligned: bool,
+            /// Blocks run as WebAssembly where they can; see `set_wasm_tier`
+            wasm_tier: bool,
+            /// Entries before a block leaves the interpreter; see `set_tiering`
+            js_threshold: u32,
+            /// Entries before a JavaScript block moves to WebAssembly
+            wasm_threshold: u32,
+            /// Instructions a compiled block may inline; see `set_region_budget`
+            region_insts: Option<u32>,
+            /// Bytes of JavaScript a compiled block may grow to
+            region_bytes: Option<usize>,
+            /// The register file WebAssembly blocks run on, filled from `r`
+            /// when one is entered and emptied back when it leaves
+            wasm_regs: $crate::alloc::boxed::Box<
+                [u64; $crate::vane_jit::template::wasm::offset::SIZE as usize / 8],
+            >,
+            pub clint: $crate::vane_jit::clint::Clint,
+            /// Interrupt lines the host holds high, as `mip` bits
+            irq_lines: u64,
+            /// `instret` at which both engines next check for interrupts
+            irq_deadline: u64,
+            /// The `virt` board, or `None` for flat RAM
+            pub virt: Option<$crate::vane_jit::virt::Virt>,
+            /// Where console output goes; without it, output waits for `console_output`
+            console: Option<$crate::alloc::rc::Rc<dyn Fn(&[u8])>>,
+            /// Console output written with no console set
+            console_output: $crate::alloc::vec::Vec<u8>,
+            /// Bytes waiting for the SBI debug console to read
+            console_input: $crate::alloc::collections::VecDeque<u8>,
         }
 
         const _: () = {
@@ -40,7 +116,11 @@ macro_rules! vane_meta {
             return;
         };
     };
+    // Blocks start in the interpreter and move up a tier as they are entered:
+    // to JavaScript after `tiering()[0]` entries, then, with the Wasm tier
+    // on, to WebAssembly after `tiering()[1]` more
     export function get$(a,b){
+        const k = `${b}`;
         const jit = () => {
             let code;
             try{
@@ -51,14 +131,115 @@ macro_rules! vane_meta {
                 return a.interp.bind(a,b);
             }
         };
-        return a.p[`${b}`]??=jit();
+        if(k in a.p)return a.p[k];
+        let f;
+        const js = () => f ??= jit(), [warm, hot] = a.tiering();
+        return a.p[k] = tier(warm, () => a.ib(b), () => a.p[k] = a.get_wasm_tier()
+            ? tier(hot, () => js()(), () => a.p[k] = wasm(a,b,js) || js())
+            : js());
     }
+    // `f` for the first `n` entries, then what `up` puts in its place; blocks
+    // that cached this one go on to that too
+    const tier = (n, f, up) => {
+        if(n <= 0)return up();
+        let g;
+        return () => (g ?? (n-- > 0 ? f : g = up()))();
+    };
+    // The block at `b` compiled to WebAssembly and wrapped as a JavaScript
+    // one, or nothing if it cannot be instantiated; `jit` compiles it to
+    // JavaScript instead
+    const wasm = (a,b,jit) => {
+        const at = a.wr, memory = a._sys("memory"), r = a._r ??= a.r;
+        const view = () => new DataView(memory.buffer, at, 280);
+        // The block's page caches, emptied whenever it is entered
+        const tlb = () => new Uint8Array(memory.buffer, at + 280, 512);
+        // The registers move into the register file for the block and back
+        // out before the reactor looks at them
+        const into = () => {
+            const d = view();
+            for(let i = 1; i < 32; i++)d.setBigUint64(8 * i, r[`x${i}`] ?? 0n, true);
+            d.setBigUint64(264, BigInt(r.i ??= 0), true);
+            d.setBigUint64(272, r.t ?? a.f, true);
+            tlb().fill(255);
+        };
+        const out = () => {
+            const d = view();
+            for(let i = 1; i < 32; i++)r[`x${i}`] = d.getBigUint64(8 * i, true);
+            r.i = Number(d.getBigUint64(264, true));
+        };
+        // Raising or stopping leaves the block, with `k` to continue with
+        let k, left;
+        const exit = f => (...x) => (out(), left = true, k = f(...x), 0n);
+        // Devices see the instruction count and may move the deadline
+        const io = f => (...x) => {
+            r.i = Number(view().getBigUint64(264, true));
+            const v = f(...x);
+            view().setBigUint64(272, r.t ?? a.f, true);
+            return v;
+        };
+        let run;
+        try{
+            run = new WebAssembly.Instance(new WebAssembly.Module(a.jw(b)), {v: {
+                memory,
+                drop_block: p => drop_block(a, p),
+                interrupt: exit(p => a.interrupt(p)),
+                load: io((p, n) => a.load(p, BigInt(n))),
+                store: io((p, n, v) => a.store(p, BigInt(n), v)),
+                trap: exit((c, t, p) => a.trap(c, t, p)),
+                ecall: exit(p => a.ecall(p)),
+                ebreak: exit((p, n) => a.ebreak(p, n)),
+                page: (p, k) => a.page(p, k),
+            }}).exports[`${b}`];
+        }catch(err){
+            console.error(err);
+            return;
+        }
+        return () => {
+            into();
+            const i = r.i;
+            left = false;
+            let pc;
+            try{
+                pc = BigInt.asUintN(64, run(at));
+            }catch(e){
+                if(!left)out();
+                if(!a.trapped)throw e;
+                return a.raise(view().getBigUint64(256, true));
+            }
+            if(left)return k;
+            out();
+            // Nothing ran, so the Wasm tier does not cover the first instruction
+            if(pc === b && r.i === i)return drop_block(a, b), a.p[`${b}`] = jit();
+            return get$(a, pc);
+        };
+    };
     export function on(){
         return Object.create(null)
     }
     export function tget(a,b){
         return (a._p??=a.p)[`${b}`]
     }
+    export function interp_at(a,b){
+        return ()=>a.interp(b);
+    }
+    // Forget the block at `b`, cutting the links other blocks hold to it so
+    // they look it up again
+    export function drop_block(a,b){
+        const p = BigInt.asUintN(64, b), k = `${p}`;
+        delete (a._p??=a.p)[k];
+        // Links are properties by pc; jump caches hold pc and block in turn
+        for(const [t, o] of a._l?.[k] ?? []){
+            const i = Array.isArray(t) ? t.indexOf(p) : -1;
+            if(i >= 0)t.splice(i, 2);
+            else delete t[k];
+            a._o?.[o]?.delete(p);
+        }
+        delete a._l?.[k];
+        // The links and caches the block itself held go with it
+        for(const q of a._o?.[k] ?? [])
+            for(const [t, o] of a._l?.[q] ?? [])if(o === p)a._l[q].delete(t);
+        delete a._o?.[k];
+    }
     export async function l(a){
         while(typeof a === "function")a = await a();
         return a;
@@ -73,6 +254,21 @@ macro_rules! vane_meta {
         if(!b)return c;
         return (a._r??=a.r)[`x${b}`]=c;
     }
+    export function freg(a,b){
+        return (a._r??=a.r)[`f${b%32}`]??=0n;
+    }
+    export function set_freg(a,b,c){
+        return (a._r??=a.r)[`f${b%32}`]=c;
+    }
+    export function instret(a){
+        return (a._r??=a.r).i??=0;
+    }
+    export function set_instret(a,b){
+        (a._r??=a.r).i=b;
+    }
+    export function set_irq_deadline(a,b){
+        (a._r??=a.r).t=b;
+    }
     export function get_memory(wasm){
         return wasm.memory;
     }
@@ -90,9 +286,16 @@ macro_rules! vane_meta {
                 #[wasm_bindgen(js_name = "get$")]
                 fn get(a: $t, b: u64) -> $crate::wasm_bindgen::prelude::JsValue;
                 fn tget(a: $t, b: u64) -> $crate::wasm_bindgen::prelude::JsValue;
+                fn drop_block(a: $t, b: u64);
+                fn interp_at(a: $t, b: u64) -> $crate::wasm_bindgen::prelude::JsValue;
                 fn on() -> $crate::wasm_bindgen::prelude::JsValue;
                 fn reg(a: $t, b: u8) -> u64;
                 fn set_reg(a: $t, b: u8, c: u64) -> u64;
+                fn freg(a: $t, b: u8) -> u64;
+                fn set_freg(a: $t, b: u8, c: u64) -> u64;
+                fn instret(a: $t) -> f64;
+                fn set_instret(a: $t, b: f64);
+                fn set_irq_deadline(a: $t, b: f64);
                 fn get_memory(
                     a: $crate::wasm_bindgen::prelude::JsValue,
                 ) -> $crate::wasm_bindgen::prelude::JsValue;
@@ -118,6 +321,542 @@ macro_rules! vane_meta {
                 fn set_reg(self, a: u8, b: u64) -> u64 {
                     set_reg(self, a, b)
                 }
+                fn freg(self, b: u8) -> u64 {
+                    freg(self, b)
+                }
+                fn set_freg(self, a: u8, b: u64) -> u64 {
+                    set_freg(self, a, b)
+                }
+                /// Instructions started so far, counting the current one
+                ///
+                /// Kept as a JS number next to the registers so generated code
+                /// can bump it cheaply; it stays exact up to 2^53.
+                fn instret_raw(self) -> u64 {
+                    instret(self) as u64
+                }
+                fn set_instret_raw(self, a: u64) {
+                    set_instret(self, a as f64)
+                }
+                /// Back `csr` with embedder callbacks
+                pub fn register_csr(&self, csr: u16, hook: $crate::vane_jit::csr::CsrHook) {
+                    self.core.lock().csrs.register(csr, hook);
+                }
+                pub fn unregister_csr(&self, csr: u16) -> Option<$crate::vane_jit::csr::CsrHook> {
+                    self.core.lock().csrs.unregister(csr)
+                }
+                /// Intercept traps before they reach the guest
+                ///
+                /// Handlers run newest first with the trap and the pc of the
+                /// trapping instruction. `Ok(Some(pc))` resumes at `pc`, `Ok(None)`
+                /// passes the trap on and `Err` ends the run with that value.
+                pub fn add_trap_handler(
+                    &self,
+                    handler: impl Fn(
+                            &$t,
+                            &$crate::vane_jit::trap::Trap,
+                            u64,
+                        ) -> Result<Option<u64>, $crate::wasm_bindgen::prelude::JsValue>
+                        + 'static,
+                ) {
+                    self.core
+                        .lock()
+                        .trap_handlers
+                        .insert(0, $crate::alloc::rc::Rc::new(handler));
+                }
+                /// Dispatch `trap` raised by the instruction at `pc`, returning where to continue
+                ///
+                /// The trapping instruction does not retire. Traps no handler takes
+                /// go to the guest's `mtvec`; with no vector installed (`mtvec`
+                /// zero) the run ends with an error instead.
+                pub fn take_trap(
+                    &self,
+                    trap: $crate::vane_jit::trap::Trap,
+                    pc: u64,
+                ) -> Result<u64, $crate::wasm_bindgen::prelude::JsValue> {
+                    let n = self.clone().instret_raw();
+                    self.clone().set_instret_raw(n.saturating_sub(1));
+                    self.deliver(trap, pc)
+                }
+                /// Take the interrupt due before the instruction at `pc`, returning where to continue
+                ///
+                /// Nothing is un-retired; with no interrupt pending and enabled,
+                /// execution goes on at `pc`.
+                pub fn take_interrupt(&self, pc: u64) -> Result<u64, $crate::wasm_bindgen::prelude::JsValue> {
+                    self.arm_interrupts();
+                    let trap = self.core.lock().csrs.pending_interrupt();
+                    match trap {
+                        Some(trap) => self.deliver(trap, pc),
+                        None => Ok(pc),
+                    }
+                }
+                /// Hand `trap` to the handlers, then to the guest
+                fn deliver(
+                    &self,
+                    trap: $crate::vane_jit::trap::Trap,
+                    pc: u64,
+                ) -> Result<u64, $crate::wasm_bindgen::prelude::JsValue> {
+                    // Handlers run unlocked so they may use the reactor themselves
+                    let handlers = self.core.lock().trap_handlers.clone();
+                    let mut to = None;
+                    for h in handlers {
+                        if let Some(pc) = h(self, &trap, pc)? {
+                            to = Some(pc & $crate::vane_jit::xlen_mask(self.core.lock().xlen));
+                            break;
+                        }
+                    }
+                    let to = match to {
+                        Some(to) => to,
+                        None => {
+                            let mut lock = self.core.lock();
+                            if lock.csrs.mtvec == 0 {
+                                return Err($crate::wasm_bindgen::prelude::JsValue::from_str(
+                                    &$crate::alloc::format!("unhandled trap at {pc:#x}: {trap}"),
+                                ));
+                            }
+                            let xlen = lock.xlen;
+                            lock.csrs.trap(&trap, pc, xlen)
+                        }
+                    };
+                    // The privilege level and interrupt enables may have changed
+                    self.arm_interrupts();
+                    Ok(to)
+                }
+                /// Refresh the external `mip` bits and set the interrupt deadline
+                ///
+                /// Called whenever the CLINT, the host's lines or the CSRs that
+                /// gate interrupts change. A pending, enabled interrupt makes the
+                /// deadline zero, so it is taken before the next instruction;
+                /// otherwise it is when the timer fires.
+                fn arm_interrupts(&self) {
+                    let instret = self.clone().instret_raw();
+                    let deadline = {
+                        let mut lock = self.core.lock();
+                        let board = lock.virt.as_mut().map_or(0, |v| v.interrupts());
+                        lock.csrs.lines = lock.irq_lines | lock.clint.pending(instret) | board;
+                        lock.irq_deadline = match lock.csrs.pending_interrupt() {
+                            Some(_) => 0,
+                            None => lock.clint.due(instret),
+                        };
+                        lock.irq_deadline
+                    };
+                    set_irq_deadline(self.clone(), deadline as f64);
+                }
+                /// Stop at `ebreak` with `hook` instead of raising a breakpoint exception
+                ///
+                /// The hook gets the pc execution stopped at and the integer
+                /// registers, which it may change, and says how to go on.
+                pub fn set_break_hook(
+                    &self,
+                    hook: impl Fn(
+                            &$t,
+                            u64,
+                            &mut [u64; 32],
+                        ) -> Result<
+                            $crate::vane_jit::trap::BreakAction,
+                            $crate::wasm_bindgen::prelude::JsValue,
+                        > + 'static,
+                ) {
+                    self.core.lock().break_hook = Some($crate::alloc::rc::Rc::new(hook));
+                }
+                pub fn clear_break_hook(&self) {
+                    let mut lock = self.core.lock();
+                    lock.break_hook = None;
+                    lock.stepping = false;
+                }
+                /// Send console output to `console` as it is written
+                ///
+                /// Console output is what the `virt` board's UART transmits and
+                /// what the SBI debug console writes.
+                pub fn set_console(&self, console: impl Fn(&[u8]) + 'static) {
+                    self.core.lock().console = Some($crate::alloc::rc::Rc::new(console));
+                }
+                /// Stop at `pc` for the break hook, returning where to continue
+                ///
+                /// `next` is where execution goes on: past an `ebreak`, or `pc`
+                /// itself when a single step ends. The `ebreak` does not retire;
+                /// with no hook set it raises a breakpoint exception instead.
+                pub fn debug_break(
+                    &self,
+                    pc: u64,
+                    next: u64,
+                ) -> Result<u64, $crate::wasm_bindgen::prelude::JsValue> {
+                    let (hook, mask) = {
+                        let mut lock = self.core.lock();
+                        lock.stepping = false;
+                        (lock.break_hook.clone(), $crate::vane_jit::xlen_mask(lock.xlen))
+                    };
+                    let hook = match hook {
+                        Some(hook) => hook,
+                        None if pc == next => return Ok(pc),
+                        None => {
+                            let trap = $crate::vane_jit::trap::Trap::exception(
+                                $crate::vane_jit::trap::Exception::Breakpoint,
+                                pc,
+                            );
+                            return self.take_trap(trap, pc);
+                        }
+                    };
+                    if pc != next {
+                        let n = self.clone().instret_raw();
+                        self.clone().set_instret_raw(n.saturating_sub(1));
+                    }
+                    let mut regs: [u64; 32] = $crate::core::array::from_fn(|i| self.clone().reg(i as u8));
+                    // Run unlocked, like the trap handlers
+                    let action = hook(self, pc, &mut regs)?;
+                    for (i, v) in regs.into_iter().enumerate().skip(1) {
+                        self.clone().set_reg(i as u8, v & mask);
+                    }
+                    match action {
+                        $crate::vane_jit::trap::BreakAction::Resume => Ok(next),
+                        $crate::vane_jit::trap::BreakAction::Step => {
+                            self.core.lock().stepping = true;
+                            Ok(next)
+                        }
+                        $crate::vane_jit::trap::BreakAction::Abort => {
+                            Err($crate::wasm_bindgen::prelude::JsValue::from_str(
+                                &$crate::alloc::format!("aborted at breakpoint {pc:#x}"),
+                            ))
+                        }
+                    }
+                }
+                /// Execute an F/D instruction on raw register bits, updating `fflags`
+                fn fpu_op(
+                    &self,
+                    code: u32,
+                    a: u64,
+                    b: u64,
+                    c: u64,
+                ) -> Result<u64, $crate::vane_jit::trap::Trap> {
+                    let mut lock = self.core.lock();
+                    let xlen = lock.xlen;
+                    match $crate::vane_jit::ext::ExtInst::decode(code, xlen) {
+                        Some(($crate::vane_jit::ext::ExtInst::Fp { op, fmt, rm, .. }, _)) => {
+                            $crate::vane_jit::fp::exec(op, fmt, rm, a, b, c, &mut lock.csrs.fcsr)
+                                .ok_or($crate::vane_jit::trap::Trap::illegal(code))
+                        }
+                        _ => Err($crate::vane_jit::trap::Trap::illegal(code)),
+                    }
+                }
+                /// Execute one of the table-driven scalar cryptography instructions
+                fn crypto_op(&self, code: u32, a: u64, b: u64) -> Result<u64, $crate::vane_jit::trap::Trap> {
+                    let xlen = self.core.lock().xlen;
+                    match $crate::vane_jit::ext::ExtInst::decode(code, xlen) {
+                        Some(($crate::vane_jit::ext::ExtInst::Crypto { op, imm, .. }, _)) => {
+                            Ok($crate::vane_jit::crypto::exec(op, a, b, imm, xlen))
+                        }
+                        _ => Err($crate::vane_jit::trap::Trap::illegal(code)),
+                    }
+                }
+                /// Execute a Zicsr instruction, returning the old CSR value for `rd`
+                ///
+                /// `value` is the `rs1` register value or the immediate. The current
+                /// instruction has already been counted, so it is not yet retired.
+                fn csr_op(&self, code: u32, value: u64) -> Result<u64, $crate::vane_jit::trap::Trap> {
+                    use $crate::vane_jit::ext::ExtInst;
+                    let illegal = $crate::vane_jit::trap::Trap::illegal(code);
+                    let xlen = self.core.lock().xlen;
+                    let mask = $crate::vane_jit::xlen_mask(xlen);
+                    let i = match ExtInst::decode(code, xlen) {
+                        Some((i, _)) => i,
+                        None => return Err(illegal),
+                    };
+                    let (op, csr) = match i {
+                        ExtInst::Csr { op, csr, .. } | ExtInst::CsrI { op, csr, .. } => (op, csr),
+                        _ => return Err(illegal),
+                    };
+                    let (read, write) = i.csr_effects().unwrap();
+                    // Hooks run unlocked so they may use the reactor themselves
+                    let hook = match self.core.lock() {
+                        lock => {
+                            lock.csrs.check_access(csr).map_err(|_| illegal)?;
+                            lock.csrs.hook(csr)
+                        }
+                    };
+                    if let Some(hook) = hook {
+                        let old = match read {
+                            true => (hook.read)() & mask,
+                            false => 0,
+                        };
+                        if write {
+                            match &hook.write {
+                                Some(w) => w(op.apply(old, value) & mask),
+                                None => return Err(illegal),
+                            }
+                        }
+                        return Ok(old);
+                    }
+                    let instret = self.clone().instret_raw().saturating_sub(1);
+                    let mut lock = self.core.lock();
+                    // The vector CSRs live with the vector register file
+                    if let Some(old) = lock.vector.read_csr(csr, xlen) {
+                        if write {
+                            lock.vector.write_csr(csr, op.apply(old, value) & mask).map_err(|_| illegal)?;
+                        }
+                        return Ok(old);
+                    }
+                    // `time` counts the CLINT's `mtime`
+                    let counter = match csr {
+                        $crate::vane_jit::csr::TIME | $crate::vane_jit::csr::TIMEH => lock.clint.mtime(instret),
+                        _ => instret,
+                    };
+                    let old = lock.csrs.read(csr, counter, xlen).map_err(|_| illegal)?;
+                    if write {
+                        lock.csrs.write(csr, op.apply(old, value) & mask, xlen).map_err(|_| illegal)?;
+                        drop(lock);
+                        use $crate::vane_jit::csr::{MIDELEG, MIE, MIP, MSTATUS, SIE, SIP, SSTATUS};
+                        if matches!(csr, MSTATUS | SSTATUS | MIE | SIE | MIP | SIP | MIDELEG) {
+                            self.arm_interrupts();
+                        }
+                    }
+                    Ok(old)
+                }
+                /// Execute a vector instruction, returning the value for its `rd`
+                ///
+                /// `a` and `b` are the integer registers named by `VInst::x_srcs`.
+                fn vector_op(&self, code: u32, a: u64, b: u64) -> Result<u64, $crate::vane_jit::trap::Trap> {
+                    use $crate::vane_jit::ext::ExtInst;
+                    let mut lock = self.core.lock();
+                    let xlen = lock.xlen;
+                    let i = match ExtInst::decode(code, xlen) {
+                        Some((ExtInst::Vector(i), _)) => i,
+                        _ => return Err($crate::vane_jit::trap::Trap::illegal(code)),
+                    };
+                    let core = &mut *lock;
+                    let (mem, csrs) = (&mut core.mem, &core.csrs);
+                    let mut access = |a: u64, access: $crate::vane_jit::mmu::Access| {
+                        let a = mem.translate(a, access, csrs, xlen, false)?;
+                        if access == $crate::vane_jit::mmu::Access::Write {
+                            mem.note_store(a);
+                        }
+                        Ok(mem.get_page(a))
+                    };
+                    $crate::vane_jit::vector::exec(i, code, &mut core.vector, a, b, xlen, &mut access)
+                }
+                /// Keep a failed operation's trap for `raise`
+                fn pend(&self, r: Result<u64, $crate::vane_jit::trap::Trap>) -> Option<u64> {
+                    match r {
+                        Ok(v) => Some(v),
+                        Err(t) => {
+                            self.core.lock().pending_trap = Some(t);
+                            None
+                        }
+                    }
+                }
+                /// `mret`, raising an illegal instruction outside machine mode
+                fn mret_op(&self) -> Result<u64, $crate::vane_jit::trap::Trap> {
+                    let r = self.core.lock().csrs.mret();
+                    self.arm_interrupts();
+                    r.ok_or($crate::vane_jit::trap::Trap::illegal(0x30200073))
+                }
+                /// `sret`, raising an illegal instruction where it is not allowed
+                fn sret_op(&self) -> Result<u64, $crate::vane_jit::trap::Trap> {
+                    let r = self.core.lock().csrs.sret();
+                    self.arm_interrupts();
+                    r.ok_or($crate::vane_jit::trap::Trap::illegal(0x10200073))
+                }
+                /// Translate `a` for `access`, noting stores for `fence.i`
+                fn translate(
+                    &self,
+                    a: u64,
+                    access: $crate::vane_jit::mmu::Access,
+                ) -> Result<u64, $crate::vane_jit::trap::Trap> {
+                    let mut lock = self.core.lock();
+                    let xlen = lock.xlen;
+                    let core = &mut *lock;
+                    let a = core.mem.translate(a, access, &core.csrs, xlen, false)?;
+                    if access == $crate::vane_jit::mmu::Access::Write {
+                        core.mem.note_store(a);
+                    }
+                    Ok(a)
+                }
+                /// What backs physical address `pa`
+                fn region(&self, pa: u64) -> $crate::vane_jit::virt::Region {
+                    use $crate::vane_jit::virt::Region;
+                    match &self.core.lock().virt {
+                        Some(v) => v.region(pa),
+                        None if $crate::vane_jit::clint::Clint::contains(pa) => Region::Device,
+                        None => Region::Ram,
+                    }
+                }
+                /// Translate `a` for `access` and find its byte in guest memory
+                ///
+                /// Gives a null pointer for device registers, which have no page.
+                fn page_op(
+                    &self,
+                    a: u64,
+                    access: $crate::vane_jit::mmu::Access,
+                ) -> Result<*mut u8, $crate::vane_jit::trap::Trap> {
+                    use $crate::vane_jit::virt::Region;
+                    let pa = self.translate(a, access)?;
+                    match self.region(pa) {
+                        Region::Ram => Ok(self.core.lock().mem.get_page(pa)),
+                        Region::Device => Ok($crate::core::ptr::null_mut()),
+                        Region::Unmapped => Err($crate::vane_jit::trap::Trap::exception(access.access_fault(), a)),
+                    }
+                }
+                /// Like `page_op`, raising an access fault for device registers
+                fn access(
+                    &self,
+                    a: u64,
+                    access: $crate::vane_jit::mmu::Access,
+                ) -> Result<*mut u8, $crate::vane_jit::trap::Trap> {
+                    let p = self.page_op(a, access)?;
+                    match p.is_null() {
+                        true => Err($crate::vane_jit::trap::Trap::exception(access.access_fault(), a)),
+                        false => Ok(p),
+                    }
+                }
+                /// Load `size` bytes at guest address `a`, zero-extended
+                ///
+                /// An access crossing a page translates each part; with
+                /// `trap_misaligned` set, a misaligned one raises instead.
+                fn load_op(&self, a: u64, size: u64) -> Result<u64, $crate::vane_jit::trap::Trap> {
+                    let mut buf = [0u8; 8];
+                    self.move_op(a, &mut buf[..size as usize], false)?;
+                    Ok(u64::from_le_bytes(buf))
+                }
+                /// Store the low `size` bytes of `v` at guest address `a`, like `load_op`
+                fn store_op(&self, a: u64, size: u64, v: u64) -> Result<(), $crate::vane_jit::trap::Trap> {
+                    self.move_op(a, &mut v.to_le_bytes()[..size as usize], true)
+                }
+                fn move_op(&self, a: u64, buf: &mut [u8], store: bool) -> Result<(), $crate::vane_jit::trap::Trap> {
+                    let (trap_misaligned, mask) = match self.core.lock() {
+                        lock => (lock.trap_misaligned, $crate::vane_jit::xlen_mask(lock.xlen)),
+                    };
+                    if trap_misaligned && a & (buf.len() as u64 - 1) != 0 {
+                        let e = match store {
+                            true => $crate::vane_jit::trap::Exception::StoreMisaligned,
+                            false => $crate::vane_jit::trap::Exception::LoadMisaligned,
+                        };
+                        return Err($crate::vane_jit::trap::Trap::exception(e, a));
+                    }
+                    let access = match store {
+                        true => $crate::vane_jit::mmu::Access::Write,
+                        false => $crate::vane_jit::mmu::Access::Read,
+                    };
+                    let pa = self.translate(a, access)?;
+                    if self.region(pa) == $crate::vane_jit::virt::Region::Device {
+                        self.device_op(pa, buf, store);
+                        return Ok(());
+                    }
+                    $crate::vane_jit::mmu::transfer(&mut |a, access| self.access(a, access), a, buf, store, mask)
+                }
+                /// Load or store device registers at physical address `pa`
+                ///
+                /// Reads may have side effects too, such as claiming a PLIC
+                /// source, so interrupts are re-armed after either.
+                fn device_op(&self, pa: u64, buf: &mut [u8], store: bool) {
+                    // The current instruction has not retired
+                    let instret = self.clone().instret_raw().saturating_sub(1);
+                    let size = buf.len() as u64;
+                    let mut v = [0u8; 8];
+                    v[..buf.len()].copy_from_slice(buf);
+                    let v = u64::from_le_bytes(v);
+                    let output = {
+                        let mut lock = self.core.lock();
+                        let core = &mut *lock;
+                        let clint = $crate::vane_jit::clint::Clint::contains(pa);
+                        let offset = pa.wrapping_sub($crate::vane_jit::clint::BASE);
+                        match (store, &mut core.virt) {
+                            (false, _) if clint => {
+                                let r = core.clint.read(offset, size, instret).to_le_bytes();
+                                buf.copy_from_slice(&r[..buf.len()]);
+                            }
+                            (true, _) if clint => core.clint.write(offset, size, v, instret),
+                            (false, Some(virt)) => {
+                                let r = virt.read(pa, size).to_le_bytes();
+                                buf.copy_from_slice(&r[..buf.len()]);
+                            }
+                            (true, Some(virt)) => virt.write(pa, size, v),
+                            (_, None) => {}
+                        }
+                        core.virt.as_mut().map(|v| $crate::core::mem::take(&mut v.uart.output))
+                    };
+                    if let Some(bytes) = output.filter(|b| !b.is_empty()) {
+                        self.console_out(&bytes);
+                    }
+                    self.arm_interrupts();
+                }
+                /// Pass `bytes` to the console, or keep them for `console_output`
+                fn console_out(&self, bytes: &[u8]) {
+                    let console = {
+                        let mut lock = self.core.lock();
+                        if lock.console.is_none() {
+                            lock.console_output.extend_from_slice(bytes);
+                        }
+                        lock.console.clone()
+                    };
+                    // The console runs unlocked, like the trap handlers
+                    if let Some(console) = console {
+                        console(bytes);
+                    }
+                }
+                /// Fetch the instruction at `pc`, 16 bits of it if compressed
+                ///
+                /// The halves are translated separately, so a 32-bit instruction
+                /// may straddle a page boundary.
+                fn fetch_op(&self, pc: u64) -> Result<u32, $crate::vane_jit::trap::Trap> {
+                    let half = |a: u64| -> Result<u32, $crate::vane_jit::trap::Trap> {
+                        let p = self.access(a, $crate::vane_jit::mmu::Access::Execute)?;
+                        Ok(unsafe { (p as *const u16).read_unaligned() } as u32)
+                    };
+                    let lo = half(pc)?;
+                    if lo & 3 != 3 {
+                        return Ok(lo);
+                    }
+                    let mask = $crate::vane_jit::xlen_mask(self.core.lock().xlen);
+                    Ok(lo | half(pc.wrapping_add(2) & mask)? << 16)
+                }
+                /// The template flags for the hart as it is now; marks blocks
+                /// as compiling from `a` until `compiling` is cleared
+                fn jit_flags(&self, a: u64) -> $crate::vane_jit::template::Flags {
+                    self.core.lock().compiling = Some(a);
+                    self.flags()
+                }
+                /// The template flags for the hart as it is now
+                fn flags(&self) -> $crate::vane_jit::template::Flags {
+                    let lock = self.core.lock();
+                    $crate::vane_jit::template::Flags::with_paging(
+                        lock.test_mode,
+                        lock.mem.paging_mode,
+                        lock.mem.shared_page_table_vaddr,
+                        lock.mem.shared_security_directory_vaddr,
+                        lock.mem.use_32bit_paging,
+                        lock.mem.use_multilevel_paging,
+                    )
+                    .with_xlen(lock.xlen)
+                    .with_trap_misaligned(lock.trap_misaligned)
+                    .with_tiering(lock.js_threshold, lock.wasm_threshold)
+                    .with_region_budget(lock.region_insts, lock.region_bytes)
+                }
+                /// Keep a failed access's trap for `raise` and throw to the block
+                fn pend_fault<T>(
+                    &self,
+                    r: Result<T, $crate::vane_jit::trap::Trap>,
+                ) -> Result<T, $crate::wasm_bindgen::prelude::JsValue> {
+                    r.map_err(|t| {
+                        let e = $crate::wasm_bindgen::prelude::JsValue::from_str(
+                            &$crate::alloc::format!("{t}"),
+                        );
+                        self.core.lock().pending_trap = Some(t);
+                        e
+                    })
+                }
+                /// `fence.i`: drop every cached block read from a page stored to since the last one
+                fn fence_i_op(&self) {
+                    let roots = self.core.lock().mem.fence_i();
+                    for root in roots {
+                        drop_block(self.clone(), root);
+                    }
+                }
+                /// Translate `a` without faulting, for reservations
+                fn reservation_addr(&self, a: u64) -> u64 {
+                    let mut lock = self.core.lock();
+                    let xlen = lock.xlen;
+                    let core = &mut *lock;
+                    let access = $crate::vane_jit::mmu::Access::Write;
+                    core.mem.translate(a, access, &core.csrs, xlen, true).unwrap_or(a)
+                }
             }
             #[$crate::wasm_bindgen::prelude::wasm_bindgen(wasm_bindgen = $crate::wasm_bindgen)]
             impl $t {
@@ -139,7 +878,281 @@ macro_rules! vane_meta {
                 pub fn set_test_mode(&self, value: bool) {
                     self.core.lock().test_mode = value;
                 }
-                
+                #[wasm_bindgen(getter, js_name = "xlen",wasm_bindgen = $crate::wasm_bindgen)]
+                pub fn get_xlen(&self) -> u32 {
+                    match self.core.lock().xlen {
+                        $crate::vane_jit::rv_asm::Xlen::Rv32 => 32,
+                        $crate::vane_jit::rv_asm::Xlen::Rv64 => 64,
+                    }
+                }
+                #[wasm_bindgen(setter, js_name = "xlen",wasm_bindgen = $crate::wasm_bindgen)]
+                pub fn set_xlen(&self, value: u32) {
+                    self.core.lock().xlen = match value {
+                        32 => $crate::vane_jit::rv_asm::Xlen::Rv32,
+                        _ => $crate::vane_jit::rv_asm::Xlen::Rv64,
+                    };
+                }
+                #[wasm_bindgen(getter, js_name = "fcsr",wasm_bindgen = $crate::wasm_bindgen)]
+                pub fn get_fcsr(&self) -> u32 {
+                    self.core.lock().csrs.fcsr
+                }
+                #[wasm_bindgen(setter, js_name = "fcsr",wasm_bindgen = $crate::wasm_bindgen)]
+                pub fn set_fcsr(&self, value: u32) {
+                    self.core.lock().csrs.fcsr = value & 0xff;
+                }
+                /// Execute an F/D instruction on raw register bits, updating `fflags`
+                ///
+                /// Returns `undefined` with the trap left for `raise` if the
+                /// instruction is illegal.
+                #[wasm_bindgen(wasm_bindgen = $crate::wasm_bindgen)]
+                pub fn fpu(&self, code: u32, a: u64, b: u64, c: u64) -> Option<u64> {
+                    let r = self.fpu_op(code, a, b, c);
+                    self.pend(r)
+                }
+                /// Execute a scalar cryptography instruction on `rs1` and `rs2`
+                ///
+                /// Returns `undefined` with the trap left for `raise` if the
+                /// instruction is illegal.
+                #[wasm_bindgen(wasm_bindgen = $crate::wasm_bindgen)]
+                pub fn crypto(&self, code: u32, a: u64, b: u64) -> Option<u64> {
+                    let r = self.crypto_op(code, a, b);
+                    self.pend(r)
+                }
+                /// Execute a vector instruction, giving the value for its `rd`
+                ///
+                /// Returns `undefined` with the trap left for `raise` if the
+                /// instruction is illegal or faults.
+                #[wasm_bindgen(wasm_bindgen = $crate::wasm_bindgen)]
+                pub fn vec(&self, code: u32, a: u64, b: u64) -> Option<u64> {
+                    let r = self.vector_op(code, a, b);
+                    self.pend(r)
+                }
+                /// VLEN in bits; setting it resets the vector unit
+                #[wasm_bindgen(getter, js_name = "vlen",wasm_bindgen = $crate::wasm_bindgen)]
+                pub fn get_vlen(&self) -> u32 {
+                    self.core.lock().vector.vlen()
+                }
+                #[wasm_bindgen(setter, js_name = "vlen",wasm_bindgen = $crate::wasm_bindgen)]
+                pub fn set_vlen(&self, value: u32) {
+                    self.core.lock().vector = $crate::vane_jit::vector::Vector::new(value);
+                }
+                /// The bytes of vector register `n`, least significant first
+                #[wasm_bindgen(wasm_bindgen = $crate::wasm_bindgen)]
+                pub fn vreg(&self, n: u8) -> $crate::alloc::vec::Vec<u8> {
+                    self.core.lock().vector.reg(n).to_vec()
+                }
+                /// Overwrite the start of vector register `n` with `bytes`
+                #[wasm_bindgen(wasm_bindgen = $crate::wasm_bindgen)]
+                pub fn set_vreg(&self, n: u8, bytes: &[u8]) {
+                    let mut lock = self.core.lock();
+                    let reg = lock.vector.reg_mut(n);
+                    let len = bytes.len().min(reg.len());
+                    reg[..len].copy_from_slice(&bytes[..len]);
+                }
+                #[wasm_bindgen(getter, js_name = "vl",wasm_bindgen = $crate::wasm_bindgen)]
+                pub fn get_vl(&self) -> u64 {
+                    self.core.lock().vector.vl
+                }
+                /// `vtype` as `csrr` would read it
+                #[wasm_bindgen(getter, js_name = "vtype",wasm_bindgen = $crate::wasm_bindgen)]
+                pub fn get_vtype(&self) -> u64 {
+                    let lock = self.core.lock();
+                    lock.vector.read_csr($crate::vane_jit::csr::VTYPE, lock.xlen).unwrap()
+                }
+                /// Instructions retired so far; backs `cycle`, `instret` and the CLINT's `mtime`
+                #[wasm_bindgen(getter, js_name = "instret",wasm_bindgen = $crate::wasm_bindgen)]
+                pub fn get_instret(&self) -> u64 {
+                    self.clone().instret_raw()
+                }
+                /// Execute a Zicsr instruction, returning the old CSR value for `rd`
+                ///
+                /// Returns `undefined` with the trap left for `raise` if the
+                /// access is illegal.
+                #[wasm_bindgen(wasm_bindgen = $crate::wasm_bindgen)]
+                pub fn csr(&self, code: u32, value: u64) -> Option<u64> {
+                    let r = self.csr_op(code, value);
+                    self.pend(r)
+                }
+                /// Return from a machine-mode trap, giving the new pc
+                #[wasm_bindgen(wasm_bindgen = $crate::wasm_bindgen)]
+                pub fn mret(&self) -> Option<u64> {
+                    let r = self.mret_op();
+                    self.pend(r)
+                }
+                /// Return from a supervisor trap, giving the new pc
+                #[wasm_bindgen(wasm_bindgen = $crate::wasm_bindgen)]
+                pub fn sret(&self) -> Option<u64> {
+                    let r = self.sret_op();
+                    self.pend(r)
+                }
+                /// Check that `sfence.vma` (encoded as `code`) may execute
+                ///
+                /// Every access walks the page tables, so there is nothing to
+                /// flush. Returns `false` with the trap left for `raise` otherwise.
+                #[wasm_bindgen(wasm_bindgen = $crate::wasm_bindgen)]
+                pub fn sfence_vma(&self, code: u32) -> bool {
+                    let mut lock = self.core.lock();
+                    if !lock.csrs.sfence_allowed() {
+                        lock.pending_trap = Some($crate::vane_jit::trap::Trap::illegal(code));
+                        return false;
+                    }
+                    true
+                }
+                /// Whether a trap is waiting for `raise`
+                #[wasm_bindgen(getter, js_name = "trapped",wasm_bindgen = $crate::wasm_bindgen)]
+                pub fn get_trapped(&self) -> bool {
+                    self.core.lock().pending_trap.is_some()
+                }
+                /// Raise exception `code` at `pc`, returning the block to continue with
+                #[wasm_bindgen(wasm_bindgen = $crate::wasm_bindgen)]
+                pub fn trap(
+                    &self,
+                    code: u64,
+                    tval: u64,
+                    pc: u64,
+                ) -> Result<$crate::wasm_bindgen::prelude::JsValue, $crate::wasm_bindgen::prelude::JsValue> {
+                    let trap = $crate::vane_jit::trap::Trap {
+                        code,
+                        interrupt: false,
+                        tval,
+                    };
+                    Ok(self.jit(self.take_trap(trap, pc)?))
+                }
+                /// Raise the trap left by a reactor call at `pc`
+                #[wasm_bindgen(wasm_bindgen = $crate::wasm_bindgen)]
+                pub fn raise(
+                    &self,
+                    pc: u64,
+                ) -> Result<$crate::wasm_bindgen::prelude::JsValue, $crate::wasm_bindgen::prelude::JsValue> {
+                    let trap = self.core.lock().pending_trap.take();
+                    match trap {
+                        Some(trap) => Ok(self.jit(self.take_trap(trap, pc)?)),
+                        None => Err($crate::wasm_bindgen::prelude::JsValue::from_str("no pending trap")),
+                    }
+                }
+                /// Raise the environment call for the current privilege level at `pc`
+                #[wasm_bindgen(wasm_bindgen = $crate::wasm_bindgen)]
+                pub fn ecall(
+                    &self,
+                    pc: u64,
+                ) -> Result<$crate::wasm_bindgen::prelude::JsValue, $crate::wasm_bindgen::prelude::JsValue> {
+                    let mode = self.core.lock().csrs.mode;
+                    let trap = $crate::vane_jit::trap::Trap::exception(
+                        $crate::vane_jit::trap::Exception::ecall(mode),
+                        0,
+                    );
+                    Ok(self.jit(self.take_trap(trap, pc)?))
+                }
+                /// Stop for the host at the `ebreak` at `pc`, which `next` follows
+                #[wasm_bindgen(wasm_bindgen = $crate::wasm_bindgen)]
+                pub fn ebreak(
+                    &self,
+                    pc: u64,
+                    next: u64,
+                ) -> Result<$crate::wasm_bindgen::prelude::JsValue, $crate::wasm_bindgen::prelude::JsValue> {
+                    Ok(self.jit(self.debug_break(pc, next)?))
+                }
+                /// Take the interrupt due before the instruction at `pc`, returning the block to continue with
+                #[wasm_bindgen(wasm_bindgen = $crate::wasm_bindgen)]
+                pub fn interrupt(
+                    &self,
+                    pc: u64,
+                ) -> Result<$crate::wasm_bindgen::prelude::JsValue, $crate::wasm_bindgen::prelude::JsValue> {
+                    Ok(self.jit(self.take_interrupt(pc)?))
+                }
+                /// Hold interrupt `code` pending, as an interrupt controller would
+                ///
+                /// It stays pending until `lower_interrupt`; codes that are not
+                /// interrupts are ignored.
+                #[wasm_bindgen(wasm_bindgen = $crate::wasm_bindgen)]
+                pub fn raise_interrupt(&self, code: u64) {
+                    if let Some(i) = $crate::vane_jit::trap::Interrupt::from_code(code) {
+                        self.core.lock().irq_lines |= i.bit();
+                        self.arm_interrupts();
+                    }
+                }
+                #[wasm_bindgen(wasm_bindgen = $crate::wasm_bindgen)]
+                pub fn lower_interrupt(&self, code: u64) {
+                    if let Some(i) = $crate::vane_jit::trap::Interrupt::from_code(code) {
+                        self.core.lock().irq_lines &= !i.bit();
+                        self.arm_interrupts();
+                    }
+                }
+                /// Move `mtime` forward by `ticks` without running instructions
+                #[wasm_bindgen(wasm_bindgen = $crate::wasm_bindgen)]
+                pub fn advance_time(&self, ticks: u64) {
+                    {
+                        let mut lock = self.core.lock();
+                        lock.clint.offset = lock.clint.offset.wrapping_add(ticks);
+                    }
+                    self.arm_interrupts();
+                }
+                /// The CLINT's `mtime`, which counts retired instructions unless advanced
+                #[wasm_bindgen(getter, js_name = "mtime",wasm_bindgen = $crate::wasm_bindgen)]
+                pub fn get_mtime(&self) -> u64 {
+                    let instret = self.clone().instret_raw();
+                    self.core.lock().clint.mtime(instret)
+                }
+                /// Switch to the `virt` board with `ram_size` bytes of RAM at `0x8000_0000`
+                ///
+                /// Blocks compiled earlier may have read what is now device
+                /// memory, so set this before running.
+                #[wasm_bindgen(wasm_bindgen = $crate::wasm_bindgen)]
+                pub fn use_virt(&self, ram_size: u64) {
+                    self.core.lock().virt = Some($crate::vane_jit::virt::Virt::new(ram_size));
+                }
+                /// The `virt` board's device tree blob, empty without the board
+                #[wasm_bindgen(wasm_bindgen = $crate::wasm_bindgen)]
+                pub fn device_tree(&self, bootargs: &str) -> $crate::alloc::vec::Vec<u8> {
+                    let lock = self.core.lock();
+                    match &lock.virt {
+                        Some(v) => v.device_tree(lock.xlen, bootargs),
+                        None => $crate::alloc::vec::Vec::new(),
+                    }
+                }
+                /// Copy the device tree to the top of RAM and pass it as firmware expects
+                ///
+                /// `a0` gets the hart ID and `a1` the blob's address, which is
+                /// also returned. Without the `virt` board nothing happens.
+                #[wasm_bindgen(wasm_bindgen = $crate::wasm_bindgen)]
+                pub fn load_device_tree(&self, bootargs: &str) -> Option<u64> {
+                    let dtb = self.device_tree(bootargs);
+                    let addr = {
+                        let mut lock = self.core.lock();
+                        let addr = lock.virt.as_ref()?.device_tree_addr(dtb.len() as u64);
+                        for (i, b) in dtb.iter().enumerate() {
+                            lock.mem.write_byte(addr + i as u64, *b);
+                        }
+                        addr
+                    };
+                    self.clone().set_reg(10, 0);
+                    self.clone().set_reg(11, addr);
+                    Some(addr)
+                }
+                /// Queue bytes for the `virt` board's UART to receive
+                #[wasm_bindgen(wasm_bindgen = $crate::wasm_bindgen)]
+                pub fn uart_input(&self, bytes: &[u8]) {
+                    if let Some(v) = self.core.lock().virt.as_mut() {
+                        v.uart.input(bytes);
+                    }
+                    self.arm_interrupts();
+                }
+                /// Take the console output written since the last call, when no console is set
+                #[wasm_bindgen(wasm_bindgen = $crate::wasm_bindgen)]
+                pub fn console_output(&self) -> $crate::alloc::vec::Vec<u8> {
+                    $crate::core::mem::take(&mut self.core.lock().console_output)
+                }
+                /// Queue bytes for the SBI debug console to read
+                #[wasm_bindgen(wasm_bindgen = $crate::wasm_bindgen)]
+                pub fn console_input(&self, bytes: &[u8]) {
+                    self.core.lock().console_input.extend(bytes);
+                }
+                /// Current privilege level as in `mstatus.MPP`
+                #[wasm_bindgen(getter, js_name = "mode",wasm_bindgen = $crate::wasm_bindgen)]
+                pub fn get_mode(&self) -> u8 {
+                    self.core.lock().csrs.mode as u8
+                }
+
                 #[wasm_bindgen(js_name = "get_paging_mode",wasm_bindgen = $crate::wasm_bindgen)]
                 pub fn get_paging_mode(&self) -> String {
                     match self.core.lock().mem.paging_mode {
@@ -200,6 +1213,64 @@ macro_rules! vane_meta {
                     self.core.lock().mem.use_multilevel_paging = value;
                 }
                 
+                #[wasm_bindgen(js_name = "get_trap_misaligned",wasm_bindgen = $crate::wasm_bindgen)]
+                pub fn get_trap_misaligned(&self) -> bool {
+                    self.core.lock().trap_misaligned
+                }
+
+                /// Trap on misaligned loads and stores rather than perform them
+                ///
+                /// Blocks compiled earlier keep the previous behaviour, so set
+                /// this before running.
+                #[wasm_bindgen(js_name = "set_trap_misaligned",wasm_bindgen = $crate::wasm_bindgen)]
+                pub fn set_trap_misaligned(&self, value: bool) {
+                    self.core.lock().trap_misaligned = value;
+                }
+
+                #[wasm_bindgen(js_name = "get_wasm_tier",wasm_bindgen = $crate::wasm_bindgen)]
+                pub fn get_wasm_tier(&self) -> bool {
+                    self.core.lock().wasm_tier
+                }
+
+                /// Move hot blocks on from JavaScript to WebAssembly
+                ///
+                /// Blocks whose first instruction the Wasm tier does not cover,
+                /// and any the host cannot instantiate, still run as JavaScript.
+                #[wasm_bindgen(js_name = "set_wasm_tier",wasm_bindgen = $crate::wasm_bindgen)]
+                pub fn set_wasm_tier(&self, value: bool) {
+                    self.core.lock().wasm_tier = value;
+                }
+
+                /// The `js_threshold` and `wasm_threshold` of the current `Flags`
+                #[wasm_bindgen(js_name = "tiering",wasm_bindgen = $crate::wasm_bindgen)]
+                pub fn tiering(&self) -> $crate::alloc::vec::Vec<u32> {
+                    let flags = self.flags();
+                    $crate::alloc::vec![flags.js_threshold, flags.wasm_threshold]
+                }
+
+                /// Set how many entries a block makes in each tier before moving up
+                ///
+                /// A block is interpreted for its first `js` entries, then runs as
+                /// JavaScript, moving to WebAssembly after `wasm` more when the
+                /// Wasm tier is on. Blocks already compiled keep their counts.
+                #[wasm_bindgen(js_name = "set_tiering",wasm_bindgen = $crate::wasm_bindgen)]
+                pub fn set_tiering(&self, js: u32, wasm: u32) {
+                    let mut lock = self.core.lock();
+                    lock.js_threshold = js;
+                    lock.wasm_threshold = wasm;
+                }
+
+                /// Bound each compiled block to `insts` instructions and `bytes` of JavaScript
+                ///
+                /// A block stops inlining at the first new instruction past either
+                /// budget and jumps to it instead; `None` leaves that one unbounded.
+                #[wasm_bindgen(js_name = "set_region_budget",wasm_bindgen = $crate::wasm_bindgen)]
+                pub fn set_region_budget(&self, insts: Option<u32>, bytes: Option<usize>) {
+                    let mut lock = self.core.lock();
+                    lock.region_insts = insts;
+                    lock.region_bytes = bytes;
+                }
+
                 #[wasm_bindgen(wasm_bindgen = $crate::wasm_bindgen)]
                 pub fn _sys(&self, a: &str) -> $crate::wasm_bindgen::prelude::JsValue {
                     match a {
@@ -207,15 +1278,101 @@ macro_rules! vane_meta {
                         _ => $crate::wasm_bindgen::prelude::JsValue::undefined(),
                     }
                 }
+                /// Find the byte at guest address `a` for a load
+                ///
+                /// Throws with the page fault left for `raise` if `a` is not
+                /// readable at the current privilege.
                 #[wasm_bindgen(wasm_bindgen = $crate::wasm_bindgen)]
-                pub fn get_page(&self, a: u64) -> *mut u8 {
-                    let mut lock = self.core.lock();
-                    match &mut lock.mem {
-                        m => m.get_page(a),
+                pub fn get_page(&self, a: u64) -> Result<*mut u8, $crate::wasm_bindgen::prelude::JsValue> {
+                    let r = self.page_op(a, $crate::vane_jit::mmu::Access::Read);
+                    self.pend_fault(r)
+                }
+                /// Find the byte at guest address `a` for a store or AMO
+                ///
+                /// Both give zero for device registers, which `load` and `store` serve.
+                #[wasm_bindgen(wasm_bindgen = $crate::wasm_bindgen)]
+                pub fn store_page(&self, a: u64) -> Result<*mut u8, $crate::wasm_bindgen::prelude::JsValue> {
+                    let r = self.page_op(a, $crate::vane_jit::mmu::Access::Write);
+                    self.pend_fault(r)
+                }
+                /// Where the 4 KiB page holding guest address `a` starts in
+                /// memory, for a WebAssembly block's page cache
+                ///
+                /// `access` is 0 to read, 1 to write and 2 to execute. Device
+                /// registers give zero, as do stores while a reservation is
+                /// held, for `load` and `store` to serve; faults throw as for
+                /// `get_page`.
+                #[wasm_bindgen(wasm_bindgen = $crate::wasm_bindgen)]
+                pub fn page(&self, a: u64, access: i32) -> Result<u32, $crate::wasm_bindgen::prelude::JsValue> {
+                    use $crate::vane_jit::mmu::Access;
+                    let r = match access {
+                        0 => self.page_op(a, Access::Read),
+                        1 => self.page_op(a, Access::Write),
+                        _ => self.access(a, Access::Execute),
+                    };
+                    let p = self.pend_fault(r)?;
+                    if p.is_null() || (access == 1 && self.core.lock().mem.reservation.is_some()) {
+                        return Ok(0);
                     }
+                    Ok((p as usize - (a & 0xfff) as usize) as u32)
+                }
+                /// Load `size` bytes at guest address `a`, zero-extended
+                ///
+                /// Throws with the trap left for `raise` if the load faults.
+                #[wasm_bindgen(wasm_bindgen = $crate::wasm_bindgen)]
+                pub fn load(&self, a: u64, size: u64) -> Result<u64, $crate::wasm_bindgen::prelude::JsValue> {
+                    let r = self.load_op(a, size);
+                    self.pend_fault(r)
+                }
+                /// Store the low `size` bytes of `v` at guest address `a`, like `load`
+                #[wasm_bindgen(wasm_bindgen = $crate::wasm_bindgen)]
+                pub fn store(&self, a: u64, size: u64, v: u64) -> Result<(), $crate::wasm_bindgen::prelude::JsValue> {
+                    let r = self.store_op(a, size, v);
+                    self.pend_fault(r)
+                }
+                /// Throw an address-misaligned exception for the access at `a`
+                #[wasm_bindgen(wasm_bindgen = $crate::wasm_bindgen)]
+                pub fn misaligned(&self, a: u64, store: bool) -> Result<(), $crate::wasm_bindgen::prelude::JsValue> {
+                    let e = match store {
+                        true => $crate::vane_jit::trap::Exception::StoreMisaligned,
+                        false => $crate::vane_jit::trap::Exception::LoadMisaligned,
+                    };
+                    self.pend_fault(Err($crate::vane_jit::trap::Trap::exception(e, a)))
+                }
+                /// Fetch the instruction at `pc` for a block's guard
+                #[wasm_bindgen(wasm_bindgen = $crate::wasm_bindgen)]
+                pub fn fetch(&self, pc: u64) -> Result<u32, $crate::wasm_bindgen::prelude::JsValue> {
+                    let r = self.fetch_op(pc);
+                    self.pend_fault(r)
+                }
+                /// Synchronise instruction fetch with earlier stores
+                #[wasm_bindgen(wasm_bindgen = $crate::wasm_bindgen)]
+                pub fn fence_i(&self) {
+                    self.fence_i_op()
+                }
+                /// Forget the block compiled at `root`, whose code changed
+                #[wasm_bindgen(js_name = "drop_block",wasm_bindgen = $crate::wasm_bindgen)]
+                pub fn drop_block_at(&self, root: u64) {
+                    drop_block(self.clone(), root)
+                }
+                /// Reserve `a` for a later `sc`; called after the `lr` load
+                #[wasm_bindgen(wasm_bindgen = $crate::wasm_bindgen)]
+                pub fn lr(&self, a: u64) {
+                    let a = self.reservation_addr(a);
+                    self.core.lock().mem.reserve(a);
+                }
+                /// Consume the reservation, returning whether an `sc` to `a` may store
+                #[wasm_bindgen(wasm_bindgen = $crate::wasm_bindgen)]
+                pub fn sc(&self, a: u64) -> bool {
+                    let a = self.reservation_addr(a);
+                    self.core.lock().mem.check_reservation(a)
                 }
                 #[wasm_bindgen(js_name = "J",wasm_bindgen = $crate::wasm_bindgen)]
                 pub fn jit(&self, a: u64) -> $crate::wasm_bindgen::prelude::JsValue {
+                    // The interpreter stops after the one instruction of a step
+                    if self.core.lock().stepping {
+                        return interp_at(self.clone(), a);
+                    }
                     return get(self.clone(), a);
                 }
                 #[wasm_bindgen]
@@ -232,25 +1389,10 @@ macro_rules! vane_meta {
                 #[wasm_bindgen(js_name = "j",wasm_bindgen = $crate::wasm_bindgen)]
                 pub fn jit_code(&self, a: u64) -> String {
                     let f = $flate;
-                    let lock = self.core.lock();
-                    let test_mode = lock.test_mode;
-                    let paging_mode = lock.mem.paging_mode;
-                    let shared_page_table_vaddr = lock.mem.shared_page_table_vaddr;
-                    let shared_security_directory_vaddr = lock.mem.shared_security_directory_vaddr;
-                    let use_32bit_paging = lock.mem.use_32bit_paging;
-                    let use_multilevel_paging = lock.mem.use_multilevel_paging;
-                    drop(lock);
-                    
-                    let flags = $crate::vane_jit::template::Flags::with_paging(
-                        test_mode,
-                        paging_mode,
-                        shared_page_table_vaddr,
-                        shared_security_directory_vaddr,
-                        use_32bit_paging,
-                        use_multilevel_paging,
-                    );
-                    
-                    return ($crate::vane_jit::template::CoreJS {
+                    let regs = $crate::vane_jit::template::RegCache::default();
+                    let region = $crate::vane_jit::template::Region::default();
+                    let flags = self.jit_flags(a);
+                    let code = ($crate::vane_jit::template::CoreJS {
                         content: &$y(&$crate::vane_jit::template::TemplateJit {
                             params: Params {
                                 react: self,
@@ -262,6 +1404,8 @@ macro_rules! vane_meta {
                                 },
                                 root: a,
                                 flate: &f,
+                                regs: &regs,
+                                region: &region,
                                 flags,
                             },
                             pc: a,
@@ -269,9 +1413,61 @@ macro_rules! vane_meta {
                             depth: 0,
                         }),
                         flate: &f,
+                        regs: &regs,
+                        region: &region,
+                        root: a,
                         flags,
                     }
                     .to_string());
+                    self.core.lock().compiling = None;
+                    code
+                }
+                /// Compile the block at `a` to a WebAssembly module exporting it by `a` in decimal
+                ///
+                /// The block stops at the first instruction the Wasm tier does not
+                /// cover; if that is the one at `a`, it returns `a` having run nothing.
+                #[wasm_bindgen(js_name = "jw",wasm_bindgen = $crate::wasm_bindgen)]
+                pub fn jit_wasm(
+                    &self,
+                    a: u64,
+                ) -> Result<$crate::alloc::vec::Vec<u8>, $crate::wasm_bindgen::prelude::JsValue> {
+                    let f = $flate;
+                    let flags = self.jit_flags(a);
+                    let jit = $crate::vane_jit::template::TemplateJit {
+                        params: Params {
+                            react: self,
+                            trial: &|a| match tget(self.clone(), a)
+                                != $crate::wasm_bindgen::prelude::JsValue::UNDEFINED
+                            {
+                                true => $crate::vane_jit::Heat::Cached,
+                                false => $crate::vane_jit::Heat::New,
+                            },
+                            root: a,
+                            flate: &f,
+                            regs: &$crate::vane_jit::template::RegCache::default(),
+                            region: &$crate::vane_jit::template::Region::default(),
+                            flags,
+                        },
+                        pc: a,
+                        labels: &$crate::vane_jit::template::Labels::default(),
+                        depth: 0,
+                    };
+                    let block = $y(&jit);
+                    let ctx = $crate::vane_jit::template::wasm::BlockCtx::default();
+                    let mut module = $crate::vane_jit::template::wasm::ModuleBuilder::new();
+                    module.block(a, &ctx, $crate::vane_jit::WasmJit::jit(&block, &ctx));
+                    self.core.lock().compiling = None;
+                    match module.finish() {
+                        Ok(m) => Ok(m.bytes),
+                        Err(e) => Err($crate::wasm_bindgen::prelude::JsValue::from_str(
+                            &$crate::alloc::format!("{e}"),
+                        )),
+                    }
+                }
+                /// Where WebAssembly blocks find the register file in `memory`
+                #[wasm_bindgen(getter, js_name = "wr",wasm_bindgen = $crate::wasm_bindgen)]
+                pub fn wasm_regs(&self) -> *mut u64 {
+                    self.core.lock().wasm_regs.as_mut_ptr()
                 }
                 #[wasm_bindgen(getter, js_name = "f",wasm_bindgen = $crate::wasm_bindgen)]
                 pub fn u64_max(&self) -> u64 {
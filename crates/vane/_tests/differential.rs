@@ -0,0 +1,541 @@
+//! Differential tests between the interpreter and the JavaScript tier
+//!
+//! Each program runs once through the interpreter and once through the
+//! JavaScript tier, and both must leave the same registers behind. Programs
+//! note where their jumps landed with `auipc`, so the registers cover the pc
+//! as well as link values.
+#![cfg(target_arch = "wasm32")]
+
+extern crate wasm_bindgen_test;
+
+use wasm_bindgen_test::*;
+
+use crate::*;
+use vane_jit::Mem;
+
+wasm_bindgen_test_configure!(run_in_browser);
+
+/// Where every program is loaded and entered
+const BASE: u64 = 0x1000;
+
+/// Encodings of the instructions the programs use
+mod enc {
+    pub const fn lui(rd: u32, imm: u32) -> u32 {
+        imm << 12 | rd << 7 | 0x37
+    }
+    pub const fn addi(rd: u32, rs1: u32, imm: i32) -> u32 {
+        (imm as u32) << 20 | rs1 << 15 | rd << 7 | 0x13
+    }
+    /// `addiw rd, rs1, imm`
+    pub const fn addiw(rd: u32, rs1: u32, imm: i32) -> u32 {
+        (imm as u32) << 20 | rs1 << 15 | rd << 7 | 0x1b
+    }
+    /// `sraiw rd, rs1, shamt`
+    pub const fn sraiw(rd: u32, rs1: u32, shamt: u32) -> u32 {
+        0x20 << 25 | shamt << 20 | rs1 << 15 | 5 << 12 | rd << 7 | 0x1b
+    }
+    /// A 32-bit register-register operation, such as `addw` or `divw`
+    pub const fn op32(funct7: u32, funct3: u32, rd: u32, rs1: u32, rs2: u32) -> u32 {
+        funct7 << 25 | rs2 << 20 | rs1 << 15 | funct3 << 12 | rd << 7 | 0x3b
+    }
+    pub const fn auipc(rd: u32) -> u32 {
+        rd << 7 | 0x17
+    }
+    pub const ECALL: u32 = 0x73;
+    pub const fn jal(rd: u32, imm: i32) -> u32 {
+        let i = imm as u32;
+        (i >> 20 & 1) << 31
+            | (i >> 1 & 0x3ff) << 21
+            | (i >> 11 & 1) << 20
+            | (i >> 12 & 0xff) << 12
+            | rd << 7
+            | 0x6f
+    }
+    pub const fn jalr(rd: u32, rs1: u32, imm: i32) -> u32 {
+        (imm as u32) << 20 | rs1 << 15 | rd << 7 | 0x67
+    }
+    pub const fn ld(rd: u32, rs1: u32, imm: i32) -> u32 {
+        (imm as u32) << 20 | rs1 << 15 | 3 << 12 | rd << 7 | 0x03
+    }
+    /// `csrrw x0, csr, rs1`
+    pub const fn csrw(csr: u32, rs1: u32) -> u32 {
+        csr << 20 | rs1 << 15 | 1 << 12 | 0x73
+    }
+    /// `csrrs rd, csr, x0`
+    pub const fn csrr(rd: u32, csr: u32) -> u32 {
+        csr << 20 | 2 << 12 | rd << 7 | 0x73
+    }
+    pub const MTVEC: u32 = 0x305;
+    pub const MEPC: u32 = 0x341;
+    pub const MCAUSE: u32 = 0x342;
+    pub const fn c_jr(rs1: u16) -> u16 {
+        0x8002 | rs1 << 7
+    }
+    pub const fn c_jalr(rs1: u16) -> u16 {
+        0x9002 | rs1 << 7
+    }
+    /// `c.addi16sp sp, -64`
+    pub const C_ADDI16SP_M64: u16 = 0x7139;
+    /// `c.addi16sp sp, 496`
+    pub const C_ADDI16SP_496: u16 = 0x617d;
+}
+
+/// A program image built up at fixed addresses
+#[derive(Default)]
+struct Program {
+    mem: Mem,
+    trap_misaligned: bool,
+    rv32: bool,
+}
+
+impl Program {
+    fn word(mut self, addr: u64, w: u32) -> Self {
+        for (i, &b) in w.to_le_bytes().iter().enumerate() {
+            self.mem.write_byte(addr + i as u64, b);
+        }
+        self
+    }
+    fn half(mut self, addr: u64, h: u16) -> Self {
+        for (i, &b) in h.to_le_bytes().iter().enumerate() {
+            self.mem.write_byte(addr + i as u64, b);
+        }
+        self
+    }
+    /// `words` one after another from `addr`
+    fn words(self, addr: u64, words: &[u32]) -> Self {
+        words
+            .iter()
+            .enumerate()
+            .fold(self, |p, (i, &w)| p.word(addr + 4 * i as u64, w))
+    }
+    /// `code` one instruction after another from `addr`, each taking two
+    /// bytes if it is compressed and four if not
+    fn code(mut self, addr: u64, code: &[u32]) -> Self {
+        let mut at = addr;
+        for &c in code {
+            (self, at) = match c & 3 {
+                3 => (self.word(at, c), at + 4),
+                _ => (self.half(at, c as u16), at + 2),
+            };
+        }
+        self
+    }
+    /// Run as an RV32 guest
+    fn rv32(mut self) -> Self {
+        self.rv32 = true;
+        self
+    }
+    /// Raise misaligned loads and stores rather than perform them
+    fn trap_misaligned(mut self) -> Self {
+        self.trap_misaligned = true;
+        self
+    }
+    /// `li a7, 93; ecall`, which ends the run successfully
+    fn exit(self, addr: u64) -> Self {
+        self.word(addr, enc::addi(17, 0, 93))
+            .word(addr + 4, enc::ECALL)
+    }
+}
+
+/// Run `build()` to its exit in the interpreter and in the JavaScript tier,
+/// returning the registers each left
+async fn both(build: impl Fn() -> Program) -> ([u64; 32], [u64; 32]) {
+    async fn run(program: Program, jit: bool) -> [u64; 32] {
+        let reactor = Reactor::new_with_mem(program.mem);
+        reactor.set_trap_misaligned(program.trap_misaligned);
+        if program.rv32 {
+            reactor.set_xlen(32);
+        }
+        let result = if jit {
+            // Every block compiles to JavaScript on its first entry
+            reactor.set_tiering(0, u32::MAX);
+            reactor.set_wasm_tier(false);
+            reactor.jit_run(BASE).await
+        } else {
+            reactor.interp(BASE).await
+        };
+        match result {
+            Err(e) if crate::has_success(e.clone()) => {}
+            r => panic!("program did not exit: {:?}", r),
+        }
+        reactor.save_regs()
+    }
+    (run(build(), false).await, run(build(), true).await)
+}
+
+/// `c.jalr` and `c.jr` link and land like the interpreter, including
+/// `c.jalr ra`, which reads its target before writing the link
+#[wasm_bindgen_test]
+async fn jumps_match_interp() {
+    let (interp, js) = both(|| {
+        Program::default()
+            .word(BASE, enc::lui(5, 1))
+            .word(BASE + 0x04, enc::addi(5, 5, 0x20))
+            .half(BASE + 0x08, enc::c_jalr(5))
+            .word(BASE + 0x0a, enc::auipc(19))
+            .exit(BASE + 0x0e)
+            .word(BASE + 0x20, enc::auipc(8))
+            .word(BASE + 0x24, enc::addi(5, 5, 0x40))
+            .half(BASE + 0x28, enc::c_jr(5))
+            .word(BASE + 0x60, enc::auipc(9))
+            .half(BASE + 0x64, enc::c_jalr(1))
+    })
+    .await;
+    assert_eq!(interp, js);
+    assert_eq!(interp[1], BASE + 0x66);
+    assert_eq!(interp[8], BASE + 0x20);
+    assert_eq!(interp[9], BASE + 0x60);
+    assert_eq!(interp[19], BASE + 0x0a);
+}
+
+/// A return to somewhere other than where its call left off misses the
+/// prediction and looks its target up like any other jump
+#[wasm_bindgen_test]
+async fn mispredicted_returns_match_interp() {
+    let (interp, js) = both(|| {
+        Program::default()
+            .word(BASE, enc::jal(1, 0x20))
+            .word(BASE + 0x04, enc::addi(10, 0, 1))
+            .word(BASE + 0x08, enc::auipc(19))
+            .exit(BASE + 0x0c)
+            // Skip the instruction after the call
+            .word(BASE + 0x20, enc::addi(1, 1, 4))
+            .word(BASE + 0x24, enc::jalr(0, 1, 0))
+    })
+    .await;
+    assert_eq!(interp, js);
+    assert_eq!(interp[10], 0);
+    assert_eq!(interp[19], BASE + 0x08);
+}
+
+/// `c.addi16sp` scales and sign-extends its immediate like the interpreter
+#[wasm_bindgen_test]
+async fn addi16sp_matches_interp() {
+    let (interp, js) = both(|| {
+        Program::default()
+            .word(BASE, enc::lui(2, 0x10))
+            .half(BASE + 0x04, enc::C_ADDI16SP_M64)
+            .word(BASE + 0x06, enc::addi(10, 2, 0))
+            .half(BASE + 0x0a, enc::C_ADDI16SP_496)
+            .exit(BASE + 0x0c)
+    })
+    .await;
+    assert_eq!(interp, js);
+    assert_eq!(interp[10], 0x10000 - 64);
+    assert_eq!(interp[2], 0x10000 - 64 + 496);
+}
+
+/// A fault part way through a block still leaves the registers the block
+/// wrote before it
+#[wasm_bindgen_test]
+async fn faults_keep_earlier_writes() {
+    let (interp, js) = both(|| {
+        Program::default()
+            .trap_misaligned()
+            .word(BASE, enc::lui(5, 1))
+            .word(BASE + 0x04, enc::addi(5, 5, 0x40))
+            .word(BASE + 0x08, enc::csrw(enc::MTVEC, 5))
+            .word(BASE + 0x0c, enc::addi(10, 0, 42))
+            .word(BASE + 0x10, enc::addi(11, 10, 1))
+            .word(BASE + 0x14, enc::ld(12, 0, 1))
+            .word(BASE + 0x18, enc::addi(10, 0, 0))
+            // The handler notes the cause and exits with `mtvec` cleared
+            .word(BASE + 0x40, enc::csrr(13, enc::MCAUSE))
+            .word(BASE + 0x44, enc::csrw(enc::MTVEC, 0))
+            .exit(BASE + 0x48)
+    })
+    .await;
+    assert_eq!(interp, js);
+    assert_eq!(interp[10..14], [42, 43, 0, 4]);
+}
+
+/// The 32-bit operations sign-extend their results and read negative
+/// operands as negative, including the division edge cases
+#[wasm_bindgen_test]
+async fn word_ops_match_interp_on_negative_operands() {
+    use enc::op32;
+    let (interp, js) = both(|| {
+        Program::default()
+            .words(
+                BASE,
+                &[
+                    enc::addi(5, 0, 1),
+                    enc::addi(11, 0, -7),
+                    enc::addi(12, 0, 2),
+                    enc::addi(13, 0, -1),
+                    enc::lui(14, 0x80000),
+                    op32(0, 0, 16, 11, 13),
+                    op32(0x20, 0, 18, 12, 11),
+                    op32(0x20, 0, 19, 11, 12),
+                    op32(1, 0, 20, 11, 12),
+                    op32(0x20, 5, 21, 11, 12),
+                    op32(0, 5, 22, 11, 12),
+                    op32(0, 1, 23, 13, 12),
+                    op32(1, 4, 24, 13, 5),
+                    op32(1, 4, 25, 14, 13),
+                    op32(1, 4, 26, 11, 0),
+                    op32(1, 6, 27, 11, 12),
+                    op32(1, 6, 28, 11, 0),
+                    op32(1, 5, 29, 11, 12),
+                    op32(1, 7, 30, 13, 0),
+                    enc::sraiw(31, 14, 4),
+                    op32(1, 6, 6, 14, 13),
+                    op32(1, 5, 7, 11, 0),
+                    enc::addiw(8, 12, -5),
+                ],
+            )
+            .exit(BASE + 0x5c)
+    })
+    .await;
+    assert_eq!(interp, js);
+    let neg = |v: i64| v as u64;
+    // addw, subw both ways, mulw
+    assert_eq!(interp[16], neg(-8));
+    assert_eq!(interp[18], 9);
+    assert_eq!(interp[19], neg(-9));
+    assert_eq!(interp[20], neg(-14));
+    // sraw keeps the sign where srlw shifts in zeroes; sllw sign-extends
+    assert_eq!(interp[21], neg(-2));
+    assert_eq!(interp[22], 0x3fff_fffe);
+    assert_eq!(interp[23], neg(-4));
+    // divw -1 / 1, the overflowing i32::MIN / -1 and division by zero
+    assert_eq!(interp[24], neg(-1));
+    assert_eq!(interp[25], neg(i32::MIN as i64));
+    assert_eq!(interp[26], u64::MAX);
+    // remw keeps the dividend's sign, and is the dividend by zero
+    assert_eq!(interp[27], neg(-1));
+    assert_eq!(interp[28], neg(-7));
+    assert_eq!(interp[29], 0x7fff_fffc);
+    assert_eq!(interp[30], u64::MAX);
+    assert_eq!(interp[31], neg(-0x0800_0000));
+    assert_eq!(interp[6], 0);
+    assert_eq!(interp[7], u64::MAX);
+    assert_eq!(interp[8], neg(-3));
+}
+
+/// Every compressed arithmetic form, with `c.addiw`, `c.addw` and `c.subw`
+/// giving negative results and `c.addiw` wrapping at 32 bits both ways
+#[wasm_bindgen_test]
+async fn compressed_arithmetic_matches_interp() {
+    let (interp, js) = both(|| {
+        Program::default()
+            .code(
+                BASE,
+                &[
+                    0x4141,     // c.li sp, 16
+                    0x5575,     // c.li a0, -3
+                    0x7585,     // c.lui a1, 0xfffe1
+                    0x0515,     // c.addi a0, 5
+                    0x35fd,     // c.addiw a1, -1
+                    0x461d,     // c.li a2, 7
+                    0x56dd,     // c.li a3, -9
+                    0x9e35,     // c.addw a2, a3
+                    0x4705,     // c.li a4, 1
+                    0x478d,     // c.li a5, 3
+                    0x9f1d,     // c.subw a4, a5
+                    0x800003b7, // lui t2, 0x80000
+                    0x33fd,     // c.addiw t2, -1
+                    0x8d1e,     // c.mv s10, t2
+                    0x2385,     // c.addiw t2, 1
+                    0x6105,     // c.addi16sp sp, 32
+                    0x0020,     // c.addi4spn s0, sp, 8
+                    0x82ae,     // c.mv t0, a1
+                    0x92aa,     // c.add t0, a0
+                    0x832e,     // c.mv t1, a1
+                    0x1306,     // c.slli t1, 33
+                    0x84ae,     // c.mv s1, a1
+                    0x90a1,     // c.srli s1, 40
+                    0x87ae,     // c.mv a5, a1
+                    0x878d,     // c.srai a5, 3
+                    0x86ae,     // c.mv a3, a1
+                    0x9ab1,     // c.andi a3, -20
+                    0x89b6,     // c.mv s3, a3
+                    0x86ae,     // c.mv a3, a1
+                    0x8e89,     // c.sub a3, a0
+                    0x8a36,     // c.mv s4, a3
+                    0x86ae,     // c.mv a3, a1
+                    0x8eb9,     // c.xor a3, a4
+                    0x8ab6,     // c.mv s5, a3
+                    0x86ae,     // c.mv a3, a1
+                    0x8ec9,     // c.or a3, a0
+                    0x8b36,     // c.mv s6, a3
+                    0x86ae,     // c.mv a3, a1
+                    0x8ef1,     // c.and a3, a2
+                    0x0001,     // c.nop
+                ],
+            )
+            .exit(BASE + 0x52)
+    })
+    .await;
+    assert_eq!(interp, js);
+    let neg = |v: i64| v as u64;
+    assert_eq!(interp[11], neg(-0x1f001));
+    assert_eq!(interp[12], neg(-2));
+    assert_eq!(interp[14], neg(-2));
+    assert_eq!(interp[26], 0x7fff_ffff);
+    assert_eq!(interp[7], neg(i32::MIN as i64));
+    assert_eq!(interp[8], 56);
+    assert_eq!(interp[9], 0xff_ffff);
+    assert_eq!(interp[15], neg(-0x1f001 >> 3));
+}
+
+/// Every compressed load and store, integer and double, through a base
+/// register and through `sp`
+#[wasm_bindgen_test]
+async fn compressed_loads_and_stores_match_interp() {
+    let (interp, js) = both(|| {
+        Program::default()
+            .code(
+                BASE,
+                &[
+                    0x00002137, // lui sp, 2
+                    0x840a,     // c.mv s0, sp
+                    0x556d,     // c.li a0, -5
+                    0xc048,     // c.sw a0, 4(s0)
+                    0x404c,     // c.lw a1, 4(s0)
+                    0xe408,     // c.sd a0, 8(s0)
+                    0x6410,     // c.ld a2, 8(s0)
+                    0xc82a,     // c.swsp a0, 16(sp)
+                    0x46c2,     // c.lwsp a3, 16(sp)
+                    0xec2e,     // c.sdsp a1, 24(sp)
+                    0x6762,     // c.ldsp a4, 24(sp)
+                    0xd2257553, // fcvt.d.l fa0, a0
+                    0xb008,     // c.fsd fa0, 32(s0)
+                    0x300c,     // c.fld fa1, 32(s0)
+                    0xb42e,     // c.fsdsp fa1, 40(sp)
+                    0x3622,     // c.fldsp fa2, 40(sp)
+                    0xe20607d3, // fmv.x.d a5, fa2
+                    0x5044,     // c.lw s1, 36(s0)
+                    0x72a2,     // c.ldsp t0, 40(sp)
+                ],
+            )
+            .exit(BASE + 0x2c)
+    })
+    .await;
+    assert_eq!(interp, js);
+    let minus5 = -5i64 as u64;
+    assert_eq!(interp[11..15], [minus5; 4]);
+    assert_eq!(interp[15], (-5f64).to_bits());
+    assert_eq!(interp[5], (-5f64).to_bits());
+    assert_eq!(interp[9], ((-5f64).to_bits() >> 32) as i32 as u64);
+}
+
+/// `c.beqz` and `c.bnez` both taken and not, forwards and backwards, and
+/// `c.j` both ways; `auipc` notes which instructions ran
+#[wasm_bindgen_test]
+async fn compressed_branches_match_interp() {
+    let (interp, js) = both(|| {
+        Program::default()
+            .code(
+                BASE,
+                &[
+                    0x4501,     // 0x00: c.li a0, 0
+                    0xc119,     // 0x02: c.beqz a0, 0x08
+                    0x00000917, // 0x04: auipc s2, 0
+                    0x00000997, // 0x08: auipc s3, 0
+                    0xe505,     // 0x0c: c.bnez a0, 0x34
+                    0x00000a17, // 0x0e: auipc s4, 0
+                    0x4505,     // 0x12: c.li a0, 1
+                    0xe119,     // 0x14: c.bnez a0, 0x1a
+                    0x00000a97, // 0x16: auipc s5, 0
+                    0xcd09,     // 0x1a: c.beqz a0, 0x34
+                    0x00000b17, // 0x1c: auipc s6, 0
+                    0x458d,     // 0x20: c.li a1, 3
+                    0x15fd,     // 0x22: c.addi a1, -1
+                    0xfdfd,     // 0x24: c.bnez a1, 0x22
+                    0xa031,     // 0x26: c.j 0x32
+                    0x00000b97, // 0x28: auipc s7, 0
+                    0x00000c17, // 0x2c: auipc s8, 0
+                    0xa021,     // 0x30: c.j 0x38
+                    0xbfed,     // 0x32: c.j 0x2c
+                    0x00000c97, // 0x34: auipc s9, 0
+                ],
+            )
+            .exit(BASE + 0x38)
+    })
+    .await;
+    assert_eq!(interp, js);
+    assert_eq!(
+        interp[18..26],
+        [
+            0,
+            BASE + 0x08,
+            BASE + 0x0e,
+            0,
+            BASE + 0x1c,
+            0,
+            BASE + 0x2c,
+            0
+        ]
+    );
+    assert_eq!(interp[11], 0);
+}
+
+/// `c.ebreak` without a break hook raises a breakpoint at itself
+#[wasm_bindgen_test]
+async fn compressed_ebreak_matches_interp() {
+    let (interp, js) = both(|| {
+        Program::default()
+            .word(BASE, enc::lui(5, 1))
+            .word(BASE + 0x04, enc::addi(5, 5, 0x40))
+            .word(BASE + 0x08, enc::csrw(enc::MTVEC, 5))
+            .code(
+                BASE + 0x0c,
+                &[
+                    0x4505, // c.li a0, 1
+                    0x9002, // c.ebreak
+                    0x4509, // c.li a0, 2
+                ],
+            )
+            .word(BASE + 0x40, enc::csrr(11, enc::MCAUSE))
+            .word(BASE + 0x44, enc::csrr(12, enc::MEPC))
+            .word(BASE + 0x48, enc::csrw(enc::MTVEC, 0))
+            .exit(BASE + 0x4c)
+    })
+    .await;
+    assert_eq!(interp, js);
+    assert_eq!(interp[10..13], [1, 3, BASE + 0x0e]);
+}
+
+/// The forms only RV32 has, `c.jal` and the single-precision loads and
+/// stores, and the shifts at RV32's width
+#[wasm_bindgen_test]
+async fn compressed_rv32_forms_match_interp() {
+    let (interp, js) = both(|| {
+        Program::default()
+            .rv32()
+            .code(
+                BASE,
+                &[
+                    0x00002137, // 0x00: lui sp, 2
+                    0x202d,     // 0x04: c.jal 0x2e
+                    0x00000917, // 0x06: auipc s2, 0
+                    0x5565,     // 0x0a: c.li a0, -7
+                    0x842a,     // 0x0c: c.mv s0, a0
+                    0x807d,     // 0x0e: c.srli s0, 31
+                    0x84aa,     // 0x10: c.mv s1, a0
+                    0x8485,     // 0x12: c.srai s1, 1
+                    0x85aa,     // 0x14: c.mv a1, a0
+                    0x05f2,     // 0x16: c.slli a1, 28
+                    0xd0057553, // 0x18: fcvt.s.w fa0, a0
+                    0x860a,     // 0x1c: c.mv a2, sp
+                    0xe248,     // 0x1e: c.fsw fa0, 4(a2)
+                    0x624c,     // 0x20: c.flw fa1, 4(a2)
+                    0xe42e,     // 0x22: c.fswsp fa1, 8(sp)
+                    0x6622,     // 0x24: c.flwsp fa2, 8(sp)
+                    0xe00606d3, // 0x26: fmv.x.w a3, fa2
+                    0x4722,     // 0x2a: c.lwsp a4, 8(sp)
+                    0xa021,     // 0x2c: c.j 0x34
+                    0x00000997, // 0x2e: auipc s3, 0
+                    0x8082,     // 0x32: c.jr ra
+                ],
+            )
+            .exit(BASE + 0x34)
+    })
+    .await;
+    assert_eq!(interp, js);
+    assert_eq!(interp[1], BASE + 0x06);
+    assert_eq!(interp[18..20], [BASE + 0x06, BASE + 0x2e]);
+    assert_eq!(interp[8..12], [1, 0xffff_fffc, 0xffff_fff9, 0x9000_0000]);
+    assert_eq!(interp[13..15], [(-7f32).to_bits() as u64; 2]);
+}
@@ -0,0 +1,107 @@
+//! Tests for the links and jump caches compiled blocks keep to one another,
+//! and for cutting them when a block is dropped
+#![cfg(target_arch = "wasm32")]
+
+extern crate wasm_bindgen_test;
+
+use js_sys::{Function, Promise};
+use wasm_bindgen::JsCast;
+use wasm_bindgen_futures::JsFuture;
+use wasm_bindgen_test::*;
+
+use crate::*;
+use vane_jit::Mem;
+
+wasm_bindgen_test_configure!(run_in_browser);
+
+/// Where the first block is loaded and entered
+const BASE: u64 = 0x1000;
+
+/// A reactor with each `(address, instruction)` loaded, compiling blocks to
+/// JavaScript on their first entry
+fn reactor(code: &[(u64, u32)]) -> Reactor {
+    let mut mem = Mem::default();
+    for &(a, c) in code {
+        for (i, &b) in c.to_le_bytes().iter().enumerate() {
+            mem.write_byte(a + i as u64, b);
+        }
+    }
+    let reactor = Reactor::new_with_mem(mem);
+    reactor.set_tiering(0, u32::MAX);
+    reactor
+}
+
+/// Run the compiled block `f`, returning the one it hands back
+async fn step(f: &JsValue) -> JsValue {
+    let out = f
+        .unchecked_ref::<Function>()
+        .call0(&JsValue::UNDEFINED)
+        .unwrap();
+    JsFuture::from(out.unchecked_into::<Promise>())
+        .await
+        .unwrap()
+}
+
+/// `jalr x0, 0(t0)` at `BASE` jumps to `addi a0, a0, 1; jalr x0, 0(t1)`,
+/// which jumps back
+#[wasm_bindgen_test]
+async fn dropped_targets_leave_jump_caches() {
+    const TARGET: u64 = BASE + 0x100;
+    let reactor = reactor(&[
+        (BASE, 0x0002_8067),
+        (TARGET, 0x0015_0513),
+        (TARGET + 4, 0x0003_0067),
+    ]);
+    Reactor::set_reg(reactor.clone(), 5, TARGET);
+    Reactor::set_reg(reactor.clone(), 6, BASE);
+    // Fill the jump cache at `BASE` with the target
+    let mut next = reactor.jit(BASE);
+    for _ in 0..6 {
+        next = step(&next).await;
+    }
+    let old = reactor.jit(TARGET);
+
+    // addi a0, a0, 100
+    for (i, &b) in 0x0645_0513u32.to_le_bytes().iter().enumerate() {
+        reactor.core.lock().mem.write_byte(TARGET + i as u64, b);
+    }
+    reactor.drop_block_at(TARGET);
+    // The jump compiles the target afresh rather than reusing the old one
+    let target = step(&reactor.jit(BASE)).await;
+    assert_ne!(target, old);
+    assert_eq!(target, reactor.jit(TARGET));
+    let a0 = Reactor::reg(reactor.clone(), 10);
+    step(&target).await;
+    assert_eq!(Reactor::reg(reactor.clone(), 10), a0 + 100);
+}
+
+/// `jal ra, f` at `BASE` calls `f: jalr x0, 0(ra)`, which returns to
+/// `addi a0, a0, 1; jalr x0, 0(t0)` and so back to `BASE`
+#[wasm_bindgen_test]
+async fn dropped_callees_keep_their_callers_caches() {
+    const RET: u64 = BASE + 4;
+    const CALLEE: u64 = BASE + 0x100;
+    let reactor = reactor(&[
+        (BASE, 0x1000_00ef),
+        (RET, 0x0015_0513),
+        (RET + 4, 0x0002_8067),
+        (CALLEE, 0x0000_8067),
+    ]);
+    Reactor::set_reg(reactor.clone(), 1, RET);
+    Reactor::set_reg(reactor.clone(), 5, BASE);
+    // The callee first, so the caller links to it rather than inlining it
+    step(&reactor.jit(CALLEE)).await;
+    let mut next = reactor.jit(BASE);
+    for _ in 0..6 {
+        next = step(&next).await;
+    }
+    let old = reactor.jit(RET);
+
+    // The return went through the caller's cache, which outlives the callee
+    reactor.drop_block_at(CALLEE);
+    reactor.drop_block_at(RET);
+    let callee = step(&reactor.jit(BASE)).await;
+    let ret = step(&callee).await;
+    assert_ne!(ret, old);
+    assert_eq!(ret, reactor.jit(RET));
+}
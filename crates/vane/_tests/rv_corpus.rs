@@ -125,6 +125,11 @@ macro_rules! rv_test {
             let (reactor, entry_point) = create_reactor_with_binary(binary_data)
                 .expect(&format!("Failed to load {}/{} binary", $isa, $binary));
 
+            // RV32 variants need 32-bit registers and address wraparound
+            if $isa.starts_with("rv32") {
+                reactor.set_xlen(32);
+            }
+
             // Run the test starting from the entry point
             let result = reactor.$kind(entry_point).await;
 
@@ -150,6 +155,11 @@ macro_rules! rv_test {
             let (reactor, entry_point) = create_reactor_with_binary(binary_data)
                 .expect(&format!("Failed to load {}/{} binary", $isa, $binary));
 
+            // RV32 variants need 32-bit registers and address wraparound
+            if $isa.starts_with("rv32") {
+                reactor.set_xlen(32);
+            }
+
             // Enable test_mode for HINT logging
             reactor.set_test_mode(true);
 
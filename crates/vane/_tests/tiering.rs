@@ -0,0 +1,87 @@
+//! Tests for moving blocks up from the interpreter to JavaScript and then to
+//! WebAssembly as they are entered
+#![cfg(target_arch = "wasm32")]
+
+extern crate wasm_bindgen_test;
+
+use js_sys::{Function, Promise};
+use wasm_bindgen::JsCast;
+use wasm_bindgen_futures::JsFuture;
+use wasm_bindgen_test::*;
+
+use crate::*;
+use vane_jit::Mem;
+
+wasm_bindgen_test_configure!(run_in_browser);
+
+/// Where the block is loaded and entered
+const BASE: u64 = 0x1000;
+
+/// How one entry of the block ran
+struct Entry {
+    /// Whether the block had been compiled by the end of the entry
+    compiled: bool,
+    /// Whether the entry finished without a promise, as only Wasm blocks do
+    sync: bool,
+    /// The block's entry in the reactor afterwards
+    current: JsValue,
+}
+
+/// `addi a0, a0, 1; jalr x0, 0(t0)` with `t0` at the block, so each entry
+/// runs it once and hands back the next
+#[wasm_bindgen_test]
+async fn blocks_move_up_at_each_threshold() {
+    let mut mem = Mem::default();
+    for (i, b) in [0x0015_0513u32, 0x0002_8067]
+        .iter()
+        .flat_map(|c| c.to_le_bytes().to_vec())
+        .enumerate()
+    {
+        mem.write_byte(BASE + i as u64, b);
+    }
+    let reactor = Reactor::new_with_mem(mem);
+    reactor.set_tiering(2, 3);
+    // The glue reads the thresholds back through the hart's Flags
+    assert_eq!(reactor.tiering(), [2, 3]);
+    reactor.set_wasm_tier(true);
+    Reactor::set_reg(reactor.clone(), 5, BASE);
+
+    let warm = reactor.jit(BASE);
+    let mut next = warm.clone();
+    let mut entries = Vec::new();
+    for _ in 0..7 {
+        let out = next.unchecked_ref::<Function>().call0(&JsValue::UNDEFINED).unwrap();
+        let sync = !out.is_instance_of::<Promise>();
+        next = match sync {
+            true => out,
+            false => JsFuture::from(out.unchecked_into::<Promise>()).await.unwrap(),
+        };
+        entries.push(Entry {
+            compiled: !reactor.core.lock().mem.code_pages.is_empty(),
+            sync,
+            current: reactor.jit(BASE),
+        });
+    }
+    // Each entry ran the block exactly once, whichever tier it was in
+    assert_eq!(Reactor::reg(reactor.clone(), 10), 7);
+
+    // Two entries in the interpreter, which compiles nothing
+    for e in &entries[..2] {
+        assert!(!e.compiled && !e.sync);
+        assert_eq!(e.current, warm);
+    }
+    // Then three as JavaScript, behind an entry counting towards Wasm
+    let hot = entries[2].current.clone();
+    assert_ne!(hot, warm);
+    for e in &entries[2..5] {
+        assert!(e.compiled && !e.sync);
+        assert_eq!(e.current, hot);
+    }
+    // Then Wasm, which returns the next block without a promise
+    let wasm = entries[5].current.clone();
+    assert_ne!(wasm, hot);
+    for e in &entries[5..] {
+        assert!(e.compiled && e.sync);
+        assert_eq!(e.current, wasm);
+    }
+}
@@ -0,0 +1,223 @@
+//! Interpreter support for instructions decoded by [`ExtInst`].
+use rv_asm::{AmoOp, Xlen};
+use vane_jit::bit;
+use vane_jit::crypto;
+use vane_jit::ext::ExtInst;
+use vane_jit::fp::{self, FpFmt};
+use vane_jit::mmu::Access;
+use vane_jit::trap::{Exception, Trap};
+use vane_jit::xlen_mask;
+
+use crate::Reactor;
+
+/// The value an AMO stores, given the loaded value `v` and the source `s`
+///
+/// Comparisons use the low 32 bits unless `double` is set.
+pub(crate) fn amo_op(op: AmoOp, v: u64, s: u64, double: bool) -> u64 {
+    let (sv, ss, uv, us) = match double {
+        true => (v as i64, s as i64, v, s),
+        false => (v as i32 as i64, s as i32 as i64, v as u32 as u64, s as u32 as u64),
+    };
+    match op {
+        AmoOp::Swap => s,
+        AmoOp::Add => v.wrapping_add(s),
+        AmoOp::Xor => v ^ s,
+        AmoOp::And => v & s,
+        AmoOp::Or => v | s,
+        AmoOp::Min => if sv < ss { v } else { s },
+        AmoOp::Max => if sv > ss { v } else { s },
+        AmoOp::Minu => if uv < us { v } else { s },
+        AmoOp::Maxu => if uv > us { v } else { s },
+    }
+}
+
+impl Reactor {
+    /// Execute one [`ExtInst`] against the interpreter's integer registers
+    ///
+    /// Floating-point registers are read and written in place, so they need
+    /// no saving around exits. `code` is the raw encoding, which CSR accesses
+    /// hand to the reactor's CSR path just as generated code does. Returns the new
+    /// pc for instructions that jump.
+    pub(crate) fn interp_ext(
+        &self,
+        i: ExtInst,
+        code: u32,
+        regs: &mut [u64; 32],
+        xlen: Xlen,
+    ) -> Result<Option<u64>, Trap> {
+        let mask = xlen_mask(xlen);
+        let aligned = |mem: u64, e: Exception| match mem & 7 {
+            0 => Ok(()),
+            _ => Err(Trap::exception(e, mem)),
+        };
+        match i {
+            ExtInst::FLoad {
+                fmt,
+                offset,
+                dest,
+                base,
+            } => {
+                let mem = regs[(base.0 % 32) as usize].wrapping_add_signed(offset.as_i64()) & mask;
+                let v = match fmt {
+                    FpFmt::S => fp::box_s(self.load_op(mem, 4)? as u32),
+                    FpFmt::D => self.load_op(mem, 8)?,
+                };
+                Self::set_freg(self.clone(), dest.0, v);
+            }
+            ExtInst::FStore {
+                fmt,
+                offset,
+                src,
+                base,
+            } => {
+                let mem = regs[(base.0 % 32) as usize].wrapping_add_signed(offset.as_i64()) & mask;
+                let v = Self::freg(self.clone(), src.0);
+                match fmt {
+                    FpFmt::S => self.store_op(mem, 4, v)?,
+                    FpFmt::D => self.store_op(mem, 8, v)?,
+                }
+            }
+            ExtInst::Fp {
+                op,
+                fmt,
+                rm,
+                dest,
+                src1,
+                src2,
+                src3,
+            } => {
+                let a = match op.src1_is_x() {
+                    true => regs[(src1.0 % 32) as usize],
+                    false => Self::freg(self.clone(), src1.0),
+                };
+                let b = Self::freg(self.clone(), src2.0);
+                let c = Self::freg(self.clone(), src3.0);
+                let v = {
+                    let mut lock = self.core.lock();
+                    fp::exec(op, fmt, rm, a, b, c, &mut lock.csrs.fcsr)
+                }
+                .ok_or(Trap::illegal(code))?;
+                match op.dest_is_x() {
+                    true => {
+                        if dest.0 % 32 != 0 {
+                            regs[(dest.0 % 32) as usize] = v & mask;
+                        }
+                    }
+                    false => {
+                        Self::set_freg(self.clone(), dest.0, v);
+                    }
+                }
+            }
+            ExtInst::LrD { dest, addr, .. } => {
+                let mem = regs[(addr.0 % 32) as usize];
+                aligned(mem, Exception::LoadMisaligned)?;
+                let v = unsafe { *(self.access(mem, Access::Read)? as *mut u64) };
+                self.lr(mem);
+                if dest.0 % 32 != 0 {
+                    regs[(dest.0 % 32) as usize] = v;
+                }
+            }
+            ExtInst::ScD {
+                dest, addr, src, ..
+            } => {
+                let mem = regs[(addr.0 % 32) as usize];
+                aligned(mem, Exception::StoreMisaligned)?;
+                let ok = self.sc(mem);
+                if ok {
+                    unsafe { *(self.access(mem, Access::Write)? as *mut u64) = regs[(src.0 % 32) as usize] };
+                }
+                if dest.0 % 32 != 0 {
+                    regs[(dest.0 % 32) as usize] = !ok as u64;
+                }
+            }
+            ExtInst::AmoD {
+                op,
+                dest,
+                addr,
+                src,
+                ..
+            } => {
+                let mem = regs[(addr.0 % 32) as usize];
+                aligned(mem, Exception::StoreMisaligned)?;
+                let p = self.access(mem, Access::Write)? as *mut u64;
+                let v = unsafe { *p };
+                unsafe { *p = amo_op(op, v, regs[(src.0 % 32) as usize], true) };
+                if dest.0 % 32 != 0 {
+                    regs[(dest.0 % 32) as usize] = v;
+                }
+            }
+            ExtInst::Csr { dest, src, .. } => {
+                let v = self.csr_op(code, regs[(src.0 % 32) as usize])?;
+                if dest.0 % 32 != 0 {
+                    regs[(dest.0 % 32) as usize] = v;
+                }
+            }
+            ExtInst::CsrI { dest, uimm, .. } => {
+                let v = self.csr_op(code, uimm as u64)?;
+                if dest.0 % 32 != 0 {
+                    regs[(dest.0 % 32) as usize] = v;
+                }
+            }
+            ExtInst::Bit {
+                op,
+                dest,
+                src1,
+                src2,
+            } => {
+                let v = bit::exec(op, regs[(src1.0 % 32) as usize], regs[(src2.0 % 32) as usize], xlen);
+                if dest.0 % 32 != 0 {
+                    regs[(dest.0 % 32) as usize] = v;
+                }
+            }
+            ExtInst::BitImm {
+                op,
+                dest,
+                src1,
+                shamt,
+            } => {
+                let v = bit::exec(op, regs[(src1.0 % 32) as usize], shamt as u64, xlen);
+                if dest.0 % 32 != 0 {
+                    regs[(dest.0 % 32) as usize] = v;
+                }
+            }
+            ExtInst::BitUnary { op, dest, src } => {
+                let v = bit::exec(op, regs[(src.0 % 32) as usize], 0, xlen);
+                if dest.0 % 32 != 0 {
+                    regs[(dest.0 % 32) as usize] = v;
+                }
+            }
+            ExtInst::Crypto {
+                op,
+                dest,
+                src1,
+                src2,
+                imm,
+            } => {
+                let v = crypto::exec(op, regs[(src1.0 % 32) as usize], regs[(src2.0 % 32) as usize], imm, xlen);
+                if dest.0 % 32 != 0 {
+                    regs[(dest.0 % 32) as usize] = v;
+                }
+            }
+            ExtInst::Mret => return self.mret_op().map(Some),
+            ExtInst::Sret => return self.sret_op().map(Some),
+            // Nothing is cached, so there is nothing to flush
+            ExtInst::SfenceVma { .. } => {
+                if !self.core.lock().csrs.sfence_allowed() {
+                    return Err(Trap::illegal(code));
+                }
+            }
+            ExtInst::Wfi => {}
+            ExtInst::FenceI => self.fence_i_op(),
+            ExtInst::Vector(i) => {
+                let x = |r: Option<rv_asm::Reg>| r.map_or(0, |r| regs[(r.0 % 32) as usize]);
+                let (a, b) = i.x_srcs();
+                let v = self.vector_op(code, x(a), x(b))?;
+                if let Some(dest) = i.x_dest().filter(|d| d.0 % 32 != 0) {
+                    regs[(dest.0 % 32) as usize] = v;
+                }
+            }
+            _ => return Err(Trap::illegal(code)),
+        }
+        Ok(None)
+    }
+}
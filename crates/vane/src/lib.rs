@@ -1,3 +1,5 @@
+mod ext;
+mod sbi;
 mod utils;
 use js_sys::Promise;
 
@@ -6,6 +8,7 @@ use rv_asm::{Inst, Reg, Xlen};
 use std::{
     cell::{OnceCell, UnsafeCell},
     collections::BTreeMap,
+    convert::TryFrom,
     fmt::Display,
     mem::transmute,
     ptr::null_mut,
@@ -14,16 +17,28 @@ use std::{
     u64,
 };
 use vane_jit::{flate::ReleaseFlate, template::TemplateJit};
+use vane_jit::mmu::Access;
+use vane_jit::trap::{BreakAction, Exception, Priv, Trap};
 use vane_jit::template::{CoreJS, Params};
-use vane_jit::Heat;
+use vane_jit::{xlen_mask, Heat};
 pub use vane_jit::Mem;
 use vane_jit::{arch::Riscv, JitCtx};
 pub use vane_jit::hint;
+pub use vane_jit::virt;
 use wasm_bindgen::prelude::*;
 
 #[cfg(test)]
 #[path = "../_tests/rv_corpus.rs"]
 mod _tests;
+#[cfg(test)]
+#[path = "../_tests/differential.rs"]
+mod differential_tests;
+#[cfg(test)]
+#[path = "../_tests/tiering.rs"]
+mod tiering_tests;
+#[cfg(test)]
+#[path = "../_tests/links.rs"]
+mod links_tests;
 vane_meta_gen::vane_meta!(Reactor, Core, Riscv, ReleaseFlate{});
 
 #[wasm_bindgen(inline_js = r#"
@@ -48,15 +63,60 @@ impl Reactor {
     /// Create a new Reactor with custom memory
     /// This is a safe constructor that allows initializing the reactor with pre-loaded memory
     pub fn new_with_mem(mem: vane_jit::Mem) -> Self {
-        Reactor {
+        let r = Reactor {
             _handle: (),
             core: Rc::new(spin::Mutex::new(Core {
                 mem,
                 state: OnceCell::new(),
                 regs: OnceCell::new(),
                 test_mode: false,
+                xlen: Xlen::Rv64,
+                csrs: Default::default(),
+                vector: Default::default(),
+                compiling: None,
+                pending_trap: None,
+                trap_handlers: Vec::new(),
+                break_hook: None,
+                stepping: false,
+                trap_misaligned: false,
+                wasm_tier: false,
+                // Code run only a few times is not worth compiling
+                js_threshold: 4,
+                wasm_threshold: 1000,
+                region_insts: Some(1024),
+                region_bytes: Some(1 << 20),
+                wasm_regs: Box::new([0; vane_jit::template::wasm::offset::SIZE as usize / 8]),
+                clint: Default::default(),
+                irq_lines: 0,
+                irq_deadline: u64::MAX,
+                virt: None,
+                console: None,
+                console_output: Vec::new(),
+                console_input: Default::default(),
             })),
+        };
+        r.add_trap_handler(Self::exit_handler);
+        r
+    }
+    /// The default trap handler, for guests without a trap vector
+    ///
+    /// An `ecall` with `a7 == 93` (`exit`) ends the run successfully; any
+    /// other `ecall` is skipped. Once the guest installs `mtvec` its calls
+    /// are delivered there instead, as are calls `medeleg` sends to `stvec`.
+    fn exit_handler(&self, trap: &Trap, pc: u64) -> Result<Option<u64>, JsValue> {
+        let ecall = trap.as_exception().is_some_and(Exception::is_ecall);
+        let unhandled = {
+            let lock = self.core.lock();
+            let delegated = lock.csrs.mode != Priv::Machine && lock.csrs.medeleg >> trap.code & 1 != 0;
+            lock.csrs.mtvec == 0 && !delegated
+        };
+        if !ecall || !unhandled {
+            return Ok(None);
+        }
+        if Self::reg(self.clone(), Reg::A7.0) == 93 {
+            return Err(log_success());
         }
+        Ok(Some(pc.wrapping_add(4)))
     }
     fn save_regs(&self) -> [u64; 32] {
         std::array::from_fn(|a| Self::reg(self.clone(), a as u8))
@@ -70,20 +130,128 @@ impl Reactor {
 #[wasm_bindgen]
 impl Reactor {
     #[wasm_bindgen]
-    pub async fn interp(&self, mut pc: u64) -> Result<JsValue, JsValue> {
-        let test_mode = self.core.lock().test_mode;
+    pub async fn interp(&self, pc: u64) -> Result<JsValue, JsValue> {
+        self.interp_from(pc, false)
+    }
+    /// Interpret the block at `pc`, until control leaves straight-line code
+    ///
+    /// Returns the next block's entry from `J`, where its entry is counted
+    /// towards compiling it.
+    #[wasm_bindgen(js_name = "ib")]
+    pub async fn interp_block(&self, pc: u64) -> Result<JsValue, JsValue> {
+        self.interp_from(pc, true)
+    }
+}
+impl Reactor {
+    fn interp_from(&self, mut pc: u64, block: bool) -> Result<JsValue, JsValue> {
+        let (test_mode, xlen) = {
+            let lock = self.core.lock();
+            (lock.test_mode, lock.xlen)
+        };
+        let mask = xlen_mask(xlen);
+        let bits = match xlen {
+            Xlen::Rv32 => 32,
+            Xlen::Rv64 => 64,
+        };
         let mut regs = self.save_regs();
+        // Instructions started, synced to JS wherever the JIT could observe it
+        let mut instret = self.clone().instret_raw();
+        // A single step asked for by the break hook, and whether its instruction has started
+        let mut step = self.core.lock().stepping;
+        let mut stepped = false;
+        // Where the last instruction would have gone on to without a jump or trap
+        let mut fall = pc;
+        //Call into the reactor with the state synced, then go on where it says
+        macro_rules! resume {
+            ($to:expr) => {{
+                self.restore_regs(&regs);
+                self.clone().set_instret_raw(instret);
+                pc = $to?;
+                regs = self.save_regs();
+                instret = self.clone().instret_raw();
+            }};
+        }
+        //Hand the trap to `take_trap`
+        macro_rules! trap {
+            ($t:expr) => {{
+                resume!(self.take_trap($t, pc));
+                continue;
+            }};
+        }
+        //Translate a data address for the access, trapping on a page fault
+        macro_rules! page {
+            ($a:expr, $access:ident) => {
+                match self.access($a, Access::$access) {
+                    Ok(p) => p,
+                    Err(t) => trap!(t),
+                }
+            };
+        }
+        //Load `size` bytes, zero-extended, trapping on a fault; the CLINT may read `mtime`
+        macro_rules! load {
+            ($a:expr, $size:expr) => {
+                match {
+                    self.clone().set_instret_raw(instret);
+                    self.load_op($a, $size)
+                } {
+                    Ok(v) => v,
+                    Err(t) => trap!(t),
+                }
+            };
+        }
+        macro_rules! store {
+            ($a:expr, $size:expr, $v:expr) => {
+                self.clone().set_instret_raw(instret);
+                if let Err(t) = self.store_op($a, $size, $v) {
+                    trap!(t)
+                }
+            };
+        }
         loop {
             regs[0] = 0;
-            let code = self.get_page(pc) as *mut u32;
-            let code = unsafe { *code };
-            let i = Inst::decode(code, Xlen::Rv64);
+            if block && pc != fall {
+                self.restore_regs(&regs);
+                self.clone().set_instret_raw(instret);
+                return Ok(self.jit(pc));
+            }
+            if instret >= self.core.lock().irq_deadline {
+                resume!(self.take_interrupt(pc));
+                continue;
+            }
+            if step && stepped {
+                resume!(self.debug_break(pc, pc));
+                step = self.core.lock().stepping;
+            }
+            stepped = step;
+            instret += 1;
+            let code = match self.fetch_op(pc) {
+                Ok(code) => code,
+                Err(t) => trap!(t),
+            };
+            fall = pc.wrapping_add(if code & 3 == 3 { 4 } else { 2 }) & mask;
+            let i = vane_jit::rvc::decode(code, xlen);
             let (i, b) = match i {
                 Ok(a) => a,
-                Err(e) => {
-                    self.restore_regs(&regs);
-                    return Err(JsValue::from_str(&format!("{e}")));
-                }
+                Err(_) => match vane_jit::ext::ExtInst::decode(code, xlen) {
+                    Some((i, b)) => {
+                        if i.csr_effects().is_some() {
+                            self.clone().set_instret_raw(instret);
+                        }
+                        match self.interp_ext(i, code, &mut regs, xlen) {
+                            Ok(Some(target)) => pc = target,
+                            Ok(None) => {
+                                pc = (match b {
+                                    rv_asm::IsCompressed::Yes => 2,
+                                    rv_asm::IsCompressed::No => 4,
+                                } + pc)
+                                    & mask
+                            }
+                            Err(t) => trap!(t),
+                        }
+                        continue;
+                    }
+                    None => trap!(Trap::illegal(code)),
+                },
             };
             
             // Check for test marker HINTs when test_mode is enabled
@@ -93,10 +261,11 @@ impl Reactor {
                 }
             }
             
-            let next = match b {
+            let next = (match b {
                 rv_asm::IsCompressed::Yes => 2,
                 rv_asm::IsCompressed::No => 4,
-            } + pc;
+            } + pc)
+                & mask;
             macro_rules! reg {
                 ($a:expr) => {
                     match $a {
@@ -104,6 +273,12 @@ impl Reactor {
                     }
                 };
             }
+            //Register value sign-extended from XLEN
+            macro_rules! sreg {
+                ($a:expr) => {
+                    ((reg!($a) << (64 - bits)) as i64) >> (64 - bits)
+                };
+            }
             macro_rules! reg32 {
                 ($a:expr) => {
                     match reg!($a) {
@@ -116,7 +291,7 @@ impl Reactor {
                     match $a {
                         Reg(r) => match $b {
                             v => {
-                                regs[(r % 32) as usize] = v;
+                                regs[(r % 32) as usize] = v & mask;
                                 v
                             }
                         },
@@ -142,13 +317,13 @@ impl Reactor {
                     set_reg!(dest => reg!(src1).wrapping_add(imm.as_u64()));
                 }
                 Inst::Slti { imm, dest, src1 } => {
-                    set_reg!(dest => match ((reg!(src1) as i64) < imm.as_i64()){
+                    set_reg!(dest => match (sreg!(src1) < imm.as_i64()){
                         true => 1,
                         false => 0,
                     });
                 }
                 Inst::Sltiu { imm, dest, src1 } => {
-                    set_reg!(dest => match ((reg!(src1)) < imm.as_u64()){
+                    set_reg!(dest => match ((reg!(src1)) < imm.as_u64() & mask){
                         true => 1,
                         false => 0,
                     });
@@ -163,20 +338,20 @@ impl Reactor {
                     set_reg!(dest => reg!(src1) ^ imm.as_u64());
                 }
                 Inst::Slli { imm, dest, src1 } => {
-                    set_reg!(dest => reg!(src1) << (imm.as_u32() % 64));
+                    set_reg!(dest => reg!(src1) << (imm.as_u32() % bits));
                 }
                 Inst::Srli { imm, dest, src1 } => {
-                    set_reg!(dest => reg!(src1) >> (imm.as_u32() % 64));
+                    set_reg!(dest => reg!(src1) >> (imm.as_u32() % bits));
                 }
                 Inst::Srai { imm, dest, src1 } => {
-                    set_reg!(dest => ((reg!(src1) as i64) >> (imm.as_u32() % 64)) as u64);
+                    set_reg!(dest => (sreg!(src1) >> (imm.as_u32() % bits)) as u64);
                 }
                 //Regs
                 Inst::Add { src2, dest, src1 } => {
                     set_reg!(dest => reg!(src1).wrapping_add(reg!(src2)));
                 }
                 Inst::Slt { src2, dest, src1 } => {
-                    set_reg!(dest => match ((reg!(src1) as i64) < (reg!(src2) as i64)){
+                    set_reg!(dest => match (sreg!(src1) < sreg!(src2)){
                         true => 1,
                         false => 0,
                     });
@@ -197,13 +372,13 @@ impl Reactor {
                     set_reg!(dest => reg!(src1) ^ reg!(src2));
                 }
                 Inst::Sll { src2, dest, src1 } => {
-                    set_reg!(dest => reg!(src1) << ((reg!(src2) & 0xffff_ffff) as u32 % 64));
+                    set_reg!(dest => reg!(src1) << (reg!(src2) as u32 % bits));
                 }
                 Inst::Srl { src2, dest, src1 } => {
-                    set_reg!(dest => reg!(src1) >> ((reg!(src2) & 0xffff_ffff) as u32 % 64));
+                    set_reg!(dest => reg!(src1) >> (reg!(src2) as u32 % bits));
                 }
                 Inst::Sra { src2, dest, src1 } => {
-                    set_reg!(dest => ((reg!(src1) as i64) >> ((reg!(src2) & 0xffff_ffff) as u32 % 64)) as u64);
+                    set_reg!(dest => (sreg!(src1) >> (reg!(src2) as u32 % bits)) as u64);
                 }
                 Inst::Sub { src2, dest, src1 } => {
                     set_reg!(dest => reg!(src1).wrapping_sub(reg!(src2)));
@@ -212,151 +387,129 @@ impl Reactor {
                 Inst::Mul { src2, dest, src1 } => {
                     set_reg!(dest => reg!(src1).wrapping_mul(reg!(src2)));
                 }
+                Inst::Mulh { src2, dest, src1 } => {
+                    set_reg!(dest => ((sreg!(src1) as i128 * sreg!(src2) as i128) >> bits) as u64);
+                }
+                Inst::Mulhsu { src2, dest, src1 } => {
+                    set_reg!(dest => ((sreg!(src1) as i128 * reg!(src2) as i128) >> bits) as u64);
+                }
+                Inst::Mulhu { src2, dest, src1 } => {
+                    set_reg!(dest => ((reg!(src1) as u128 * reg!(src2) as u128) >> bits) as u64);
+                }
+                //Division by zero and overflow follow the spec instead of trapping
                 Inst::Div { src2, dest, src1 } => {
-                    set_reg!(dest => ((reg!(src1) as i64) / (reg!(src2) as i64)) as u64);
+                    set_reg!(dest => match sreg!(src2) {
+                        0 => u64::MAX,
+                        d => sreg!(src1).wrapping_div(d) as u64,
+                    });
                 }
                 Inst::Divu { src2, dest, src1 } => {
-                    set_reg!(dest => reg!(src1) / reg!(src2));
+                    set_reg!(dest => reg!(src1).checked_div(reg!(src2)).unwrap_or(u64::MAX));
                 }
                 Inst::Rem { src2, dest, src1 } => {
-                    set_reg!(dest => ((reg!(src1) as i64) % (reg!(src2) as i64)) as u64);
+                    set_reg!(dest => match sreg!(src2) {
+                        0 => reg!(src1),
+                        d => sreg!(src1).wrapping_rem(d) as u64,
+                    });
                 }
                 Inst::Remu { src2, dest, src1 } => {
-                    set_reg!(dest => reg!(src1) % reg!(src2));
+                    set_reg!(dest => reg!(src1).checked_rem(reg!(src2)).unwrap_or(reg!(src1)));
                 }
                 //Jumps
                 Inst::Jal { offset, dest } => {
                     set_reg!(dest => next);
-                    pc = pc.wrapping_add_signed(offset.as_i64()) & (!1);
+                    pc = pc.wrapping_add_signed(offset.as_i64()) & (!1) & mask;
                     continue;
                 }
                 Inst::Jalr { offset, base, dest } => {
+                    // `dest` may be `base`
+                    let target = reg!(base).wrapping_add_signed(offset.as_i64()) & (!1) & mask;
                     set_reg!(dest => next);
-                    pc = reg!(base).wrapping_add_signed(offset.as_i64()) & (!1);
+                    pc = target;
                     continue;
                 }
                 //Branches
                 Inst::Beq { offset, src1, src2 } => {
                     if reg!(src1) == reg!(src2) {
-                        pc = pc.wrapping_add_signed(offset.as_i64());
+                        pc = pc.wrapping_add_signed(offset.as_i64()) & mask;
                         continue;
                     }
                 }
                 Inst::Bne { offset, src1, src2 } => {
                     if reg!(src1) != reg!(src2) {
-                        pc = pc.wrapping_add_signed(offset.as_i64());
+                        pc = pc.wrapping_add_signed(offset.as_i64()) & mask;
                         continue;
                     }
                 }
                 Inst::Bltu { offset, src1, src2 } => {
                     if reg!(src1) < reg!(src2) {
-                        pc = pc.wrapping_add_signed(offset.as_i64());
+                        pc = pc.wrapping_add_signed(offset.as_i64()) & mask;
                         continue;
                     }
                 }
                 Inst::Bgeu { offset, src1, src2 } => {
                     if reg!(src1) >= reg!(src2) {
-                        pc = pc.wrapping_add_signed(offset.as_i64());
+                        pc = pc.wrapping_add_signed(offset.as_i64()) & mask;
                         continue;
                     }
                 }
                 Inst::Blt { offset, src1, src2 } => {
-                    if (reg!(src1) as i64) < (reg!(src2) as i64) {
-                        pc = pc.wrapping_add_signed(offset.as_i64());
+                    if sreg!(src1) < sreg!(src2) {
+                        pc = pc.wrapping_add_signed(offset.as_i64()) & mask;
                         continue;
                     }
                 }
                 Inst::Bge { offset, src1, src2 } => {
-                    if (reg!(src1) as i64) >= (reg!(src2) as i64) {
-                        pc = pc.wrapping_add_signed(offset.as_i64());
+                    if sreg!(src1) >= sreg!(src2) {
+                        pc = pc.wrapping_add_signed(offset.as_i64()) & mask;
                         continue;
                     }
                 }
                 //Loads
                 Inst::Lb { offset, dest, base } => {
-                    let mem = reg!(base).wrapping_add_signed(offset.as_i64());
-                    set_reg!(dest => match self.get_page(mem){
-                        p => unsafe{
-                            *(p as *mut i8)
-                        }
-                    } as i64 as u64);
+                    let mem = reg!(base).wrapping_add_signed(offset.as_i64()) & mask;
+                    set_reg!(dest => load!(mem, 1) as i8 as i64 as u64);
                 }
                 Inst::Lbu { offset, dest, base } => {
-                    let mem = reg!(base).wrapping_add_signed(offset.as_i64());
-                    set_reg!(dest => match self.get_page(mem){
-                        p => unsafe{
-                            *(p as *mut u8)
-                        }
-                    } as u64);
+                    let mem = reg!(base).wrapping_add_signed(offset.as_i64()) & mask;
+                    set_reg!(dest => load!(mem, 1));
                 }
                 Inst::Lh { offset, dest, base } => {
-                    let mem = reg!(base).wrapping_add_signed(offset.as_i64());
-                    set_reg!(dest => match self.get_page(mem){
-                        p => unsafe{
-                            *(p as *mut i16)
-                        }
-                    } as i64 as u64);
+                    let mem = reg!(base).wrapping_add_signed(offset.as_i64()) & mask;
+                    set_reg!(dest => load!(mem, 2) as i16 as i64 as u64);
                 }
                 Inst::Lhu { offset, dest, base } => {
-                    let mem = reg!(base).wrapping_add_signed(offset.as_i64());
-                    set_reg!(dest => match self.get_page(mem){
-                        p => unsafe{
-                            *(p as *mut u16)
-                        }
-                    } as u64);
+                    let mem = reg!(base).wrapping_add_signed(offset.as_i64()) & mask;
+                    set_reg!(dest => load!(mem, 2));
                 }
                 Inst::Lw { offset, dest, base } => {
-                    let mem = reg!(base).wrapping_add_signed(offset.as_i64());
-                    set_reg!(dest => match self.get_page(mem){
-                        p => unsafe{
-                            *(p as *mut i32)
-                        }
-                    } as i64 as u64);
+                    let mem = reg!(base).wrapping_add_signed(offset.as_i64()) & mask;
+                    set_reg!(dest => load!(mem, 4) as i32 as i64 as u64);
                 }
                 Inst::Lwu { offset, dest, base } => {
-                    let mem = reg!(base).wrapping_add_signed(offset.as_i64());
-                    set_reg!(dest => match self.get_page(mem){
-                        p => unsafe{
-                            *(p as *mut u32)
-                        }
-                    } as u64);
+                    let mem = reg!(base).wrapping_add_signed(offset.as_i64()) & mask;
+                    set_reg!(dest => load!(mem, 4));
                 }
                 Inst::Ld { offset, dest, base } => {
-                    let mem = reg!(base).wrapping_add_signed(offset.as_i64());
-                    set_reg!(dest => match self.get_page(mem){
-                        p => unsafe{
-                            *(p as *mut u64)
-                        }
-                    });
+                    let mem = reg!(base).wrapping_add_signed(offset.as_i64()) & mask;
+                    set_reg!(dest => load!(mem, 8));
                 }
                 //Stores
                 Inst::Sb { offset, src, base } => {
-                    let mem = reg!(base).wrapping_add_signed(offset.as_i64());
-                    let ptr = self.get_page(mem);
-                    unsafe {
-                        *(ptr as *mut u8) = (reg!(src) & 0xff) as u8;
-                    }
+                    let mem = reg!(base).wrapping_add_signed(offset.as_i64()) & mask;
+                    store!(mem, 1, reg!(src));
                 }
                 Inst::Sh { offset, src, base } => {
-                    let mem = reg!(base).wrapping_add_signed(offset.as_i64());
-                    let ptr = self.get_page(mem);
-                    unsafe {
-                        *(ptr as *mut u16) = (reg!(src) & 0xffff) as u16;
-                    }
+                    let mem = reg!(base).wrapping_add_signed(offset.as_i64()) & mask;
+                    store!(mem, 2, reg!(src));
                 }
                 Inst::Sw { offset, src, base } => {
-                    let mem = reg!(base).wrapping_add_signed(offset.as_i64());
-                    let ptr = self.get_page(mem);
-                    unsafe {
-                        *(ptr as *mut u32) = (reg!(src) & 0xffff_ffff) as u32;
-                    }
+                    let mem = reg!(base).wrapping_add_signed(offset.as_i64()) & mask;
+                    store!(mem, 4, reg!(src));
                 }
                 Inst::Sd { offset, src, base } => {
-                    let mem = reg!(base).wrapping_add_signed(offset.as_i64());
-                    let ptr = self.get_page(mem);
-                    unsafe {
-                        *(ptr as *mut u64) = reg!(src);
-                    }
+                    let mem = reg!(base).wrapping_add_signed(offset.as_i64()) & mask;
+                    store!(mem, 8, reg!(src));
                 }
                 //Fence
                 Inst::Fence { fence } => {}
@@ -372,7 +525,7 @@ impl Reactor {
                     set_reg32!(dest => reg32!(src1) >> (imm.as_u32() % 32));
                 }
                 Inst::SraiW { imm, dest, src1 } => {
-                    set_reg32!(dest => ((reg32!(src1) as i64) >> (imm.as_u32() % 32)) as u32);
+                    set_reg32!(dest => ((reg32!(src1) as i32) >> (imm.as_u32() % 32)) as u32);
                 }
                 //Regs
                 Inst::AddW { src2, dest, src1 } => {
@@ -385,7 +538,7 @@ impl Reactor {
                     set_reg32!(dest => reg32!(src1) >> ((reg32!(src2) & 0xffff_ffff) as u32 % 32));
                 }
                 Inst::SraW { src2, dest, src1 } => {
-                    set_reg32!(dest => ((reg32!(src1) as i64) >> ((reg32!(src2) & 0xffff_ffff) as u32 % 32)) as u32);
+                    set_reg32!(dest => ((reg32!(src1) as i32) >> (reg32!(src2) % 32)) as u32);
                 }
                 Inst::SubW { src2, dest, src1 } => {
                     set_reg32!(dest => reg32!(src1).wrapping_sub(reg32!(src2)));
@@ -395,31 +548,81 @@ impl Reactor {
                     set_reg32!(dest => reg32!(src1).wrapping_mul(reg32!(src2)));
                 }
                 Inst::DivW { src2, dest, src1 } => {
-                    set_reg32!(dest => ((reg32!(src1) as i32) / (reg32!(src2) as i32)) as u32);
+                    set_reg32!(dest => match reg32!(src2) as i32 {
+                        0 => u32::MAX,
+                        d => (reg32!(src1) as i32).wrapping_div(d) as u32,
+                    });
                 }
                 Inst::DivuW { src2, dest, src1 } => {
-                    set_reg32!(dest => reg32!(src1) / reg32!(src2));
+                    set_reg32!(dest => reg32!(src1).checked_div(reg32!(src2)).unwrap_or(u32::MAX));
                 }
                 Inst::RemW { src2, dest, src1 } => {
-                    set_reg32!(dest => ((reg32!(src1) as i32) % (reg32!(src2) as i32)) as u32);
+                    set_reg32!(dest => match reg32!(src2) as i32 {
+                        0 => reg32!(src1),
+                        d => (reg32!(src1) as i32).wrapping_rem(d) as u32,
+                    });
                 }
                 Inst::RemuW { src2, dest, src1 } => {
-                    set_reg32!(dest => reg32!(src1) % reg32!(src2));
+                    set_reg32!(dest => reg32!(src1).checked_rem(reg32!(src2)).unwrap_or(reg32!(src1)));
                 }
-                //Ecall
+                //Atomics
+                Inst::LrW { dest, addr, .. } => {
+                    let mem = reg!(addr);
+                    if mem & 3 != 0 {
+                        trap!(Trap::exception(Exception::LoadMisaligned, mem));
+                    }
+                    set_reg!(dest => match page!(mem, Read){
+                        p => unsafe{
+                            *(p as *mut i32)
+                        }
+                    } as i64 as u64);
+                    self.lr(mem);
+                }
+                Inst::ScW { dest, addr, src, .. } => {
+                    let mem = reg!(addr);
+                    if mem & 3 != 0 {
+                        trap!(Trap::exception(Exception::StoreMisaligned, mem));
+                    }
+                    let ok = self.sc(mem);
+                    if ok {
+                        let ptr = page!(mem, Write);
+                        unsafe {
+                            *(ptr as *mut u32) = (reg!(src) & 0xffff_ffff) as u32;
+                        }
+                    }
+                    set_reg!(dest => !ok as u64);
+                }
+                Inst::AmoW { op, dest, addr, src, .. } => {
+                    let mem = reg!(addr);
+                    if mem & 3 != 0 {
+                        trap!(Trap::exception(Exception::StoreMisaligned, mem));
+                    }
+                    let ptr = page!(mem, Write) as *mut u32;
+                    let v = unsafe { *ptr } as i32 as i64 as u64;
+                    unsafe {
+                        *ptr = ext::amo_op(op, v, reg!(src), false) as u32;
+                    }
+                    set_reg!(dest => v);
+                }
+                //Traps
                 Inst::Ecall => {
-                    self.restore_regs(&regs);
-                    self._ecall().await?;
-                    regs = self.save_regs();
+                    let mode = self.core.lock().csrs.mode;
+                    trap!(Trap::exception(Exception::ecall(mode), 0));
                 }
-                _ => {
-                    self.restore_regs(&regs);
-                    return Err(JsValue::from_str(&format!("iop:{i}")));
+                Inst::Ebreak => {
+                    resume!(self.debug_break(pc, next));
+                    step = self.core.lock().stepping;
+                    stepped = false;
+                    continue;
                 }
+                _ => trap!(Trap::illegal(code)),
             }
             pc = next;
         }
     }
+}
+#[wasm_bindgen]
+impl Reactor {
     // #[wasm_bindgen()]
     // pub fn r(&self, a: usize) -> u64 {
     //     if a == 0 {
@@ -434,11 +637,80 @@ impl Reactor {
     //     }
     //     unsafe { &mut *self.core.get() }.regs[a - 1] = x;
     // }
-    #[wasm_bindgen(js_name = "ecall")]
-    pub async fn _ecall(&self) -> Result<JsValue, JsValue> {
-        if self.save_regs()[Reg::A7.0 as usize] == 93 {
-            return Err(log_success());
-        }
-        Ok(JsValue::undefined())
+    /// Back CSR `csr` with JS callbacks; without `write` the CSR is read-only
+    ///
+    /// `read` returns a BigInt and `write` receives one.
+    #[wasm_bindgen(js_name = "register_csr")]
+    pub fn register_csr_js(&self, csr: u16, read: js_sys::Function, write: Option<js_sys::Function>) {
+        let hook = vane_jit::csr::CsrHook {
+            read: Rc::new(move || {
+                read.call0(&JsValue::UNDEFINED)
+                    .ok()
+                    .and_then(|v| u64::try_from(v).ok())
+                    .unwrap_or(0)
+            }),
+            write: write.map(|write| -> Rc<dyn Fn(u64)> {
+                Rc::new(move |v| {
+                    let _ = write.call1(&JsValue::UNDEFINED, &JsValue::from(v));
+                })
+            }),
+        };
+        self.register_csr(csr, hook);
+    }
+    #[wasm_bindgen(js_name = "unregister_csr")]
+    pub fn unregister_csr_js(&self, csr: u16) {
+        self.unregister_csr(csr);
+    }
+    /// Intercept traps with a JS callback, ahead of earlier handlers
+    ///
+    /// `f` is called with the `mcause` value, `mtval` and the trapping pc as
+    /// BigInts. Returning a BigInt resumes there, returning `undefined` passes
+    /// the trap on, and throwing ends the run with the thrown value.
+    #[wasm_bindgen(js_name = "on_trap")]
+    pub fn on_trap(&self, f: js_sys::Function) {
+        self.add_trap_handler(move |r: &Reactor, trap: &Trap, pc: u64| {
+            let cause = trap.cause(r.core.lock().xlen);
+            let v = f.call3(
+                &JsValue::UNDEFINED,
+                &JsValue::from(cause),
+                &JsValue::from(trap.tval),
+                &JsValue::from(pc),
+            )?;
+            Ok(u64::try_from(v).ok())
+        });
+    }
+    /// Stop at `ebreak` with a JS callback, replacing any earlier one
+    ///
+    /// `f` is called with the pc as a BigInt and an array of the 32 integer
+    /// registers as BigInts; changes to the array are written back. Returning
+    /// `"step"` runs one instruction and calls `f` again, `"abort"` ends the
+    /// run, and anything else resumes. Throwing ends the run with the thrown value.
+    #[wasm_bindgen(js_name = "on_break")]
+    pub fn on_break(&self, f: js_sys::Function) {
+        self.set_break_hook(move |_: &Reactor, pc: u64, regs: &mut [u64; 32]| {
+            let array: js_sys::Array = regs.iter().map(|&v| JsValue::from(v)).collect();
+            let v = f.call2(&JsValue::UNDEFINED, &JsValue::from(pc), &array)?;
+            for (i, r) in regs.iter_mut().enumerate() {
+                if let Ok(v) = u64::try_from(array.get(i as u32)) {
+                    *r = v;
+                }
+            }
+            Ok(v.as_string()
+                .and_then(|s| BreakAction::from_name(&s))
+                .unwrap_or(BreakAction::Resume))
+        });
+    }
+    /// Send console output to a JS callback
+    ///
+    /// `f` is called with a `Uint8Array` of the bytes written.
+    #[wasm_bindgen(js_name = "on_console")]
+    pub fn on_console(&self, f: js_sys::Function) {
+        self.set_console(move |bytes: &[u8]| {
+            let _ = f.call1(&JsValue::UNDEFINED, &js_sys::Uint8Array::from(bytes));
+        });
+    }
+    #[wasm_bindgen(js_name = "clear_break")]
+    pub fn clear_break_js(&self) {
+        self.clear_break_hook();
     }
 }
@@ -0,0 +1,156 @@
+//! A built-in SBI for supervisor-mode payloads.
+//!
+//! With [`Reactor::enable_sbi`] the reactor stands in for machine-mode
+//! firmware: supervisor `ecall`s are answered by [`Reactor::sbi_handler`]
+//! rather than delivered to the guest, so a kernel boots without OpenSBI
+//! in guest memory.
+use rv_asm::Reg;
+use vane_jit::csr::{self, mstatus};
+use vane_jit::sbi::{error, reset, suspend, Call, SbiRet};
+use vane_jit::trap::{Exception, Interrupt, Priv, Trap};
+use vane_jit::virt::Region;
+use vane_jit::xlen_mask;
+use wasm_bindgen::prelude::*;
+
+use crate::{log_success, Reactor};
+
+/// Exceptions left to a supervisor kernel: all but `ecall`s from S and M
+const MEDELEG: u64 = 0xb1ff;
+/// The supervisor software, timer and external interrupts
+const MIDELEG: u64 = 0x222;
+
+impl Reactor {
+    /// Answer an `ecall` from supervisor mode as SBI firmware would
+    ///
+    /// The result goes in `a0` and `a1` and execution resumes after the
+    /// `ecall`. Shutting down, rebooting and stopping the hart end the run.
+    fn sbi_handler(&self, trap: &Trap, pc: u64) -> Result<Option<u64>, JsValue> {
+        if trap.as_exception() != Some(Exception::EcallS) {
+            return Ok(None);
+        }
+        let xlen = self.core.lock().xlen;
+        let reg = |r: Reg| Self::reg(self.clone(), r.0);
+        let args = [Reg::A0, Reg::A1, Reg::A2, Reg::A3, Reg::A4, Reg::A5].map(reg);
+        let mut next = pc.wrapping_add(4);
+        let ret = match Call::decode(reg(Reg::A7), reg(Reg::A6), args, xlen) {
+            Ok(call) => self.sbi_call(call, pc, &mut next)?,
+            Err(e) => SbiRet::err(e),
+        };
+        let mask = xlen_mask(xlen);
+        Self::set_reg(self.clone(), Reg::A0.0, ret.error as u64 & mask);
+        Self::set_reg(self.clone(), Reg::A1.0, ret.value & mask);
+        Ok(Some(next))
+    }
+
+    /// Carry out `call`, made by the `ecall` at `pc`; `next` is where execution goes on
+    fn sbi_call(&self, call: Call, pc: u64, next: &mut u64) -> Result<SbiRet, JsValue> {
+        Ok(match call {
+            Call::Base(ret) => ret,
+            Call::SetTimer(time) => {
+                self.core.lock().clint.stimecmp = time;
+                self.arm_interrupts();
+                SbiRet::ok(0)
+            }
+            Call::SendIpi(me) => {
+                if me {
+                    self.core.lock().csrs.mip |= Interrupt::SupervisorSoftware.bit();
+                    self.arm_interrupts();
+                }
+                SbiRet::ok(0)
+            }
+            Call::FenceI(me) => {
+                if me {
+                    self.fence_i_op();
+                }
+                SbiRet::ok(0)
+            }
+            Call::SfenceVma => SbiRet::ok(0),
+            Call::HartStop => return Err(JsValue::from_str(&format!("hart stopped at {pc:#x}"))),
+            Call::HartStatus(state) => SbiRet::ok(state),
+            Call::HartSuspend { kind, resume, opaque } => {
+                if kind == suspend::NON_RETENTIVE {
+                    // Resume as if the hart had just started: translation
+                    // and interrupts off, `a0` the hart ID and `a1` `opaque`
+                    {
+                        let mut lock = self.core.lock();
+                        lock.csrs.satp = 0;
+                        lock.csrs.mstatus &= !mstatus::SIE;
+                    }
+                    self.arm_interrupts();
+                    *next = resume;
+                    return Ok(SbiRet::ok(opaque));
+                }
+                SbiRet::ok(0)
+            }
+            Call::SystemReset { kind, reason } => {
+                return Err(match (kind, reason) {
+                    (reset::SHUTDOWN, 0) => log_success(),
+                    (reset::SHUTDOWN, _) => JsValue::from_str("system failure"),
+                    _ => JsValue::from_str("reboot requested"),
+                });
+            }
+            Call::ConsoleWrite { len, addr } => {
+                if !self.console_ram(addr, len) {
+                    return Ok(SbiRet::err(error::INVALID_PARAM));
+                }
+                let bytes: Vec<u8> = {
+                    let lock = self.core.lock();
+                    (0..len).map(|i| lock.mem.read_byte(addr + i)).collect()
+                };
+                self.console_out(&bytes);
+                SbiRet::ok(len)
+            }
+            Call::ConsoleRead { len, addr } => {
+                if !self.console_ram(addr, len) {
+                    return Ok(SbiRet::err(error::INVALID_PARAM));
+                }
+                let mut lock = self.core.lock();
+                let n = len.min(lock.console_input.len() as u64);
+                for i in 0..n {
+                    let b = lock.console_input.pop_front().unwrap_or(0);
+                    lock.mem.write_byte(addr + i, b);
+                }
+                SbiRet::ok(n)
+            }
+            Call::ConsoleWriteByte(b) => {
+                self.console_out(&[b]);
+                SbiRet::ok(0)
+            }
+        })
+    }
+
+    /// Whether the `len` bytes at physical address `addr` are all RAM
+    fn console_ram(&self, addr: u64, len: u64) -> bool {
+        match len.checked_sub(1) {
+            None => true,
+            Some(last) => {
+                addr.checked_add(last).is_some()
+                    && self.region(addr) == Region::Ram
+                    && self.region(addr + last) == Region::Ram
+            }
+        }
+    }
+}
+
+#[wasm_bindgen]
+impl Reactor {
+    /// Run a supervisor-mode payload on the built-in SBI
+    ///
+    /// Supervisor `ecall`s are answered by the reactor, the supervisor
+    /// interrupts and the exceptions a kernel handles are delegated, and
+    /// the hart drops to supervisor mode. Call this before running.
+    #[wasm_bindgen]
+    pub fn enable_sbi(&self) {
+        self.add_trap_handler(Self::sbi_handler);
+        {
+            let mut lock = self.core.lock();
+            let xlen = lock.xlen;
+            let csrs = &mut lock.csrs;
+            for (csr, value) in [(csr::MEDELEG, MEDELEG), (csr::MIDELEG, MIDELEG), (csr::MCOUNTEREN, 7)] {
+                let _ = csrs.write(csr, value, xlen);
+            }
+            csrs.mode = Priv::Supervisor;
+        }
+        self.arm_interrupts();
+    }
+}
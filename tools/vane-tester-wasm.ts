@@ -68,7 +68,7 @@ function parseELF(buffer: Uint8Array) {
     }
   }
 
-  return { entry: e_entry, segments };
+  return { entry: e_entry, segments, is64 };
 }
 
 async function loadWasmPkg(pkgPath: string): Promise<any> {
@@ -106,6 +106,7 @@ async function main() {
   const shared_security_directory_vaddr = argv.shared_security_directory_vaddr ? Number(argv.shared_security_directory_vaddr) : undefined;
   const use_32bit_paging = !!argv.use_32bit_paging || !!argv.use32bitPaging;
   const use_multilevel_paging = !!argv.use_multilevel_paging || !!argv.useMultilevelPaging;
+  const xlen = argv.xlen ? Number(argv.xlen) : undefined;
 
   const pkgDir = path.join(__dirname, '..', 'crates', 'vane', 'pkg');
   if (!await exists(pkgDir)) { console.error(`Expected wasm pkg at ${pkgDir} - build it first`); process.exit(3); }
@@ -157,6 +158,7 @@ async function main() {
   }
 
   if (reactor.set_test_mode) try { reactor.set_test_mode(test_mode); } catch {}
+  try { reactor.xlen = xlen ?? (elf.is64 ? 64 : 32); } catch {}
   if (reactor.set_paging_mode) try { reactor.set_paging_mode(paging); } catch {}
   if (reactor.set_use_32bit_paging) try { reactor.set_use_32bit_paging(use_32bit_paging); } catch {}
   if (reactor.set_use_multilevel_paging) try { reactor.set_use_multilevel_paging(use_multilevel_paging); } catch {}